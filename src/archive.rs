@@ -0,0 +1,287 @@
+//! FArchive primitives as saved games and other blobs outside packages
+//! use them, and a decoder for such blobs driven by a RON schema.
+
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind, Read, Result, Seek},
+};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use serde::{Deserialize, Serialize, Serializer, ser::SerializeMap};
+
+pub use crate::upkreader::read_fstring_stream as read_fstring;
+use crate::{upkreader::FName, utils::heuristics};
+
+/// A TArray's element count.
+pub fn read_array_len<R: Read>(r: &mut R) -> Result<usize> {
+    let n = r.read_i32::<LittleEndian>()?;
+    heuristics::check(
+        "max-archive-array",
+        n as i64,
+        format_args!("implausible array count {n}"),
+    )?;
+    Ok(n as usize)
+}
+
+/// A TArray, each element read by `elem`.
+pub fn read_array<R: Read, T>(
+    r: &mut R,
+    mut elem: impl FnMut(&mut R) -> Result<T>,
+) -> Result<Vec<T>> {
+    let n = read_array_len(r)?;
+    // Grown as elements arrive; the count alone isn't trusted with memory.
+    let mut out = Vec::with_capacity(n.min(4096));
+    for _ in 0..n {
+        out.push(elem(r)?);
+    }
+    Ok(out)
+}
+
+/// UBOOL: four bytes, anything but 0 is true.
+pub fn read_bool<R: Read>(r: &mut R) -> Result<bool> {
+    Ok(r.read_u32::<LittleEndian>()? != 0)
+}
+
+pub fn read_guid<R: Read>(r: &mut R) -> Result<[u32; 4]> {
+    let mut g = [0u32; 4];
+    for part in &mut g {
+        *part = r.read_u32::<LittleEndian>()?;
+    }
+    Ok(g)
+}
+
+/// An FName as a name table index and instance number; blobs without a
+/// name table store names as FStrings instead.
+pub fn read_fname<R: Read>(r: &mut R) -> Result<FName> {
+    Ok(FName {
+        name_index: r.read_i32::<LittleEndian>()?,
+        name_instance: r.read_i32::<LittleEndian>()?,
+    })
+}
+
+/// A decoded field. Serializes as plain JSON, with struct fields in
+/// schema order.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+    Struct(Vec<(String, Value)>),
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            Value::Null => s.serialize_unit(),
+            Value::Bool(b) => s.serialize_bool(*b),
+            Value::Int(v) => s.serialize_i64(*v),
+            Value::UInt(v) => s.serialize_u64(*v),
+            Value::Float(v) => s.serialize_f64(*v),
+            Value::String(v) => s.serialize_str(v),
+            Value::Array(v) => v.serialize(s),
+            Value::Struct(fields) => {
+                let mut m = s.serialize_map(Some(fields.len()))?;
+                for (k, v) in fields {
+                    m.serialize_entry(k, v)?;
+                }
+                m.end()
+            }
+        }
+    }
+}
+
+/// One field of a schema.
+#[derive(Debug, Clone, Deserialize)]
+pub enum FieldType {
+    Byte,
+    /// UBOOL, four bytes.
+    Bool,
+    /// One byte.
+    Bool8,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Int64,
+    UInt64,
+    Float,
+    Double,
+    String,
+    /// Index and instance; see `read_fname`.
+    Name,
+    Guid,
+    /// That many bytes, shown as hex.
+    Bytes(usize),
+    /// That many bytes, left out of the output.
+    Skip(usize),
+    /// TArray: count, then elements.
+    Array(Box<FieldType>),
+    /// A static array: that many elements, no count.
+    Fixed(Box<FieldType>, usize),
+    /// One of the schema's `structs`.
+    Struct(String),
+}
+
+/// The layout of a blob: `root` fields in order, with named structs they
+/// can refer to.
+///
+/// ```ron
+/// (
+///     root: [
+///         ("Version", Int),
+///         ("Player", String),
+///         ("Items", Array(Struct("Item"))),
+///     ],
+///     structs: {
+///         "Item": [("Id", Int), ("Count", Int)],
+///     },
+/// )
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct Schema {
+    pub root: Vec<(String, FieldType)>,
+    #[serde(default)]
+    pub structs: HashMap<String, Vec<(String, FieldType)>>,
+}
+
+impl Schema {
+    pub fn from_ron(text: &str) -> Result<Self> {
+        let schema: Schema = ron::from_str(text)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("schema: {e}")))?;
+        schema.check()?;
+        Ok(schema)
+    }
+
+    /// Every `Struct` names a struct the schema has.
+    fn check(&self) -> Result<()> {
+        fn walk(s: &Schema, ty: &FieldType, at: &str) -> Result<()> {
+            match ty {
+                FieldType::Array(inner) | FieldType::Fixed(inner, _) => walk(s, inner, at),
+                FieldType::Struct(name) if !s.structs.contains_key(name) => Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("schema: {at} uses struct '{name}', which isn't defined"),
+                )),
+                _ => Ok(()),
+            }
+        }
+        for (name, ty) in &self.root {
+            walk(self, ty, name)?;
+        }
+        for (sname, fields) in &self.structs {
+            for (name, ty) in fields {
+                walk(self, ty, &format!("{sname}.{name}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes `r` from its current position as `root`. Errors name the
+    /// field being read and where.
+    pub fn decode<R: Read + Seek>(&self, r: &mut R) -> Result<Value> {
+        self.fields(r, &self.root, "", 0)
+    }
+
+    fn fields<R: Read + Seek>(
+        &self,
+        r: &mut R,
+        fields: &[(String, FieldType)],
+        path: &str,
+        depth: usize,
+    ) -> Result<Value> {
+        let mut out = Vec::with_capacity(fields.len());
+        for (name, ty) in fields {
+            let at = if path.is_empty() {
+                name.clone()
+            } else {
+                format!("{path}.{name}")
+            };
+            let v = self.field(r, ty, &at, depth)?;
+            if !matches!(ty, FieldType::Skip(_)) {
+                out.push((name.clone(), v));
+            }
+        }
+        Ok(Value::Struct(out))
+    }
+
+    fn field<R: Read + Seek>(
+        &self,
+        r: &mut R,
+        ty: &FieldType,
+        path: &str,
+        depth: usize,
+    ) -> Result<Value> {
+        let pos = r.stream_position()?;
+        let ctx = |e: Error| Error::new(e.kind(), format!("{path} at 0x{pos:X}: {e}"));
+        let v = match ty {
+            FieldType::Byte => Value::UInt(r.read_u8().map_err(ctx)?.into()),
+            FieldType::Bool => Value::Bool(read_bool(r).map_err(ctx)?),
+            FieldType::Bool8 => Value::Bool(r.read_u8().map_err(ctx)? != 0),
+            FieldType::Short => Value::Int(r.read_i16::<LittleEndian>().map_err(ctx)?.into()),
+            FieldType::UShort => Value::UInt(r.read_u16::<LittleEndian>().map_err(ctx)?.into()),
+            FieldType::Int => Value::Int(r.read_i32::<LittleEndian>().map_err(ctx)?.into()),
+            FieldType::UInt => Value::UInt(r.read_u32::<LittleEndian>().map_err(ctx)?.into()),
+            FieldType::Int64 => Value::Int(r.read_i64::<LittleEndian>().map_err(ctx)?),
+            FieldType::UInt64 => Value::UInt(r.read_u64::<LittleEndian>().map_err(ctx)?),
+            FieldType::Float => Value::Float(r.read_f32::<LittleEndian>().map_err(ctx)?.into()),
+            FieldType::Double => Value::Float(r.read_f64::<LittleEndian>().map_err(ctx)?),
+            FieldType::String => Value::String(read_fstring(r).map_err(ctx)?),
+            FieldType::Name => {
+                let n = read_fname(r).map_err(ctx)?;
+                Value::String(match n.name_instance {
+                    0 => format!("#{}", n.name_index),
+                    i => format!("#{}_{}", n.name_index, i - 1),
+                })
+            }
+            FieldType::Guid => {
+                let g = read_guid(r).map_err(ctx)?;
+                Value::String(format!(
+                    "{:08X}-{:08X}-{:08X}-{:08X}",
+                    g[0], g[1], g[2], g[3]
+                ))
+            }
+            FieldType::Bytes(n) | FieldType::Skip(n) => {
+                let mut buf = vec![0u8; *n];
+                r.read_exact(&mut buf).map_err(ctx)?;
+                match ty {
+                    FieldType::Skip(_) => Value::Null,
+                    _ => Value::String(buf.iter().map(|b| format!("{b:02x}")).collect::<String>()),
+                }
+            }
+            FieldType::Array(inner) => {
+                let n = read_array_len(r).map_err(ctx)?;
+                self.elements(r, inner, n, path, depth)?
+            }
+            FieldType::Fixed(inner, n) => self.elements(r, inner, *n, path, depth)?,
+            FieldType::Struct(name) => {
+                let max = heuristics::get().max_struct_depth;
+                if depth as u64 >= max {
+                    return Err(ctx(heuristics::exceeded(
+                        "max-struct-depth",
+                        format_args!("structs nested over {max} deep"),
+                    )));
+                }
+                self.fields(r, &self.structs[name], path, depth + 1)?
+            }
+        };
+        Ok(v)
+    }
+
+    fn elements<R: Read + Seek>(
+        &self,
+        r: &mut R,
+        ty: &FieldType,
+        n: usize,
+        path: &str,
+        depth: usize,
+    ) -> Result<Value> {
+        let mut out = Vec::with_capacity(n.min(4096));
+        for i in 0..n {
+            out.push(self.field(r, ty, &format!("{path}[{i}]"), depth)?);
+        }
+        Ok(Value::Array(out))
+    }
+}
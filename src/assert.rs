@@ -0,0 +1,770 @@
+//! Invariants about a package, for mod build pipelines to check what they
+//! produced:
+//!
+//! ```text
+//! ue3-tools assert Out.upk -e "export('MyMod.Weapon').size < 65536" \
+//!                          -e "name_exists('MyName')"
+//! ```
+//!
+//! An expression is comparisons (`== != < <= > >=`) of numbers, strings and
+//! booleans, combined with `&& || !` and grouped with parentheses; numbers
+//! take `+ - * /`. Functions:
+//!
+//! | function                | value                                          |
+//! |-------------------------|------------------------------------------------|
+//! | `export(path)`          | an export by path or index, as `peek` finds it |
+//! | `export_exists(path)`   | whether that lookup succeeds                   |
+//! | `import_exists(path)`   | an import by full name or path                 |
+//! | `name_exists(text)`     | the name table has the exact text              |
+//! | `exports_of(class)`     | number of exports of a class                   |
+//! | `export_count()` …      | also `import_count()`, `name_count()`          |
+//! | `file_size()`           | bytes on disk                                  |
+//!
+//! An export has `.index`, `.size`, `.offset`, `.class`, `.name` (full
+//! name), `.flags`, `.export_flags` and `.prop(name)` / `.prop(name, i)`
+//! for a scalar tagged property. Object and string comparisons are exact;
+//! object lookups ignore case the way the engine does.
+
+use std::{
+    fmt,
+    io::{Error, ErrorKind, Result},
+    path::Path,
+};
+
+use crate::{
+    exit::validation_failed,
+    offsets::find_export,
+    schemadb::{LazyPackage, SchemaDb, open_package_file},
+    upkprops::PropertyValue,
+    utils::term::{Color, paint},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    And,
+    Or,
+}
+
+impl Op {
+    fn text(self) -> &'static str {
+        match self {
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::And => "&&",
+            Op::Or => "||",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Ident(String),
+    Op(Op),
+    Not,
+    LParen,
+    RParen,
+    Comma,
+    Dot,
+}
+
+fn syntax(src: &str, at: usize, msg: impl fmt::Display) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!(
+            "{msg} at column {}\n  {src}\n  {:>w$}",
+            at + 1,
+            "^",
+            w = at + 1
+        ),
+    )
+}
+
+fn tokenize(src: &str) -> Result<Vec<(usize, Token)>> {
+    let b = src.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < b.len() {
+        let c = b[i];
+        let start = i;
+        let two = b.get(i..i + 2).unwrap_or_default();
+        let op2 = match two {
+            b"==" => Some(Op::Eq),
+            b"!=" => Some(Op::Ne),
+            b"<=" => Some(Op::Le),
+            b">=" => Some(Op::Ge),
+            b"&&" => Some(Op::And),
+            b"||" => Some(Op::Or),
+            _ => None,
+        };
+        if let Some(op) = op2 {
+            out.push((start, Token::Op(op)));
+            i += 2;
+            continue;
+        }
+        let tok = match c {
+            b' ' | b'\t' | b'\r' | b'\n' => {
+                i += 1;
+                continue;
+            }
+            b'<' => Token::Op(Op::Lt),
+            b'>' => Token::Op(Op::Gt),
+            b'+' => Token::Op(Op::Add),
+            b'-' => Token::Op(Op::Sub),
+            b'*' => Token::Op(Op::Mul),
+            b'/' => Token::Op(Op::Div),
+            b'!' => Token::Not,
+            b'(' => Token::LParen,
+            b')' => Token::RParen,
+            b',' => Token::Comma,
+            b'.' => Token::Dot,
+            b'\'' | b'"' => {
+                let end = src[i + 1..]
+                    .find(c as char)
+                    .ok_or_else(|| syntax(src, start, "unterminated string"))?;
+                let s = src[i + 1..i + 1 + end].to_string();
+                i += end + 2;
+                out.push((start, Token::Str(s)));
+                continue;
+            }
+            b'0'..=b'9' => {
+                let len = src[i..]
+                    .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_' || ch == '.'))
+                    .unwrap_or(src.len() - i);
+                let text = src[i..i + len].replace('_', "");
+                i += len;
+                let tok = if let Some(h) = text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+                    i64::from_str_radix(h, 16).ok().map(Token::Int)
+                } else if text.contains('.') {
+                    text.parse().ok().map(Token::Float)
+                } else {
+                    text.parse().ok().map(Token::Int)
+                };
+                out.push((
+                    start,
+                    tok.ok_or_else(|| syntax(src, start, format!("bad number '{text}'")))?,
+                ));
+                continue;
+            }
+            c if c.is_ascii_alphabetic() || c == b'_' => {
+                let len = src[i..]
+                    .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+                    .unwrap_or(src.len() - i);
+                out.push((start, Token::Ident(src[i..i + len].to_string())));
+                i += len;
+                continue;
+            }
+            _ => {
+                let ch = src[i..].chars().next().unwrap_or('?');
+                return Err(syntax(src, start, format!("unexpected '{ch}'")));
+            }
+        };
+        out.push((start, tok));
+        i += 1;
+    }
+    Ok(out)
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    /// `target.field` or `target.method(args)`.
+    Member(Box<Expr>, String, Option<Vec<Expr>>),
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |f: &mut fmt::Formatter<'_>, args: &[Expr]| {
+            for (i, a) in args.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{a}")?;
+            }
+            Ok(())
+        };
+        match self {
+            Expr::Int(n) => write!(f, "{n}"),
+            Expr::Float(x) => write!(f, "{x}"),
+            Expr::Str(s) => write!(f, "'{s}'"),
+            Expr::Bool(b) => write!(f, "{b}"),
+            Expr::Not(e) => write!(f, "!{e}"),
+            Expr::Neg(e) => write!(f, "-{e}"),
+            Expr::Binary(op, l, r) => write!(f, "({l} {} {r})", op.text()),
+            Expr::Call(name, args) => {
+                write!(f, "{name}(")?;
+                list(f, args)?;
+                f.write_str(")")
+            }
+            Expr::Member(target, field, None) => write!(f, "{target}.{field}"),
+            Expr::Member(target, method, Some(args)) => {
+                write!(f, "{target}.{method}(")?;
+                list(f, args)?;
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn column(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.src.len(), |(at, _)| *at)
+    }
+
+    fn error(&self, msg: impl fmt::Display) -> Error {
+        syntax(self.src, self.column(), msg)
+    }
+
+    fn expect(&mut self, want: Token, what: &str) -> Result<()> {
+        if self.peek() == Some(&want) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(format!("expected {what}")))
+        }
+    }
+
+    fn binary(
+        &mut self,
+        ops: &[Op],
+        next: fn(&mut Self) -> Result<Expr>,
+        chain: bool,
+    ) -> Result<Expr> {
+        let mut lhs = next(self)?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            if !ops.contains(&op) {
+                break;
+            }
+            self.pos += 1;
+            let rhs = next(self)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+            if !chain {
+                break;
+            }
+        }
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        self.binary(&[Op::Or], Self::and, true)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        self.binary(&[Op::And], Self::not, true)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.compare()
+    }
+
+    /// `a < b < c` doesn't mean what it looks like, so comparisons don't
+    /// chain.
+    fn compare(&mut self) -> Result<Expr> {
+        self.binary(
+            &[Op::Eq, Op::Ne, Op::Lt, Op::Le, Op::Gt, Op::Ge],
+            Self::sum,
+            false,
+        )
+    }
+
+    fn sum(&mut self) -> Result<Expr> {
+        self.binary(&[Op::Add, Op::Sub], Self::product, true)
+    }
+
+    fn product(&mut self) -> Result<Expr> {
+        self.binary(&[Op::Mul, Op::Div], Self::unary, true)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.peek() == Some(&Token::Op(Op::Sub)) {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        let mut e = self.primary()?;
+        while self.peek() == Some(&Token::Dot) {
+            self.pos += 1;
+            let Some(Token::Ident(field)) = self.peek().cloned() else {
+                return Err(self.error("expected a field name"));
+            };
+            self.pos += 1;
+            let args = if self.peek() == Some(&Token::LParen) {
+                Some(self.args()?)
+            } else {
+                None
+            };
+            e = Expr::Member(Box::new(e), field, args);
+        }
+        Ok(e)
+    }
+
+    fn args(&mut self) -> Result<Vec<Expr>> {
+        self.expect(Token::LParen, "'('")?;
+        let mut args = Vec::new();
+        if self.peek() == Some(&Token::RParen) {
+            self.pos += 1;
+            return Ok(args);
+        }
+        loop {
+            args.push(self.or()?);
+            match self.peek() {
+                Some(Token::Comma) => self.pos += 1,
+                Some(Token::RParen) => {
+                    self.pos += 1;
+                    return Ok(args);
+                }
+                _ => return Err(self.error("expected ',' or ')'")),
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let Some(tok) = self.peek().cloned() else {
+            return Err(self.error("unexpected end of expression"));
+        };
+        self.pos += 1;
+        match tok {
+            Token::Int(n) => Ok(Expr::Int(n)),
+            Token::Float(x) => Ok(Expr::Float(x)),
+            Token::Str(s) => Ok(Expr::Str(s)),
+            Token::Ident(id) if id == "true" => Ok(Expr::Bool(true)),
+            Token::Ident(id) if id == "false" => Ok(Expr::Bool(false)),
+            Token::Ident(id) => {
+                if self.peek() != Some(&Token::LParen) {
+                    return Err(self.error(format!("'{id}' needs arguments: {id}(…)")));
+                }
+                Ok(Expr::Call(id, self.args()?))
+            }
+            Token::LParen => {
+                let e = self.or()?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(e)
+            }
+            _ => {
+                self.pos -= 1;
+                Err(self.error("expected a value"))
+            }
+        }
+    }
+}
+
+fn parse(src: &str) -> Result<Expr> {
+    let mut p = Parser {
+        src,
+        tokens: tokenize(src)?,
+        pos: 0,
+    };
+    let e = p.or()?;
+    if p.pos < p.tokens.len() {
+        return Err(p.error("unexpected trailing input"));
+    }
+    Ok(e)
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    Export(i32),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{n}"),
+            Value::Float(x) => write!(f, "{x}"),
+            Value::Str(s) => write!(f, "'{s}'"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Export(i) => write!(f, "export #{i}"),
+        }
+    }
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::Int(_) | Value::Float(_) => "a number",
+            Value::Str(_) => "a string",
+            Value::Bool(_) => "a boolean",
+            Value::Export(_) => "an export",
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match self {
+            Value::Int(n) => Some(*n as f64),
+            Value::Float(x) => Some(*x),
+            _ => None,
+        }
+    }
+}
+
+/// An expression that couldn't be evaluated: a missing export, a type
+/// mismatch. Counts as a failed assertion rather than a usage error.
+fn eval_err(msg: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg.into())
+}
+
+struct Ctx<'a> {
+    lp: &'a LazyPackage,
+    db: Option<&'a SchemaDb>,
+    file_size: u64,
+}
+
+impl Ctx<'_> {
+    fn string_arg(&self, func: &str, args: &[Expr], at: usize) -> Result<String> {
+        match args.get(at).map(|a| self.eval(a)).transpose()? {
+            Some(Value::Str(s)) => Ok(s),
+            Some(v) => Err(eval_err(format!(
+                "{func}: argument {} is {}, not a string",
+                at + 1,
+                v.kind()
+            ))),
+            None => Err(eval_err(format!("{func}: missing argument {}", at + 1))),
+        }
+    }
+
+    /// An export path, or its 1-based index.
+    fn export_arg(&self, func: &str, args: &[Expr]) -> Result<String> {
+        Self::arity(func, args, 1)?;
+        match self.eval(&args[0])? {
+            Value::Int(n) => Ok(n.to_string()),
+            Value::Str(s) => Ok(s),
+            v => Err(eval_err(format!(
+                "{func}: argument is {}, not a path or index",
+                v.kind()
+            ))),
+        }
+    }
+
+    fn arity(func: &str, args: &[Expr], want: usize) -> Result<()> {
+        if args.len() == want {
+            Ok(())
+        } else {
+            Err(eval_err(format!(
+                "{func} takes {want} argument(s), got {}",
+                args.len()
+            )))
+        }
+    }
+
+    fn call(&self, func: &str, args: &[Expr]) -> Result<Value> {
+        let pak = &self.lp.pak;
+        let counted = |n: usize| {
+            Self::arity(func, args, 0)?;
+            Ok(Value::Int(n as i64))
+        };
+        match func {
+            "export" => Ok(Value::Export(find_export(
+                self.lp,
+                &self.export_arg(func, args)?,
+            )?)),
+            "export_exists" => {
+                let target = self.export_arg(func, args)?;
+                Ok(Value::Bool(find_export(self.lp, &target).is_ok()))
+            }
+            "import_exists" => {
+                Self::arity(func, args, 1)?;
+                let path = self.string_arg(func, args, 0)?;
+                let found = (1..=pak.import_table.len() as i32).any(|i| {
+                    pak.get_import_full_name(-i).eq_ignore_ascii_case(&path)
+                        || pak.get_import_path_name(-i).eq_ignore_ascii_case(&path)
+                });
+                Ok(Value::Bool(found))
+            }
+            "name_exists" => {
+                Self::arity(func, args, 1)?;
+                let name = self.string_arg(func, args, 0)?;
+                Ok(Value::Bool(pak.name_table.contains(&name)))
+            }
+            "exports_of" => {
+                Self::arity(func, args, 1)?;
+                let class = self.string_arg(func, args, 0)?;
+                let n = (1..=pak.export_table.len() as i32)
+                    .filter(|&i| self.lp.export_class_name(i).eq_ignore_ascii_case(&class))
+                    .count();
+                Ok(Value::Int(n as i64))
+            }
+            "export_count" => counted(pak.export_table.len()),
+            "import_count" => counted(pak.import_table.len()),
+            "name_count" => counted(pak.name_table.len()),
+            "file_size" => counted(self.file_size as usize),
+            _ => Err(eval_err(format!("unknown function '{func}'"))),
+        }
+    }
+
+    fn member(&self, idx: i32, field: &str, args: Option<&[Expr]>) -> Result<Value> {
+        let exp = &self.lp.pak.export_table[(idx - 1) as usize];
+        let v = match (field, args) {
+            ("index", None) => Value::Int(idx as i64),
+            ("size", None) => Value::Int(exp.serial_size as i64),
+            ("offset", None) => Value::Int(exp.serial_offset as i64),
+            ("class", None) => Value::Str(self.lp.export_class_name(idx)),
+            ("name", None) => Value::Str(self.lp.export_full_name(idx)),
+            ("flags", None) => Value::Int(exp.object_flags as i64),
+            ("export_flags", None) => Value::Int(exp.export_flags as i64),
+            ("prop", Some(args)) => {
+                if args.is_empty() || args.len() > 2 {
+                    return Err(eval_err("prop takes a name and an optional array index"));
+                }
+                let name = self.string_arg("prop", args, 0)?;
+                let index = match args.get(1).map(|a| self.eval(a)).transpose()? {
+                    Some(Value::Int(n)) => n as i32,
+                    Some(v) => {
+                        return Err(eval_err(format!(
+                            "prop: index is {}, not a number",
+                            v.kind()
+                        )));
+                    }
+                    None => 0,
+                };
+                return self.prop(idx, &name, index);
+            }
+            (_, Some(_)) => return Err(eval_err(format!("exports have no method '{field}'"))),
+            (_, None) => return Err(eval_err(format!("exports have no field '{field}'"))),
+        };
+        Ok(v)
+    }
+
+    fn prop(&self, idx: i32, name: &str, index: i32) -> Result<Value> {
+        let pak = &self.lp.pak;
+        let (props, _) = self.lp.export_props(idx, self.db)?;
+        let p = props
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name) && p.array_index == index)
+            .ok_or_else(|| {
+                eval_err(format!(
+                    "{} has no property {name}[{index}] (defaults aren't stored)",
+                    self.lp.export_full_name(idx)
+                ))
+            })?;
+        Ok(match &p.value {
+            PropertyValue::Byte(b) => Value::Int(*b as i64),
+            PropertyValue::Int(n) => Value::Int(*n as i64),
+            PropertyValue::Bool(b) => Value::Bool(*b),
+            PropertyValue::Float(x) => Value::Float(*x as f64),
+            PropertyValue::Name(f) => Value::Str(pak.fname_to_string(f)),
+            PropertyValue::EnumLabel(s)
+            | PropertyValue::String(s)
+            | PropertyValue::ObjectRef(s) => Value::Str(s.clone()),
+            PropertyValue::Object(0) => Value::Str("None".to_string()),
+            PropertyValue::Object(i) if *i > 0 => Value::Str(pak.get_export_path_name(*i)),
+            PropertyValue::Object(i) => Value::Str(pak.get_import_path_name(*i)),
+            _ => {
+                return Err(eval_err(format!(
+                    "{name} is a {}, which isn't comparable",
+                    p.prop_type
+                )));
+            }
+        })
+    }
+
+    fn eval(&self, e: &Expr) -> Result<Value> {
+        match e {
+            Expr::Int(n) => Ok(Value::Int(*n)),
+            Expr::Float(x) => Ok(Value::Float(*x)),
+            Expr::Str(s) => Ok(Value::Str(s.clone())),
+            Expr::Bool(b) => Ok(Value::Bool(*b)),
+            Expr::Not(inner) => Ok(Value::Bool(!self.truth(inner)?)),
+            Expr::Neg(inner) => match self.eval(inner)? {
+                Value::Int(n) => n
+                    .checked_neg()
+                    .map(Value::Int)
+                    .ok_or_else(|| eval_err(format!("-({n}) overflows"))),
+                Value::Float(x) => Ok(Value::Float(-x)),
+                v => Err(eval_err(format!("can't negate {}", v.kind()))),
+            },
+            Expr::Binary(Op::And, l, r) => Ok(Value::Bool(self.truth(l)? && self.truth(r)?)),
+            Expr::Binary(Op::Or, l, r) => Ok(Value::Bool(self.truth(l)? || self.truth(r)?)),
+            Expr::Binary(op, l, r) => binary(*op, self.eval(l)?, self.eval(r)?),
+            Expr::Call(func, args) => self.call(func, args),
+            Expr::Member(target, field, args) => match self.eval(target)? {
+                Value::Export(idx) => self.member(idx, field, args.as_deref()),
+                v => Err(eval_err(format!("{target} is {}, not an export", v.kind()))),
+            },
+        }
+    }
+
+    fn truth(&self, e: &Expr) -> Result<bool> {
+        match self.eval(e)? {
+            Value::Bool(b) => Ok(b),
+            v => Err(eval_err(format!("{e} is {}, not true or false", v.kind()))),
+        }
+    }
+}
+
+fn binary(op: Op, l: Value, r: Value) -> Result<Value> {
+    let mismatch = |l: &Value, r: &Value| {
+        eval_err(format!(
+            "can't apply {} to {} and {}",
+            op.text(),
+            l.kind(),
+            r.kind()
+        ))
+    };
+    if let (Value::Int(a), Value::Int(b)) = (&l, &r) {
+        let (a, b) = (*a, *b);
+        let checked = match op {
+            Op::Add => a.checked_add(b),
+            Op::Sub => a.checked_sub(b),
+            Op::Mul => a.checked_mul(b),
+            Op::Div if b == 0 => return Err(eval_err("division by zero")),
+            Op::Div => a.checked_div(b),
+            _ => return Ok(Value::Bool(compare(op, a.cmp(&b)))),
+        };
+        return checked
+            .map(Value::Int)
+            .ok_or_else(|| eval_err(format!("{a} {} {b} overflows", op.text())));
+    }
+    if let (Some(a), Some(b)) = (l.number(), r.number()) {
+        return match op {
+            Op::Add => Ok(Value::Float(a + b)),
+            Op::Sub => Ok(Value::Float(a - b)),
+            Op::Mul => Ok(Value::Float(a * b)),
+            Op::Div => Ok(Value::Float(a / b)),
+            _ => a
+                .partial_cmp(&b)
+                .map(|o| Value::Bool(compare(op, o)))
+                .ok_or_else(|| eval_err("comparison with NaN")),
+        };
+    }
+    match (&l, &r) {
+        (Value::Str(a), Value::Str(b)) if !is_arith(op) => Ok(Value::Bool(compare(op, a.cmp(b)))),
+        (Value::Bool(a), Value::Bool(b)) if matches!(op, Op::Eq | Op::Ne) => {
+            Ok(Value::Bool(compare(op, a.cmp(b))))
+        }
+        (Value::Export(a), Value::Export(b)) if matches!(op, Op::Eq | Op::Ne) => {
+            Ok(Value::Bool(compare(op, a.cmp(b))))
+        }
+        _ => Err(mismatch(&l, &r)),
+    }
+}
+
+fn is_arith(op: Op) -> bool {
+    matches!(op, Op::Add | Op::Sub | Op::Mul | Op::Div)
+}
+
+fn compare(op: Op, o: std::cmp::Ordering) -> bool {
+    match op {
+        Op::Eq => o.is_eq(),
+        Op::Ne => o.is_ne(),
+        Op::Lt => o.is_lt(),
+        Op::Le => o.is_le(),
+        Op::Gt => o.is_gt(),
+        Op::Ge => o.is_ge(),
+        _ => unreachable!("not a comparison"),
+    }
+}
+
+/// For a failed comparison, what each side came out as; `lhs < rhs` alone
+/// doesn't say by how much.
+fn operands(ctx: &Ctx, e: &Expr) -> Option<String> {
+    let Expr::Binary(op, l, r) = e else {
+        return None;
+    };
+    if matches!(op, Op::And | Op::Or) || is_arith(*op) {
+        return None;
+    }
+    let l = ctx.eval(l).ok()?;
+    let r = ctx.eval(r).ok()?;
+    Some(format!("{l} {} {r}", op.text()))
+}
+
+pub fn assert_cmd(
+    upk_path: &Path,
+    exprs: &[String],
+    game_root: Option<&Path>,
+    verbose: bool,
+) -> Result<()> {
+    // Syntax errors are the pipeline's bug, not the package's: report them
+    // all before opening anything.
+    let parsed: Vec<Expr> = exprs
+        .iter()
+        .map(|src| parse(src).map_err(|e| Error::new(e.kind(), format!("--expr \"{src}\": {e}"))))
+        .collect::<Result<_>>()?;
+
+    let path = upk_path;
+    let lp = open_package_file(path)?;
+    let db = match game_root {
+        Some(gr) if !gr.as_os_str().is_empty() => Some(SchemaDb::new(gr)?.with_verbose(verbose)),
+        _ => None,
+    };
+    let ctx = Ctx {
+        lp: &lp,
+        db: db.as_ref(),
+        file_size: std::fs::metadata(path)?.len(),
+    };
+
+    let mut failed = 0usize;
+    for (src, e) in exprs.iter().zip(&parsed) {
+        match ctx.truth(e) {
+            Ok(true) => println!("  {} {src}", paint(Color::Green, "OK  ")),
+            Ok(false) => {
+                failed += 1;
+                println!("  {} {src}", paint(Color::Red, "FAIL"));
+                if let Some(got) = operands(&ctx, e) {
+                    println!("       {}", paint(Color::Gray, format!("got {got}")));
+                }
+            }
+            Err(err) => {
+                failed += 1;
+                println!("  {} {src}", paint(Color::Red, "FAIL"));
+                println!("       {}", paint(Color::Gray, err));
+            }
+        }
+    }
+
+    println!("assert: {} of {} held", exprs.len() - failed, exprs.len());
+    if failed > 0 {
+        return Err(validation_failed(format!(
+            "{failed} assertion(s) failed for {}",
+            upk_path.display()
+        )));
+    }
+    Ok(())
+}
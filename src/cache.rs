@@ -0,0 +1,84 @@
+use std::{
+    collections::HashMap,
+    io::Result,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::schemadb::{LazyPackage, open_package_file};
+
+type Slot = Arc<Mutex<Option<Arc<LazyPackage>>>>;
+
+/// Opened packages shared between threads; wrap it in an `Arc` and hand
+/// clones to workers. Each file is read and decompressed once, however many
+/// threads ask for it at the same time, and every caller gets the same
+/// buffer.
+#[derive(Default)]
+pub struct PackageCache {
+    slots: Mutex<HashMap<PathBuf, Slot>>,
+}
+
+/// A panic while opening a package leaves nothing half-written behind, so
+/// a poisoned lock is still usable.
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn key(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl PackageCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens `path` or returns the copy another thread already opened.
+    /// Threads asking for the same file wait for the first one to finish;
+    /// other files aren't held up. Failed opens aren't cached.
+    pub fn get(&self, path: &Path) -> Result<Arc<LazyPackage>> {
+        let slot = lock(&self.slots).entry(key(path)).or_default().clone();
+        let mut pkg = lock(&slot);
+        if let Some(p) = &*pkg {
+            return Ok(p.clone());
+        }
+        let p = Arc::new(open_package_file(path)?);
+        *pkg = Some(p.clone());
+        Ok(p)
+    }
+
+    /// The cached package, without opening it.
+    pub fn get_cached(&self, path: &Path) -> Option<Arc<LazyPackage>> {
+        let slot = lock(&self.slots).get(&key(path))?.clone();
+        lock(&slot).clone()
+    }
+
+    /// Drops the cache's reference; callers still holding the package keep
+    /// it alive until they let go.
+    pub fn evict(&self, path: &Path) -> bool {
+        lock(&self.slots).remove(&key(path)).is_some()
+    }
+
+    pub fn clear(&self) {
+        lock(&self.slots).clear();
+    }
+
+    /// Every package currently held.
+    pub fn packages(&self) -> Vec<Arc<LazyPackage>> {
+        let slots: Vec<Slot> = lock(&self.slots).values().cloned().collect();
+        slots.iter().filter_map(|s| lock(s).clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.packages().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bytes of package data held by the cache.
+    pub fn resident_bytes(&self) -> usize {
+        self.packages().iter().map(|p| p.bytes.len()).sum()
+    }
+}
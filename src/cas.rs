@@ -0,0 +1,227 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{
+    hash::{file_sha256, is_sha256_hex},
+    readonly,
+    term::{Color, paint},
+    walk::{contained_path, files_under, rel_key, stem},
+};
+
+const OBJECTS: &str = "objects";
+const MANIFESTS: &str = "manifests";
+
+#[derive(Subcommand)]
+pub enum CasCmd {
+    #[command(about = "Write a package's extracted files out of a store (extract --cas)")]
+    Checkout {
+        store: PathBuf,
+        package: String,
+        #[arg(long = "out-dir", short = 'd', value_name = "DIR")]
+        out_dir: Option<PathBuf>,
+        /// Hard-link instead of copying. Editing a linked file in place
+        /// changes it for every package that shares it.
+        #[arg(long)]
+        link: bool,
+    },
+
+    #[command(about = "Packages, files and bytes saved in a store")]
+    Stats { store: PathBuf },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub hash: String,
+    pub size: u64,
+}
+
+/// One package's extracted tree: path (relative to the package directory
+/// `extract` would have written, `/`-separated) → content.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub package: String,
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+fn manifest_path(store: &Path, package: &str) -> PathBuf {
+    store.join(MANIFESTS).join(format!("{package}.json"))
+}
+
+fn object_path(store: &Path, hash: &str) -> PathBuf {
+    store.join(OBJECTS).join(&hash[..2]).join(hash)
+}
+
+fn load_manifest(store: &Path, package: &str) -> Result<Option<Manifest>> {
+    let path = manifest_path(store, package);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(&path)?;
+    let manifest: Manifest = serde_json::from_str(&text)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {e}", path.display())))?;
+    if let Some((rel, e)) = manifest.files.iter().find(|(_, e)| !is_sha256_hex(&e.hash)) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{}: {rel} has hash '{}', not a lowercase SHA-256",
+                path.display(),
+                e.hash
+            ),
+        ));
+    }
+    Ok(Some(manifest))
+}
+
+/// Where `extract --cas` writes before the files move into the store; on
+/// the store's filesystem so moving them is a rename.
+pub fn staging_dir(store: &Path) -> PathBuf {
+    store.join(format!(".staging-{}", std::process::id()))
+}
+
+/// Moves the files `extract` wrote under `staged` (one package's
+/// directory) into the store and records them in the package's manifest,
+/// on top of what earlier extractions of it recorded.
+pub fn ingest(store: &Path, staged: &Path, package: &str) -> Result<()> {
+    let mut manifest = load_manifest(store, package)?.unwrap_or_else(|| Manifest {
+        package: package.to_string(),
+        files: BTreeMap::new(),
+    });
+    let (mut added, mut shared, mut new_bytes) = (0usize, 0usize, 0u64);
+    for f in files_under(staged) {
+        let rel = rel_key(staged, &f);
+        let hash = file_sha256(&f)?;
+        let size = std::fs::metadata(&f)?.len();
+        let obj = object_path(store, &hash);
+        if obj.exists() {
+            readonly::remove_file(&f)?;
+            shared += 1;
+        } else {
+            readonly::create_dir_all(obj.parent().unwrap())?;
+            readonly::rename(&f, &obj)?;
+            added += 1;
+            new_bytes += size;
+        }
+        manifest.files.insert(rel, ManifestEntry { hash, size });
+    }
+
+    let path = manifest_path(store, package);
+    readonly::create_dir_all(path.parent().unwrap())?;
+    let text = serde_json::to_string_pretty(&manifest).map_err(Error::other)?;
+    readonly::write(&path, text)?;
+    println!(
+        "Store: {} new object(s) ({new_bytes} bytes), {} already stored → {}",
+        paint(Color::Green, added),
+        paint(Color::Gray, shared),
+        path.display()
+    );
+    Ok(())
+}
+
+fn checkout(store: &Path, package: &str, out_dir: Option<&Path>, link: bool) -> Result<()> {
+    let manifest = load_manifest(store, package)?.ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            format!("no manifest for '{package}' in {}", store.display()),
+        )
+    })?;
+    let dir = out_dir
+        .unwrap_or(Path::new("output"))
+        .join(contained_path(&manifest.package, "the checkout directory")?);
+    for (rel, e) in &manifest.files {
+        let obj = object_path(store, &e.hash);
+        let dst = dir.join(contained_path(rel, "the checkout directory")?);
+        if let Some(parent) = dst.parent() {
+            readonly::create_dir_all(parent)?;
+        }
+        if dst.exists() {
+            readonly::remove_file(&dst)?;
+        }
+        let done = if link {
+            readonly::hard_link(&obj, &dst)
+        } else {
+            readonly::copy(&obj, &dst).map(|_| ())
+        };
+        done.map_err(|err| Error::new(err.kind(), format!("{}: {err}", obj.display())))?;
+    }
+    println!(
+        "Checked out {} file(s) of {package} → {}",
+        manifest.files.len(),
+        dir.display()
+    );
+    Ok(())
+}
+
+fn stats(store: &Path) -> Result<()> {
+    let dir = store.join(MANIFESTS);
+    let entries = std::fs::read_dir(&dir)
+        .map_err(|e| Error::new(e.kind(), format!("{}: {e}", dir.display())))?;
+    let (mut packages, mut files, mut referenced) = (0usize, 0usize, 0u64);
+    let mut unique: BTreeMap<String, u64> = BTreeMap::new();
+    for e in entries.flatten() {
+        let p = e.path();
+        let stem = stem(&p);
+        if stem.is_empty() {
+            continue;
+        }
+        let Some(m) = load_manifest(store, &stem)? else {
+            continue;
+        };
+        packages += 1;
+        files += m.files.len();
+        for f in m.files.values() {
+            referenced += f.size;
+            unique.insert(f.hash.clone(), f.size);
+        }
+    }
+    let stored: u64 = unique.values().sum();
+    let orphans = files_under(&store.join(OBJECTS))
+        .iter()
+        .filter_map(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+        .collect::<BTreeSet<_>>()
+        .difference(&unique.keys().cloned().collect())
+        .count();
+
+    println!("{packages} package(s), {files} file(s)");
+    println!("  referenced  {referenced} bytes");
+    println!(
+        "  stored      {stored} bytes in {} object(s){}",
+        unique.len(),
+        if orphans > 0 {
+            format!(" (+{orphans} unreferenced)")
+        } else {
+            String::new()
+        }
+    );
+    if referenced > 0 {
+        println!(
+            "  saved       {}",
+            paint(
+                Color::Green,
+                format!(
+                    "{} bytes ({:.1}%)",
+                    referenced - stored,
+                    100.0 * (referenced - stored) as f64 / referenced as f64
+                )
+            )
+        );
+    }
+    Ok(())
+}
+
+pub fn run(cmd: CasCmd) -> Result<()> {
+    match cmd {
+        CasCmd::Checkout {
+            store,
+            package,
+            out_dir,
+            link,
+        } => checkout(&store, &package, out_dir.as_deref(), link),
+        CasCmd::Stats { store } => stats(&store),
+    }
+}
@@ -0,0 +1,440 @@
+//! What changed between two versions of a game (or of one package), with
+//! re-cooks told apart from edits. A patch re-cooks far more assets than
+//! it edits: their mips or audio streams are rebuilt and everything moves,
+//! but the properties and the layout of the payload stay the same. Those
+//! are counted; exports whose properties, script or native structure
+//! changed are listed.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write as _,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    disasm::export_disassembly,
+    native::{NativePayload, NativeReadCtx, NativeRegistry},
+    schemadb::{LazyPackage, open_package_file},
+    upkprops::{Property, PropertyValue},
+    upkreader::UPKPak,
+    utils::{
+        deadline,
+        hash::{ContentHash, file_hash},
+        pool::{self, par_map},
+        term::{self, Color, paint},
+        walk::{package_files, rel_key},
+    },
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Change {
+    /// Properties, script or native structure differ; what, briefly.
+    Content(String),
+    /// Only bulk payloads differ; which ones.
+    Recook(Vec<String>),
+    /// Same data, different bytes: offsets, net index, renumbered names.
+    Moved,
+}
+
+#[derive(Default)]
+struct PackageDiff {
+    /// `(full name, change)`, in export order of the new package.
+    changed: Vec<(String, Change)>,
+    added: Vec<String>,
+    removed: Vec<String>,
+}
+
+impl PackageDiff {
+    fn count(&self, f: impl Fn(&Change) -> bool) -> usize {
+        self.changed.iter().filter(|(_, c)| f(c)).count()
+    }
+
+    fn has_content(&self) -> bool {
+        !self.added.is_empty()
+            || !self.removed.is_empty()
+            || self.count(|c| matches!(c, Change::Content(_))) > 0
+    }
+}
+
+pub fn digest(bytes: &[u8]) -> String {
+    let mut h = ContentHash::new();
+    h.update(bytes);
+    h.hex()
+}
+
+/// Property values with names and object references spelled out, so the
+/// same value compares equal across two name / import tables.
+fn canon(pak: &UPKPak, v: &PropertyValue, out: &mut String) {
+    use PropertyValue::*;
+    match v {
+        None => out.push_str("None"),
+        Byte(b) => _ = write!(out, "{b}"),
+        Int(i) => _ = write!(out, "{i}"),
+        Bool(b) => _ = write!(out, "{b}"),
+        Float(f) => _ = write!(out, "{:08x}", f.to_bits()),
+        Object(0) => out.push_str("None"),
+        Object(i) if *i > 0 => out.push_str(&pak.get_export_full_name(*i)),
+        Object(i) => out.push_str(&pak.get_import_full_name(*i)),
+        ObjectRef(s) | EnumLabel(s) | String(s) => _ = write!(out, "{s:?}"),
+        Name(f) => out.push_str(&pak.fname_to_string(f)),
+        Array(items) => {
+            out.push('[');
+            for x in items {
+                canon(pak, x, out);
+                out.push(',');
+            }
+            out.push(']');
+        }
+        Struct(fields) => {
+            out.push('(');
+            for p in fields {
+                _ = write!(out, "{}[{}]=", p.name, p.array_index);
+                canon(pak, &p.value, out);
+                out.push(',');
+            }
+            out.push(')');
+        }
+        AtomicStruct(fields) => {
+            out.push('(');
+            for (n, x) in fields {
+                _ = write!(out, "{n}=");
+                canon(pak, x, out);
+                out.push(',');
+            }
+            out.push(')');
+        }
+        Raw(bytes) => _ = write!(out, "<{}>", digest(bytes)),
+    }
+}
+
+fn canon_props(pak: &UPKPak, props: &[Property]) -> BTreeMap<String, String> {
+    props
+        .iter()
+        .map(|p| {
+            let mut v = String::new();
+            canon(pak, &p.value, &mut v);
+            (format!("{}[{}]", p.name, p.array_index), v)
+        })
+        .collect()
+}
+
+/// The payload without its bulk data: what a re-cook keeps.
+pub fn shape(p: &NativePayload) -> String {
+    match p {
+        NativePayload::Texture2D(t) => {
+            let mips: Vec<String> = t
+                .mips
+                .iter()
+                .chain(&t.cached_pvrtc_mips)
+                .map(|m| format!("{}x{}", m.size_x, m.size_y))
+                .collect();
+            format!(
+                "tex {:?} {:?} [{}] {}",
+                t.format_label,
+                t.tfc_name,
+                mips.join(" "),
+                digest(&t.trailing_raw)
+            )
+        }
+        NativePayload::SoundNodeWave(s) => format!(
+            "wave {:?} {:?} {:?} {}",
+            s.num_channels,
+            s.sample_rate,
+            s.duration,
+            digest(&s.trailing_raw)
+        ),
+        NativePayload::SwfMovie(m) => format!("swf {}", digest(&m.raw_data)),
+        NativePayload::Raw { bytes } => format!("raw {}", digest(bytes)),
+        NativePayload::Empty { tail } => format!("empty {}", digest(tail)),
+        NativePayload::NativeProps { .. } => "native props".to_string(),
+    }
+}
+
+struct Parsed {
+    props: BTreeMap<String, String>,
+    /// Disassembled statements of functions, states and classes: their
+    /// bytecode holds name and object indices a rebuild renumbers.
+    script: Option<Vec<String>>,
+    payload: Result<NativePayload>,
+}
+
+fn parse_export(lp: &LazyPackage, registry: &NativeRegistry, i: i32) -> Result<Parsed> {
+    let blob = lp.export_blob(i)?;
+    let (props, end) = lp.export_props(i, None)?;
+    let tail = blob.get(end..).unwrap_or_default();
+    let class = lp.export_class_name(i);
+    let payload = match registry.for_class(None, None, &class) {
+        Some(ser) => ser
+            .read(&NativeReadCtx {
+                blob: tail,
+                props: &props,
+                ver: lp.header.p_ver,
+                l_ver: lp.header.l_ver,
+                pak: &lp.pak,
+                db: None,
+                self_ref: None,
+                class_ref: None,
+            })
+            .map(|r| r.payload),
+        None => Ok(NativePayload::Raw {
+            bytes: tail.to_vec(),
+        }),
+    };
+    let script = lp
+        .pak
+        .script_kind(i)
+        .and_then(|_| export_disassembly(lp, i).ok())
+        .map(|d| d.statements.into_iter().map(|s| s.text).collect());
+    Ok(Parsed {
+        props: canon_props(&lp.pak, &props),
+        script,
+        payload,
+    })
+}
+
+fn classify(
+    old: (&LazyPackage, i32),
+    new: (&LazyPackage, i32),
+    registry: &NativeRegistry,
+) -> Result<Option<Change>> {
+    let (a_blob, b_blob) = (old.0.export_blob(old.1)?, new.0.export_blob(new.1)?);
+    if a_blob == b_blob {
+        return Ok(None);
+    }
+    let a = parse_export(old.0, registry, old.1)?;
+    let b = parse_export(new.0, registry, new.1)?;
+
+    let mut keys: BTreeSet<&String> = a.props.keys().collect();
+    keys.extend(b.props.keys());
+    let differing: Vec<&str> = keys
+        .into_iter()
+        .filter(|k| a.props.get(*k) != b.props.get(*k))
+        .map(|k| k.strip_suffix("[0]").unwrap_or(k))
+        .collect();
+    if !differing.is_empty() {
+        return Ok(Some(Change::Content(format!(
+            "properties {}",
+            differing.join(", ")
+        ))));
+    }
+
+    if a.script.is_some() || b.script.is_some() {
+        // The rest of a struct's data is the same kind of index soup;
+        // with equal script and properties, call it moved.
+        return Ok(Some(if a.script == b.script {
+            Change::Moved
+        } else {
+            Change::Content("script".to_string())
+        }));
+    }
+
+    let (pa, pb) = match (a.payload, b.payload) {
+        (Ok(pa), Ok(pb)) => (pa, pb),
+        (Err(e), _) | (_, Err(e)) => {
+            return Ok(Some(Change::Content(format!(
+                "native data (unparsed: {e})"
+            ))));
+        }
+    };
+    if shape(&pa) != shape(&pb) {
+        let what = match &pa {
+            NativePayload::Texture2D(_) | NativePayload::SoundNodeWave(_) => "payload layout",
+            _ => "native data",
+        };
+        return Ok(Some(Change::Content(what.to_string())));
+    }
+    let (ba, bb) = (pa.bulk(), pb.bulk());
+    let rebuilt: Vec<String> = ba
+        .iter()
+        .zip(&bb)
+        .filter(|(x, y)| !x.same_data(y))
+        .map(|(x, _)| x.label.clone())
+        .collect();
+    if !rebuilt.is_empty() {
+        return Ok(Some(Change::Recook(rebuilt)));
+    }
+    // Shape and bulk equal: the net index or inline bulk offsets moved.
+    Ok(Some(Change::Moved))
+}
+
+fn export_keys(lp: &LazyPackage) -> HashMap<String, i32> {
+    (1..=lp.pak.export_table.len() as i32)
+        .map(|i| (lp.export_full_name(i).to_lowercase(), i))
+        .collect()
+}
+
+fn diff_packages(old: &Path, new: &Path) -> Result<PackageDiff> {
+    let a = open_package_file(old)?;
+    let b = open_package_file(new)?;
+    let registry = NativeRegistry::standard();
+    let a_keys = export_keys(&a);
+    let mut diff = PackageDiff::default();
+    let mut seen = BTreeSet::new();
+    for j in 1..=b.pak.export_table.len() as i32 {
+        let name = b.export_full_name(j);
+        let key = name.to_lowercase();
+        let Some(&i) = a_keys.get(&key) else {
+            diff.added.push(name);
+            continue;
+        };
+        seen.insert(key);
+        let change = classify((&a, i), (&b, j), &registry)
+            .unwrap_or_else(|e| Some(Change::Content(format!("not comparable: {e}"))));
+        if let Some(c) = change {
+            diff.changed.push((name, c));
+        }
+    }
+    diff.removed = (1..=a.pak.export_table.len() as i32)
+        .map(|i| a.export_full_name(i))
+        .filter(|n| !seen.contains(&n.to_lowercase()))
+        .collect();
+    Ok(diff)
+}
+
+fn same_file(a: &Path, b: &Path) -> Result<bool> {
+    if std::fs::metadata(a)?.len() != std::fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    Ok(file_hash(a)? == file_hash(b)?)
+}
+
+fn print_diff(rel: &str, d: &PackageDiff, all: bool) {
+    let content = d.count(|c| matches!(c, Change::Content(_)));
+    let recooked = d.count(|c| matches!(c, Change::Recook(_)));
+    let moved = d.count(|c| *c == Change::Moved);
+    let mut parts = Vec::new();
+    if content > 0 {
+        parts.push(paint(Color::Yellow, format!("{content} changed")).to_string());
+    }
+    if !d.added.is_empty() || !d.removed.is_empty() {
+        parts.push(format!("+{} -{} exports", d.added.len(), d.removed.len()));
+    }
+    if recooked > 0 {
+        parts.push(format!("{recooked} re-cooked"));
+    }
+    if moved > 0 {
+        parts.push(format!("{moved} moved"));
+    }
+    if parts.is_empty() {
+        parts.push("only tables differ".to_string());
+    }
+    println!("{}: {}", paint(Color::Highlight, rel), parts.join(", "));
+    for (name, c) in &d.changed {
+        match c {
+            Change::Content(what) => println!(
+                "  {} {name}: {}",
+                paint(Color::Yellow, "content"),
+                paint(Color::Gray, what)
+            ),
+            Change::Recook(bulk) if all => println!(
+                "  {} {name}: {}",
+                paint(Color::Gray, "recook "),
+                paint(Color::Gray, bulk.join(", "))
+            ),
+            Change::Moved if all => println!("  {} {name}", paint(Color::Gray, "moved  ")),
+            _ => {}
+        }
+    }
+    for n in &d.added {
+        println!("  {} {n}", paint(Color::Green, "added  "));
+    }
+    for n in &d.removed {
+        println!("  {} {n}", paint(Color::Red, "removed"));
+    }
+}
+
+pub fn changes_cmd(old: &Path, new: &Path, all: bool, jobs: Option<usize>) -> Result<()> {
+    if old.is_file() && new.is_file() {
+        if same_file(old, new)? {
+            println!("{}: identical", new.display());
+            return Ok(());
+        }
+        let d = diff_packages(old, new)?;
+        print_diff(&new.to_string_lossy(), &d, all);
+        return Ok(());
+    }
+    if !old.is_dir() || !new.is_dir() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "give two game directories or two package files",
+        ));
+    }
+
+    let list = |root: &Path| -> BTreeMap<String, PathBuf> {
+        package_files(root)
+            .into_iter()
+            .map(|p| (rel_key(root, &p), p))
+            .collect()
+    };
+    let (a, b) = (list(old), list(new));
+    let pairs: Vec<(&String, &PathBuf, &PathBuf)> = b
+        .iter()
+        .filter_map(|(rel, pb)| a.get(rel).map(|pa| (rel, pa, pb)))
+        .collect();
+
+    let results = par_map(&pairs, pool::jobs(jobs), |(_, pa, pb)| {
+        // The deadline is per thread and per package.
+        deadline::arm();
+        same_file(pa, pb).and_then(|same| {
+            if same {
+                Ok(None)
+            } else {
+                diff_packages(pa, pb).map(Some)
+            }
+        })
+    });
+
+    let (mut unchanged, mut content, mut recook_only, mut failed) = (0, 0, 0, 0);
+    for ((rel, _, _), r) in pairs.iter().zip(results) {
+        match r {
+            Ok(None) => unchanged += 1,
+            Ok(Some(d)) => {
+                if d.has_content() {
+                    content += 1;
+                } else {
+                    recook_only += 1;
+                }
+                if d.has_content() || all {
+                    print_diff(rel, &d, all);
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                term::warn("skip", format_args!("{rel}: {e}"));
+            }
+        }
+    }
+    let added: Vec<&String> = b.keys().filter(|k| !a.contains_key(*k)).collect();
+    let removed: Vec<&String> = a.keys().filter(|k| !b.contains_key(*k)).collect();
+    for rel in &added {
+        println!("{} {rel}", paint(Color::Green, "added package  "));
+    }
+    for rel in &removed {
+        println!("{} {rel}", paint(Color::Red, "removed package"));
+    }
+
+    println!(
+        "\n{} package(s) compared: {unchanged} unchanged, {} with content changes, \
+         {recook_only} only re-cooked{}; {} added, {} removed",
+        pairs.len(),
+        paint(Color::Yellow, content),
+        if failed > 0 {
+            format!(", {failed} unreadable")
+        } else {
+            String::new()
+        },
+        added.len(),
+        removed.len()
+    );
+    if recook_only > 0 && !all {
+        println!(
+            "{}",
+            paint(
+                Color::Gray,
+                "--all lists the re-cooked packages and exports too"
+            )
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,311 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::Subcommand;
+
+use crate::{
+    delta,
+    upkreader::UpkHeader,
+    utils::{
+        backup::backup_original,
+        compress::{Tuning, compress_blocks, write_chunk},
+        decompress::{CHUNK_SIZE, CompressedChunk, CompressionMethod, upk_decompress},
+        pool, readonly,
+        stats::{self, Phase},
+    },
+};
+
+#[derive(Subcommand)]
+pub enum ChunksCmd {
+    #[command(about = "List the compressed chunk table")]
+    List { upk_path: PathBuf },
+
+    #[command(about = "Write one chunk's data to a file (inflated unless --raw)")]
+    Export {
+        upk_path: PathBuf,
+        index: usize,
+        #[arg(long)]
+        raw: bool,
+        #[arg(long = "out", short = 'o', value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+
+    #[command(about = "Replace one chunk, leaving the others as stored; in place (.bak) unless -o")]
+    Import {
+        upk_path: PathBuf,
+        index: usize,
+        file: PathBuf,
+        #[arg(long)]
+        raw: bool,
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..=9))]
+        level: Option<u32>,
+        #[arg(long, conflicts_with = "level")]
+        fast: bool,
+        #[arg(long = "out", short = 'o', value_name = "FILE")]
+        out: Option<PathBuf>,
+        #[arg(long, short = 'j')]
+        jobs: Option<usize>,
+        #[arg(long, value_name = "FILE")]
+        emit_delta: Option<PathBuf>,
+    },
+}
+
+/// Summary of a StoreCompressed package and its length on disk.
+fn read_summary(path: &Path) -> Result<(UpkHeader, u64)> {
+    let mut r = BufReader::new(File::open(path)?);
+    let h = UpkHeader::read(&mut r)?;
+    if h.compression_method == CompressionMethod::None || h.compressed_chunks.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} has no compressed chunks", path.display()),
+        ));
+    }
+    let len = r.stream_position()?;
+    Ok((h, len))
+}
+
+fn chunk_at(h: &UpkHeader, index: usize) -> Result<CompressedChunk> {
+    h.compressed_chunks.get(index).copied().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "chunk {index} out of range (package has {})",
+                h.compressed_chunks.len()
+            ),
+        )
+    })
+}
+
+fn read_range(r: &mut File, at: u64, len: u64) -> Result<Vec<u8>> {
+    r.seek(SeekFrom::Start(at))?;
+    let mut buf = vec![0u8; len as usize];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Inflated size of a chunk stream, which must start at byte 0 of `stream`.
+fn inflated_len(stream: &[u8], mode: CompressionMethod) -> Result<usize> {
+    let probe = CompressedChunk {
+        decompressed_offset: 0,
+        decompressed_size: 0,
+        compressed_offset: 0,
+        compressed_size: stream.len() as u32,
+    };
+    let mut out = upk_decompress(Cursor::new(stream), mode, &[probe])?;
+    Ok(out.pop().map_or(0, |d| d.len()))
+}
+
+fn list(upk_path: &Path) -> Result<()> {
+    let (h, _) = read_summary(upk_path)?;
+    println!(
+        "{} chunk(s), {:?}",
+        h.compressed_chunks.len(),
+        h.compression_method
+    );
+    println!("  #    unpacked @ offset           packed @ offset");
+    for (i, c) in h.compressed_chunks.iter().enumerate() {
+        println!(
+            "  {i:<4} {:>9} @ 0x{:08X}  {:>9} @ 0x{:08X}",
+            c.decompressed_size, c.decompressed_offset, c.compressed_size, c.compressed_offset
+        );
+    }
+    Ok(())
+}
+
+fn export(src: &Path, index: usize, raw: bool, out: Option<&Path>) -> Result<()> {
+    let (h, _) = read_summary(src)?;
+    let c = chunk_at(&h, index)?;
+    let mut f = File::open(src)?;
+    let data = if raw {
+        read_range(&mut f, c.compressed_offset as u64, c.compressed_size as u64)?
+    } else {
+        upk_decompress(BufReader::new(f), h.compression_method, &[c])?
+            .pop()
+            .unwrap_or_default()
+    };
+    let out = match out {
+        Some(o) => o.to_path_buf(),
+        None => {
+            let stem = src.file_stem().unwrap_or_default().to_string_lossy();
+            let ext = if raw { "chunk" } else { "bin" };
+            src.with_file_name(format!("{stem}.chunk{index}.{ext}"))
+        }
+    };
+    readonly::write(&out, &data)?;
+    println!(
+        "Chunk {index} ({} bytes at 0x{:08X}) → {} ({} bytes{})",
+        c.decompressed_size,
+        c.decompressed_offset,
+        out.display(),
+        data.len(),
+        if raw { ", as stored" } else { "" }
+    );
+    Ok(())
+}
+
+struct ImportArgs<'a> {
+    upk_path: &'a Path,
+    index: usize,
+    file: &'a Path,
+    raw: bool,
+    tuning: Tuning,
+    out: Option<&'a Path>,
+    jobs: Option<usize>,
+    emit_delta: Option<&'a Path>,
+}
+
+fn import(a: ImportArgs) -> Result<()> {
+    let src = a.upk_path;
+    let (h, summary_len) = read_summary(src)?;
+    let c = chunk_at(&h, a.index)?;
+    let mode = h.compression_method;
+    if a.tuning.zlib_level.is_some() && mode != CompressionMethod::Zlib {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("--level applies to zlib; this package uses {mode:?}"),
+        ));
+    }
+    if (c.compressed_offset as u64) < summary_len {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("chunk {} overlaps the summary", a.index),
+        ));
+    }
+    if c.compressed_offset as u64 + c.compressed_size as u64 > std::fs::metadata(src)?.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("chunk {} extends past end of file", a.index),
+        ));
+    }
+    let data = std::fs::read(a.file)?;
+
+    // The chunk must inflate to exactly the bytes it replaces; anything else
+    // would move every offset after it.
+    let unpacked = if a.raw {
+        inflated_len(&data, mode)?
+    } else {
+        data.len()
+    };
+    if unpacked != c.decompressed_size as usize {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{} inflates to {unpacked} bytes, chunk {} holds {}",
+                a.file.display(),
+                a.index,
+                c.decompressed_size
+            ),
+        ));
+    }
+    let stream = if a.raw {
+        data
+    } else {
+        let jobs = pool::jobs(a.jobs);
+        let blocks: Vec<&[u8]> = data.chunks(CHUNK_SIZE as usize).collect();
+        let packed = compress_blocks(&blocks, mode, a.tuning, jobs)?;
+        let mut s = Vec::new();
+        write_chunk(&mut s, &blocks, &packed)?;
+        s
+    };
+
+    // Chunks after the replaced one (in file order) move by the size change.
+    let shift = stream.len() as i64 - c.compressed_size as i64;
+    let mut new_h = h.clone();
+    for n in &mut new_h.compressed_chunks {
+        if n.compressed_offset > c.compressed_offset {
+            n.compressed_offset = (n.compressed_offset as i64 + shift) as u32;
+        }
+    }
+    new_h.compressed_chunks[a.index].compressed_size = stream.len() as u32;
+    let mut summary = Vec::new();
+    new_h.write(Cursor::new(&mut summary))?;
+    if summary.len() as u64 != summary_len {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "summary doesn't re-serialize to its original size; layout not understood",
+        ));
+    }
+
+    let dst = match a.out {
+        Some(o) => o.to_path_buf(),
+        None => {
+            if let Some(bak) = backup_original(src)? {
+                println!("Backup: {}", bak.display());
+            }
+            src.to_path_buf()
+        }
+    };
+    let mut part = dst.as_os_str().to_os_string();
+    part.push(".part");
+    let part = PathBuf::from(part);
+
+    let _t = stats::time(Phase::Output);
+    let mut r = File::open(src)?;
+    let file_len = r.metadata()?.len();
+    let before = c.compressed_offset as u64;
+    let after = before + c.compressed_size as u64;
+    let mut w = BufWriter::new(readonly::create(&part)?);
+    w.write_all(&summary)?;
+    r.seek(SeekFrom::Start(summary_len))?;
+    std::io::copy(&mut (&mut r).take(before - summary_len), &mut w)?;
+    w.write_all(&stream)?;
+    r.seek(SeekFrom::Start(after))?;
+    std::io::copy(&mut (&mut r).take(file_len - after), &mut w)?;
+    w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    drop(r);
+    readonly::rename(&part, &dst)?;
+
+    println!(
+        "Chunk {}: {} → {} bytes packed ({shift:+}), {} chunk(s) after it moved → {}",
+        a.index,
+        c.compressed_size,
+        stream.len(),
+        h.compressed_chunks
+            .iter()
+            .filter(|n| n.compressed_offset > c.compressed_offset)
+            .count(),
+        dst.display()
+    );
+    if let Some(d) = a.emit_delta {
+        delta::emit_delta(&delta::original_for(src), &dst, d)?;
+    }
+    Ok(())
+}
+
+pub fn run(cmd: ChunksCmd) -> Result<()> {
+    match cmd {
+        ChunksCmd::List { upk_path } => list(&upk_path),
+        ChunksCmd::Export {
+            upk_path,
+            index,
+            raw,
+            out,
+        } => export(&upk_path, index, raw, out.as_deref()),
+        ChunksCmd::Import {
+            upk_path,
+            index,
+            file,
+            raw,
+            level,
+            fast,
+            out,
+            jobs,
+            emit_delta,
+        } => import(ImportArgs {
+            upk_path: &upk_path,
+            index,
+            file: &file,
+            raw,
+            tuning: Tuning {
+                zlib_level: level,
+                fast,
+            },
+            out: out.as_deref(),
+            jobs,
+            emit_delta: emit_delta.as_deref(),
+        }),
+    }
+}
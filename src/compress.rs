@@ -0,0 +1,99 @@
+use std::{
+    io::{BufWriter, Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use clap::ValueEnum;
+
+use crate::utils::{
+    compress::{Tuning, compress_package},
+    decompress::{CompressionMethod, read_package_image},
+    pool, readonly,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Method {
+    Lzo,
+    Zlib,
+}
+
+impl From<Method> for CompressionMethod {
+    fn from(m: Method) -> Self {
+        match m {
+            Method::Lzo => CompressionMethod::Lzo,
+            Method::Zlib => CompressionMethod::Zlib,
+        }
+    }
+}
+
+fn default_out(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}.compressed.upk"))
+}
+
+/// `-12345 bytes, -40.1%` relative to `before`.
+fn size_delta(before: u64, after: u64) -> String {
+    let d = after as i64 - before as i64;
+    let pct = if before == 0 {
+        0.0
+    } else {
+        d as f64 * 100.0 / before as f64
+    };
+    format!("{d:+} bytes, {pct:+.1}%")
+}
+
+/// Already-compressed input is inflated first, so this also converts
+/// between methods.
+pub fn compress_cmd(
+    path: &Path,
+    out: Option<&Path>,
+    method: Method,
+    tuning: Tuning,
+    jobs: Option<usize>,
+) -> Result<()> {
+    if tuning.zlib_level.is_some() && method != Method::Zlib {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "--level applies to zlib; use --fast for quicker LZO",
+        ));
+    }
+    let src = path;
+    let out = out.map(PathBuf::from).unwrap_or_else(|| default_out(src));
+    let jobs = pool::jobs(jobs);
+    let in_size = std::fs::metadata(src)?.len();
+    let started = Instant::now();
+    let image = read_package_image(src)?;
+
+    let mut part = out.as_os_str().to_os_string();
+    part.push(".part");
+    let part = PathBuf::from(part);
+    let mut w = BufWriter::new(readonly::create(&part)?);
+    let header = compress_package(
+        &image.bytes,
+        &image.header,
+        method.into(),
+        tuning,
+        jobs,
+        &mut w,
+    )?;
+    w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    drop(image);
+    readonly::rename(&part, &out)?;
+
+    let out_size = std::fs::metadata(&out)?.len();
+    let setting = match (tuning.zlib_level, tuning.fast) {
+        (Some(l), _) => format!("{method:?} level {l}"),
+        (None, true) => format!("{method:?} fast"),
+        (None, false) => format!("{method:?}"),
+    };
+    println!(
+        "{} → {}: {in_size} → {out_size} bytes ({}), {setting}, {} chunk(s), {jobs} job(s), {:.2}s",
+        src.display(),
+        out.display(),
+        size_delta(in_size, out_size),
+        header.compressed_chunks_count,
+        started.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
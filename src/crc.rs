@@ -0,0 +1,84 @@
+use std::io::{Error, ErrorKind, Result};
+
+use clap::ValueEnum;
+
+use crate::utils::{
+    crc::{NAME_HASH_BUCKETS, mem_crc, name_hash, str_crc, str_crc_caps, zip_crc},
+    term::{Color, paint},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CrcKind {
+    /// appStrCrc
+    Str,
+    /// appStrCrcCaps
+    StrCaps,
+    /// appStrihash, the FName hash
+    Name,
+    /// appMemCrc over the bytes
+    Mem,
+    /// zlib CRC-32 over the bytes
+    Zip,
+}
+
+impl CrcKind {
+    fn of_bytes(self) -> bool {
+        matches!(self, CrcKind::Mem | CrcKind::Zip)
+    }
+}
+
+fn compute(kind: CrcKind, text: &str, bytes: &[u8]) -> u32 {
+    match kind {
+        CrcKind::Str => str_crc(text),
+        CrcKind::StrCaps => str_crc_caps(text),
+        CrcKind::Name => name_hash(text),
+        CrcKind::Mem => mem_crc(bytes, 0),
+        CrcKind::Zip => zip_crc(bytes),
+    }
+}
+
+/// With `kind`, one `0xCRC  input` line per input; without, every CRC
+/// that applies. `files` reads the inputs as paths, which only the byte
+/// CRCs apply to.
+pub fn crc_cmd(inputs: &[String], files: bool, kind: Option<CrcKind>) -> Result<()> {
+    if files && kind.is_some_and(|k| !k.of_bytes()) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "--file only goes with --kind mem or zip",
+        ));
+    }
+    for input in inputs {
+        let data = if files {
+            std::fs::read(input).map_err(|e| Error::new(e.kind(), format!("{input}: {e}")))?
+        } else {
+            input.as_bytes().to_vec()
+        };
+        if let Some(k) = kind {
+            println!("0x{:08X}  {input}", compute(k, input, &data));
+            continue;
+        }
+
+        println!("{}", paint(Color::Highlight, input));
+        let all: &[CrcKind] = if files {
+            &[CrcKind::Mem, CrcKind::Zip]
+        } else {
+            CrcKind::value_variants()
+        };
+        for &k in all {
+            let label = k.to_possible_value().map(|v| v.get_name().to_string());
+            let crc = compute(k, input, &data);
+            print!("  {:<9} 0x{crc:08X}", label.unwrap_or_default());
+            if k == CrcKind::Name {
+                print!(
+                    "  {}",
+                    paint(
+                        Color::Gray,
+                        format_args!("bucket {}", crc & (NAME_HASH_BUCKETS - 1))
+                    )
+                );
+            }
+            println!();
+        }
+    }
+    Ok(())
+}
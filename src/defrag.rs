@@ -0,0 +1,215 @@
+//! Dead space in a package: bytes nothing in the summary or the tables
+//! points at. Saving appends replaced exports and grown tables and leaves
+//! the old copies where they were, so a package edited a few times keeps
+//! growing; `--compact` rewrites it without them.
+
+use std::{
+    io::{Cursor, Result},
+    path::Path,
+};
+
+use crate::{
+    package::{Package, RegionKind},
+    upkreader::read_name,
+    utils::{
+        backup::backup_original,
+        decompress::read_raw_header,
+        term::{Color, paint},
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Waste {
+    /// Right after the summary: the chunk table of a package that was
+    /// stored compressed, dropped when the summary was rewritten.
+    ChunkTable,
+    /// Elsewhere before the exports: tables a save moved to the end.
+    Tables,
+    /// Among the export data: blobs a save replaced, or padding.
+    Gap,
+    /// After everything referenced.
+    Trailing,
+}
+
+impl Waste {
+    fn label(self) -> &'static str {
+        match self {
+            Waste::ChunkTable => "chunk-table leftover",
+            Waste::Tables => "superseded tables",
+            Waste::Gap => "replaced export data",
+            Waste::Trailing => "trailing bytes",
+        }
+    }
+}
+
+struct Span {
+    kind: Waste,
+    start: usize,
+    len: usize,
+    /// The region right before the span, for locating it.
+    after: RegionKind,
+}
+
+/// Whether `bytes` is nothing but whole name entries: the name table a
+/// save moved to the end when names were added.
+fn is_name_table(bytes: &[u8]) -> bool {
+    let mut cur = Cursor::new(bytes);
+    while (cur.position() as usize) < bytes.len() {
+        if read_name(&mut cur).is_err() {
+            return false;
+        }
+    }
+    !bytes.is_empty() && cur.position() as usize == bytes.len()
+}
+
+fn dead_spans(pkg: &Package) -> Result<Vec<Span>> {
+    let regions = pkg.regions()?;
+    let summary_end = regions.first().map_or(0, |r| r.end());
+    // Tables live before `header_size`, export data after it, however
+    // many times either was moved since.
+    let data_start = pkg.header.header_size.max(0) as usize;
+    let last_export_end = regions
+        .iter()
+        .filter(|r| matches!(r.kind, RegionKind::Export(_)))
+        .map(|r| r.end())
+        .max()
+        .unwrap_or(0);
+
+    let mut out = Vec::new();
+    let mut covered = 0usize;
+    let mut after = RegionKind::Summary;
+    let mut push = |start: usize, end: usize, after: RegionKind| {
+        if end <= start {
+            return;
+        }
+        let kind = if end <= data_start {
+            if start == summary_end && !is_name_table(&pkg.bytes[start..end]) {
+                Waste::ChunkTable
+            } else {
+                Waste::Tables
+            }
+        } else if end <= last_export_end {
+            Waste::Gap
+        } else {
+            Waste::Trailing
+        };
+        out.push(Span {
+            kind,
+            start,
+            len: end - start,
+            after,
+        });
+    };
+    for r in &regions {
+        push(covered, r.start, after);
+        if r.end() > covered {
+            covered = r.end();
+            after = r.kind;
+        }
+    }
+    push(covered, pkg.bytes.len(), after);
+    Ok(out)
+}
+
+fn describe(kind: RegionKind) -> String {
+    match kind {
+        RegionKind::Summary => "the summary".to_string(),
+        RegionKind::Names => "the name table".to_string(),
+        RegionKind::Imports => "the import table".to_string(),
+        RegionKind::Exports => "the export table".to_string(),
+        RegionKind::Depends => "the depends map".to_string(),
+        RegionKind::Guids => "the import / export GUIDs".to_string(),
+        RegionKind::Thumbnails => "the thumbnail table".to_string(),
+        RegionKind::Export(i) => format!("export #{i}"),
+    }
+}
+
+pub fn defrag_cmd(upk_path: &Path, compact: bool, out: Option<&Path>, verbose: bool) -> Result<()> {
+    let src = upk_path;
+    let on_disk = std::fs::metadata(src)?.len();
+    let compressed = read_raw_header(src)?.compressed_chunks_count > 0;
+    let mut pkg = Package::open(src)?;
+    let spans = dead_spans(&pkg)?;
+
+    let total = pkg.bytes.len();
+    let dead: usize = spans.iter().map(|s| s.len).sum();
+    println!(
+        "{}: {total} bytes{}, {} dead ({:.1}%)",
+        src.display(),
+        if compressed {
+            format!(" decompressed ({on_disk} on disk)")
+        } else {
+            String::new()
+        },
+        paint(
+            if dead > 0 {
+                Color::Yellow
+            } else {
+                Color::Green
+            },
+            dead
+        ),
+        dead as f64 * 100.0 / total.max(1) as f64
+    );
+    for kind in [
+        Waste::ChunkTable,
+        Waste::Tables,
+        Waste::Gap,
+        Waste::Trailing,
+    ] {
+        let of_kind: Vec<&Span> = spans.iter().filter(|s| s.kind == kind).collect();
+        if of_kind.is_empty() {
+            continue;
+        }
+        println!(
+            "  {:<22} {:>10} bytes in {} span(s)",
+            kind.label(),
+            of_kind.iter().map(|s| s.len).sum::<usize>(),
+            of_kind.len()
+        );
+        if verbose {
+            for s in of_kind {
+                println!(
+                    "    {} {:>10} bytes after {}",
+                    paint(Color::Gray, format!("[{:08x}]", s.start)),
+                    s.len,
+                    describe(s.after)
+                );
+            }
+        }
+    }
+
+    if !compact {
+        if dead > 0 {
+            println!("Run with --compact to rewrite the package without them.");
+        }
+        return Ok(());
+    }
+    let dst = match out {
+        Some(o) => o.to_path_buf(),
+        None => {
+            if let Some(bak) = backup_original(src)? {
+                println!("Backup: {}", bak.display());
+            }
+            src.to_path_buf()
+        }
+    };
+    let stats = pkg.save_compact(&dst)?;
+    let before = if compressed { total as u64 } else { on_disk };
+    println!(
+        "Compacted: {before} → {} bytes ({} reclaimed) → {}",
+        paint(Color::Green, stats.bytes_written),
+        before.saturating_sub(stats.bytes_written),
+        dst.display()
+    );
+    if compressed {
+        println!(
+            "  {}",
+            paint(
+                Color::Gray,
+                "written uncompressed; run `compress` on it to store it compressed again"
+            )
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+use clap::Subcommand;
+
+use crate::{
+    exit::validation_failed,
+    utils::{
+        backup::{backup_original, original_of},
+        hash::sha256_hex,
+        readonly,
+        spill::PackageBytes,
+        term, vcdiff,
+    },
+};
+
+#[derive(Subcommand)]
+pub enum DeltaCmd {
+    #[command(about = "Apply a delta to the package it was made from; in place (.bak) unless -o")]
+    Apply {
+        original: PathBuf,
+        patch: PathBuf,
+        #[arg(long = "out", short = 'o', value_name = "FILE")]
+        out: Option<PathBuf>,
+        /// Apply a delta that doesn't say which file it expects (plain
+        /// xdelta3 output).
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// First line of the VCDIFF application header, followed by `source` and
+/// `target` lines of size and SHA-256.
+const APP_TAG: &str = "ue3-tools delta 1";
+
+/// What a delta for an edit of `src` is taken against: its `.bak` when
+/// there is one, so repeated edits still diff against the shipped file.
+pub fn original_for(src: &Path) -> PathBuf {
+    original_of(src).unwrap_or_else(|_| src.to_path_buf())
+}
+
+/// `--emit-delta`: writes a VCDIFF (xdelta3-compatible) patch turning
+/// `original` into `modified`, so a mod can ship without the package.
+pub fn emit_delta(original: &Path, modified: &Path, out: &Path) -> Result<()> {
+    let source = PackageBytes::map_file(original)?;
+    let target = PackageBytes::map_file(modified)?;
+    let header = format!(
+        "{APP_TAG}\nsource {} {}\ntarget {} {}\n",
+        source.len(),
+        sha256_hex(&source),
+        target.len(),
+        sha256_hex(&target)
+    );
+    let (delta, stats) = vcdiff::encode(&source, &target, header.as_bytes())?;
+    readonly::write(out, &delta)?;
+    println!(
+        "Delta: {} ({} bytes; {} copied from {}, {} added)",
+        out.display(),
+        delta.len(),
+        stats.copied,
+        original.display(),
+        stats.added
+    );
+    Ok(())
+}
+
+/// Size and SHA-256 of one side, from the application header.
+struct Expect {
+    size: u64,
+    sha256: String,
+}
+
+/// `source` and `target` as written by `emit_delta`; `None` for deltas
+/// from other tools.
+fn expectations(header: &[u8]) -> Option<(Expect, Expect)> {
+    let text = std::str::from_utf8(header).ok()?;
+    let mut lines = text.lines();
+    if lines.next()? != APP_TAG {
+        return None;
+    }
+    let mut side = |name: &str| {
+        let mut f = lines.next()?.split_whitespace();
+        (f.next()? == name).then_some(())?;
+        Some(Expect {
+            size: f.next()?.parse().ok()?,
+            sha256: f.next()?.to_ascii_lowercase(),
+        })
+    };
+    Some((side("source")?, side("target")?))
+}
+
+fn apply(src: &Path, patch_path: &Path, out: Option<&Path>, force: bool) -> Result<()> {
+    let (original, patch) = (src.display(), patch_path.display());
+    let delta =
+        std::fs::read(patch_path).map_err(|e| Error::new(e.kind(), format!("{patch}: {e}")))?;
+    let source = PackageBytes::map_file(src)?;
+    let expect = vcdiff::app_header(&delta)?.and_then(expectations);
+    match &expect {
+        Some((want, _)) => {
+            let got = sha256_hex(&source);
+            if source.len() as u64 != want.size || got != want.sha256 {
+                return Err(validation_failed(format!(
+                    "{original} is not the file {patch} was made from \
+                     (expected {} bytes, SHA-256 {}; found {} bytes, {got})",
+                    want.size,
+                    want.sha256,
+                    source.len()
+                )));
+            }
+            println!("Source matches: {original}");
+        }
+        None if force => term::warn(
+            "unverified",
+            format_args!("{patch} names no source hash; applying to {original} as is"),
+        ),
+        None => {
+            return Err(validation_failed(format!(
+                "{patch} names no source hash, so {original} can't be checked; \
+                 rerun with --force to apply anyway"
+            )));
+        }
+    }
+
+    let result = vcdiff::decode(&source, &delta)
+        .map_err(|e| Error::new(e.kind(), format!("{patch}: {e}")))?;
+    if let Some((_, want)) = &expect
+        && (result.len() as u64 != want.size || sha256_hex(&result) != want.sha256)
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{patch}: result doesn't match the hash it records; nothing written"),
+        ));
+    }
+    drop(source);
+
+    let dst = match out {
+        Some(o) => o.to_path_buf(),
+        None => {
+            if let Some(bak) = backup_original(src)? {
+                println!("Backup: {}", bak.display());
+            }
+            src.to_path_buf()
+        }
+    };
+    let mut part = dst.as_os_str().to_os_string();
+    part.push(".part");
+    let part = PathBuf::from(part);
+    readonly::write(&part, &result)?;
+    readonly::rename(&part, &dst)?;
+    println!("Wrote {} ({} bytes)", dst.display(), result.len());
+    Ok(())
+}
+
+pub fn run(cmd: DeltaCmd) -> Result<()> {
+    match cmd {
+        DeltaCmd::Apply {
+            original,
+            patch,
+            out,
+            force,
+        } => apply(&original, &patch, out.as_deref(), force),
+    }
+}
@@ -0,0 +1,1046 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    path::Path,
+};
+
+use crate::{
+    offsets::find_export,
+    schema::{SchemaParseCtx, parse_export_schema},
+    schemadb::{LazyPackage, open_package_file},
+    upkreader::{FName, ScriptKind, UPKPak},
+    utils::{
+        backup::original_of,
+        deadline, heuristics,
+        term::{self, Color, paint},
+    },
+    versions::script_pointer_size,
+};
+
+const EX_LOCAL_VARIABLE: u8 = 0x00;
+const EX_INSTANCE_VARIABLE: u8 = 0x01;
+const EX_DEFAULT_VARIABLE: u8 = 0x02;
+const EX_STATE_VARIABLE: u8 = 0x03;
+const EX_RETURN: u8 = 0x04;
+const EX_SWITCH: u8 = 0x05;
+const EX_JUMP: u8 = 0x06;
+const EX_JUMP_IF_NOT: u8 = 0x07;
+const EX_STOP: u8 = 0x08;
+const EX_ASSERT: u8 = 0x09;
+const EX_CASE: u8 = 0x0A;
+const EX_NOTHING: u8 = 0x0B;
+const EX_LABEL_TABLE: u8 = 0x0C;
+const EX_GOTO_LABEL: u8 = 0x0D;
+const EX_EAT_RETURN_VALUE: u8 = 0x0E;
+const EX_LET: u8 = 0x0F;
+const EX_DYN_ARRAY_ELEMENT: u8 = 0x10;
+const EX_NEW: u8 = 0x11;
+const EX_CLASS_CONTEXT: u8 = 0x12;
+const EX_META_CAST: u8 = 0x13;
+const EX_LET_BOOL: u8 = 0x14;
+const EX_END_PARM_VALUE: u8 = 0x15;
+const EX_END_FUNCTION_PARMS: u8 = 0x16;
+const EX_SELF: u8 = 0x17;
+const EX_SKIP: u8 = 0x18;
+const EX_CONTEXT: u8 = 0x19;
+const EX_ARRAY_ELEMENT: u8 = 0x1A;
+const EX_VIRTUAL_FUNCTION: u8 = 0x1B;
+const EX_FINAL_FUNCTION: u8 = 0x1C;
+const EX_INT_CONST: u8 = 0x1D;
+const EX_FLOAT_CONST: u8 = 0x1E;
+const EX_STRING_CONST: u8 = 0x1F;
+const EX_OBJECT_CONST: u8 = 0x20;
+const EX_NAME_CONST: u8 = 0x21;
+const EX_ROTATION_CONST: u8 = 0x22;
+const EX_VECTOR_CONST: u8 = 0x23;
+const EX_BYTE_CONST: u8 = 0x24;
+const EX_INT_ZERO: u8 = 0x25;
+const EX_INT_ONE: u8 = 0x26;
+const EX_TRUE: u8 = 0x27;
+const EX_FALSE: u8 = 0x28;
+const EX_NATIVE_PARM: u8 = 0x29;
+const EX_NO_OBJECT: u8 = 0x2A;
+const EX_INT_CONST_BYTE: u8 = 0x2C;
+const EX_BOOL_VARIABLE: u8 = 0x2D;
+const EX_DYNAMIC_CAST: u8 = 0x2E;
+const EX_ITERATOR: u8 = 0x2F;
+const EX_ITERATOR_POP: u8 = 0x30;
+const EX_ITERATOR_NEXT: u8 = 0x31;
+const EX_STRUCT_CMP_EQ: u8 = 0x32;
+const EX_STRUCT_CMP_NE: u8 = 0x33;
+const EX_UNICODE_STRING_CONST: u8 = 0x34;
+const EX_STRUCT_MEMBER: u8 = 0x35;
+const EX_DYN_ARRAY_LENGTH: u8 = 0x36;
+const EX_GLOBAL_FUNCTION: u8 = 0x37;
+const EX_PRIMITIVE_CAST: u8 = 0x38;
+const EX_DYN_ARRAY_INSERT: u8 = 0x39;
+const EX_RETURN_NOTHING: u8 = 0x3A;
+const EX_EQUAL_EQUAL_DEL_DEL: u8 = 0x3B;
+const EX_NOT_EQUAL_DEL_DEL: u8 = 0x3C;
+const EX_EQUAL_EQUAL_DEL_FUNC: u8 = 0x3D;
+const EX_NOT_EQUAL_DEL_FUNC: u8 = 0x3E;
+const EX_EMPTY_DELEGATE: u8 = 0x3F;
+const EX_DYN_ARRAY_REMOVE: u8 = 0x40;
+const EX_DEBUG_INFO: u8 = 0x41;
+const EX_DELEGATE_FUNCTION: u8 = 0x42;
+const EX_DELEGATE_PROPERTY: u8 = 0x43;
+const EX_LET_DELEGATE: u8 = 0x44;
+const EX_CONDITIONAL: u8 = 0x45;
+const EX_DYN_ARRAY_FIND: u8 = 0x46;
+const EX_DYN_ARRAY_FIND_STRUCT: u8 = 0x47;
+const EX_LOCAL_OUT_VARIABLE: u8 = 0x48;
+const EX_DEFAULT_PARM_VALUE: u8 = 0x49;
+const EX_EMPTY_PARM_VALUE: u8 = 0x4A;
+const EX_INSTANCE_DELEGATE: u8 = 0x4B;
+const EX_INTERFACE_CONTEXT: u8 = 0x51;
+const EX_INTERFACE_CAST: u8 = 0x52;
+const EX_END_OF_SCRIPT: u8 = 0x53;
+const EX_DYN_ARRAY_ADD: u8 = 0x54;
+const EX_DYN_ARRAY_ADD_ITEM: u8 = 0x55;
+const EX_DYN_ARRAY_REMOVE_ITEM: u8 = 0x56;
+const EX_DYN_ARRAY_INSERT_ITEM: u8 = 0x57;
+const EX_DYN_ARRAY_ITERATOR: u8 = 0x58;
+const EX_DYN_ARRAY_SORT: u8 = 0x59;
+const EX_FILTER_EDITOR_ONLY: u8 = 0x5A;
+const EX_EXTENDED_NATIVE: u8 = 0x60;
+const EX_FIRST_NATIVE: u8 = 0x70;
+
+pub fn opcode_name(op: u8) -> &'static str {
+    match op {
+        EX_LOCAL_VARIABLE => "LocalVariable",
+        EX_INSTANCE_VARIABLE => "InstanceVariable",
+        EX_DEFAULT_VARIABLE => "DefaultVariable",
+        EX_STATE_VARIABLE => "StateVariable",
+        EX_RETURN => "Return",
+        EX_SWITCH => "Switch",
+        EX_JUMP => "Jump",
+        EX_JUMP_IF_NOT => "JumpIfNot",
+        EX_STOP => "Stop",
+        EX_ASSERT => "Assert",
+        EX_CASE => "Case",
+        EX_NOTHING => "Nothing",
+        EX_LABEL_TABLE => "LabelTable",
+        EX_GOTO_LABEL => "GotoLabel",
+        EX_EAT_RETURN_VALUE => "EatReturnValue",
+        EX_LET => "Let",
+        EX_DYN_ARRAY_ELEMENT => "DynArrayElement",
+        EX_NEW => "New",
+        EX_CLASS_CONTEXT => "ClassContext",
+        EX_META_CAST => "MetaCast",
+        EX_LET_BOOL => "LetBool",
+        EX_END_PARM_VALUE => "EndParmValue",
+        EX_END_FUNCTION_PARMS => "EndFunctionParms",
+        EX_SELF => "Self",
+        EX_SKIP => "Skip",
+        EX_CONTEXT => "Context",
+        EX_ARRAY_ELEMENT => "ArrayElement",
+        EX_VIRTUAL_FUNCTION => "VirtualFunction",
+        EX_FINAL_FUNCTION => "FinalFunction",
+        EX_INT_CONST => "IntConst",
+        EX_FLOAT_CONST => "FloatConst",
+        EX_STRING_CONST => "StringConst",
+        EX_OBJECT_CONST => "ObjectConst",
+        EX_NAME_CONST => "NameConst",
+        EX_ROTATION_CONST => "RotationConst",
+        EX_VECTOR_CONST => "VectorConst",
+        EX_BYTE_CONST => "ByteConst",
+        EX_INT_ZERO => "IntZero",
+        EX_INT_ONE => "IntOne",
+        EX_TRUE => "True",
+        EX_FALSE => "False",
+        EX_NATIVE_PARM => "NativeParm",
+        EX_NO_OBJECT => "NoObject",
+        EX_INT_CONST_BYTE => "IntConstByte",
+        EX_BOOL_VARIABLE => "BoolVariable",
+        EX_DYNAMIC_CAST => "DynamicCast",
+        EX_ITERATOR => "Iterator",
+        EX_ITERATOR_POP => "IteratorPop",
+        EX_ITERATOR_NEXT => "IteratorNext",
+        EX_STRUCT_CMP_EQ => "StructCmpEq",
+        EX_STRUCT_CMP_NE => "StructCmpNe",
+        EX_UNICODE_STRING_CONST => "UnicodeStringConst",
+        EX_STRUCT_MEMBER => "StructMember",
+        EX_DYN_ARRAY_LENGTH => "DynArrayLength",
+        EX_GLOBAL_FUNCTION => "GlobalFunction",
+        EX_PRIMITIVE_CAST => "PrimitiveCast",
+        EX_DYN_ARRAY_INSERT => "DynArrayInsert",
+        EX_RETURN_NOTHING => "ReturnNothing",
+        EX_EQUAL_EQUAL_DEL_DEL => "EqualEqual_DelDel",
+        EX_NOT_EQUAL_DEL_DEL => "NotEqual_DelDel",
+        EX_EQUAL_EQUAL_DEL_FUNC => "EqualEqual_DelFunc",
+        EX_NOT_EQUAL_DEL_FUNC => "NotEqual_DelFunc",
+        EX_EMPTY_DELEGATE => "EmptyDelegate",
+        EX_DYN_ARRAY_REMOVE => "DynArrayRemove",
+        EX_DEBUG_INFO => "DebugInfo",
+        EX_DELEGATE_FUNCTION => "DelegateFunction",
+        EX_DELEGATE_PROPERTY => "DelegateProperty",
+        EX_LET_DELEGATE => "LetDelegate",
+        EX_CONDITIONAL => "Conditional",
+        EX_DYN_ARRAY_FIND => "DynArrayFind",
+        EX_DYN_ARRAY_FIND_STRUCT => "DynArrayFindStruct",
+        EX_LOCAL_OUT_VARIABLE => "LocalOutVariable",
+        EX_DEFAULT_PARM_VALUE => "DefaultParmValue",
+        EX_EMPTY_PARM_VALUE => "EmptyParmValue",
+        EX_INSTANCE_DELEGATE => "InstanceDelegate",
+        EX_INTERFACE_CONTEXT => "InterfaceContext",
+        EX_INTERFACE_CAST => "InterfaceCast",
+        EX_END_OF_SCRIPT => "EndOfScript",
+        EX_DYN_ARRAY_ADD => "DynArrayAdd",
+        EX_DYN_ARRAY_ADD_ITEM => "DynArrayAddItem",
+        EX_DYN_ARRAY_REMOVE_ITEM => "DynArrayRemoveItem",
+        EX_DYN_ARRAY_INSERT_ITEM => "DynArrayInsertItem",
+        EX_DYN_ARRAY_ITERATOR => "DynArrayIterator",
+        EX_DYN_ARRAY_SORT => "DynArraySort",
+        EX_FILTER_EDITOR_ONLY => "FilterEditorOnly",
+        0x60..=0x6F => "ExtendedNative",
+        EX_FIRST_NATIVE..=0xFF => "Native",
+        _ => "Unknown",
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub disk_offset: u32,
+    pub mem_offset: u32,
+    pub disk_size: u32,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefKind {
+    /// 4 bytes on disk, a pointer in memory.
+    Object,
+    /// Index + instance, 8 bytes either way.
+    Name,
+}
+
+/// An object or name index embedded in on-disk bytecode.
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptRef {
+    pub disk_offset: u32,
+    pub kind: RefKind,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Disassembly {
+    pub statements: Vec<Statement>,
+    /// Bytecode as laid out in memory; `None` where the engine swaps in
+    /// pointers / global name indices at load time.
+    pub pattern: Vec<Option<u8>>,
+    /// Every index the walker decoded, in disk order.
+    pub refs: Vec<ScriptRef>,
+    /// Every opcode read and its disk offset, in walk order; the last one
+    /// is where decoding stopped when `error` is set.
+    pub opcodes: Vec<(u32, u8)>,
+    /// Bytes walked on disk and what they take in memory; they differ by
+    /// the pointer-size growth of each object reference.
+    pub disk_size: u32,
+    pub mem_size: u32,
+    pub error: Option<String>,
+}
+
+impl Disassembly {
+    pub fn statement_at_disk(&self, disk_offset: u32) -> Option<&Statement> {
+        self.statements
+            .iter()
+            .rev()
+            .find(|s| s.disk_offset <= disk_offset)
+    }
+
+    pub fn statement_at_mem(&self, mem_offset: u32) -> Option<&Statement> {
+        self.statements
+            .iter()
+            .rev()
+            .find(|s| s.mem_offset <= mem_offset)
+    }
+
+    /// The object indices at the places `refs` recorded in `script`.
+    pub fn objects<'a>(&'a self, script: &'a [u8]) -> impl Iterator<Item = i32> + 'a {
+        self.refs
+            .iter()
+            .filter(|r| r.kind == RefKind::Object)
+            .filter_map(|r| script.get(r.disk_offset as usize..r.disk_offset as usize + 4))
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+/// Where the bytecode of a Function/State/Class export lives inside its blob.
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptSpan {
+    pub offset_in_blob: u64,
+    pub disk_size: u32,
+    pub mem_size: u32,
+}
+
+/// `kind` comes from `UPKPak::script_kind`.
+pub fn script_span(blob: &[u8], kind: ScriptKind, pak: &UPKPak, p_ver: i16) -> Option<ScriptSpan> {
+    let entry = parse_export_schema(blob, kind.as_str(), pak, SchemaParseCtx::pc(p_ver)).ok()??;
+    let header = entry.as_struct_header()?;
+    if header.on_disk_script_size <= 0 {
+        return None;
+    }
+    Some(ScriptSpan {
+        offset_in_blob: header.script_offset_in_blob,
+        disk_size: header.on_disk_script_size as u32,
+        mem_size: header.bytecode_size.max(0) as u32,
+    })
+}
+
+pub fn export_disassembly(lp: &LazyPackage, idx: i32) -> Result<Disassembly> {
+    let code = export_script(lp, idx)?;
+    Ok(disassemble(code, &lp.pak, lp.header.p_ver))
+}
+
+/// The on-disk bytecode of a Function/State/Class export.
+pub fn export_script(lp: &LazyPackage, idx: i32) -> Result<&[u8]> {
+    let blob = lp.export_blob(idx)?;
+    let span = lp
+        .pak
+        .script_kind(idx)
+        .and_then(|kind| script_span(blob, kind, &lp.pak, lp.header.p_ver))
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} has no bytecode", lp.pak.get_export_full_name(idx)),
+            )
+        })?;
+    let start = span.offset_in_blob as usize;
+    let code = blob
+        .get(start..start + span.disk_size as usize)
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("script of export #{idx} runs past its blob"),
+            )
+        })?;
+    Ok(code)
+}
+
+pub fn print_statement(st: &Statement, marked: bool) {
+    let marker = paint(Color::Highlight, if marked { ">" } else { " " });
+    println!(
+        "{marker} 0x{:04X}  {}  {}",
+        st.mem_offset,
+        paint(Color::Gray, format!("[disk 0x{:04X}]", st.disk_offset)),
+        st.text
+    );
+}
+
+pub fn disasm_cmd(upk_path: &Path, function: &str) -> Result<()> {
+    let lp = open_package_file(upk_path)?;
+    let idx = find_export(&lp, function)?;
+    let dis = export_disassembly(&lp, idx)?;
+    print_disassembly(&lp, idx, &dis);
+    if let Some(e) = &dis.error {
+        term::warn("disasm", e);
+    }
+    Ok(())
+}
+
+/// Every export `UPKPak::functions` finds, one after another.
+pub fn disasm_all_cmd(upk_path: &Path) -> Result<()> {
+    let lp = open_package_file(upk_path)?;
+    let (mut scripts, mut native, mut partial) = (0, 0, 0);
+    for (idx, _) in lp.pak.functions() {
+        // Native functions and script-less classes have none.
+        let Ok(dis) = export_disassembly(&lp, idx) else {
+            native += 1;
+            continue;
+        };
+        scripts += 1;
+        print_disassembly(&lp, idx, &dis);
+        if let Some(e) = &dis.error {
+            partial += 1;
+            term::warn(
+                "disasm",
+                format_args!("{}: {e}", lp.pak.get_export_full_name(idx)),
+            );
+        }
+        println!();
+    }
+    println!(
+        "{scripts} script(s), {} decoded to the end; {native} without bytecode",
+        paint(
+            if partial > 0 {
+                Color::Yellow
+            } else {
+                Color::Green
+            },
+            scripts - partial
+        )
+    );
+    Ok(())
+}
+
+fn print_disassembly(lp: &LazyPackage, idx: i32, dis: &Disassembly) {
+    println!("{}", lp.pak.get_export_full_name(idx));
+    for st in &dis.statements {
+        print_statement(st, false);
+    }
+    let objects = dis
+        .refs
+        .iter()
+        .filter(|r| r.kind == RefKind::Object)
+        .count();
+    println!(
+        "{} byte(s) on disk, {} in memory; {objects} object / {} name reference(s)",
+        dis.disk_size,
+        dis.mem_size,
+        dis.refs.len() - objects
+    );
+}
+
+enum Line<'a> {
+    /// The statement as it is in `b`.
+    Same(&'a Statement),
+    Removed(&'a Statement),
+    Added(&'a Statement),
+}
+
+/// Statement-level diff on the disassembled text, so bytecode that only
+/// moved (a jump target shifted) still lines up.
+fn diff_statements<'a>(a: &'a [Statement], b: &'a [Statement]) -> Vec<Line<'a>> {
+    let prefix = a
+        .iter()
+        .zip(b)
+        .take_while(|(x, y)| x.text == y.text)
+        .count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x.text == y.text)
+        .count();
+    let (ma, mb) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    // LCS table over what's left; functions differ in a few statements.
+    let mut lcs = vec![vec![0u32; mb.len() + 1]; ma.len() + 1];
+    for i in (0..ma.len()).rev() {
+        for j in (0..mb.len()).rev() {
+            lcs[i][j] = if ma[i].text == mb[j].text {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out: Vec<Line> = b[..prefix].iter().map(Line::Same).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < ma.len() || j < mb.len() {
+        if i < ma.len() && j < mb.len() && ma[i].text == mb[j].text {
+            out.push(Line::Same(&mb[j]));
+            i += 1;
+            j += 1;
+        } else if i < ma.len() && (j == mb.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push(Line::Removed(&ma[i]));
+            i += 1;
+        } else {
+            out.push(Line::Added(&mb[j]));
+            j += 1;
+        }
+    }
+    out.extend(b[b.len() - suffix..].iter().map(Line::Same));
+    out
+}
+
+/// `disasm --diff`: the function in `original` (the `.bak` when `None`)
+/// against the one in `upk_path`.
+pub fn disasm_diff_cmd(upk_path: &Path, function: &str, original: Option<&Path>) -> Result<()> {
+    let original = match original {
+        Some(o) => o.to_path_buf(),
+        None => original_of(upk_path)?,
+    };
+    let old_lp = open_package_file(&original)?;
+    let new_lp = open_package_file(upk_path)?;
+    let old = export_disassembly(&old_lp, find_export(&old_lp, function)?)?;
+    let idx = find_export(&new_lp, function)?;
+    let new = export_disassembly(&new_lp, idx)?;
+
+    println!(
+        "{}\n{} {}\n{} {}",
+        new_lp.pak.get_export_full_name(idx),
+        paint(Color::Red, "---"),
+        original.display(),
+        paint(Color::Green, "+++"),
+        upk_path.display()
+    );
+    let (mut added, mut removed) = (0, 0);
+    for line in diff_statements(&old.statements, &new.statements) {
+        match line {
+            Line::Same(st) => println!("  0x{:04X}  {}", st.mem_offset, st.text),
+            Line::Removed(st) => {
+                removed += 1;
+                println!(
+                    "{} 0x{:04X}  {}",
+                    paint(Color::Red, "-"),
+                    st.mem_offset,
+                    paint(Color::Red, &st.text)
+                );
+            }
+            Line::Added(st) => {
+                added += 1;
+                println!(
+                    "{} 0x{:04X}  {}",
+                    paint(Color::Green, "+"),
+                    st.mem_offset,
+                    paint(Color::Green, &st.text)
+                );
+            }
+        }
+    }
+    for (side, dis) in [("original", &old), ("modified", &new)] {
+        if let Some(e) = &dis.error {
+            term::warn("disasm", format_args!("{side}: {e}"));
+        }
+    }
+    println!(
+        "{} → {} statement(s); {added} added, {removed} removed",
+        old.statements.len(),
+        new.statements.len()
+    );
+    Ok(())
+}
+
+pub fn disassemble(script: &[u8], pak: &UPKPak, p_ver: i16) -> Disassembly {
+    let mut w = Walker {
+        data: script,
+        pos: 0,
+        mem: 0,
+        pak,
+        ptr: script_pointer_size(p_ver),
+        depth: 0,
+        pattern: Vec::new(),
+        refs: Vec::new(),
+        ops: Vec::new(),
+    };
+    let mut out = Disassembly::default();
+    while w.pos < script.len() {
+        let disk_offset = w.pos as u32;
+        let mem_offset = w.mem as u32;
+        let op = script[w.pos];
+        match w.expr() {
+            Ok(text) => out.statements.push(Statement {
+                disk_offset,
+                mem_offset,
+                disk_size: (w.pos - disk_offset as usize) as u32,
+                text,
+            }),
+            Err(e) => {
+                out.error = Some(format!(
+                    "statement @0x{disk_offset:04X} (opcode 0x{op:02X}): {e}"
+                ));
+                break;
+            }
+        }
+        if op == EX_END_OF_SCRIPT {
+            break;
+        }
+    }
+    out.pattern = w.pattern;
+    out.refs = w.refs;
+    out.opcodes = w.ops;
+    out.disk_size = w.pos as u32;
+    out.mem_size = w.mem as u32;
+    out
+}
+
+/// Copy of `script` with every object index passed through `obj` and every
+/// name through `name`, at the places `dis.refs` recorded. Widths on disk
+/// are fixed, so both sizes and all jump offsets stay as they were.
+pub fn reserialize_script(
+    script: &[u8],
+    dis: &Disassembly,
+    mut obj: impl FnMut(i32) -> Result<i32>,
+    mut name: impl FnMut(FName) -> Result<FName>,
+) -> Result<Vec<u8>> {
+    if let Some(e) = &dis.error {
+        // Refs past an undecodable token are unknown; rewriting the rest
+        // would leave stale indices behind.
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("bytecode not fully decoded: {e}"),
+        ));
+    }
+    let mut out = script.to_vec();
+    for r in &dis.refs {
+        let at = r.disk_offset as usize;
+        let field = |n: usize| -> Result<[u8; 4]> {
+            script
+                .get(at + n..at + n + 4)
+                .and_then(|b| b.try_into().ok())
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::UnexpectedEof,
+                        format!("reference at 0x{at:04X} runs past the script"),
+                    )
+                })
+        };
+        match r.kind {
+            RefKind::Object => {
+                let new = obj(i32::from_le_bytes(field(0)?))?;
+                out[at..at + 4].copy_from_slice(&new.to_le_bytes());
+            }
+            RefKind::Name => {
+                let new = name(FName {
+                    name_index: i32::from_le_bytes(field(0)?),
+                    name_instance: i32::from_le_bytes(field(4)?),
+                })?;
+                out[at..at + 4].copy_from_slice(&new.name_index.to_le_bytes());
+                out[at + 4..at + 8].copy_from_slice(&new.name_instance.to_le_bytes());
+            }
+        }
+    }
+    Ok(out)
+}
+
+struct Walker<'a> {
+    data: &'a [u8],
+    pos: usize,
+    mem: usize,
+    pak: &'a UPKPak,
+    ptr: usize,
+    depth: usize,
+    pattern: Vec<Option<u8>>,
+    refs: Vec<ScriptRef>,
+    ops: Vec<(u32, u8)>,
+}
+
+impl Walker<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8]> {
+        if self.pos + n > self.data.len() {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("need {n} byte(s) at 0x{:04X}", self.pos),
+            ));
+        }
+        let s = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(s)
+    }
+
+    fn exact(&mut self, n: usize) -> Result<&[u8]> {
+        let start = self.pos;
+        self.take(n)?;
+        self.mem += n;
+        self.pattern
+            .extend(self.data[start..start + n].iter().map(|&b| Some(b)));
+        Ok(&self.data[start..start + n])
+    }
+
+    fn wildcard(&mut self, disk: usize, mem: usize) -> Result<&[u8]> {
+        let start = self.pos;
+        self.take(disk)?;
+        self.mem += mem;
+        self.pattern.extend(std::iter::repeat_n(None, mem));
+        Ok(&self.data[start..start + disk])
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.exact(1)?[0])
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let b = self.exact(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        let b = self.exact(4)?;
+        Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_bits(self.i32()? as u32))
+    }
+
+    fn obj(&mut self) -> Result<String> {
+        let ptr = self.ptr;
+        self.refs.push(ScriptRef {
+            disk_offset: self.pos as u32,
+            kind: RefKind::Object,
+        });
+        let b = self.wildcard(4, ptr)?;
+        let idx = i32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+        Ok(obj_label(self.pak, idx))
+    }
+
+    fn fname(&mut self) -> Result<FName> {
+        self.refs.push(ScriptRef {
+            disk_offset: self.pos as u32,
+            kind: RefKind::Name,
+        });
+        let b = self.wildcard(8, 8)?;
+        Ok(FName {
+            name_index: i32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            name_instance: i32::from_le_bytes([b[4], b[5], b[6], b[7]]),
+        })
+    }
+
+    fn name(&mut self) -> Result<String> {
+        let fname = self.fname()?;
+        Ok(self.pak.fname_to_string(&fname))
+    }
+
+    fn cstring(&mut self) -> Result<String> {
+        let mut s = String::new();
+        loop {
+            let c = self.u8()?;
+            if c == 0 {
+                break;
+            }
+            s.push(c as char);
+        }
+        Ok(s)
+    }
+
+    fn wstring(&mut self) -> Result<String> {
+        let mut u = Vec::new();
+        loop {
+            let c = self.u16()?;
+            if c == 0 {
+                break;
+            }
+            u.push(c);
+        }
+        Ok(String::from_utf16_lossy(&u))
+    }
+
+    fn args(&mut self) -> Result<String> {
+        let mut parts = Vec::new();
+        loop {
+            match self.peek() {
+                Some(EX_END_FUNCTION_PARMS) => {
+                    self.u8()?;
+                    break;
+                }
+                Some(_) => parts.push(self.expr()?),
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "unterminated parameter list",
+                    ));
+                }
+            }
+        }
+        Ok(parts.join(", "))
+    }
+
+    fn opt_end_parms(&mut self) -> Result<()> {
+        if self.peek() == Some(EX_END_FUNCTION_PARMS) {
+            self.u8()?;
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self) -> Result<String> {
+        deadline::check("disassembling")?;
+        self.depth += 1;
+        let max = heuristics::get().max_script_depth;
+        if self.depth as u64 > max {
+            return Err(heuristics::exceeded(
+                "max-script-depth",
+                format_args!("expression nesting deeper than {max}"),
+            ));
+        }
+        let r = self.expr_inner();
+        self.depth -= 1;
+        r
+    }
+
+    fn expr_inner(&mut self) -> Result<String> {
+        let at = self.pos;
+        let op = self.u8()?;
+        self.ops.push((at as u32, op));
+        Ok(match op {
+            EX_LOCAL_VARIABLE
+            | EX_INSTANCE_VARIABLE
+            | EX_STATE_VARIABLE
+            | EX_LOCAL_OUT_VARIABLE
+            | EX_NATIVE_PARM => self.obj()?,
+            EX_DEFAULT_VARIABLE => format!("default.{}", self.obj()?),
+            EX_RETURN => format!("return {}", self.expr()?),
+            EX_SWITCH => {
+                let _prop = self.obj()?;
+                let _size = self.u8()?;
+                format!("switch ({})", self.expr()?)
+            }
+            EX_JUMP => format!("jump 0x{:04X}", self.u16()?),
+            EX_JUMP_IF_NOT => {
+                let off = self.u16()?;
+                format!("if (!({})) jump 0x{off:04X}", self.expr()?)
+            }
+            EX_STOP => "stop".into(),
+            EX_ASSERT => {
+                let line = self.u16()?;
+                let _debug = self.u8()?;
+                format!("assert({})  // line {line}", self.expr()?)
+            }
+            EX_CASE => {
+                let next = self.u16()?;
+                if next == 0xFFFF {
+                    "default:".into()
+                } else {
+                    format!("case {}:  // next 0x{next:04X}", self.expr()?)
+                }
+            }
+            EX_NOTHING | EX_END_PARM_VALUE | EX_EMPTY_PARM_VALUE => String::new(),
+            EX_LABEL_TABLE => {
+                let mut labels = Vec::new();
+                loop {
+                    let name = self.fname()?;
+                    let off = self.i32()?;
+                    if self.pak.name_table.is_none(&name) {
+                        break;
+                    }
+                    let name = self.pak.fname_to_string(&name);
+                    labels.push(format!("{name}@0x{off:04X}"));
+                }
+                format!("labels [{}]", labels.join(", "))
+            }
+            EX_GOTO_LABEL => format!("goto {}", self.expr()?),
+            EX_EAT_RETURN_VALUE => format!("// discard {}", self.obj()?),
+            EX_LET | EX_LET_BOOL | EX_LET_DELEGATE => {
+                let lhs = self.expr()?;
+                let rhs = self.expr()?;
+                format!("{lhs} = {rhs}")
+            }
+            EX_DYN_ARRAY_ELEMENT | EX_ARRAY_ELEMENT => {
+                let index = self.expr()?;
+                let array = self.expr()?;
+                format!("{array}[{index}]")
+            }
+            EX_NEW => {
+                let outer = self.expr()?;
+                let name = self.expr()?;
+                let flags = self.expr()?;
+                let class = self.expr()?;
+                let template = self.expr()?;
+                format!("new({outer}, {name}, {flags}) {class}({template})")
+            }
+            EX_CLASS_CONTEXT | EX_CONTEXT => {
+                let ctx = self.expr()?;
+                let _skip = self.u16()?;
+                let _field = self.obj()?;
+                let _size = self.u8()?;
+                let member = self.expr()?;
+                if op == EX_CLASS_CONTEXT {
+                    format!("{ctx}.static.{member}")
+                } else {
+                    format!("{ctx}.{member}")
+                }
+            }
+            EX_META_CAST | EX_DYNAMIC_CAST | EX_INTERFACE_CAST => {
+                let class = self.obj()?;
+                format!("{class}({})", self.expr()?)
+            }
+            EX_SELF => "self".into(),
+            EX_SKIP => {
+                let _skip = self.u16()?;
+                self.expr()?
+            }
+            EX_VIRTUAL_FUNCTION | EX_GLOBAL_FUNCTION => {
+                let name = self.name()?;
+                let args = self.args()?;
+                if op == EX_GLOBAL_FUNCTION {
+                    format!("global.{name}({args})")
+                } else {
+                    format!("{name}({args})")
+                }
+            }
+            EX_FINAL_FUNCTION => {
+                let func = self.obj()?;
+                format!("{func}({})", self.args()?)
+            }
+            EX_INT_CONST => self.i32()?.to_string(),
+            EX_FLOAT_CONST => format!("{:?}", self.f32()?),
+            EX_STRING_CONST => format!("{:?}", self.cstring()?),
+            EX_OBJECT_CONST => self.obj()?,
+            EX_NAME_CONST => format!("'{}'", self.name()?),
+            EX_ROTATION_CONST => {
+                let (p, y, r) = (self.i32()?, self.i32()?, self.i32()?);
+                format!("rot({p}, {y}, {r})")
+            }
+            EX_VECTOR_CONST => {
+                let (x, y, z) = (self.f32()?, self.f32()?, self.f32()?);
+                format!("vect({x:?}, {y:?}, {z:?})")
+            }
+            EX_BYTE_CONST | EX_INT_CONST_BYTE => self.u8()?.to_string(),
+            EX_INT_ZERO => "0".into(),
+            EX_INT_ONE => "1".into(),
+            EX_TRUE => "true".into(),
+            EX_FALSE => "false".into(),
+            EX_NO_OBJECT | EX_EMPTY_DELEGATE => "None".into(),
+            EX_BOOL_VARIABLE | EX_INTERFACE_CONTEXT => self.expr()?,
+            EX_ITERATOR => {
+                let it = self.expr()?;
+                let end = self.u16()?;
+                format!("foreach {it}  // end 0x{end:04X}")
+            }
+            EX_ITERATOR_POP => "// iterator pop".into(),
+            EX_ITERATOR_NEXT => "// iterator next".into(),
+            EX_STRUCT_CMP_EQ | EX_STRUCT_CMP_NE => {
+                let _struct = self.obj()?;
+                let a = self.expr()?;
+                let b = self.expr()?;
+                let cmp = if op == EX_STRUCT_CMP_EQ { "==" } else { "!=" };
+                format!("{a} {cmp} {b}")
+            }
+            EX_UNICODE_STRING_CONST => format!("{:?}", self.wstring()?),
+            EX_STRUCT_MEMBER => {
+                let member = self.obj()?;
+                let _struct = self.obj()?;
+                let _copy = self.u8()?;
+                let _modify = self.u8()?;
+                format!("{}.{member}", self.expr()?)
+            }
+            EX_DYN_ARRAY_LENGTH => format!("{}.Length", self.expr()?),
+            EX_PRIMITIVE_CAST => {
+                let cast = self.u8()?;
+                format!("cast<0x{cast:02X}>({})", self.expr()?)
+            }
+            EX_DYN_ARRAY_INSERT | EX_DYN_ARRAY_REMOVE => {
+                let array = self.expr()?;
+                let index = self.expr()?;
+                let count = self.expr()?;
+                self.opt_end_parms()?;
+                let verb = if op == EX_DYN_ARRAY_INSERT {
+                    "Insert"
+                } else {
+                    "Remove"
+                };
+                format!("{array}.{verb}({index}, {count})")
+            }
+            EX_RETURN_NOTHING => format!("return  // {}", self.obj()?),
+            EX_EQUAL_EQUAL_DEL_DEL
+            | EX_NOT_EQUAL_DEL_DEL
+            | EX_EQUAL_EQUAL_DEL_FUNC
+            | EX_NOT_EQUAL_DEL_FUNC => {
+                let args = self.args()?;
+                format!("{}({args})", opcode_name(op))
+            }
+            EX_DEBUG_INFO => {
+                let _ver = self.i32()?;
+                let line = self.i32()?;
+                let _pos = self.i32()?;
+                let _code = self.u8()?;
+                format!("// debug line {line}")
+            }
+            EX_DELEGATE_FUNCTION => {
+                let _local = self.u8()?;
+                let prop = self.obj()?;
+                let name = self.name()?;
+                format!("{prop}<{name}>({})", self.args()?)
+            }
+            EX_DELEGATE_PROPERTY => {
+                let name = self.name()?;
+                let _prop = self.obj()?;
+                name
+            }
+            EX_INSTANCE_DELEGATE => self.name()?,
+            EX_CONDITIONAL => {
+                let cond = self.expr()?;
+                let _skip_a = self.u16()?;
+                let a = self.expr()?;
+                let _skip_b = self.u16()?;
+                let b = self.expr()?;
+                format!("({cond} ? {a} : {b})")
+            }
+            EX_DYN_ARRAY_FIND => {
+                let array = self.expr()?;
+                let _skip = self.u16()?;
+                let value = self.expr()?;
+                self.opt_end_parms()?;
+                format!("{array}.Find({value})")
+            }
+            EX_DYN_ARRAY_FIND_STRUCT => {
+                let array = self.expr()?;
+                let _skip = self.u16()?;
+                let member = self.expr()?;
+                let value = self.expr()?;
+                self.opt_end_parms()?;
+                format!("{array}.Find({member}, {value})")
+            }
+            EX_DEFAULT_PARM_VALUE => {
+                let _size = self.u16()?;
+                let v = self.expr()?;
+                if self.peek() == Some(EX_END_PARM_VALUE) {
+                    self.u8()?;
+                }
+                format!("// default = {v}")
+            }
+            EX_END_OF_SCRIPT => "// end of script".into(),
+            EX_DYN_ARRAY_ADD | EX_DYN_ARRAY_ADD_ITEM | EX_DYN_ARRAY_REMOVE_ITEM => {
+                let array = self.expr()?;
+                let _skip = self.u16()?;
+                let value = self.expr()?;
+                self.opt_end_parms()?;
+                let verb = match op {
+                    EX_DYN_ARRAY_ADD => "Add",
+                    EX_DYN_ARRAY_ADD_ITEM => "AddItem",
+                    _ => "RemoveItem",
+                };
+                format!("{array}.{verb}({value})")
+            }
+            EX_DYN_ARRAY_INSERT_ITEM => {
+                let array = self.expr()?;
+                let _skip = self.u16()?;
+                let index = self.expr()?;
+                let value = self.expr()?;
+                self.opt_end_parms()?;
+                format!("{array}.InsertItem({index}, {value})")
+            }
+            EX_DYN_ARRAY_ITERATOR => {
+                let array = self.expr()?;
+                let item = self.expr()?;
+                let has_index = self.u8()?;
+                let index = self.expr()?;
+                let end = self.u16()?;
+                if has_index != 0 {
+                    format!("foreach {array}({item}, {index})  // end 0x{end:04X}")
+                } else {
+                    format!("foreach {array}({item})  // end 0x{end:04X}")
+                }
+            }
+            EX_DYN_ARRAY_SORT => {
+                let array = self.expr()?;
+                let _skip = self.u16()?;
+                let func = self.expr()?;
+                self.opt_end_parms()?;
+                format!("{array}.Sort({func})")
+            }
+            EX_FILTER_EDITOR_ONLY => format!("// editor-only until 0x{:04X}", self.u16()?),
+            EX_EXTENDED_NATIVE..=0x6F => {
+                let lo = self.u8()? as u16;
+                let idx = (((op - EX_EXTENDED_NATIVE) as u16) << 8) | lo;
+                format!("native#{idx}({})", self.args()?)
+            }
+            EX_FIRST_NATIVE..=0xFF => format!("native#{op}({})", self.args()?),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown opcode 0x{op:02X} at 0x{at:04X}"),
+                ));
+            }
+        })
+    }
+}
+
+fn obj_label(pak: &UPKPak, idx: i32) -> String {
+    if idx > 0 {
+        pak.export_table
+            .get((idx - 1) as usize)
+            .map(|e| pak.fname_to_string(&e.object_name))
+            .unwrap_or_else(|| format!("<export #{idx}>"))
+    } else if idx < 0 {
+        pak.import_table
+            .get((-idx - 1) as usize)
+            .map(|i| pak.fname_to_string(&i.object_name))
+            .unwrap_or_else(|| format!("<import #{idx}>"))
+    } else {
+        "None".into()
+    }
+}
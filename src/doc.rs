@@ -0,0 +1,417 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as FmtWrite,
+    io::{BufWriter, Result, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use crate::{
+    orphans::SCRIPT_CLASSES,
+    pseudo::{leaf_name, type_of},
+    schema::SchemaEntry,
+    schemadb::{LazyPackage, ResolvedRef, SchemaDb, open_package_file},
+    upkpacker::export_path_dotted,
+    upkprops::{Property, PropertyValue},
+    upkreader::{PackageFlags, ScriptKind},
+    utils::readonly,
+    versions::*,
+};
+
+/// CDO values listed per object before the rest is summarised.
+const MAX_CDO_VALUES: usize = 40;
+
+/// Modifiers in the order UnrealScript declares them.
+const FUNC_MODIFIERS: &[(u32, &str)] = &[
+    (FUNC_PRIVATE, "private"),
+    (FUNC_PROTECTED, "protected"),
+    (FUNC_STATIC, "static"),
+    (FUNC_FINAL, "final"),
+    (FUNC_NATIVE, "native"),
+    (FUNC_SIMULATED, "simulated"),
+    (FUNC_SINGULAR, "singular"),
+    (FUNC_LATENT, "latent"),
+    (FUNC_ITERATOR, "iterator"),
+    (FUNC_EXEC, "exec"),
+];
+
+fn cell(s: &str) -> String {
+    s.replace('|', "\\|")
+}
+
+fn local_ref(lp: &LazyPackage, idx: i32) -> ResolvedRef {
+    ResolvedRef {
+        stem_lc: lp.stem_lc.clone(),
+        export_idx: idx,
+    }
+}
+
+fn exports_of(lp: &LazyPackage, kind: ScriptKind) -> Vec<i32> {
+    lp.pak
+        .functions()
+        .filter(|(_, k)| *k == kind)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+fn object_text(lp: &LazyPackage, idx: i32) -> String {
+    if idx > 0 {
+        export_path_dotted(&lp.pak, idx)
+    } else {
+        leaf_name(&lp.pak, idx)
+    }
+}
+
+/// One-line rendering; nested data is summarised rather than expanded.
+pub fn value_text(lp: &LazyPackage, v: &PropertyValue, depth: usize) -> String {
+    use PropertyValue::*;
+    match v {
+        None => "None".into(),
+        Byte(b) => b.to_string(),
+        Int(i) => i.to_string(),
+        Bool(b) => b.to_string(),
+        Float(f) => f.to_string(),
+        Object(i) => object_text(lp, *i),
+        ObjectRef(s) => s.split_once(' ').map_or(s.as_str(), |x| x.1).to_string(),
+        Name(f) => lp.pak.fname_to_string(f),
+        EnumLabel(s) => s.clone(),
+        String(s) => format!("{s:?}"),
+        Array(items) if depth == 0 && items.len() <= 4 => {
+            let parts: Vec<_> = items.iter().map(|x| value_text(lp, x, 1)).collect();
+            format!("[{}]", parts.join(", "))
+        }
+        Array(items) => format!("[{} item(s)]", items.len()),
+        Struct(fields) if depth == 0 && fields.len() <= 4 => {
+            let parts: Vec<_> = fields
+                .iter()
+                .map(|p| format!("{}={}", p.name, value_text(lp, &p.value, 1)))
+                .collect();
+            format!("({})", parts.join(", "))
+        }
+        Struct(fields) => format!("({} field(s))", fields.len()),
+        AtomicStruct(fields) if depth == 0 => {
+            let parts: Vec<_> = fields
+                .iter()
+                .map(|(n, x)| format!("{n}={}", value_text(lp, x, 1)))
+                .collect();
+            format!("({})", parts.join(", "))
+        }
+        AtomicStruct(fields) => format!("({} field(s))", fields.len()),
+        Raw(bytes) => format!("<{} bytes>", bytes.len()),
+    }
+}
+
+fn write_summary(out: &mut String, lp: &LazyPackage, file_size: u64) {
+    let h = &lp.header;
+    let flags: Vec<&str> = PackageFlags::from_bits_retain(h.pak_flags)
+        .iter_names()
+        .map(|(n, _)| n)
+        .collect();
+    let guid: Vec<String> = h
+        .guid
+        .iter()
+        .map(|g| format!("{:08X}", *g as u32))
+        .collect();
+
+    let _ = writeln!(out, "## Summary\n");
+    let _ = writeln!(out, "| Field | Value |\n|---|---|");
+    let _ = writeln!(out, "| File | `{}` |", lp.path.display());
+    let _ = writeln!(out, "| Size | {file_size} bytes |");
+    let _ = writeln!(
+        out,
+        "| Version | {} / {} (engine {}, cooker {}) |",
+        h.p_ver, h.l_ver, h.engine_ver, h.cooker_ver
+    );
+    let _ = writeln!(
+        out,
+        "| Flags | 0x{:08X} {} |",
+        h.pak_flags,
+        if flags.is_empty() {
+            "-".to_string()
+        } else {
+            flags.join(", ")
+        }
+    );
+    let _ = writeln!(
+        out,
+        "| Compression | {:?} ({} chunk(s)) |",
+        h.compression_method, h.compressed_chunks_count
+    );
+    let _ = writeln!(
+        out,
+        "| Tables | {} names, {} imports, {} exports |",
+        lp.pak.name_table.len(),
+        lp.pak.import_table.len(),
+        lp.pak.export_table.len()
+    );
+    let _ = writeln!(out, "| GUID | {} |", guid.join("-"));
+    if !h.additional_packages.is_empty() {
+        let _ = writeln!(
+            out,
+            "| Cooked with | {} |",
+            cell(&h.additional_packages.join(", "))
+        );
+    }
+    out.push('\n');
+}
+
+fn write_classes(out: &mut String, lp: &LazyPackage, db: &SchemaDb) {
+    let classes = exports_of(lp, ScriptKind::Class);
+    if classes.is_empty() {
+        return;
+    }
+    let _ = writeln!(out, "## Classes\n");
+    let _ = writeln!(
+        out,
+        "| Class | Extends | Within | Config | Flags | Variables | Functions | States |"
+    );
+    let _ = writeln!(out, "|---|---|---|---|---|---|---|---|");
+    for idx in classes {
+        let r = local_ref(lp, idx);
+        let name = leaf_name(&lp.pak, idx);
+        let Ok(entry) = db.entry(&r) else {
+            let _ = writeln!(out, "| {name} | ? | ? | ? | ? | ? | ? | ? |");
+            continue;
+        };
+        let SchemaEntry::Class { header, extra, .. } = &*entry else {
+            continue;
+        };
+        let (mut vars, mut funcs, mut states) = (0, 0, 0);
+        for (_, _, c) in db.list_children(&r).unwrap_or_default() {
+            match &*c {
+                SchemaEntry::Property(_) => vars += 1,
+                SchemaEntry::Function { .. } => funcs += 1,
+                SchemaEntry::State { .. } => states += 1,
+                _ => {}
+            }
+        }
+        let config = lp.pak.fname_to_string(&extra.class_config_name);
+        let _ = writeln!(
+            out,
+            "| {name} | {} | {} | {} | 0x{:08X} | {vars} | {funcs} | {states} |",
+            leaf_name(&lp.pak, header.super_struct),
+            leaf_name(&lp.pak, extra.class_within),
+            if config.is_empty() { "None" } else { &config },
+            extra.class_flags
+        );
+    }
+    out.push('\n');
+}
+
+fn signature(lp: &LazyPackage, db: &SchemaDb, idx: i32) -> Option<String> {
+    let r = local_ref(lp, idx);
+    let entry = db.entry(&r).ok()?;
+    let SchemaEntry::Function { extra, .. } = &*entry else {
+        return None;
+    };
+    let flags = extra.function_flags;
+
+    let mut ret = None;
+    let mut params = Vec::new();
+    for (name, _, c) in db.list_children(&r).unwrap_or_default() {
+        let SchemaEntry::Property(kind) = &*c else {
+            continue;
+        };
+        let common = kind.common();
+        let ty = type_of(db, &lp.pak, &lp.stem_lc, kind);
+        if common.property_flags & CPF_RETURN_PARM != 0 {
+            ret = Some(ty);
+        } else if common.property_flags & CPF_PARM != 0 {
+            let mut p = String::new();
+            if common.property_flags & CPF_OPTIONAL_PARM != 0 {
+                p.push_str("optional ");
+            }
+            if common.property_flags & CPF_OUT_PARM != 0 {
+                p.push_str("out ");
+            }
+            let _ = write!(p, "{ty} {name}");
+            if common.array_dim > 1 {
+                let _ = write!(p, "[{}]", common.array_dim);
+            }
+            params.push(p);
+        }
+    }
+
+    let mut s = String::new();
+    for (bit, word) in FUNC_MODIFIERS {
+        if flags & bit != 0 {
+            s.push_str(word);
+            s.push(' ');
+        }
+    }
+    if flags & FUNC_NET != 0 {
+        s.push_str(if flags & FUNC_NETRELIABLE != 0 {
+            "reliable "
+        } else {
+            "unreliable "
+        });
+        if flags & FUNC_NETSERVER != 0 {
+            s.push_str("server ");
+        } else if flags & FUNC_NETCLIENT != 0 {
+            s.push_str("client ");
+        }
+    }
+    s.push_str(if flags & FUNC_DELEGATE != 0 {
+        "delegate "
+    } else if flags & FUNC_PREOPERATOR != 0 {
+        "preoperator "
+    } else if flags & FUNC_OPERATOR != 0 {
+        "operator "
+    } else if flags & FUNC_EVENT != 0 {
+        "event "
+    } else {
+        "function "
+    });
+    if let Some(t) = ret {
+        let _ = write!(s, "{t} ");
+    }
+    let _ = write!(s, "{}({})", leaf_name(&lp.pak, idx), params.join(", "));
+    if flags & FUNC_CONST != 0 {
+        s.push_str(" const");
+    }
+    Some(s)
+}
+
+fn write_functions(out: &mut String, lp: &LazyPackage, db: &SchemaDb) {
+    let funcs = exports_of(lp, ScriptKind::Function);
+    if funcs.is_empty() {
+        return;
+    }
+    // Grouped under their class or state.
+    let mut by_owner: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for idx in funcs {
+        let owner = object_text(lp, lp.pak.export_table[(idx - 1) as usize].outer_index);
+        let sig = signature(lp, db, idx)
+            .unwrap_or_else(|| format!("function {}(?)", leaf_name(&lp.pak, idx)));
+        by_owner.entry(owner).or_default().push(sig);
+    }
+    let _ = writeln!(out, "## Functions\n");
+    for (owner, sigs) in by_owner {
+        let _ = writeln!(out, "### {owner}\n");
+        for s in sigs {
+            let _ = writeln!(out, "- `{s}`");
+        }
+        out.push('\n');
+    }
+}
+
+fn write_defaults(out: &mut String, lp: &LazyPackage, db: &SchemaDb) {
+    let cdos: Vec<i32> = (1..=lp.pak.export_table.len() as i32)
+        .filter(|i| {
+            lp.pak.export_table[(*i - 1) as usize].object_flags & RF_CLASS_DEFAULT_OBJECT != 0
+        })
+        .collect();
+    if cdos.is_empty() {
+        return;
+    }
+    // Defaults only store what differs from the parent class, so every
+    // tagged property here is a value the package chose.
+    let _ = writeln!(out, "## Default values\n");
+    for idx in cdos {
+        let _ = writeln!(out, "### {}\n", export_path_dotted(&lp.pak, idx));
+        let props: Vec<Property> = match lp.export_props(idx, Some(db)) {
+            Ok((props, _)) => props,
+            Err(e) => {
+                let _ = writeln!(out, "_Properties not readable: {e}_\n");
+                continue;
+            }
+        };
+        if props.is_empty() {
+            let _ = writeln!(out, "_No overridden values._\n");
+            continue;
+        }
+        for p in props.iter().take(MAX_CDO_VALUES) {
+            let name = if p.array_index > 0 {
+                format!("{}[{}]", p.name, p.array_index)
+            } else {
+                p.name.clone()
+            };
+            let _ = writeln!(out, "- `{name}` = `{}`", value_text(lp, &p.value, 0));
+        }
+        if props.len() > MAX_CDO_VALUES {
+            let _ = writeln!(out, "- … {} more", props.len() - MAX_CDO_VALUES);
+        }
+        out.push('\n');
+    }
+}
+
+fn write_assets(out: &mut String, lp: &LazyPackage) {
+    let mut assets: Vec<(String, String, u64)> = Vec::new();
+    for (k, e) in lp.pak.export_table.iter().enumerate() {
+        let idx = k as i32 + 1;
+        let class = lp.export_class_name(idx);
+        if SCRIPT_CLASSES.contains(&class.as_str()) || e.object_flags & RF_CLASS_DEFAULT_OBJECT != 0
+        {
+            continue;
+        }
+        assets.push((
+            class,
+            export_path_dotted(&lp.pak, idx),
+            e.serial_size.max(0) as u64,
+        ));
+    }
+    if assets.is_empty() {
+        return;
+    }
+    assets.sort();
+
+    let mut totals: BTreeMap<&str, (usize, u64)> = BTreeMap::new();
+    for (class, _, size) in &assets {
+        let t = totals.entry(class.as_str()).or_default();
+        t.0 += 1;
+        t.1 += size;
+    }
+    let _ = writeln!(out, "## Assets\n");
+    let _ = writeln!(out, "| Class | Count | Bytes |\n|---|---|---|");
+    for (class, (n, bytes)) in &totals {
+        let _ = writeln!(out, "| {class} | {n} | {bytes} |");
+    }
+    out.push('\n');
+    let _ = writeln!(out, "| Object | Class | Bytes |\n|---|---|---|");
+    for (class, path, size) in &assets {
+        let _ = writeln!(out, "| {} | {class} | {size} |", cell(path));
+    }
+    out.push('\n');
+}
+
+pub fn render(lp: &LazyPackage, db: &SchemaDb, file_size: u64) -> String {
+    let stem = lp
+        .path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| lp.stem_lc.clone());
+    let mut out = String::new();
+    let _ = writeln!(out, "# {stem}\n");
+    write_summary(&mut out, lp, file_size);
+    write_classes(&mut out, lp, db);
+    write_functions(&mut out, lp, db);
+    write_defaults(&mut out, lp, db);
+    write_assets(&mut out, lp);
+    out
+}
+
+pub fn doc_cmd(
+    upk_path: &Path,
+    out: Option<&Path>,
+    game_root: Option<&Path>,
+    verbose: bool,
+) -> Result<()> {
+    let path = upk_path;
+    let lp = Rc::new(open_package_file(path)?);
+    // Without --game-root only this package's own types can be resolved;
+    // imported ones show up by name.
+    let db = SchemaDb::new(game_root.unwrap_or(Path::new("")))?.with_verbose(verbose);
+    db.inject_package(lp.clone());
+
+    let md = render(&lp, &db, std::fs::metadata(path)?.len());
+    match out {
+        Some(o) => {
+            let mut w = BufWriter::new(readonly::create(o)?);
+            w.write_all(md.as_bytes())?;
+            w.flush()?;
+            println!("Wrote {}", o.display());
+        }
+        None => print!("{md}"),
+    }
+    Ok(())
+}
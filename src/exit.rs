@@ -0,0 +1,56 @@
+//! Process exit codes, so scripts can branch on why a command failed:
+//!
+//! | code | meaning                                                  |
+//! |------|----------------------------------------------------------|
+//! | 0    | success                                                  |
+//! | 1    | any other failure (I/O, bad arguments, ...)              |
+//! | 2    | object or file not found                                 |
+//! | 3    | package couldn't be parsed                               |
+//! | 4    | unsupported feature (compression method, engine, ...)    |
+//! | 5    | validation failed (`validate`, `names diff`, conflicts)  |
+//!
+//! Commands report these through the `io::ErrorKind` of the error they
+//! return; validation failures use [`validation_failed`].
+
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+};
+
+pub const SUCCESS: u8 = 0;
+pub const FAILURE: u8 = 1;
+pub const NOT_FOUND: u8 = 2;
+pub const PARSE_ERROR: u8 = 3;
+pub const UNSUPPORTED: u8 = 4;
+pub const VALIDATION_FAILED: u8 = 5;
+
+#[derive(Debug)]
+pub struct ValidationFailed(pub String);
+
+impl fmt::Display for ValidationFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ValidationFailed {}
+
+/// An error that exits with [`VALIDATION_FAILED`].
+pub fn validation_failed(msg: impl Into<String>) -> Error {
+    Error::other(ValidationFailed(msg.into()))
+}
+
+pub fn code_for(e: &Error) -> u8 {
+    match e.kind() {
+        ErrorKind::NotFound => NOT_FOUND,
+        ErrorKind::InvalidData | ErrorKind::UnexpectedEof => PARSE_ERROR,
+        ErrorKind::Unsupported => UNSUPPORTED,
+        _ if e
+            .get_ref()
+            .is_some_and(|inner| inner.is::<ValidationFailed>()) =>
+        {
+            VALIDATION_FAILED
+        }
+        _ => FAILURE,
+    }
+}
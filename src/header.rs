@@ -0,0 +1,290 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::Subcommand;
+
+use crate::{
+    delta,
+    exit::validation_failed,
+    schemadb::open_package_file,
+    upkreader::{PackageFlags, UpkHeader},
+    utils::{backup::backup_original, decompress::is_fully_compressed, readonly, sniff, term},
+};
+
+#[derive(Subcommand)]
+pub enum HeaderCmd {
+    #[command(about = "Change package flags: `Cooked|ContainsScript`, `+Need,-Trash` or `0x...`")]
+    Set {
+        upk_path: PathBuf,
+        #[arg(long)]
+        flags: String,
+        #[arg(long)]
+        force: bool,
+        #[arg(long = "out", short = 'o', value_name = "FILE")]
+        out: Option<PathBuf>,
+        #[arg(long, value_name = "FILE")]
+        emit_delta: Option<PathBuf>,
+    },
+}
+
+fn flag_by_name(name: &str) -> Result<PackageFlags> {
+    PackageFlags::all()
+        .iter_names()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, f)| f)
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown package flag '{name}'"),
+            )
+        })
+}
+
+/// A number replaces the flags, bare names replace them with that set and
+/// `+Name` / `-Name` edit the current value.
+pub fn parse_flags(spec: &str, current: u32) -> Result<u32> {
+    let spec = spec.trim();
+    if let Some(hex) = spec.strip_prefix("0x").or_else(|| spec.strip_prefix("0X")) {
+        return u32::from_str_radix(hex, 16)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("{spec}: {e}")));
+    }
+    if let Ok(v) = spec.parse::<u32>() {
+        return Ok(v);
+    }
+
+    let tokens: Vec<&str> = spec
+        .split([',', '|'])
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .collect();
+    let relative = tokens.iter().filter(|t| t.starts_with(['+', '-'])).count();
+    if relative != 0 && relative != tokens.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "mix of +/- and bare flag names; use one style",
+        ));
+    }
+    let mut v = if relative == 0 { 0 } else { current };
+    for t in tokens {
+        if let Some(n) = t.strip_prefix('+') {
+            v |= flag_by_name(n)?.bits();
+        } else if let Some(n) = t.strip_prefix('-') {
+            v &= !flag_by_name(n)?.bits();
+        } else {
+            v |= flag_by_name(t)?.bits();
+        }
+    }
+    Ok(v)
+}
+
+fn describe(bits: u32) -> String {
+    let names: Vec<&str> = PackageFlags::from_bits_retain(bits)
+        .iter_names()
+        .map(|(n, _)| n)
+        .collect();
+    if names.is_empty() {
+        "-".into()
+    } else {
+        names.join("|")
+    }
+}
+
+/// What the package actually contains, as far as flags care.
+#[derive(Default)]
+struct Contents {
+    script_exports: usize,
+    map_exports: usize,
+}
+
+fn scan_contents(path: &Path) -> Result<Contents> {
+    let lp = open_package_file(path)?;
+    let mut c = Contents::default();
+    for i in 1..=lp.pak.export_table.len() as i32 {
+        match lp.export_class_name(i).as_str() {
+            "Class" | "Function" | "State" | "ScriptStruct" => c.script_exports += 1,
+            "World" | "Level" => c.map_exports += 1,
+            _ => {}
+        }
+    }
+    Ok(c)
+}
+
+/// Errors are combinations the engine can't load; warnings are legal but
+/// likely not what was meant.
+fn check_flags(
+    h: &UpkHeader,
+    contents: &Contents,
+    old: u32,
+    new: u32,
+) -> (Vec<String>, Vec<String>) {
+    let (mut errors, mut warnings) = (Vec::new(), Vec::new());
+    let set = |f: PackageFlags| old & f.bits() == 0 && new & f.bits() != 0;
+    let cleared = |f: PackageFlags| old & f.bits() != 0 && new & f.bits() == 0;
+
+    if cleared(PackageFlags::StoreCompressed) && h.compressed_chunks_count > 0 {
+        errors.push(format!(
+            "clearing StoreCompressed but the summary still lists {} compressed chunk(s); decompress the package first",
+            h.compressed_chunks_count
+        ));
+    }
+    if set(PackageFlags::StoreCompressed) && h.compressed_chunks_count == 0 {
+        errors.push(
+            "setting StoreCompressed without a chunk table; the loader would read plain data as chunks".into(),
+        );
+    }
+    if set(PackageFlags::StoreFullyCompressed) {
+        errors.push(
+            "StoreFullyCompressed describes how the whole file is stored and can't be toggled on a header".into(),
+        );
+    }
+    if set(PackageFlags::ContainsScript) && contents.script_exports == 0 {
+        errors.push(
+            "setting ContainsScript but the package has no class/function/state exports".into(),
+        );
+    }
+    if cleared(PackageFlags::ContainsScript) && contents.script_exports > 0 {
+        warnings.push(format!(
+            "clearing ContainsScript while {} script export(s) remain; script may not be linked on load",
+            contents.script_exports
+        ));
+    }
+    if set(PackageFlags::ContainsMap) && contents.map_exports == 0 {
+        warnings.push("setting ContainsMap but no World/Level export was found".into());
+    }
+    if cleared(PackageFlags::ContainsMap) && contents.map_exports > 0 {
+        warnings.push(
+            "clearing ContainsMap on a package with a World/Level; it won't open as a map".into(),
+        );
+    }
+    if cleared(PackageFlags::Cooked) && h.cooker_ver != 0 {
+        warnings.push(format!(
+            "clearing Cooked on a package saved by cooker v{}; the engine will expect editor-only data that was stripped",
+            h.cooker_ver
+        ));
+    }
+    if set(PackageFlags::Cooked) && h.cooker_ver == 0 {
+        warnings
+            .push("setting Cooked on a package that was never cooked (cooker version 0)".into());
+    }
+    if set(PackageFlags::ServerSideOnly) {
+        warnings.push("ServerSideOnly packages are skipped by clients".into());
+    }
+    if (old ^ new) & PackageFlags::FilterEditorOnly.bits() != 0 {
+        warnings.push(
+            "FilterEditorOnly changes how exports are deserialized; it must match how they were saved".into(),
+        );
+    }
+    let unknown = new & !PackageFlags::all().bits();
+    if unknown != 0 {
+        warnings.push(format!("unknown flag bit(s) 0x{unknown:08X}"));
+    }
+    (errors, warnings)
+}
+
+fn set_flags(
+    src: &Path,
+    spec: &str,
+    force: bool,
+    out: Option<&Path>,
+    emit_delta: Option<&Path>,
+) -> Result<()> {
+    // This command's own --force shadows the global one.
+    if force {
+        sniff::set_force(true);
+    }
+    if is_fully_compressed(src)? {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            format!(
+                "{} is fully compressed; its summary is inside the compressed stream",
+                src.display()
+            ),
+        ));
+    }
+    let h = UpkHeader::read(&mut BufReader::new(File::open(src)?))?;
+    // Summary: tag, versions, header size, folder FString, then the flags.
+    let flags_at = 16 + h.path.len() as u64;
+
+    let new = parse_flags(spec, h.pak_flags)?;
+    let contents = scan_contents(src)?;
+    let (errors, warnings) = check_flags(&h, &contents, h.pak_flags, new);
+
+    println!("  flags  0x{:08X}  {}", h.pak_flags, describe(h.pak_flags));
+    println!("     →   0x{new:08X}  {}", describe(new));
+    for w in &warnings {
+        term::warn("warning", w);
+    }
+    for e in &errors {
+        term::error("error", e);
+    }
+    if !errors.is_empty() && !force {
+        return Err(validation_failed(format!(
+            "{} invalid flag change(s); rerun with --force to write anyway",
+            errors.len()
+        )));
+    }
+    if new == h.pak_flags {
+        println!("Flags unchanged");
+        return Ok(());
+    }
+
+    let mut f = File::open(src)?;
+    f.seek(SeekFrom::Start(flags_at))?;
+    let mut cur = [0u8; 4];
+    f.read_exact(&mut cur)?;
+    drop(f);
+    if u32::from_le_bytes(cur) != h.pak_flags {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("flags not found at 0x{flags_at:X}; summary layout not understood"),
+        ));
+    }
+
+    let dst = match out {
+        Some(o) => o.to_path_buf(),
+        None => {
+            if let Some(bak) = backup_original(src)? {
+                println!("Backup: {}", bak.display());
+            }
+            src.to_path_buf()
+        }
+    };
+    // Patched in a copy and renamed over `dst`, so an interrupted write
+    // never leaves a half-written package.
+    let mut part = dst.as_os_str().to_os_string();
+    part.push(".part");
+    let part = PathBuf::from(part);
+    readonly::copy(src, &part)?;
+    let mut f = OpenOptions::new().write(true).open(&part)?;
+    f.seek(SeekFrom::Start(flags_at))?;
+    f.write_all(&new.to_le_bytes())?;
+    f.sync_all()?;
+    drop(f);
+    readonly::rename(&part, &dst)?;
+    println!("Wrote {}", dst.display());
+    if let Some(d) = emit_delta {
+        delta::emit_delta(&delta::original_for(src), &dst, d)?;
+    }
+    Ok(())
+}
+
+pub fn run(cmd: HeaderCmd) -> Result<()> {
+    match cmd {
+        HeaderCmd::Set {
+            upk_path,
+            flags,
+            force,
+            out,
+            emit_delta,
+        } => set_flags(
+            &upk_path,
+            &flags,
+            force,
+            out.as_deref(),
+            emit_delta.as_deref(),
+        ),
+    }
+}
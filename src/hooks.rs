@@ -0,0 +1,127 @@
+//! Callbacks for applications embedding the library (mod managers, asset
+//! databases) that want to follow extraction and saving as it happens
+//! rather than rescanning the output afterwards:
+//!
+//! ```ignore
+//! struct Log;
+//! impl Hooks for Log {
+//!     fn on_file_written(&self, ev: &FileWritten) {
+//!         println!("{} ← {}", ev.path.display(), ev.object);
+//!     }
+//! }
+//! let id = hooks::register(Arc::new(Log));
+//! upkreader::extract_by_name(/* … */)?;
+//! hooks::unregister(id);
+//! ```
+//!
+//! Hooks run synchronously on the thread doing the work, in registration
+//! order, so a slow one slows the extraction down.
+
+use std::{
+    path::Path,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use crate::{
+    native::{NativePayload, SidecarRole},
+    package::SaveStats,
+    upkprops::Property,
+};
+
+/// An export whose properties and native payload were read during
+/// extraction. Class and struct definitions rendered from the schema
+/// aren't parsed this way and don't show up here.
+pub struct ExportParsed<'a> {
+    /// 1-based export index.
+    pub index: i32,
+    /// `Class Outer.Name`, as `list` prints it.
+    pub object: &'a str,
+    pub class: &'a str,
+    pub props: &'a [Property],
+    pub payload: &'a NativePayload,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// The object's `.uo`.
+    Object,
+    /// A file the `.uo` points at: bulk data, a preview, an info dump.
+    Sidecar(SidecarRole),
+    /// `assets.json` or `object-names.json` in the output root.
+    Manifest,
+}
+
+pub struct FileWritten<'a> {
+    pub path: &'a Path,
+    /// The object the file belongs to; empty for manifests.
+    pub object: &'a str,
+    pub kind: FileKind,
+}
+
+pub struct PackageSaved<'a> {
+    pub path: &'a Path,
+    pub stats: &'a SaveStats,
+}
+
+/// Every method defaults to doing nothing; implement the ones you need.
+pub trait Hooks: Send + Sync {
+    fn on_export_parsed(&self, _ev: &ExportParsed) {}
+
+    fn on_file_written(&self, _ev: &FileWritten) {}
+
+    fn on_package_saved(&self, _ev: &PackageSaved) {}
+}
+
+/// Returned by `register`, to hand back to `unregister`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HookId(u64);
+
+static HOOKS: RwLock<Vec<(HookId, Arc<dyn Hooks>)>> = RwLock::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+pub fn register(hooks: Arc<dyn Hooks>) -> HookId {
+    let id = HookId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    HOOKS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .push((id, hooks));
+    id
+}
+
+/// Returns false when `id` wasn't registered (or was already removed).
+pub fn unregister(id: HookId) -> bool {
+    let mut list = HOOKS.write().unwrap_or_else(|e| e.into_inner());
+    let before = list.len();
+    list.retain(|(i, _)| *i != id);
+    list.len() != before
+}
+
+fn each(f: impl Fn(&dyn Hooks)) {
+    // A snapshot, so a hook may register or unregister without deadlocking.
+    let list: Vec<Arc<dyn Hooks>> = HOOKS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .map(|(_, h)| h.clone())
+        .collect();
+    for h in &list {
+        f(h.as_ref());
+    }
+}
+
+pub(crate) fn export_parsed(ev: &ExportParsed) {
+    each(|h| h.on_export_parsed(ev));
+}
+
+pub(crate) fn file_written(path: &Path, object: &str, kind: FileKind) {
+    each(|h| {
+        h.on_file_written(&FileWritten { path, object, kind });
+    });
+}
+
+pub(crate) fn package_saved(path: &Path, stats: &SaveStats) {
+    each(|h| h.on_package_saved(&PackageSaved { path, stats }));
+}
@@ -0,0 +1,359 @@
+use std::{
+    collections::BTreeMap,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+    time::{Instant, UNIX_EPOCH},
+};
+
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    plan::forced_copies,
+    schemadb::open_package_file,
+    upkpacker::export_path_dotted,
+    utils::{
+        config::config_dir,
+        deadline,
+        hash::{ContentHash, file_hash},
+        pool::{self, par_map},
+        readonly,
+        term::{self, Color, paint},
+        walk::{package_files, rel_key},
+    },
+};
+
+/// Bumped when entries change shape; an index of another version is
+/// rebuilt from scratch.
+const INDEX_VERSION: u32 = 1;
+
+#[derive(Subcommand)]
+pub enum IndexCmd {
+    #[command(about = "Index every package under a game dir; re-parses only changed files")]
+    Build {
+        game_dir: PathBuf,
+        #[arg(long, value_name = "FILE")]
+        index: Option<PathBuf>,
+        #[arg(long, short = 'j')]
+        jobs: Option<usize>,
+    },
+
+    #[command(about = "Find the packages holding an object, by name or dotted path")]
+    Find {
+        game_dir: PathBuf,
+        object: String,
+        #[arg(long, value_name = "FILE")]
+        index: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedExport {
+    pub class: String,
+    /// Dotted path inside the package.
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub size: u64,
+    /// Modification time, nanoseconds since the epoch.
+    pub mtime: u64,
+    pub hash: String,
+    /// Why the package couldn't be read; kept so an unchanged broken file
+    /// isn't retried every run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub p_ver: i16,
+    #[serde(default)]
+    pub l_ver: i16,
+    #[serde(default)]
+    pub flags: u32,
+    #[serde(default)]
+    pub exports: Vec<IndexedExport>,
+    /// Packages imported from.
+    #[serde(default)]
+    pub imports: Vec<String>,
+    /// `Package.Path` of cooked copies of other packages' objects.
+    #[serde(default)]
+    pub forced: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PackageIndex {
+    pub version: u32,
+    /// For display; a name that isn't UTF-8 is stored lossily.
+    pub game_dir: String,
+    /// Path relative to `game_dir`, `/`-separated.
+    pub packages: BTreeMap<String, IndexEntry>,
+}
+
+impl PackageIndex {
+    /// The index file for `game_dir` under the config directory.
+    pub fn default_path(game_dir: &Path) -> Result<PathBuf> {
+        let canon = game_dir
+            .canonicalize()
+            .unwrap_or_else(|_| game_dir.to_path_buf());
+        let mut h = ContentHash::new();
+        h.update(canon.to_string_lossy().as_bytes());
+        let stem = canon
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "root".to_string());
+        Ok(config_dir()?
+            .join("index")
+            .join(format!("{stem}-{}.json", h.hex())))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+        serde_json::from_str(&text)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {e}", path.display())))
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            readonly::create_dir_all(parent)?;
+        }
+        let text = serde_json::to_string(self).map_err(Error::other)?;
+        let mut part = path.as_os_str().to_os_string();
+        part.push(".part");
+        readonly::write(&part, text)?;
+        readonly::rename(&part, path)
+    }
+}
+
+fn index_path(game_dir: &Path, index: Option<&Path>) -> Result<PathBuf> {
+    match index {
+        Some(p) => Ok(p.to_path_buf()),
+        None => PackageIndex::default_path(game_dir),
+    }
+}
+
+fn parse_entry(path: &Path, size: u64, mtime: u64, hash: String) -> IndexEntry {
+    let mut entry = IndexEntry {
+        size,
+        mtime,
+        hash,
+        error: None,
+        p_ver: 0,
+        l_ver: 0,
+        flags: 0,
+        exports: Vec::new(),
+        imports: Vec::new(),
+        forced: Vec::new(),
+    };
+    deadline::arm();
+    let lp = match open_package_file(path) {
+        Ok(lp) => lp,
+        Err(e) => {
+            entry.error = Some(e.to_string());
+            return entry;
+        }
+    };
+    let pak = &lp.pak;
+    entry.p_ver = lp.header.p_ver;
+    entry.l_ver = lp.header.l_ver;
+    entry.flags = lp.header.pak_flags;
+    entry.exports = (1..=pak.export_table.len() as i32)
+        .map(|i| IndexedExport {
+            class: lp.export_class_name(i),
+            path: export_path_dotted(pak, i),
+        })
+        .collect();
+    entry.imports = pak
+        .import_table
+        .iter()
+        .filter(|imp| imp.outer_index == 0)
+        .map(|imp| pak.fname_to_string(&imp.object_name))
+        .collect();
+    entry.imports.sort();
+    entry.imports.dedup();
+    entry.forced = forced_copies(pak)
+        .into_iter()
+        .map(|(_, pkg, below)| format!("{pkg}.{below}"))
+        .collect();
+    entry
+}
+
+enum Outcome {
+    /// Size and mtime match; not even hashed.
+    Unchanged,
+    /// Touched but the same bytes; only the mtime is updated.
+    Rehashed(IndexEntry),
+    Parsed(IndexEntry),
+}
+
+fn update_one(path: &Path, old: Option<&IndexEntry>) -> Result<Outcome> {
+    let meta = std::fs::metadata(path)?;
+    let size = meta.len();
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos() as u64);
+    if old.is_some_and(|o| o.size == size && o.mtime == mtime) {
+        return Ok(Outcome::Unchanged);
+    }
+    let hash = file_hash(path)?;
+    if let Some(o) = old.filter(|o| o.size == size && o.hash == hash) {
+        let mut e = o.clone();
+        e.mtime = mtime;
+        return Ok(Outcome::Rehashed(e));
+    }
+    Ok(Outcome::Parsed(parse_entry(path, size, mtime, hash)))
+}
+
+fn build(root: &Path, index: Option<&Path>, jobs: Option<usize>) -> Result<()> {
+    let started = Instant::now();
+    if !root.is_dir() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{} is not a directory", root.display()),
+        ));
+    }
+    let path = index_path(root, index)?;
+    let fresh = || PackageIndex {
+        version: INDEX_VERSION,
+        game_dir: root.to_string_lossy().to_string(),
+        packages: BTreeMap::new(),
+    };
+    let mut idx = if path.exists() {
+        match PackageIndex::load(&path) {
+            Ok(i) if i.version == INDEX_VERSION => i,
+            Ok(_) => {
+                println!("Index format changed; rebuilding {}", path.display());
+                fresh()
+            }
+            Err(e) => {
+                term::warn("rebuild", format_args!("{e}"));
+                fresh()
+            }
+        }
+    } else {
+        fresh()
+    };
+    idx.game_dir = root.to_string_lossy().to_string();
+
+    let files: Vec<(String, PathBuf)> = package_files(root)
+        .into_iter()
+        .map(|p| (rel_key(root, &p), p))
+        .collect();
+    let jobs = pool::jobs(jobs);
+    let results = par_map(&files, jobs, |(key, p)| {
+        // The deadline is per thread and per package.
+        deadline::arm();
+        update_one(p, idx.packages.get(key))
+    });
+
+    let (mut unchanged, mut rehashed, mut parsed, mut failed) = (0, 0, 0, 0);
+    let mut packages = BTreeMap::new();
+    for ((key, p), r) in files.iter().zip(results) {
+        // Whatever is left in the old index afterwards is gone from disk.
+        let old = idx.packages.remove(key);
+        let entry = match r {
+            Ok(Outcome::Unchanged) => {
+                unchanged += 1;
+                old
+            }
+            Ok(Outcome::Rehashed(e)) => {
+                rehashed += 1;
+                Some(e)
+            }
+            Ok(Outcome::Parsed(e)) => {
+                parsed += 1;
+                if let Some(err) = &e.error {
+                    failed += 1;
+                    term::warn("skip", format_args!("{}: {err}", p.display()));
+                }
+                Some(e)
+            }
+            Err(e) => {
+                failed += 1;
+                term::warn("skip", format_args!("{}: {e}", p.display()));
+                None
+            }
+        };
+        if let Some(e) = entry {
+            packages.insert(key.clone(), e);
+        }
+    }
+    let removed = idx.packages.len();
+    idx.packages = packages;
+    idx.save(&path)?;
+
+    println!(
+        "Indexed {} package(s) in {:.1?} ({jobs} job(s)): {parsed} parsed, {} unchanged, \
+         {rehashed} touched but identical, {removed} removed, {failed} unreadable",
+        files.len(),
+        started.elapsed(),
+        unchanged
+    );
+    println!("Index: {}", paint(Color::Gray, path.display()));
+    Ok(())
+}
+
+/// The index of `root` (or the file `index`) and where it was read from.
+pub fn open(root: &Path, index: Option<&Path>) -> Result<(PathBuf, PackageIndex)> {
+    let path = index_path(root, index)?;
+    let idx = PackageIndex::load(&path).map_err(|e| {
+        Error::new(
+            e.kind(),
+            format!("{e} (run `index build {}` first)", root.display()),
+        )
+    })?;
+    Ok((path, idx))
+}
+
+fn find(root: &Path, object: &str, index: Option<&Path>) -> Result<()> {
+    let (_, idx) = open(root, index)?;
+    let want = object.to_ascii_lowercase();
+    let matches = |p: &str| {
+        let p = p.to_ascii_lowercase();
+        p == want || p.ends_with(&format!(".{want}"))
+    };
+
+    let mut hits = 0usize;
+    for (rel, e) in &idx.packages {
+        for x in e.exports.iter().filter(|x| matches(&x.path)) {
+            hits += 1;
+            // Copies sit under an export named after their package, so
+            // their dotted path is the one `forced` records.
+            let copy = if e.forced.contains(&x.path) {
+                paint(Color::Gray, " (cooked copy)").to_string()
+            } else {
+                String::new()
+            };
+            println!(
+                "{}  {} {}{copy}",
+                paint(Color::Highlight, rel),
+                paint(Color::Cyan, &x.class),
+                x.path
+            );
+        }
+    }
+    if hits == 0 {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("no '{object}' in the index of {}", root.display()),
+        ));
+    }
+    Ok(())
+}
+
+pub fn run(cmd: IndexCmd) -> Result<()> {
+    match cmd {
+        IndexCmd::Build {
+            game_dir,
+            index,
+            jobs,
+        } => build(&game_dir, index.as_deref(), jobs),
+        IndexCmd::Find {
+            game_dir,
+            object,
+            index,
+        } => find(&game_dir, &object, index.as_deref()),
+    }
+}
@@ -0,0 +1,13 @@
+pub mod archive;
+pub mod cache;
+pub mod hooks;
+pub mod native;
+pub mod objects;
+pub mod package;
+pub mod pseudo;
+pub mod schema;
+pub mod schemadb;
+pub mod upkprops;
+pub mod upkreader;
+pub mod utils;
+pub mod versions;
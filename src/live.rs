@@ -0,0 +1,341 @@
+#[cfg(not(target_os = "linux"))]
+compile_error!(
+    "the `live` feature reads /proc/<pid>/mem and is Linux-only (works for Proton/Wine games)"
+);
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{Error, ErrorKind, Result},
+    os::unix::fs::FileExt,
+    path::Path,
+};
+
+use crate::{
+    disasm,
+    schemadb::{LazyPackage, open_package_file},
+    upkreader::ScriptKind,
+    utils::term::{Color, paint},
+};
+
+const SCAN_CHUNK: usize = 16 * 1024 * 1024;
+const MIN_EXACT_BYTES: usize = 8;
+const MAX_HITS: usize = 8;
+const NAME_SAMPLE: usize = 24;
+
+struct Region {
+    start: u64,
+    end: u64,
+}
+
+/// Read-only view of another process' address space.
+pub struct Process {
+    pid: u32,
+    mem: File,
+    regions: Vec<Region>,
+}
+
+impl Process {
+    pub fn attach(pid: u32) -> Result<Self> {
+        let maps = fs::read_to_string(format!("/proc/{pid}/maps"))?;
+        let mut regions = Vec::new();
+        for line in maps.lines() {
+            let mut it = line.split_whitespace();
+            let (Some(range), Some(perms)) = (it.next(), it.next()) else {
+                continue;
+            };
+            let path = it.nth(3).unwrap_or("");
+            if !perms.starts_with('r') || path.starts_with("[v") {
+                continue;
+            }
+            let Some((a, b)) = range.split_once('-') else {
+                continue;
+            };
+            let (Ok(start), Ok(end)) = (u64::from_str_radix(a, 16), u64::from_str_radix(b, 16))
+            else {
+                continue;
+            };
+            regions.push(Region { start, end });
+        }
+        let mem = File::open(format!("/proc/{pid}/mem")).map_err(|e| {
+            Error::new(
+                e.kind(),
+                format!("open /proc/{pid}/mem: {e} (same user + ptrace_scope=0, or run as root)"),
+            )
+        })?;
+        Ok(Self { pid, mem, regions })
+    }
+
+    pub fn readable_bytes(&self) -> u64 {
+        self.regions.iter().map(|r| r.end - r.start).sum()
+    }
+
+    /// Walks every readable mapping in chunks that overlap by `overlap` bytes,
+    /// so matches straddling a chunk border are still seen once.
+    fn scan(&self, overlap: usize, mut f: impl FnMut(u64, &[u8])) {
+        let mut buf = vec![0u8; SCAN_CHUNK + overlap];
+        for r in &self.regions {
+            let mut addr = r.start;
+            while addr < r.end {
+                let want = ((r.end - addr) as usize).min(buf.len());
+                let got = match self.mem.read_at(&mut buf[..want], addr) {
+                    Ok(n) if n > 0 => n,
+                    _ => break,
+                };
+                f(addr, &buf[..got]);
+                if got <= overlap || addr + got as u64 >= r.end {
+                    break;
+                }
+                addr += (got - overlap) as u64;
+            }
+        }
+    }
+}
+
+struct Pattern {
+    bytes: Vec<Option<u8>>,
+    anchor_at: usize,
+    anchor: [u8; 4],
+}
+
+impl Pattern {
+    fn new(bytes: Vec<Option<u8>>) -> Option<Self> {
+        if bytes.iter().filter(|b| b.is_some()).count() < MIN_EXACT_BYTES {
+            return None;
+        }
+        let anchor_at = (0..bytes.len().saturating_sub(3)).find(|&i| {
+            let w = &bytes[i..i + 4];
+            w.iter().all(|b| b.is_some()) && w.iter().any(|b| *b != w[0])
+        })?;
+        let a = &bytes[anchor_at..anchor_at + 4];
+        let anchor = [a[0]?, a[1]?, a[2]?, a[3]?];
+        Some(Self {
+            bytes,
+            anchor_at,
+            anchor,
+        })
+    }
+
+    fn matches(&self, hay: &[u8]) -> bool {
+        hay.len() >= self.bytes.len()
+            && self
+                .bytes
+                .iter()
+                .zip(hay)
+                .all(|(p, h)| p.is_none_or(|b| b == *h))
+    }
+}
+
+/// Finds every pattern in one pass over the process memory.
+fn find_all(proc_: &Process, patterns: &[Pattern]) -> Vec<Vec<u64>> {
+    let mut by_anchor: HashMap<[u8; 4], Vec<usize>> = HashMap::new();
+    let mut filter = vec![false; 1 << 16];
+    for (i, p) in patterns.iter().enumerate() {
+        by_anchor.entry(p.anchor).or_default().push(i);
+        filter[u16::from_le_bytes([p.anchor[0], p.anchor[1]]) as usize] = true;
+    }
+    let overlap = patterns.iter().map(|p| p.bytes.len()).max().unwrap_or(0);
+    let mut hits = vec![Vec::new(); patterns.len()];
+
+    proc_.scan(overlap, |base, buf| {
+        for i in 0..buf.len().saturating_sub(3) {
+            if !filter[u16::from_le_bytes([buf[i], buf[i + 1]]) as usize] {
+                continue;
+            }
+            let key = [buf[i], buf[i + 1], buf[i + 2], buf[i + 3]];
+            let Some(ids) = by_anchor.get(&key) else {
+                continue;
+            };
+            for &id in ids {
+                let p = &patterns[id];
+                let Some(start) = i.checked_sub(p.anchor_at) else {
+                    continue;
+                };
+                let addr = base + start as u64;
+                let h: &mut Vec<u64> = &mut hits[id];
+                if h.len() < MAX_HITS && !h.contains(&addr) && p.matches(&buf[start..]) {
+                    h.push(addr);
+                }
+            }
+        }
+    });
+    hits
+}
+
+fn function_patterns(lp: &LazyPackage, filter: Option<&str>) -> Vec<(String, Vec<Option<u8>>)> {
+    let mut out = Vec::new();
+    for (idx, kind) in lp.pak.functions() {
+        if kind != ScriptKind::Function {
+            continue;
+        }
+        let path = lp.pak.get_export_path_name(idx);
+        if let Some(f) = filter
+            && !path.to_lowercase().contains(&f.to_lowercase())
+        {
+            continue;
+        }
+        let Ok(blob) = lp.export_blob(idx) else {
+            continue;
+        };
+        let Some(span) = disasm::script_span(blob, kind, &lp.pak, lp.header.p_ver) else {
+            continue;
+        };
+        let start = span.offset_in_blob as usize;
+        let Some(code) = blob.get(start..start + span.disk_size as usize) else {
+            continue;
+        };
+        let dis = disasm::disassemble(code, &lp.pak, lp.header.p_ver);
+        out.push((path, dis.pattern));
+    }
+    out
+}
+
+fn report_names(proc_: &Process, lp: &LazyPackage) {
+    let mut sample: Vec<&String> = lp
+        .pak
+        .name_table
+        .iter()
+        .filter(|n| n.len() >= 10 && n.is_ascii())
+        .collect();
+    sample.sort_by_key(|n| std::cmp::Reverse(n.len()));
+    sample.truncate(NAME_SAMPLE);
+    if sample.is_empty() {
+        println!("Names: no distinctive names to look for");
+        return;
+    }
+
+    let patterns: Vec<Pattern> = sample
+        .iter()
+        .filter_map(|n| {
+            let mut b: Vec<Option<u8>> = n.bytes().map(Some).collect();
+            b.push(Some(0));
+            Pattern::new(b)
+        })
+        .collect();
+    let hits = find_all(proc_, &patterns);
+    let found: Vec<u64> = hits.iter().filter_map(|h| h.first().copied()).collect();
+    if found.is_empty() {
+        println!(
+            "Names: none of {} sampled name(s) found — package is probably not loaded",
+            patterns.len()
+        );
+        return;
+    }
+    let lo = found.iter().min().copied().unwrap_or(0);
+    let hi = found.iter().max().copied().unwrap_or(0);
+    println!(
+        "Names: {}/{} sampled name(s) resident, spread over 0x{lo:X}..0x{hi:X}",
+        found.len(),
+        patterns.len()
+    );
+}
+
+pub fn live_cmd(
+    pid: u32,
+    upk_path: &Path,
+    original: Option<&Path>,
+    function: Option<&str>,
+    verbose: bool,
+) -> Result<()> {
+    let proc_ = Process::attach(pid)?;
+    println!(
+        "Attached read-only to pid {} ({} MiB readable)",
+        proc_.pid,
+        proc_.readable_bytes() / (1024 * 1024)
+    );
+
+    let patched = open_package_file(upk_path)?;
+    report_names(&proc_, &patched);
+
+    let mut funcs = function_patterns(&patched, function);
+    let originals: HashMap<String, Vec<Option<u8>>> = match original {
+        Some(o) => function_patterns(&open_package_file(o)?, function)
+            .into_iter()
+            .collect(),
+        None => HashMap::new(),
+    };
+    if original.is_some() {
+        funcs.retain(|(path, pat)| originals.get(path) != Some(pat));
+        println!("{} function(s) differ from the original", funcs.len());
+    }
+    if funcs.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            "no function bytecode to look for",
+        ));
+    }
+
+    let mut patterns = Vec::new();
+    let mut slots = Vec::new();
+    let mut too_short = 0usize;
+    for (path, pat) in &funcs {
+        let p = Pattern::new(pat.clone()).map(|p| {
+            patterns.push(p);
+            patterns.len() - 1
+        });
+        let o = originals
+            .get(path)
+            .cloned()
+            .and_then(Pattern::new)
+            .map(|p| {
+                patterns.push(p);
+                patterns.len() - 1
+            });
+        if p.is_none() {
+            too_short += 1;
+        }
+        slots.push((path, p, o));
+    }
+    let hits = find_all(&proc_, &patterns);
+
+    let (mut applied, mut missing) = (0usize, 0usize);
+    for (path, p, o) in slots {
+        let Some(p) = p else {
+            if verbose {
+                println!(
+                    "  {}",
+                    paint(Color::Gray, format!("{path}: too short to identify"))
+                );
+            }
+            continue;
+        };
+        let new_at = &hits[p];
+        let old_at = o.map(|o| hits[o].as_slice()).unwrap_or(&[]);
+        match (new_at.first(), old_at.first()) {
+            (Some(a), None) => {
+                applied += 1;
+                let tag = if original.is_some() {
+                    "applied"
+                } else {
+                    "resident"
+                };
+                println!("  {:11} {path} @ 0x{a:X}", paint(Color::Green, tag));
+            }
+            (None, Some(b)) => {
+                missing += 1;
+                println!(
+                    "  {} {path}: engine still runs the original @ 0x{b:X}",
+                    paint(Color::Red, "not applied")
+                );
+            }
+            (Some(a), Some(b)) => println!(
+                "  {}   {path}: patched @ 0x{a:X}, original @ 0x{b:X}",
+                paint(Color::Yellow, "ambiguous")
+            ),
+            (None, None) => {
+                if verbose || original.is_some() {
+                    println!("  {} {path}", paint(Color::Gray, "not resident"));
+                }
+            }
+        }
+    }
+    println!(
+        "\nSummary: {applied} {}, {missing} not applied, {too_short} too short to identify",
+        if original.is_some() {
+            "applied"
+        } else {
+            "resident"
+        }
+    );
+    Ok(())
+}
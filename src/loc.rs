@@ -0,0 +1,362 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{BufWriter, Error, ErrorKind, Result, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use clap::Subcommand;
+
+use crate::{
+    native::Convert,
+    package::Package,
+    report::csv_escape,
+    schemadb::{LazyPackage, SchemaDb, open_package_file},
+    upkpacker::export_path_dotted,
+    upkprops::{Property, PropertyValue},
+    upkreader::UPKPak,
+    utils::{
+        readonly,
+        term::{self, Color, paint},
+        walk::{is_package, package_files, stem},
+    },
+};
+
+const LOC_TAG: &str = "_LOC_";
+
+#[derive(Subcommand)]
+pub enum LocCmd {
+    #[command(about = "List packages with their _LOC_<LANG> companions")]
+    List { game_dir: PathBuf },
+
+    #[command(about = "Clone a localization package (e.g. Foo_LOC_INT) into a new language")]
+    Clone {
+        package: PathBuf,
+        lang: String,
+        #[arg(long = "out", short = 'o', value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+
+    #[command(about = "CSV of every string property: object.path, source text, localized text")]
+    Review {
+        source: PathBuf,
+        localized: PathBuf,
+        #[arg(long = "out", short = 'o', value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+}
+
+/// `Foo_LOC_INT` → `("Foo", "INT")`.
+pub fn split_loc_stem(stem: &str) -> Option<(&str, &str)> {
+    let i = stem.to_ascii_uppercase().rfind(LOC_TAG)?;
+    let (base, lang) = (&stem[..i], &stem[i + LOC_TAG.len()..]);
+    (!base.is_empty() && !lang.is_empty() && lang.chars().all(|c| c.is_ascii_alphanumeric()))
+        .then_some((base, lang))
+}
+
+/// Base package stem, whether `pkg` is the base itself or one of its
+/// localization packages.
+fn base_stem(pkg: &Path) -> String {
+    let stem = stem(pkg);
+    match split_loc_stem(&stem) {
+        Some((base, _)) => base.to_string(),
+        None => stem,
+    }
+}
+
+/// `<base>_LOC_<lang>` next to `pkg`, matched case-insensitively.
+pub fn companion(pkg: &Path, lang: &str) -> Option<PathBuf> {
+    let want = format!("{}{LOC_TAG}{lang}", base_stem(pkg));
+    let dir = pkg
+        .parent()
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|e| e.path())
+        .find(|p| is_package(p) && stem(p).eq_ignore_ascii_case(&want))
+}
+
+fn contains_object(pak: &UPKPak, object: &str) -> bool {
+    (1..=pak.export_table.len() as i32).any(|i| {
+        let full = pak.get_export_full_name(i);
+        full.contains(object) || UPKPak::ue_name_to_path(&full).contains(object)
+    })
+}
+
+/// `extract --lang`: localized objects come from the companion package,
+/// everything else from the base one.
+pub fn extract_localized(
+    base: &Path,
+    objects: &[&str],
+    output_dir: &Path,
+    lang: &str,
+    game_root: Option<&Path>,
+    verbose: bool,
+    convert: &[Convert],
+) -> Result<()> {
+    let loc = companion(base, lang);
+    if loc.is_none() {
+        term::warn(
+            "loc",
+            format_args!(
+                "no {}{LOC_TAG}{} next to {}, using it as is",
+                base_stem(base),
+                lang.to_ascii_uppercase(),
+                base.display()
+            ),
+        );
+    }
+    let is_loc_input = split_loc_stem(&stem(base)).is_some();
+
+    if objects.is_empty() {
+        if !is_loc_input || loc.is_none() {
+            crate::extract_file(base, &[], output_dir, game_root, verbose, convert)?;
+        }
+        if let Some(l) = &loc {
+            println!("Localized companion: {}", l.display());
+            crate::extract_file(l, &[], output_dir, game_root, verbose, convert)?;
+        }
+        return Ok(());
+    }
+
+    let (localized, rest): (Vec<&str>, Vec<&str>) = match &loc {
+        Some(l) => {
+            let lp = open_package_file(l)?;
+            objects.iter().partition(|o| contains_object(&lp.pak, o))
+        }
+        None => (Vec::new(), objects.to_vec()),
+    };
+    if let (Some(l), false) = (&loc, localized.is_empty()) {
+        println!("Using localized {}", l.display());
+        crate::extract_file(l, &localized, output_dir, game_root, verbose, convert)?;
+    }
+    if !rest.is_empty() {
+        crate::extract_file(base, &rest, output_dir, game_root, verbose, convert)?;
+    }
+    Ok(())
+}
+
+fn list(root: &Path) -> Result<()> {
+    let mut bases: BTreeMap<String, (Option<PathBuf>, Vec<String>)> = BTreeMap::new();
+    for p in package_files(root) {
+        let stem = stem(&p);
+        match split_loc_stem(&stem) {
+            Some((base, lang)) => bases
+                .entry(base.to_ascii_lowercase())
+                .or_default()
+                .1
+                .push(lang.to_ascii_uppercase()),
+            None => bases.entry(stem.to_ascii_lowercase()).or_default().0 = Some(p),
+        }
+    }
+
+    let mut paired = 0usize;
+    for (base, (path, langs)) in &bases {
+        if langs.is_empty() {
+            continue;
+        }
+        paired += 1;
+        match path {
+            Some(p) => println!(
+                "  {}  [{}]",
+                p.strip_prefix(root).unwrap_or(p).display(),
+                langs.join(", ")
+            ),
+            None => println!(
+                "  {} (no base package)  [{}]",
+                paint(Color::Yellow, base),
+                langs.join(", ")
+            ),
+        }
+    }
+    println!("{paired} package(s) with localization companions");
+    Ok(())
+}
+
+fn clone(src: &Path, lang: &str, out: Option<&Path>) -> Result<()> {
+    let stem = stem(src);
+    let Some((base, from)) = split_loc_stem(&stem) else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{stem} is not a localization package (<Name>{LOC_TAG}<LANG>)"),
+        ));
+    };
+    let lang = lang.to_ascii_uppercase();
+    let new_stem = format!("{base}{LOC_TAG}{lang}");
+    let out = match out {
+        Some(o) => o.to_path_buf(),
+        None => {
+            let ext = src.extension().map(|e| e.to_string_lossy().to_string());
+            src.with_file_name(match ext {
+                Some(e) => format!("{new_stem}.{e}"),
+                None => new_stem.clone(),
+            })
+        }
+    };
+    if out.exists() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} already exists", out.display()),
+        ));
+    }
+
+    // The package object and anything else carrying the old language tag is
+    // renamed; the rest is kept for translators to edit.
+    let mut pkg = Package::open(src)?;
+    let old_tag = format!("{LOC_TAG}{from}").to_ascii_uppercase();
+    let mut renamed = 0usize;
+    for n in &mut pkg.names {
+        if n.name.eq_ignore_ascii_case(&stem) {
+            n.name = new_stem.clone();
+            renamed += 1;
+        } else if let Some(i) = n.name.to_ascii_uppercase().rfind(&old_tag)
+            && i + old_tag.len() == n.name.len()
+        {
+            n.name = format!("{}{LOC_TAG}{lang}", &n.name[..i]);
+            renamed += 1;
+        }
+    }
+    pkg.save(&out)?;
+    println!(
+        "{} → {} ({renamed} name(s) retagged {from} → {lang})",
+        src.display(),
+        out.display()
+    );
+    Ok(())
+}
+
+fn collect_strings(prefix: &str, props: &[Property], out: &mut BTreeMap<String, String>) {
+    for p in props {
+        let key = if p.array_index > 0 {
+            format!("{prefix}.{}[{}]", p.name, p.array_index)
+        } else {
+            format!("{prefix}.{}", p.name)
+        };
+        collect_value(&key, &p.value, out);
+    }
+}
+
+fn collect_value(key: &str, v: &PropertyValue, out: &mut BTreeMap<String, String>) {
+    match v {
+        PropertyValue::String(s) => {
+            out.insert(key.to_string(), s.clone());
+        }
+        PropertyValue::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                collect_value(&format!("{key}[{i}]"), item, out);
+            }
+        }
+        PropertyValue::Struct(fields) => collect_strings(key, fields, out),
+        PropertyValue::AtomicStruct(fields) => {
+            for (name, fv) in fields {
+                collect_value(&format!("{key}.{name}"), fv, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `object.path.Property[i]` → text for every string property in the
+/// package. Exports whose properties don't parse are counted, not fatal.
+fn string_table(lp: &LazyPackage, db: Option<&SchemaDb>) -> (BTreeMap<String, String>, usize) {
+    let mut out = BTreeMap::new();
+    let mut failed = 0;
+    for idx in 1..=lp.pak.export_table.len() as i32 {
+        match lp.export_props(idx, db) {
+            Ok((props, _)) => collect_strings(&export_path_dotted(&lp.pak, idx), &props, &mut out),
+            Err(_) => failed += 1,
+        }
+    }
+    (out, failed)
+}
+
+fn review_status(src: Option<&String>, loc: Option<&String>) -> &'static str {
+    match (src, loc) {
+        (Some(_), None) => "missing_localized",
+        (None, Some(_)) => "missing_source",
+        (Some(s), Some(l)) if s.is_empty() && l.is_empty() => "empty",
+        (Some(_), Some(l)) if l.is_empty() => "empty_localized",
+        (Some(s), Some(_)) if s.is_empty() => "empty_source",
+        (Some(s), Some(l)) if s == l => "untranslated",
+        _ => "ok",
+    }
+}
+
+fn review(
+    source: &Path,
+    localized: &Path,
+    out: Option<&Path>,
+    game_root: Option<&Path>,
+    verbose: bool,
+) -> Result<()> {
+    let db = match game_root.filter(|g| !g.as_os_str().is_empty()) {
+        Some(gr) => Some(SchemaDb::new(gr)?.with_verbose(verbose)),
+        None => None,
+    };
+    let mut tables = Vec::new();
+    for path in [source, localized] {
+        let lp = Rc::new(open_package_file(path)?);
+        if let Some(d) = &db {
+            d.inject_package(lp.clone());
+        }
+        let (t, failed) = string_table(&lp, db.as_ref());
+        if failed > 0 {
+            term::warn(
+                "loc",
+                format_args!(
+                    "{}: {failed} export(s) without readable properties",
+                    path.display()
+                ),
+            );
+        }
+        tables.push(t);
+    }
+    let (src, loc) = (&tables[0], &tables[1]);
+    let keys: BTreeSet<&String> = src.keys().chain(loc.keys()).collect();
+
+    let mut w: Box<dyn Write> = match out {
+        Some(o) => Box::new(BufWriter::new(readonly::create(o)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    writeln!(w, "object.path,source,localized,status")?;
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for k in &keys {
+        let (s, l) = (src.get(*k), loc.get(*k));
+        let status = review_status(s, l);
+        *counts.entry(status).or_default() += 1;
+        writeln!(
+            w,
+            "{},{},{},{status}",
+            csv_escape(k),
+            csv_escape(s.map(String::as_str).unwrap_or("")),
+            csv_escape(l.map(String::as_str).unwrap_or(""))
+        )?;
+    }
+    w.flush()?;
+
+    let summary: Vec<String> = counts.iter().map(|(s, n)| format!("{n} {s}")).collect();
+    eprintln!(
+        "loc review: {} string(s){}",
+        keys.len(),
+        if summary.is_empty() {
+            String::new()
+        } else {
+            format!(": {}", summary.join(", "))
+        }
+    );
+    Ok(())
+}
+
+pub fn run(cmd: LocCmd, game_root: Option<&Path>, verbose: bool) -> Result<()> {
+    match cmd {
+        LocCmd::List { game_dir } => list(&game_dir),
+        LocCmd::Clone { package, lang, out } => clone(&package, &lang, out.as_deref()),
+        LocCmd::Review {
+            source,
+            localized,
+            out,
+        } => review(&source, &localized, out.as_deref(), game_root, verbose),
+    }
+}
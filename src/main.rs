@@ -1,162 +1,178 @@
 use crate::upkreader::{UPKPak, UpkHeader, get_obj_props};
-use clap::{Parser, Subcommand};
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
 use std::{
-    fs::{self, File},
-    io::{BufReader, BufWriter, Cursor, Read, Result, Seek, SeekFrom, Write},
-    path::Path,
+    fs,
+    io::{BufWriter, Cursor, Read, Result, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Instant,
 };
 
 use self::{
     types::font::{FontConfig, create_font_blobs, create_font_upk},
-    utils::decompress::{CompressionMethod, upk_decompress},
+    utils::{
+        compress::Tuning,
+        decompress::read_package_image,
+        readonly,
+        spill::PackageBytes,
+        stats::{self, Phase},
+        term,
+        walk::stem,
+    },
+};
+use ue3_tools::{
+    archive, native, package, pseudo, schema, schemadb, upkprops, upkreader, utils, versions,
 };
 
-mod native;
-mod pseudo;
+mod assert;
+mod cas;
+mod changes;
+mod chunks;
+mod compress;
+mod crc;
+mod defrag;
+mod delta;
+mod disasm;
+mod doc;
+mod exit;
+mod header;
+mod index;
+#[cfg(feature = "live")]
+mod live;
+mod loc;
+mod merge;
+mod names;
+mod offsets;
+mod opstats;
+mod orphans;
+mod patch;
+mod peek;
+mod plan;
+mod profiles;
+mod propagate;
 mod pseudo_parse;
-mod schema;
-mod schemadb;
+mod report;
+mod roundtrip;
+mod savegame;
+mod selftest;
+mod serve;
+mod split;
+mod symbolicate;
+mod symbols;
+mod table;
 mod types;
 mod ui;
 mod upkpacker;
-mod upkprops;
-mod upkreader;
-mod utils;
-mod versions;
-
-fn upk_header_cursor(path: &str) -> Result<(Cursor<Vec<u8>>, upkreader::UpkHeader)> {
-    let path = Path::new(path);
-    let file = File::open(path)?;
-    let mut reader = BufReader::new(file);
-
-    let filesize = reader.seek(SeekFrom::End(0))?;
-    reader.seek(SeekFrom::Start(0))?;
-
-    let header = UpkHeader::read(&mut reader)?;
-    println!("{}", header);
-
-    if header.compression_method == CompressionMethod::None || header.compressed_chunks_count == 0 {
-        reader.seek(SeekFrom::Start(0))?;
-        let mut buf = Vec::with_capacity(filesize as usize);
-        reader.read_to_end(&mut buf)?;
-        return Ok((Cursor::new(buf), header));
-    }
-
-    println!("File is compressed, decompressing in memory...");
-
-    let mut cloned_header = header.clone();
-    cloned_header.compression_method = CompressionMethod::None;
-    cloned_header.compressed_chunks_count = 0;
-    cloned_header.compressed_chunks.clear();
-    cloned_header.pak_flags = header.pak_flags & !upkreader::PackageFlags::StoreCompressed.bits();
-
-    let mut chunks = header.compressed_chunks.clone();
-    chunks.sort_by_key(|c| c.decompressed_offset);
-
-    let dec_data = upk_decompress(&mut reader, header.compression_method, &chunks)
-        .expect("Decompression error");
-
-    let dec_total = chunks
-        .iter()
-        .zip(dec_data.iter())
-        .map(|(c, d)| c.decompressed_offset as usize + d.len())
-        .max()
-        .unwrap_or(0);
-
-    let mut buf: Vec<u8> = Vec::with_capacity(dec_total.max(filesize as usize));
-    {
-        let mut w = std::io::Cursor::new(&mut buf);
-        cloned_header.write(&mut w)?;
-    }
-
-    for (i, dec) in dec_data.iter().enumerate() {
-        if i != 0 {
-            let prev = chunks[i - 1].compressed_offset + chunks[i - 1].compressed_size;
-            let gap = chunks[i].compressed_offset.saturating_sub(prev);
-            if gap > 0 {
-                reader.seek(SeekFrom::Start(prev as u64))?;
-                let mut gap_buf = vec![0u8; gap as usize];
-                reader.read_exact(&mut gap_buf)?;
-                buf.extend_from_slice(&gap_buf);
-            }
-        }
-        let target = chunks[i].decompressed_offset as usize;
-        if buf.len() < target {
-            buf.resize(target, 0);
-        } else if buf.len() > target {
-            buf[target..target + dec.len()].copy_from_slice(dec);
-            continue;
+mod validate;
+mod verify;
+mod workspace;
+
+fn upk_header_cursor(
+    path: impl AsRef<Path>,
+) -> Result<(Cursor<PackageBytes>, upkreader::UpkHeader)> {
+    let image = read_package_image(path.as_ref())?;
+    println!("{}", image.raw_header);
+
+    if image.was_compressed() {
+        if image.bytes.is_mapped() {
+            println!("File is compressed, decompressed to a temp file (over --mem-budget)");
+        } else {
+            println!("File is compressed, decompressed in memory");
         }
-        buf.extend_from_slice(dec);
-    }
-
-    let last_compressed_end = chunks
-        .last()
-        .map(|c| (c.compressed_offset + c.compressed_size) as u64)
-        .unwrap_or(0);
-    if filesize > last_compressed_end {
-        reader.seek(SeekFrom::Start(last_compressed_end))?;
-        let mut tail = Vec::with_capacity((filesize - last_compressed_end) as usize);
-        reader.read_to_end(&mut tail)?;
-        buf.extend_from_slice(&tail);
     }
 
-    Ok((Cursor::new(buf), cloned_header))
+    Ok((Cursor::new(image.bytes), image.header))
 }
 
-fn getlist(path: &str) -> Result<()> {
-    let (cursor, header): (Cursor<Vec<u8>>, upkreader::UpkHeader) = upk_header_cursor(path)?;
-    let mut cur: Cursor<&Vec<u8>> = Cursor::new(cursor.get_ref());
+fn getlist(path: &Path) -> Result<()> {
+    let (cursor, header): (Cursor<PackageBytes>, upkreader::UpkHeader) = upk_header_cursor(path)?;
+    let mut cur: Cursor<&PackageBytes> = Cursor::new(cursor.get_ref());
 
     let pak = UPKPak::parse_upk(&mut cur, &header)?;
     let list = upkreader::list_full_obj_paths(&pak);
-    for (i, path) in list.iter().enumerate() {
-        println!("#{} {}", i, path);
+    let rows: Vec<(&str, &str)> = list
+        .iter()
+        .map(|full| full.split_once(' ').unwrap_or(("", full)))
+        .collect();
+    let iw = list.len().saturating_sub(1).to_string().len() + 1;
+    let cw = term::column_width(rows.iter().map(|(class, _)| *class));
+    for (i, (class, path)) in rows.iter().enumerate() {
+        println!(
+            "{:<iw$} {:<cw$} {path}",
+            term::paint(term::Color::Gray, format!("#{i}")),
+            term::paint(term::Color::Cyan, class)
+        );
     }
 
     Ok(())
 }
 
-fn dump_names(upk_path: &str, mut output_path: &str) -> Result<()> {
-    if output_path.is_empty() {
-        output_path = "names_table.txt";
-    }
+fn dump_names(upk_path: &Path, output_path: Option<&Path>, long: bool) -> Result<()> {
+    let output_path = output_path.unwrap_or(Path::new("names_table.txt"));
 
-    let (cursor, header): (Cursor<Vec<u8>>, upkreader::UpkHeader) = upk_header_cursor(upk_path)?;
-    let mut cur: Cursor<&Vec<u8>> = Cursor::new(cursor.get_ref());
+    let (cursor, header): (Cursor<PackageBytes>, upkreader::UpkHeader) =
+        upk_header_cursor(upk_path)?;
+    let mut cur: Cursor<&PackageBytes> = Cursor::new(cursor.get_ref());
     cur.seek(SeekFrom::Start(header.name_offset as u64))?;
 
     println!("Names: (count = {})", header.name_count);
 
-    let nt_file = File::create(Path::new(output_path))?;
+    let _t = stats::time(Phase::Output);
+    let nt_file = readonly::create(output_path)?;
     let mut writer = BufWriter::new(nt_file);
 
     for i in 0..header.name_count {
         let s = upkreader::read_name(&mut cur)?;
-        println!("Name[{}]: {}", i, s.name);
+        if long {
+            println!(
+                "Name[{}]: {:<40} {:08X}:{:08X}  {}",
+                i,
+                s.name,
+                s.flags >> 32,
+                s.flags as u32,
+                term::paint(term::Color::Gray, versions::format_name_flags(s.flags))
+            );
+        } else {
+            println!("Name[{}]: {}", i, s.name);
+        }
         writeln!(writer, "{}", s.name)?;
     }
 
     Ok(())
 }
 
+/// Object paths from `--list-file`: one per line, blank lines and `#`
+/// comments skipped.
+fn read_list_file(path: &Path) -> Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Extracts `paths` (everything when empty) from one parse of the package.
 fn extract_file(
-    upk_path: &str,
-    path: &str,
-    mut output_dir: &str,
-    all: bool,
-    game_root: Option<&str>,
+    upk_path: &Path,
+    paths: &[&str],
+    mut output_dir: &Path,
+    game_root: Option<&Path>,
     verbose: bool,
+    convert: &[native::Convert],
 ) -> Result<()> {
-    if output_dir.is_empty() {
-        output_dir = "output";
+    if output_dir.as_os_str().is_empty() {
+        output_dir = Path::new("output");
     }
 
-    let output_dir_path = Path::new(output_dir);
+    let filename = upk_path.file_stem().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{}: no file name", upk_path.display()),
+        )
+    })?;
 
-    let filename = Path::new(upk_path).file_stem().unwrap();
-
-    let pbuf = output_dir_path.join(filename);
+    let pbuf = output_dir.join(filename);
     let dir_path: &Path = pbuf.as_path();
 
     let (mut cursor, header) = upk_header_cursor(upk_path)?;
@@ -164,16 +180,16 @@ fn extract_file(
     let up = UPKPak::parse_upk(&mut cur, &header)?;
 
     if !dir_path.exists() {
-        std::fs::create_dir_all(dir_path)?;
+        readonly::create_dir_all(dir_path)?;
     }
 
     let db = match game_root {
-        Some(gr) if !gr.is_empty() => {
-            let db = schemadb::SchemaDb::new(Path::new(gr))?.with_verbose(verbose);
+        Some(gr) if !gr.as_os_str().is_empty() => {
+            let db = schemadb::SchemaDb::new(gr)?.with_verbose(verbose);
             let stem_lc = filename.to_string_lossy().to_lowercase();
             let lp = std::rc::Rc::new(schemadb::LazyPackage {
                 stem_lc: stem_lc.clone(),
-                path: Path::new(upk_path).to_path_buf(),
+                path: upk_path.to_path_buf(),
                 bytes: cursor.get_ref().clone(),
                 header: header.clone(),
                 pak: up.clone(),
@@ -188,31 +204,31 @@ fn extract_file(
     upkreader::extract_by_name(
         &mut cursor,
         &up,
-        path,
+        paths,
         dir_path,
-        all,
         header.p_ver,
         db.as_ref(),
         &stem_lc,
+        convert,
     )?;
     Ok(())
 }
 
-fn pack_upk(_ron_path: &str) -> Result<()> {
+fn pack_upk(_ron_path: &Path) -> Result<()> {
     unimplemented!("For now");
 }
 
-fn print_obj_elements(ron_path: &str, path: &str) -> Result<()> {
-    if path.is_empty() {
+fn print_obj_elements(ron_path: &Path, path: &Path) -> Result<()> {
+    if path.as_os_str().is_empty() {
         panic!("No object file provided");
     }
 
-    if ron_path.is_empty() {
+    if ron_path.as_os_str().is_empty() {
         panic!("No `.ron` file provided");
     }
 
-    let ron_file =
-        fs::read_to_string(ron_path).unwrap_or_else(|_| panic!("File `{}` not found", ron_path));
+    let ron_file = fs::read_to_string(ron_path)
+        .unwrap_or_else(|_| panic!("File `{}` not found", ron_path.display()));
     let ron_data: (String, String, UpkHeader, UPKPak) =
         ron::from_str(&ron_file).expect("RON Error");
 
@@ -229,11 +245,46 @@ fn print_obj_elements(ron_path: &str, path: &str) -> Result<()> {
 #[derive(Parser)]
 #[command(name = "ue3-tools")]
 #[command(about = "Unreal3 upk stuff")]
+#[command(
+    after_help = "Exit codes: 0 success, 1 other failure, 2 not found, 3 parse error, \
+                  4 unsupported, 5 validation failed\n\
+                  Read-only commands also take packages inside mod archives: \
+                  mod.zip://Path/To/Package.upk (7z needs --features sevenz)"
+)]
 struct Cli {
     #[arg(long, global = true)]
-    game_root: Option<String>,
+    game_root: Option<PathBuf>,
     #[arg(short, long, global = true)]
     verbose: bool,
+    #[arg(long, global = true, value_name = "SIZE")]
+    mem_budget: Option<String>,
+    /// Abort parsing after this long (30s, 500ms, 2m); per package in
+    /// batch scans.
+    #[arg(long, global = true, value_name = "DURATION")]
+    deadline: Option<String>,
+    #[arg(long, global = true)]
+    force: bool,
+    #[arg(long, global = true)]
+    no_color: bool,
+    /// Write the lossy/heuristic decisions made during the run, with
+    /// their codes, as JSON (`-` for stdout).
+    #[arg(long, global = true, value_name = "FILE")]
+    warnings_json: Option<PathBuf>,
+    /// Override a sanity limit, e.g. max-count=4M; repeatable. Keys:
+    /// max-count, max-archive-array, max-mips, max-script-depth,
+    /// max-struct-depth, max-children, max-class-chain, native-scan-limit.
+    /// Defaults come from heuristics.toml in the config directory.
+    #[arg(long = "heuristic", global = true, value_name = "KEY=VALUE")]
+    heuristics: Vec<String>,
+    /// Refuse every write except to the outputs named on the command line
+    /// (-o, --out-dir, --json, ...), so an original game install can't be
+    /// touched; also UE3_TOOLS_READONLY=1.
+    #[arg(long, global = true)]
+    read_only: bool,
+    /// Print wall time per phase (decompress, parse tables, parse
+    /// properties, write output) and peak RSS to stderr when done.
+    #[arg(long, global = true)]
+    stats: bool,
     #[command(subcommand)]
     command: Commands,
 }
@@ -242,51 +293,148 @@ struct Cli {
 enum Commands {
     #[command(about = "Print header info of upk file")]
     UpkHeader {
-        path: String,
+        path: PathBuf,
     },
 
     Decompress {
-        path: String,
+        path: PathBuf,
+    },
+
+    #[command(about = "Compress a package (StoreCompressed), chunks spread over worker threads")]
+    Compress {
+        path: PathBuf,
+        #[arg(long = "out", short = 'o', value_name = "FILE")]
+        out: Option<PathBuf>,
+        #[arg(long, value_enum, default_value = "lzo")]
+        method: compress::Method,
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..=9))]
+        level: Option<u32>,
+        #[arg(long, conflicts_with = "level")]
+        fast: bool,
+        #[arg(long, short = 'j')]
+        jobs: Option<usize>,
+    },
+
+    #[command(about = "Export / re-import single compressed chunks")]
+    Chunks {
+        #[command(subcommand)]
+        action: chunks::ChunksCmd,
+    },
+
+    #[command(about = "Edit package summary fields")]
+    Header {
+        #[command(subcommand)]
+        action: header::HeaderCmd,
     },
 
     #[command(about = "Print elements in object")]
     Elements {
-        ron_path: String,
-        path: String,
+        ron_path: PathBuf,
+        path: PathBuf,
     },
 
     #[command(about = "Print list of objects in upk file")]
     List {
-        path: String,
+        path: PathBuf,
     },
 
     #[command(about = "Print or extract names in upk file")]
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Names {
-        path: String,
-        output_path: Option<String>,
+        #[arg(required = true)]
+        path: Option<PathBuf>,
+        output_path: Option<PathBuf>,
+        /// Also print each name's flags: the raw n_fh/n_fl pair and the
+        /// RF_ bits it holds.
+        #[arg(long)]
+        long: bool,
+        #[command(subcommand)]
+        action: Option<names::NamesCmd>,
     },
 
-    #[command(about = "Extract specific object from upk")]
+    #[command(about = "Extract objects from upk (all of them when no path is given)")]
     Extract {
-        upk_path: String,
-        path: Option<String>,
-        output_dir: Option<String>,
+        upk_path: PathBuf,
+        paths: Vec<String>,
+        #[arg(long = "out-dir", short = 'd', value_name = "DIR")]
+        output_dir: Option<PathBuf>,
+        #[arg(long, value_name = "FILE")]
+        list_file: Option<PathBuf>,
+        #[arg(long)]
+        lang: Option<String>,
+        /// Extra files per export besides the native one (dds, gfx,
+        /// audio): `png` previews, `json` info; comma-separated.
+        #[arg(long, value_delimiter = ',', value_name = "KINDS")]
+        convert: Vec<native::Convert>,
+        /// Store files once per content under STORE, with a manifest per
+        /// package; `cas checkout` writes the tree back out.
+        #[arg(long, value_name = "STORE", conflicts_with_all = ["output_dir", "lang"])]
+        cas: Option<PathBuf>,
     },
 
     Pack {
-        ron_path: String,
+        ron_path: PathBuf,
     },
 
     #[command(about = "Compile edited .uo files into loader-ready .bin + .namemap overrides")]
     PackMod {
-        extracted_dir: String,
+        extracted_dir: PathBuf,
         #[arg(long = "out", short = 'o', value_name = "DIR")]
-        out_dir: Option<String>,
+        out_dir: Option<PathBuf>,
+        /// Compress re-imported bulk data (texture mips); bare flag keeps
+        /// each block's original method.
+        #[arg(long, value_name = "METHOD", num_args = 0..=1, default_missing_value = "original")]
+        compress_bulk: Option<upkpacker::BulkArg>,
+    },
+
+    #[command(about = "Content-addressed extraction store (extract --cas): checkout, stats")]
+    Cas {
+        #[command(subcommand)]
+        action: cas::CasCmd,
+    },
+
+    #[command(about = "Package index of a game dir: build (incrementally) and query")]
+    Index {
+        #[command(subcommand)]
+        action: index::IndexCmd,
+    },
+
+    #[command(about = "List packages holding cooked copies of modified ones: a rebuild plan")]
+    Plan {
+        game_dir: PathBuf,
+        /// Package names or files, or pack-mod output to narrow it down to
+        /// the edited objects.
+        #[arg(required = true)]
+        modified: Vec<String>,
+    },
+
+    #[command(about = "Apply edits made to a package to cooked copies of its objects")]
+    Propagate {
+        /// The edited package.
+        edited: PathBuf,
+        game_dir: PathBuf,
+        /// The package before the edit; `<EDITED>.bak` by default.
+        #[arg(long, value_name = "UPK")]
+        original: Option<PathBuf>,
+        #[arg(long)]
+        dry_run: bool,
+        /// Times to retry a package another process has locked.
+        #[arg(long, default_value_t = 5)]
+        retries: u32,
+        /// Milliseconds to wait after writing each package.
+        #[arg(long, value_name = "MS", default_value_t = 0)]
+        throttle: u64,
+    },
+
+    #[command(about = "Carry pack-mod overrides over to another build of their package")]
+    Patch {
+        #[command(subcommand)]
+        action: patch::PatchCmd,
     },
 
     #[command(about = "Create a UE3 Font UPK from a TrueType / OpenType font file")]
     CreateFont {
-        font_file: String,
+        font_file: PathBuf,
 
         font_name: String,
 
@@ -317,12 +465,12 @@ enum Commands {
         #[arg(long, default_value_t = 684)]
         upk_version: i16,
 
-        output_dir: Option<String>,
+        output_dir: Option<PathBuf>,
     },
 
     #[command(about = "Dump the meta-object schema for every export in a UPK")]
     SchemaDump {
-        upk_path: String,
+        upk_path: PathBuf,
         #[arg(long)]
         class_filter: Option<String>,
     },
@@ -335,18 +483,295 @@ enum Commands {
 
     #[command(about = "open UI")]
     Ui,
+
+    #[cfg(feature = "live")]
+    #[command(about = "Check a running game (read-only) for patched function bytecode")]
+    Live {
+        pid: u32,
+        upk_path: PathBuf,
+        #[arg(long, value_name = "UPK")]
+        original: Option<PathBuf>,
+        #[arg(long)]
+        function: Option<String>,
+    },
+
+    #[command(about = "One row per package under a game dir: versions, flags, compression, counts")]
+    Report {
+        game_dir: PathBuf,
+        #[arg(long, value_enum, default_value = "csv")]
+        format: report::ReportFormat,
+        #[arg(long = "out", short = 'o', value_name = "FILE")]
+        out: Option<PathBuf>,
+        #[arg(long)]
+        compressed: bool,
+        #[arg(long, value_name = "DIR")]
+        decompress_all: Option<PathBuf>,
+        #[arg(long, short = 'j')]
+        jobs: Option<usize>,
+    },
+
+    #[command(about = "Disassemble the bytecode of a function / state / class export")]
+    Disasm {
+        upk_path: PathBuf,
+        #[arg(required_unless_present = "all")]
+        function: Option<String>,
+        /// Every function, state and class with bytecode in the package.
+        #[arg(long, conflicts_with_all = ["function", "diff"])]
+        all: bool,
+        /// Compare against ORIGINAL, or the package's .bak when no value
+        /// is given.
+        #[arg(long, value_name = "ORIGINAL", num_args = 0..=1, default_missing_value = "")]
+        diff: Option<PathBuf>,
+    },
+
+    #[command(about = "Opcode frequencies and unknown opcodes over every script under a directory")]
+    OpcodeStats {
+        dir: PathBuf,
+    },
+
+    #[command(about = "Show the bytecode around script callstack frames (`-` reads stdin)")]
+    Symbolicate {
+        upk_path: PathBuf,
+        #[arg(required = true)]
+        frames: Vec<String>,
+        #[arg(long, default_value_t = 3)]
+        context: usize,
+    },
+
+    #[command(about = "Engine CRCs of strings (appStrCrc, FName hash) or files (appMemCrc, zlib)")]
+    Crc {
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        /// Inputs are files to checksum.
+        #[arg(long)]
+        file: bool,
+        #[arg(long, value_enum)]
+        kind: Option<crc::CrcKind>,
+    },
+
+    #[command(about = "Pair packages with their _LOC_<LANG> companions, clone a language")]
+    Loc {
+        #[command(subcommand)]
+        action: loc::LocCmd,
+    },
+
+    #[command(about = "Combine two modified copies of a package against their original")]
+    Merge3 {
+        original: PathBuf,
+        a: PathBuf,
+        b: PathBuf,
+        #[arg(long = "out", short = 'o', value_name = "FILE")]
+        out: Option<PathBuf>,
+        #[arg(long, value_enum)]
+        prefer: Option<merge::Side>,
+        #[arg(long, value_name = "FILE", requires = "out")]
+        emit_delta: Option<PathBuf>,
+    },
+
+    #[command(about = "List exports nothing in the package references (stripping candidates)")]
+    Orphans {
+        upk_path: PathBuf,
+        #[arg(long)]
+        include_public: bool,
+    },
+
+    #[command(about = "Hexdump the start of an export and decode it as bytecode or properties")]
+    Peek {
+        upk_path: PathBuf,
+        object: String,
+        #[arg(long, default_value_t = 256)]
+        bytes: usize,
+    },
+
+    #[command(about = "Check table offsets, export data ranges and index references")]
+    Validate {
+        upk_path: PathBuf,
+        /// Point references to duplicate names at the first entry of the
+        /// same text; in place (.bak) unless -o.
+        #[arg(long)]
+        dedupe_names: bool,
+        #[arg(
+            long = "out",
+            short = 'o',
+            value_name = "FILE",
+            requires = "dedupe_names"
+        )]
+        out: Option<PathBuf>,
+    },
+
+    #[command(about = "Markdown documentation: summary, classes, functions, defaults, assets")]
+    Doc {
+        upk_path: PathBuf,
+        #[arg(long = "out", short = 'o', value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+
+    #[command(about = "Compare a game install with a manifest of clean package hashes")]
+    VerifyInstall {
+        game_dir: PathBuf,
+        /// Manifest file; all of `profiles/manifests/*.json` by default.
+        #[arg(long, value_name = "FILE")]
+        db: Option<PathBuf>,
+        /// Check against this version instead of the best-matching one.
+        #[arg(long, value_name = "LABEL")]
+        game_version: Option<String>,
+        /// Record this install as clean version LABEL into FILE instead.
+        #[arg(
+            long,
+            value_name = "FILE",
+            requires = "game_version",
+            conflicts_with = "db"
+        )]
+        record: Option<PathBuf>,
+        /// Game name stored with --record.
+        #[arg(long, requires = "record")]
+        game: Option<String>,
+        #[arg(long, short = 'j')]
+        jobs: Option<usize>,
+    },
+
+    #[command(about = "Manage downloaded game profile / native-table data")]
+    Profiles {
+        #[command(subcommand)]
+        action: profiles::ProfilesCmd,
+    },
+
+    #[command(about = "Apply binary deltas made with --emit-delta (or xdelta3)")]
+    Delta {
+        #[command(subcommand)]
+        action: delta::DeltaCmd,
+    },
+
+    #[command(about = "Decode saved games and other FArchive blobs outside packages")]
+    Savegame {
+        #[command(subcommand)]
+        action: savegame::SavegameCmd,
+    },
+
+    #[command(about = "Inspect or patch export / import table entries by field name")]
+    Table {
+        #[command(subcommand)]
+        action: table::TableCmd,
+    },
+
+    #[command(about = "Track extracted assets against their source packages")]
+    Workspace {
+        #[command(subcommand)]
+        action: workspace::WorkspaceCmd,
+    },
+
+    #[command(about = "Compare two game versions (or packages), re-cooked assets apart from edits")]
+    Changes {
+        old: PathBuf,
+        new: PathBuf,
+        /// List re-cooked / moved exports and packages too, not only counts.
+        #[arg(long)]
+        all: bool,
+        #[arg(long, short = 'j')]
+        jobs: Option<usize>,
+    },
+
+    #[command(about = "Report dead space in a package; --compact rewrites it without")]
+    Defrag {
+        upk_path: PathBuf,
+        #[arg(long)]
+        compact: bool,
+        /// Write the compacted package here instead of in place (.bak).
+        #[arg(long = "out", short = 'o', value_name = "FILE", requires = "compact")]
+        out: Option<PathBuf>,
+    },
+
+    #[command(about = "Check expressions about a package; exits non-zero when one doesn't hold")]
+    Assert {
+        upk_path: PathBuf,
+        /// e.g. "export('Pkg.Obj').size < 65536"; repeat for more.
+        #[arg(long = "expr", short = 'e', value_name = "EXPR", required = true)]
+        exprs: Vec<String>,
+    },
+
+    #[command(about = "Browse indexed packages over HTTP: HTML pages and a JSON API, read-only")]
+    Serve {
+        game_dir: PathBuf,
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+        /// Address to listen on; 0.0.0.0 to let the rest of the network in.
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
+        #[arg(long, value_name = "FILE")]
+        index: Option<PathBuf>,
+    },
+
+    #[command(about = "Plan splitting a package into several that each fit a size budget")]
+    Split {
+        upk_path: PathBuf,
+        /// Maximum size per package, e.g. 4M or 262144.
+        #[arg(long, value_name = "SIZE")]
+        budget: String,
+        /// Write the plan (groups and cross-package imports per part) here.
+        #[arg(long, value_name = "FILE")]
+        json: Option<PathBuf>,
+    },
+
+    #[command(about = "Extract every export, repack it unchanged and byte-compare with the input")]
+    Roundtrip {
+        upk_path: PathBuf,
+        /// Keep the extracted / packed intermediates in DIR.
+        #[arg(long, value_name = "DIR")]
+        keep: Option<PathBuf>,
+    },
+
+    #[command(about = "Parse randomly mutated copies of a package, failing on panics and hangs")]
+    Selftest {
+        #[arg(long, value_name = "UPK", required_unless_present = "exercise")]
+        mutate: Option<PathBuf>,
+        #[arg(long, default_value_t = 1000)]
+        iterations: u64,
+        #[arg(long, default_value_t = 1)]
+        seed: u64,
+        /// Seconds one parse may take before it counts as a hang.
+        #[arg(long, default_value_t = 10)]
+        timeout: u64,
+        /// Write the minimized failing copy here.
+        #[arg(long = "out", short = 'o', value_name = "FILE")]
+        out: Option<PathBuf>,
+        /// Parse one file and exit; what `--mutate` runs each copy through.
+        #[arg(long, hide = true, conflicts_with = "mutate")]
+        exercise: Option<PathBuf>,
+    },
+
+    #[command(about = "Map a raw file offset to its export / bytecode statement, or back")]
+    Where {
+        upk_path: PathBuf,
+        file_offset: Option<String>,
+        #[arg(long, conflicts_with = "file_offset")]
+        export: Option<String>,
+        #[arg(long, requires = "export")]
+        rel: Option<String>,
+        #[arg(long, requires = "export")]
+        script: Option<String>,
+    },
+
+    #[command(
+        about = "Write a symbol file (IDA / Ghidra / JSON) of a package's tables, exports and bytecode"
+    )]
+    Symbols {
+        upk_path: PathBuf,
+        #[arg(long, value_enum, default_value = "idc")]
+        format: symbols::SymbolFormat,
+        #[arg(long = "out", short = 'o', value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
 }
 
-fn schema_resolve(starting: &str, full_path: &str, game_root: &str, verbose: bool) -> Result<()> {
+fn schema_resolve(starting: &str, full_path: &str, game_root: &Path, verbose: bool) -> Result<()> {
     use crate::schemadb::SchemaDb;
-    use std::path::Path;
 
-    let db = SchemaDb::new(Path::new(game_root))?.with_verbose(verbose);
+    let db = SchemaDb::new(game_root)?.with_verbose(verbose);
     println!(
         "Indexed {} package(s), {} TFC(s) under {}",
         db.known_package_count(),
         db.tfc_index.len(),
-        game_root
+        game_root.display()
     );
 
     let r = db.resolve_full_path(starting, full_path)?;
@@ -383,20 +808,105 @@ fn schema_resolve(starting: &str, full_path: &str, game_root: &str, verbose: boo
     Ok(())
 }
 
-fn upk_decompress_to_file(path: &str) -> Result<()> {
+fn upk_decompress_to_file(path: &Path) -> Result<()> {
     let (cur, _head) = upk_header_cursor(path)?;
-    let path = Path::new(path);
-    let fp = format!(
-        "{}.decompressed.upk",
-        path.file_stem().and_then(|s| s.to_str()).unwrap()
-    );
-    let mut file = File::create(path.with_file_name(fp))?;
+    let mut fp = path.file_stem().unwrap_or_default().to_os_string();
+    fp.push(".decompressed.upk");
+    let _t = stats::time(Phase::Output);
+    let mut file = readonly::create(path.with_file_name(fp))?;
     file.write_all(cur.get_ref())?;
     Ok(())
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+/// Arguments that name where a command writes; what `--read-only` lets
+/// through.
+const OUTPUT_ARGS: &[&str] = &[
+    "out",
+    "out_dir",
+    "output_dir",
+    "output_path",
+    "decompress_all",
+    "keep",
+    "json",
+    "emit_delta",
+    "record",
+    "warnings_json",
+];
+
+fn allow_outputs(m: &ArgMatches) {
+    for id in OUTPUT_ARGS {
+        let Ok(Some(values)) = m.try_get_raw(id) else {
+            continue;
+        };
+        for v in values.filter(|v| *v != "-") {
+            utils::readonly::allow(v);
+        }
+    }
+    if let Some((_, sub)) = m.subcommand() {
+        allow_outputs(sub);
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = match Cli::command()
+        .try_get_matches()
+        .and_then(|m| Cli::from_arg_matches(&m).map(|cli| (cli, m)))
+    {
+        Ok((cli, m)) => {
+            allow_outputs(&m);
+            cli
+        }
+        // clap's own usage-error code (2) would read as "not found".
+        Err(e) => {
+            let _ = e.print();
+            return ExitCode::from(if e.use_stderr() {
+                exit::FAILURE
+            } else {
+                exit::SUCCESS
+            });
+        }
+    };
+    let warnings_json = cli.warnings_json.clone();
+    let stats = cli.stats;
+    let started = Instant::now();
+    let result = run(cli);
+    if stats {
+        utils::stats::report(started.elapsed());
+    }
+    // Written whatever the outcome: a failed run's warnings often say why.
+    if let Some(p) = &warnings_json
+        && let Err(e) = utils::lossy::write_json(p)
+    {
+        eprintln!(
+            "{}: {}: {e}",
+            term::epaint(term::Color::Red, "error"),
+            p.display()
+        );
+    }
+    match result {
+        Ok(()) => ExitCode::from(exit::SUCCESS),
+        Err(e) => {
+            eprintln!("{}: {e}", term::epaint(term::Color::Red, "error"));
+            ExitCode::from(exit::code_for(&e))
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    utils::heuristics::set(utils::heuristics::HeuristicsConfig::load(&cli.heuristics)?);
+    if cli.read_only {
+        utils::readonly::set_enabled(true);
+    }
+    utils::stats::set_enabled(cli.stats);
+    if let Some(b) = &cli.mem_budget {
+        utils::spill::set_memory_budget(Some(utils::spill::parse_size(b)?));
+    }
+    if let Some(d) = &cli.deadline {
+        utils::deadline::set_limit(Some(utils::deadline::parse_duration(d)?));
+    }
+    utils::deadline::arm();
+    utils::sniff::set_force(cli.force);
+    utils::term::set_no_color(cli.no_color);
 
     match cli.command {
         Commands::UpkHeader { path } => {
@@ -405,46 +915,134 @@ fn main() -> Result<()> {
         Commands::Decompress { path } => {
             upk_decompress_to_file(&path)?;
         }
+        Commands::Compress {
+            path,
+            out,
+            method,
+            level,
+            fast,
+            jobs,
+        } => compress::compress_cmd(
+            &path,
+            out.as_deref(),
+            method,
+            Tuning {
+                zlib_level: level,
+                fast,
+            },
+            jobs,
+        )?,
+        Commands::Chunks { action } => chunks::run(action)?,
+        Commands::Header { action } => header::run(action)?,
 
         Commands::Elements { ron_path, path } => {
             print_obj_elements(&ron_path, &path)?;
         }
         Commands::List { path } => getlist(&path)?,
-        Commands::Names { path, output_path } => {
-            let out = output_path.as_deref().unwrap_or("");
-            dump_names(&path, out)?
+        Commands::Names {
+            action: Some(action),
+            ..
+        } => names::run(action)?,
+        Commands::Names {
+            path,
+            output_path,
+            long,
+            ..
+        } => {
+            // clap requires the path unless a subcommand is given.
+            dump_names(&path.unwrap_or_default(), output_path.as_deref(), long)?
         }
         Commands::Extract {
             upk_path,
-            path,
+            mut paths,
             output_dir,
+            list_file,
+            lang,
+            convert,
+            cas,
         } => {
-            let out = output_dir.as_deref().unwrap_or("");
-            let mut extract_all = true;
-            if path.is_some() {
-                extract_all = false;
+            if let Some(f) = &list_file {
+                paths.extend(read_list_file(f)?);
+                if paths.is_empty() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("{} lists no objects", f.display()),
+                    ));
+                }
+            }
+            let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+            if let Some(store) = &cas {
+                let staging = cas::staging_dir(store);
+                let package = stem(&upk_path);
+                let extracted = extract_file(
+                    &upk_path,
+                    &paths,
+                    &staging,
+                    cli.game_root.as_deref(),
+                    cli.verbose,
+                    &convert,
+                )
+                .and_then(|()| cas::ingest(store, &staging.join(&package), &package));
+                let _ = std::fs::remove_dir_all(&staging);
+                return extracted;
+            }
+            let out = output_dir.as_deref().unwrap_or(Path::new(""));
+            match lang {
+                Some(lang) => loc::extract_localized(
+                    &upk_path,
+                    &paths,
+                    out,
+                    &lang,
+                    cli.game_root.as_deref(),
+                    cli.verbose,
+                    &convert,
+                )?,
+                None => extract_file(
+                    &upk_path,
+                    &paths,
+                    out,
+                    cli.game_root.as_deref(),
+                    cli.verbose,
+                    &convert,
+                )?,
             }
-            extract_file(
-                &upk_path,
-                path.as_deref().unwrap_or(""),
-                out,
-                extract_all,
-                cli.game_root.as_deref(),
-                cli.verbose,
-            )?
         }
         Commands::Pack { .. } => unimplemented!(),
         Commands::PackMod {
             extracted_dir,
             out_dir,
+            compress_bulk,
         } => {
             pack_mod_cmd(
                 &extracted_dir,
                 cli.game_root.as_deref(),
                 out_dir.as_deref(),
+                upkpacker::bulk_compression(compress_bulk),
                 cli.verbose,
             )?;
         }
+        Commands::Cas { action } => cas::run(action)?,
+        Commands::Index { action } => index::run(action)?,
+        Commands::Plan { game_dir, modified } => plan::plan_cmd(&game_dir, &modified, cli.verbose)?,
+        Commands::Propagate {
+            edited,
+            game_dir,
+            original,
+            dry_run,
+            retries,
+            throttle,
+        } => propagate::propagate_cmd(
+            &edited,
+            &game_dir,
+            original.as_deref(),
+            dry_run,
+            &utils::retry::Backoff {
+                retries,
+                ..Default::default()
+            },
+            std::time::Duration::from_millis(throttle),
+        )?,
+        Commands::Patch { action } => patch::run(action)?,
         Commands::CreateFont {
             font_file,
             font_name,
@@ -459,7 +1057,7 @@ fn main() -> Result<()> {
             upk_version,
             output_dir,
         } => {
-            let out_dir = output_dir.as_deref().unwrap_or("output");
+            let out_dir = output_dir.as_deref().unwrap_or(Path::new("output"));
             create_font_cmd(
                 &font_file,
                 &font_name,
@@ -486,42 +1084,251 @@ fn main() -> Result<()> {
             starting_pkg,
             full_path,
         } => {
-            let gr = cli.game_root.as_deref().unwrap_or("");
-            if gr.is_empty() {
+            let gr = cli.game_root.as_deref().unwrap_or(Path::new(""));
+            if gr.as_os_str().is_empty() {
                 eprintln!("--game-root required for schema-resolve");
                 std::process::exit(1);
             }
             schema_resolve(&starting_pkg, &full_path, gr, cli.verbose)?;
         }
         Commands::Ui => open_ui(cli.game_root.as_deref(), cli.verbose)?,
+        #[cfg(feature = "live")]
+        Commands::Live {
+            pid,
+            upk_path,
+            original,
+            function,
+        } => live::live_cmd(
+            pid,
+            &upk_path,
+            original.as_deref(),
+            function.as_deref(),
+            cli.verbose,
+        )?,
+        Commands::Report {
+            game_dir,
+            format,
+            out,
+            compressed,
+            decompress_all,
+            jobs,
+        } => report::report_cmd(
+            &game_dir,
+            format,
+            out.as_deref(),
+            compressed,
+            decompress_all.as_deref(),
+            jobs,
+        )?,
+        Commands::Disasm {
+            upk_path,
+            all: true,
+            ..
+        } => disasm::disasm_all_cmd(&upk_path)?,
+        // clap requires a function unless --all is given.
+        Commands::Disasm {
+            upk_path,
+            function,
+            diff: None,
+            ..
+        } => disasm::disasm_cmd(&upk_path, &function.unwrap_or_default())?,
+        Commands::Disasm {
+            upk_path,
+            function,
+            diff: Some(original),
+            ..
+        } => disasm::disasm_diff_cmd(
+            &upk_path,
+            &function.unwrap_or_default(),
+            Some(original.as_path()).filter(|o| !o.as_os_str().is_empty()),
+        )?,
+        Commands::OpcodeStats { dir } => opstats::opstats_cmd(&dir)?,
+        Commands::Symbolicate {
+            upk_path,
+            frames,
+            context,
+        } => symbolicate::symbolicate_cmd(&upk_path, &frames, context)?,
+        Commands::Crc { inputs, file, kind } => crc::crc_cmd(&inputs, file, kind)?,
+        Commands::Loc { action } => loc::run(action, cli.game_root.as_deref(), cli.verbose)?,
+        Commands::Merge3 {
+            original,
+            a,
+            b,
+            out,
+            prefer,
+            emit_delta,
+        } => merge::merge3_cmd(
+            &original,
+            &a,
+            &b,
+            out.as_deref(),
+            prefer,
+            emit_delta.as_deref(),
+        )?,
+        Commands::Orphans {
+            upk_path,
+            include_public,
+        } => orphans::orphans_cmd(
+            &upk_path,
+            include_public,
+            cli.game_root.as_deref(),
+            cli.verbose,
+        )?,
+        Commands::Peek {
+            upk_path,
+            object,
+            bytes,
+        } => peek::peek_cmd(
+            &upk_path,
+            &object,
+            bytes,
+            cli.game_root.as_deref(),
+            cli.verbose,
+        )?,
+        Commands::Validate {
+            upk_path,
+            dedupe_names,
+            out,
+        } => validate::validate_cmd(
+            &upk_path,
+            dedupe_names,
+            out.as_deref(),
+            cli.game_root.as_deref(),
+            cli.verbose,
+        )?,
+        Commands::Doc { upk_path, out } => doc::doc_cmd(
+            &upk_path,
+            out.as_deref(),
+            cli.game_root.as_deref(),
+            cli.verbose,
+        )?,
+        Commands::VerifyInstall {
+            game_dir,
+            game_version,
+            record: Some(out),
+            game,
+            jobs,
+            ..
+        } => verify::record_cmd(
+            &game_dir,
+            &out,
+            game_version.as_deref().unwrap_or_default(),
+            game.as_deref(),
+            jobs,
+        )?,
+        Commands::VerifyInstall {
+            game_dir,
+            db,
+            game_version,
+            jobs,
+            ..
+        } => verify::verify_install_cmd(
+            &game_dir,
+            db.as_deref(),
+            game_version.as_deref(),
+            jobs,
+            cli.verbose,
+        )?,
+        Commands::Profiles { action } => profiles::run(action)?,
+        Commands::Delta { action } => delta::run(action)?,
+        Commands::Savegame { action } => savegame::run(action)?,
+        Commands::Table { action } => table::run(action)?,
+        Commands::Workspace { action } => workspace::run(action, cli.verbose)?,
+        Commands::Changes {
+            old,
+            new,
+            all,
+            jobs,
+        } => changes::changes_cmd(&old, &new, all, jobs)?,
+        Commands::Defrag {
+            upk_path,
+            compact,
+            out,
+        } => defrag::defrag_cmd(&upk_path, compact, out.as_deref(), cli.verbose)?,
+        Commands::Assert { upk_path, exprs } => {
+            assert::assert_cmd(&upk_path, &exprs, cli.game_root.as_deref(), cli.verbose)?
+        }
+        Commands::Serve {
+            game_dir,
+            port,
+            bind,
+            index,
+        } => serve::serve_cmd(&game_dir, &bind, port, index.as_deref(), cli.verbose)?,
+        Commands::Split {
+            upk_path,
+            budget,
+            json,
+        } => split::split_cmd(&upk_path, &budget, json.as_deref(), cli.verbose)?,
+        Commands::Roundtrip { upk_path, keep } => roundtrip::roundtrip_cmd(
+            &upk_path,
+            cli.game_root.as_deref(),
+            keep.as_deref(),
+            cli.verbose,
+        )?,
+        Commands::Selftest {
+            mutate,
+            iterations,
+            seed,
+            timeout,
+            out,
+            exercise,
+        } => match (mutate, exercise) {
+            (_, Some(path)) => selftest::exercise(&path)?,
+            (Some(upk), None) => {
+                selftest::mutate_cmd(&upk, iterations, seed, timeout, out.as_deref())?
+            }
+            (None, None) => unreachable!("clap requires one of them"),
+        },
+        Commands::Where {
+            upk_path,
+            file_offset,
+            export,
+            rel,
+            script,
+        } => offsets::where_cmd(
+            &upk_path,
+            file_offset.as_deref(),
+            export.as_deref(),
+            rel.as_deref(),
+            script.as_deref(),
+        )?,
+        Commands::Symbols {
+            upk_path,
+            format,
+            out,
+        } => symbols::symbols_cmd(&upk_path, format, out.as_deref())?,
     }
 
     Ok(())
 }
 
 fn pack_mod_cmd(
-    extracted_dir: &str,
-    game_root: Option<&str>,
-    out_dir: Option<&str>,
+    extracted_dir: &Path,
+    game_root: Option<&Path>,
+    out_dir: Option<&Path>,
+    bulk: native::BulkCompression,
     verbose: bool,
 ) -> Result<()> {
-    use std::path::Path;
-
     let opts = upkpacker::PackOptions {
-        extracted_dir: Path::new(extracted_dir),
-        game_root: game_root.filter(|s| !s.is_empty()).map(Path::new),
-        out_dir: out_dir.filter(|s| !s.is_empty()).map(Path::new),
+        extracted_dir,
+        game_root: game_root.filter(|p| !p.as_os_str().is_empty()),
+        out_dir: out_dir.filter(|p| !p.as_os_str().is_empty()),
         verbose,
+        only_files: None,
+        package_paths: None,
+        keep_names: false,
+        bulk,
     };
-    upkpacker::pack_mod(&opts)
+    upkpacker::pack_mod(&opts)?;
+    Ok(())
 }
 
-fn open_ui(game_root: Option<&str>, verbose: bool) -> Result<()> {
-    let gr = game_root.map(std::path::PathBuf::from);
+fn open_ui(game_root: Option<&Path>, verbose: bool) -> Result<()> {
+    let gr = game_root.map(Path::to_path_buf);
     ui::run(gr, verbose).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
 }
 
-fn schema_dump(upk_path: &str, class_filter: Option<&str>) -> Result<()> {
+fn schema_dump(upk_path: &Path, class_filter: Option<&str>) -> Result<()> {
     use crate::schema::{SchemaParseCtx, parse_export_schema};
 
     let (mut cursor, header) = upk_header_cursor(upk_path)?;
@@ -626,7 +1433,7 @@ fn summarize_entry(e: &crate::schema::SchemaEntry) -> String {
     }
 }
 fn create_font_cmd(
-    font_file: &str,
+    font_file: &Path,
     font_name: &str,
     size: f32,
     dpi: u32,
@@ -637,10 +1444,17 @@ fn create_font_cmd(
     chars: Option<&str>,
     write_upk: bool,
     upk_version: i16,
-    out_dir: &str,
+    out_dir: &Path,
 ) -> std::io::Result<()> {
+    // FreeType only opens paths it can pass on as UTF-8.
+    let font_path = font_file.to_str().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{}: font path is not valid UTF-8", font_file.display()),
+        )
+    })?;
     let cfg = FontConfig {
-        font_path: font_file.to_string(),
+        font_path: font_path.to_string(),
         font_name: font_name.to_string(),
         size_pt: size,
         dpi,
@@ -652,11 +1466,11 @@ fn create_font_cmd(
         upk_version,
     };
 
-    std::fs::create_dir_all(out_dir)?;
-    create_font_blobs(&cfg, Path::new(out_dir))?;
+    readonly::create_dir_all(out_dir)?;
+    create_font_blobs(&cfg, out_dir)?;
 
     if write_upk {
-        let out_path = Path::new(out_dir).join(format!("{}.upk", font_name));
+        let out_path = out_dir.join(format!("{}.upk", font_name));
         create_font_upk(&cfg, &out_path)?;
     }
 
@@ -0,0 +1,224 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    path::Path,
+};
+
+use clap::ValueEnum;
+
+use crate::{
+    delta,
+    exit::validation_failed,
+    package::Package,
+    upkpacker::export_path_dotted,
+    upkreader::{Export, NameEntry},
+    utils::term::{self, Color, paint},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Side {
+    A,
+    B,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pick {
+    Original,
+    A,
+    B,
+    Same,
+    Conflict,
+}
+
+pub struct MergePlan {
+    pub picks: Vec<(i32, String, Pick)>,
+    /// Names appended after the original table, taken from whichever variant
+    /// has the longer (compatible) list.
+    pub extra_names: Vec<NameEntry>,
+    pub warnings: Vec<String>,
+}
+
+impl MergePlan {
+    pub fn count(&self, p: Pick) -> usize {
+        self.picks.iter().filter(|(_, _, x)| *x == p).count()
+    }
+}
+
+/// The variant whose blob goes into the merge for `pick`, with conflicts
+/// settled by `prefer`; `None` keeps the original.
+fn source(pick: Pick, prefer: Option<Side>) -> Option<Side> {
+    match (pick, prefer) {
+        (Pick::A | Pick::Same, _) | (Pick::Conflict, Some(Side::A)) => Some(Side::A),
+        (Pick::B, _) | (Pick::Conflict, Some(Side::B)) => Some(Side::B),
+        _ => None,
+    }
+}
+
+/// Table entry fields a mod could change; serial offset / size move with
+/// every re-save and are not compared.
+fn entry_changed(o: &Export, v: &Export) -> bool {
+    o.class_index != v.class_index
+        || o.super_index != v.super_index
+        || o.outer_index != v.outer_index
+        || o.object_name != v.object_name
+        || o.archetype != v.archetype
+        || o.object_flags != v.object_flags
+        || o.export_flags != v.export_flags
+}
+
+fn check_layout(orig: &Package, v: &Package, label: &str) -> Result<()> {
+    if v.exports.len() != orig.exports.len() || v.imports.len() != orig.imports.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "variant {label} has {} export(s) / {} import(s), original has {} / {}; only in-place edits can be merged",
+                v.exports.len(),
+                v.imports.len(),
+                orig.exports.len(),
+                orig.imports.len()
+            ),
+        ));
+    }
+    let n = orig.names.len();
+    if v.names.len() < n
+        || v.names[..n]
+            .iter()
+            .zip(&orig.names)
+            .any(|(a, b)| a.name != b.name)
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("variant {label} rewrote existing names; only appended names can be merged"),
+        ));
+    }
+    Ok(())
+}
+
+/// `prefer` settles conflicts, and so decides which variant's appended
+/// names the merged blobs need.
+pub fn plan(orig: &Package, a: &Package, b: &Package, prefer: Option<Side>) -> Result<MergePlan> {
+    check_layout(orig, a, "A")?;
+    check_layout(orig, b, "B")?;
+
+    let pak = orig.pak();
+    let mut picks = Vec::with_capacity(orig.exports.len());
+    let mut warnings = Vec::new();
+    for i in 0..orig.exports.len() {
+        let idx = i as i32 + 1;
+        let key = export_path_dotted(&pak, idx);
+        for (label, v) in [("A", a), ("B", b)] {
+            if entry_changed(&orig.exports[i], &v.exports[i]) {
+                warnings.push(format!(
+                    "{key}: export table entry changed in {label}; only its data is merged"
+                ));
+            }
+        }
+        let o = orig.export_blob(idx)?;
+        let va = a.export_blob(idx)?;
+        let vb = b.export_blob(idx)?;
+        let pick = match (va != o, vb != o) {
+            (false, false) => Pick::Original,
+            (true, false) => Pick::A,
+            (false, true) => Pick::B,
+            (true, true) if va == vb => Pick::Same,
+            (true, true) => Pick::Conflict,
+        };
+        picks.push((idx, key, pick));
+    }
+
+    let n = orig.names.len();
+    let (ea, eb) = (&a.names[n..], &b.names[n..]);
+    // A blob both variants agree on may index either list.
+    let uses = |side: Side| {
+        picks
+            .iter()
+            .any(|(_, _, p)| *p == Pick::Same || source(*p, prefer) == Some(side))
+    };
+    let extra_names = if ea.iter().zip(eb).all(|(x, y)| x.name == y.name) {
+        if ea.len() >= eb.len() { ea } else { eb }.to_vec()
+    } else if !uses(Side::B) {
+        ea.to_vec()
+    } else if !uses(Side::A) {
+        eb.to_vec()
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "both variants appended different names ({} vs {}) and the merge takes exports from both; their exports index them differently and cannot be combined",
+                ea.len(),
+                eb.len()
+            ),
+        ));
+    };
+
+    Ok(MergePlan {
+        picks,
+        extra_names,
+        warnings,
+    })
+}
+
+pub fn merge3_cmd(
+    original: &Path,
+    a_path: &Path,
+    b_path: &Path,
+    out: Option<&Path>,
+    prefer: Option<Side>,
+    emit_delta: Option<&Path>,
+) -> Result<()> {
+    let orig = Package::open(original)?;
+    let a = Package::open(a_path)?;
+    let b = Package::open(b_path)?;
+    let plan = plan(&orig, &a, &b, prefer)?;
+
+    for (idx, key, pick) in &plan.picks {
+        let tag = match pick {
+            Pick::Original => continue,
+            Pick::A => paint(Color::Green, "A"),
+            Pick::B => paint(Color::Green, "B"),
+            Pick::Same => paint(Color::Green, "A = B"),
+            Pick::Conflict => paint(Color::Red, "conflict"),
+        };
+        println!("  {tag:<8}  #{idx} {key}");
+    }
+    for w in &plan.warnings {
+        term::warn("warning", w);
+    }
+    let conflicts = plan.count(Pick::Conflict);
+    println!(
+        "merge3: {} from A, {} from B, {} identical in both, {conflicts} conflict(s)",
+        plan.count(Pick::A),
+        plan.count(Pick::B),
+        plan.count(Pick::Same)
+    );
+
+    let Some(out) = out else {
+        return Ok(());
+    };
+    if conflicts > 0 && prefer.is_none() {
+        return Err(validation_failed(format!(
+            "{conflicts} export(s) modified by both variants; resolve with --prefer a|b"
+        )));
+    }
+
+    let mut merged = Package::open(original)?;
+    merged.names.extend(plan.extra_names.iter().cloned());
+    for (idx, _, pick) in &plan.picks {
+        let from = match source(*pick, prefer) {
+            Some(Side::A) => &a,
+            Some(Side::B) => &b,
+            None => continue,
+        };
+        merged.set_export_blob(*idx, from.export_blob(*idx)?.to_vec())?;
+    }
+    let stats = merged.save(out)?;
+    println!(
+        "Wrote {}: {} export(s) replaced, {} name(s) added",
+        out.display(),
+        stats.replaced_exports,
+        stats.added_names
+    );
+    if let Some(d) = emit_delta {
+        delta::emit_delta(original, out, d)?;
+    }
+    Ok(())
+}
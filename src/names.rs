@@ -0,0 +1,131 @@
+use std::{
+    collections::HashMap,
+    io::Result,
+    path::{Path, PathBuf},
+};
+
+use clap::Subcommand;
+
+use crate::{
+    exit::validation_failed,
+    package::Package,
+    upkreader::NameEntry,
+    utils::{
+        backup::original_of,
+        term::{Color, paint},
+    },
+};
+
+#[derive(Subcommand)]
+pub enum NamesCmd {
+    #[command(about = "Compare two name tables (B alone: against B.bak); exits 5 when they differ")]
+    Diff { a: PathBuf, b: Option<PathBuf> },
+}
+
+pub enum NameChange<'a> {
+    Removed(usize, &'a NameEntry),
+    Added(usize, &'a NameEntry),
+    Flags(usize, &'a NameEntry, u64),
+}
+
+pub struct NameDiff<'a> {
+    pub changes: Vec<NameChange<'a>>,
+    /// Names present in both tables but at a different index.
+    pub moved: usize,
+}
+
+/// Names are matched by exact text; removals come in `a` order, then
+/// additions and flag changes in `b` order.
+pub fn diff<'a>(a: &'a [NameEntry], b: &'a [NameEntry]) -> NameDiff<'a> {
+    let index = |t: &'a [NameEntry]| -> HashMap<&'a str, usize> {
+        let mut m = HashMap::with_capacity(t.len());
+        for (i, n) in t.iter().enumerate() {
+            m.entry(n.name.as_str()).or_insert(i);
+        }
+        m
+    };
+    let (ia, ib) = (index(a), index(b));
+
+    let mut changes = Vec::new();
+    let mut moved = 0;
+    for (i, n) in a.iter().enumerate() {
+        if !ib.contains_key(n.name.as_str()) {
+            changes.push(NameChange::Removed(i, n));
+        }
+    }
+    for (i, n) in b.iter().enumerate() {
+        match ia.get(n.name.as_str()) {
+            None => changes.push(NameChange::Added(i, n)),
+            Some(&j) => {
+                if a[j].flags != n.flags {
+                    changes.push(NameChange::Flags(i, n, a[j].flags));
+                }
+                if j != i {
+                    moved += 1;
+                }
+            }
+        }
+    }
+    NameDiff { changes, moved }
+}
+
+/// Returns whether the tables differ.
+pub fn diff_cmd(a_path: &Path, b_path: &Path) -> Result<()> {
+    let a = Package::open(a_path)?;
+    let b = Package::open(b_path)?;
+    let d = diff(&a.names, &b.names);
+
+    let (mut added, mut removed, mut flags) = (0, 0, 0);
+    for c in &d.changes {
+        match c {
+            NameChange::Removed(i, n) => {
+                removed += 1;
+                println!(
+                    "{} #{i:<6} {:<40} 0x{:016X}",
+                    paint(Color::Red, "-"),
+                    n.name,
+                    n.flags
+                );
+            }
+            NameChange::Added(i, n) => {
+                added += 1;
+                println!(
+                    "{} #{i:<6} {:<40} 0x{:016X}",
+                    paint(Color::Green, "+"),
+                    n.name,
+                    n.flags
+                );
+            }
+            NameChange::Flags(i, n, old) => {
+                flags += 1;
+                println!(
+                    "{} #{i:<6} {:<40} 0x{old:016X} → 0x{:016X}",
+                    paint(Color::Yellow, "~"),
+                    n.name,
+                    n.flags
+                );
+            }
+        }
+    }
+    println!(
+        "names: {} → {} entries; {added} added, {removed} removed, {flags} flag change(s), {} moved",
+        a.names.len(),
+        b.names.len(),
+        d.moved
+    );
+    if !d.changes.is_empty() || d.moved > 0 {
+        return Err(validation_failed("name tables differ"));
+    }
+    Ok(())
+}
+
+pub fn run(cmd: NamesCmd) -> Result<()> {
+    match cmd {
+        NamesCmd::Diff { a, b: Some(b) } => diff_cmd(&a, &b),
+        NamesCmd::Diff { a: b, b: None } => {
+            let a = original_of(&b)?;
+            println!("Original: {}", a.display());
+            diff_cmd(&a, &b)
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Serializable summaries of parsed assets: what `--convert json` writes
+//! and what the extraction's `assets.json` lists. Field names are part of
+//! the output format; add fields, don't rename them.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    upkprops::{Property, PropertyValue},
+    upkreader::UPKPak,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "class")]
+pub enum AssetInfo {
+    Texture2D(Texture2DInfo),
+    SwfMovie(SwfMovieInfo),
+    SoundNodeWave(SoundWaveInfo),
+    Font(FontInfo),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MipInfo {
+    pub width: i32,
+    pub height: i32,
+    pub bytes: usize,
+    /// `inline`, `tfc:<cache>` or `missing`.
+    pub source: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Texture2DInfo {
+    pub format: Option<String>,
+    pub texture_file_cache: Option<String>,
+    pub mips: Vec<MipInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SwfMovieInfo {
+    pub bytes: usize,
+    /// First three bytes: `GFX`, or `CFX` when the body is zlib'd.
+    pub magic: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BulkInfo {
+    pub bytes: usize,
+    pub format: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SoundWaveInfo {
+    pub num_channels: Option<i32>,
+    pub sample_rate: Option<i32>,
+    pub duration: Option<f32>,
+    pub raw_data: BulkInfo,
+    pub compressed_pc: BulkInfo,
+    pub compressed_xbox360: BulkInfo,
+    pub compressed_ps3: BulkInfo,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FontInfo {
+    pub characters: usize,
+    /// Full names of the glyph page textures.
+    pub textures: Vec<String>,
+    pub is_remapped: bool,
+    pub em_scale: Option<f32>,
+    pub ascent: Option<f32>,
+    pub descent: Option<f32>,
+    pub leading: Option<f32>,
+    pub kerning: Option<i32>,
+}
+
+/// Fonts are tagged properties only, so their summary comes from those.
+pub fn font_info(props: &[Property], pak: &UPKPak) -> FontInfo {
+    let get = |name: &str| props.iter().find(|p| p.name == name).map(|p| &p.value);
+    let float = |name: &str| match get(name) {
+        Some(PropertyValue::Float(f)) => Some(*f),
+        _ => None,
+    };
+    let items = |name: &str| match get(name) {
+        Some(PropertyValue::Array(v)) => v.as_slice(),
+        _ => &[],
+    };
+    let textures = items("Textures")
+        .iter()
+        .filter_map(|v| match v {
+            PropertyValue::Object(i) if *i > 0 => Some(pak.get_export_full_name(*i)),
+            PropertyValue::Object(i) if *i < 0 => Some(pak.get_import_full_name(*i)),
+            PropertyValue::ObjectRef(s) => Some(s.clone()),
+            _ => None,
+        })
+        .collect();
+    FontInfo {
+        characters: items("Characters").len(),
+        textures,
+        is_remapped: match get("IsRemapped") {
+            Some(PropertyValue::Bool(b)) => *b,
+            Some(PropertyValue::Int(i)) => *i != 0,
+            _ => false,
+        },
+        em_scale: float("EmScale"),
+        ascent: float("Ascent"),
+        descent: float("Descent"),
+        leading: float("Leading"),
+        kerning: match get("Kerning") {
+            Some(PropertyValue::Int(i)) => Some(*i),
+            _ => None,
+        },
+    }
+}
+
+/// `assets.json`, written next to extracted files: the parsed summary of
+/// every export that has one, keyed by its `.uo` relative to the output
+/// directory.
+pub const ASSETS_FILE: &str = "assets.json";
+
+/// Bumped on a breaking change to the entries.
+pub const ASSETS_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssetEntry {
+    /// `Class Outer.Name`, as `list` prints it.
+    pub object: String,
+    #[serde(flatten)]
+    pub info: AssetInfo,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssetManifest {
+    pub version: u32,
+    pub assets: BTreeMap<String, AssetEntry>,
+}
+
+impl Default for AssetManifest {
+    fn default() -> Self {
+        Self {
+            version: ASSETS_VERSION,
+            assets: BTreeMap::new(),
+        }
+    }
+}
@@ -1,22 +1,35 @@
 use std::{
     collections::HashMap,
-    io::{Read, Result, Seek},
+    fmt,
+    io::{Cursor, Error, ErrorKind, Read, Result, Seek, Write},
     path::{Path, PathBuf},
     rc::Rc,
+    str::FromStr,
 };
 
 use crate::{
     schemadb::{ResolvedRef, SchemaDb},
     upkprops::Property,
     upkreader::UPKPak,
-    versions::BULKDATA_STORE_IN_SEPARATE_FILE,
+    utils::{
+        compress::{Tuning, compress_blocks, write_chunk},
+        decompress::{CHUNK_SIZE, CompressedChunk, CompressionMethod, upk_decompress},
+        pool, readonly,
+    },
+    versions::{
+        BULKDATA_SERIALIZE_COMPRESSED, BULKDATA_SERIALIZE_COMPRESSED_LZO,
+        BULKDATA_SERIALIZE_COMPRESSED_LZX, BULKDATA_SERIALIZE_COMPRESSED_ZLIB,
+        BULKDATA_STORE_IN_SEPARATE_FILE,
+    },
 };
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
+pub mod info;
 pub mod soundnodewave;
 pub mod swfmovie;
 pub mod texture2d;
 
+pub use info::{AssetInfo, FontInfo, SoundWaveInfo, SwfMovieInfo, Texture2DInfo};
 pub use soundnodewave::{SoundNodeWavePayload, SoundNodeWaveSer};
 pub use swfmovie::{SwfMoviePayload, SwfMovieSer};
 pub use texture2d::{Mip, MipSource, Texture2DPayload, Texture2DSer};
@@ -61,6 +74,89 @@ impl BulkBlock {
     pub fn is_external(&self) -> bool {
         self.flags & BULKDATA_STORE_IN_SEPARATE_FILE != 0
     }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        w.write_u32::<LittleEndian>(self.flags)?;
+        w.write_i32::<LittleEndian>(self.element_count)?;
+        w.write_i32::<LittleEndian>(self.size_on_disk)?;
+        w.write_i32::<LittleEndian>(self.offset_in_file)?;
+        if !self.is_external() {
+            w.write_all(&self.data)?;
+        }
+        Ok(())
+    }
+}
+
+/// How bulk data replaced on import is stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BulkCompression {
+    /// Uncompressed, whatever the original was.
+    #[default]
+    None,
+    /// The method of the block being replaced (uncompressed if it was).
+    Original,
+    Method(CompressionMethod),
+}
+
+fn bulk_method(flags: u32) -> CompressionMethod {
+    if flags & BULKDATA_SERIALIZE_COMPRESSED_ZLIB != 0 {
+        CompressionMethod::Zlib
+    } else if flags & BULKDATA_SERIALIZE_COMPRESSED_LZO != 0 {
+        CompressionMethod::Lzo
+    } else if flags & BULKDATA_SERIALIZE_COMPRESSED_LZX != 0 {
+        CompressionMethod::Lzx
+    } else {
+        CompressionMethod::None
+    }
+}
+
+/// Inline bulk data as the engine reads it: compressed payloads are the
+/// same tagged block stream as package chunks.
+pub fn inflate_bulk(flags: u32, data: &[u8]) -> Result<Vec<u8>> {
+    let mode = bulk_method(flags);
+    if mode == CompressionMethod::None || data.is_empty() {
+        return Ok(data.to_vec());
+    }
+    let stream = CompressedChunk {
+        decompressed_offset: 0,
+        decompressed_size: 0,
+        compressed_offset: 0,
+        compressed_size: data.len() as u32,
+    };
+    Ok(upk_decompress(Cursor::new(data), mode, &[stream])?
+        .pop()
+        .unwrap_or_default())
+}
+
+/// Stores `raw` in place of a block that had `flags`. Returns the new flags
+/// and the bytes to write; `element_count` stays the raw length and
+/// `size_on_disk` becomes the length of what's returned.
+pub fn deflate_bulk(flags: u32, raw: &[u8], mode: BulkCompression) -> Result<(u32, Vec<u8>)> {
+    let method = match mode {
+        BulkCompression::None => CompressionMethod::None,
+        BulkCompression::Original => bulk_method(flags),
+        BulkCompression::Method(m) => m,
+    };
+    let flag = match method {
+        CompressionMethod::None => 0,
+        CompressionMethod::Zlib => BULKDATA_SERIALIZE_COMPRESSED_ZLIB,
+        CompressionMethod::Lzo => BULKDATA_SERIALIZE_COMPRESSED_LZO,
+        CompressionMethod::Lzx => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "LZX bulk data can't be written; pick zlib or lzo",
+            ));
+        }
+    };
+    let flags = flags & !(BULKDATA_STORE_IN_SEPARATE_FILE | BULKDATA_SERIALIZE_COMPRESSED) | flag;
+    if flag == 0 || raw.is_empty() {
+        return Ok((flags, raw.to_vec()));
+    }
+    let blocks: Vec<&[u8]> = raw.chunks(CHUNK_SIZE as usize).collect();
+    let packed = compress_blocks(&blocks, method, Tuning::default(), pool::jobs(None))?;
+    let mut out = Vec::new();
+    write_chunk(&mut out, &blocks, &packed)?;
+    Ok((flags, out))
 }
 
 #[derive(Debug, Clone)]
@@ -88,6 +184,87 @@ impl NativePayload {
     }
 }
 
+/// One bulk payload inside a native export: a texture mip, an audio
+/// stream. What a re-cook rewrites; the rest of the export is layout.
+#[derive(Debug, Clone)]
+pub struct BulkView<'a> {
+    /// `mip 0 (512x512)`, `compressed_pc`, …
+    pub label: String,
+    pub flags: u32,
+    pub element_count: i32,
+    pub size_on_disk: i32,
+    pub offset_in_file: i32,
+    /// Inline data (inflated where the parser does); empty when stored in
+    /// a TFC that wasn't loaded.
+    pub data: &'a [u8],
+}
+
+impl BulkView<'_> {
+    pub fn is_external(&self) -> bool {
+        self.flags & BULKDATA_STORE_IN_SEPARATE_FILE != 0
+    }
+
+    /// Same payload, wherever it sits: the offset only counts for data in
+    /// a separate file, where it's all there is to compare.
+    pub fn same_data(&self, other: &BulkView) -> bool {
+        self.element_count == other.element_count
+            && self.size_on_disk == other.size_on_disk
+            && self.data == other.data
+            && (!self.is_external() || self.offset_in_file == other.offset_in_file)
+    }
+}
+
+fn mip_view<'a>(prefix: &str, i: usize, m: &'a Mip) -> BulkView<'a> {
+    BulkView {
+        label: format!("{prefix}{i} ({}x{})", m.size_x, m.size_y),
+        flags: m.flags,
+        element_count: m.element_count,
+        size_on_disk: m.size_on_disk,
+        offset_in_file: m.offset_in_file,
+        data: &m.data,
+    }
+}
+
+fn block_view<'a>(label: &str, b: &'a BulkBlock) -> BulkView<'a> {
+    BulkView {
+        label: label.to_string(),
+        flags: b.flags,
+        element_count: b.element_count,
+        size_on_disk: b.size_on_disk,
+        offset_in_file: b.offset_in_file,
+        data: &b.data,
+    }
+}
+
+impl NativePayload {
+    /// The bulk payloads, in serialization order.
+    pub fn bulk(&self) -> Vec<BulkView<'_>> {
+        match self {
+            NativePayload::Texture2D(p) => {
+                let mips = p
+                    .mips
+                    .iter()
+                    .enumerate()
+                    .map(|(i, m)| mip_view("mip ", i, m));
+                let pvrtc = p.cached_pvrtc_mips.iter().enumerate();
+                mips.chain(pvrtc.map(|(i, m)| mip_view("pvrtc mip ", i, m)))
+                    .collect()
+            }
+            NativePayload::SoundNodeWave(p) => [
+                ("raw_data", &p.raw_data),
+                ("compressed_pc", &p.compressed_pc),
+                ("compressed_xbox360", &p.compressed_xbox360),
+                ("compressed_ps3", &p.compressed_ps3),
+            ]
+            .into_iter()
+            .filter(|(_, b)| !b.is_empty())
+            .map(|(label, b)| block_view(label, b))
+            .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
 pub struct NativeRead {
     pub payload: NativePayload,
     pub consumed_props: Vec<String>,
@@ -113,6 +290,70 @@ pub struct NativeReadCtx<'a> {
     pub class_ref: Option<ResolvedRef>,
 }
 
+/// What `extract --convert` writes next to a `.uo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Convert {
+    /// The payload in its own format (DDS, GFx, audio): what re-import reads.
+    Native,
+    /// A PNG of the largest texture mip.
+    Png,
+    /// The payload's metadata as JSON.
+    Json,
+}
+
+impl FromStr for Convert {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "native" | "dds" | "gfx" | "audio" => Ok(Convert::Native),
+            "png" => Ok(Convert::Png),
+            "json" => Ok(Convert::Json),
+            _ => Err(format!("unknown converter '{s}' (native / dds, png, json)")),
+        }
+    }
+}
+
+/// Why a sidecar exists, recorded in the `.uo` so re-import only reads
+/// the files it can rebuild the export from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SidecarRole {
+    Source,
+    Preview,
+    Info,
+}
+
+impl SidecarRole {
+    pub fn label(self) -> &'static str {
+        match self {
+            SidecarRole::Source => "source",
+            SidecarRole::Preview => "preview",
+            SidecarRole::Info => "info",
+        }
+    }
+
+    pub fn from_label(s: &str) -> Option<Self> {
+        Some(match s {
+            "source" => SidecarRole::Source,
+            "preview" => SidecarRole::Preview,
+            "info" => SidecarRole::Info,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for SidecarRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sidecar {
+    pub path: PathBuf,
+    pub role: SidecarRole,
+}
+
 pub struct NativeInjectCtx<'a> {
     pub props: &'a mut Vec<Property>,
     pub native_tail: &'a mut Vec<u8>,
@@ -121,6 +362,7 @@ pub struct NativeInjectCtx<'a> {
     pub externalized_prop: Option<String>,
     pub ver: i16,
     pub pak: &'a UPKPak,
+    pub bulk: BulkCompression,
 }
 
 pub trait NativeSerializer {
@@ -137,6 +379,22 @@ pub trait NativeSerializer {
         Ok(Vec::new())
     }
 
+    /// An image of the payload for `--convert png`.
+    fn emit_preview(
+        &self,
+        _payload: &NativePayload,
+        _dir: &Path,
+        _stem: &str,
+    ) -> Result<Option<PathBuf>> {
+        Ok(None)
+    }
+
+    /// Metadata for `--convert json` and `assets.json`; bulk bytes are
+    /// summarized, not included.
+    fn info(&self, _payload: &NativePayload) -> Option<AssetInfo> {
+        None
+    }
+
     fn inject_external(&self, _ctx: &mut NativeInjectCtx) -> Result<bool> {
         Ok(false)
     }
@@ -144,13 +402,69 @@ pub trait NativeSerializer {
 
 pub struct NativeRegistry {
     map: HashMap<&'static str, Rc<dyn NativeSerializer>>,
+    convert: Vec<Convert>,
 }
 
 impl NativeRegistry {
     pub fn empty() -> Self {
         Self {
             map: HashMap::new(),
+            convert: vec![Convert::Native],
+        }
+    }
+
+    /// Converters `emit` runs besides `Native`, which always does: the
+    /// `.uo` points at its files and re-import needs them.
+    pub fn with_convert(mut self, convert: &[Convert]) -> Self {
+        for c in convert {
+            if !self.convert.contains(c) {
+                self.convert.push(*c);
+            }
+        }
+        self
+    }
+
+    /// Writes the sidecars the selected converters produce for `payload`.
+    pub fn emit(
+        &self,
+        ser: &dyn NativeSerializer,
+        payload: &NativePayload,
+        dir: &Path,
+        stem: &str,
+    ) -> Result<Vec<Sidecar>> {
+        let mut out = Vec::new();
+        for c in &self.convert {
+            match c {
+                Convert::Native => out.extend(
+                    ser.emit_external(payload, dir, stem)?
+                        .into_iter()
+                        .map(|path| Sidecar {
+                            path,
+                            role: SidecarRole::Source,
+                        }),
+                ),
+                Convert::Png => {
+                    if let Some(path) = ser.emit_preview(payload, dir, stem)? {
+                        out.push(Sidecar {
+                            path,
+                            role: SidecarRole::Preview,
+                        });
+                    }
+                }
+                Convert::Json => {
+                    if let Some(v) = ser.info(payload) {
+                        let path = dir.join(format!("{stem}.json"));
+                        let text = serde_json::to_string_pretty(&v).map_err(Error::other)?;
+                        readonly::write(&path, text)?;
+                        out.push(Sidecar {
+                            path,
+                            role: SidecarRole::Info,
+                        });
+                    }
+                }
+            }
         }
+        Ok(out)
     }
 
     pub fn standard() -> Self {
@@ -1,14 +1,21 @@
 use std::{
-    fs::File,
-    io::{Cursor, Result, Write},
+    io::{Cursor, Error, ErrorKind, Result, Write},
     path::{Path, PathBuf},
 };
 
 use byteorder::{LittleEndian, WriteBytesExt};
 
 use crate::{
-    native::{BulkBlock, NativePayload, NativeRead, NativeReadCtx, NativeSerializer},
+    native::{
+        AssetInfo, BulkBlock, NativeInjectCtx, NativePayload, NativeRead, NativeReadCtx,
+        NativeSerializer, SoundWaveInfo, deflate_bulk, inflate_bulk, info::BulkInfo,
+    },
     upkprops::{Property, PropertyValue},
+    utils::{
+        lossy::{self, Code},
+        readonly,
+        term::{self, Color, paint},
+    },
 };
 
 #[derive(Debug, Clone)]
@@ -127,6 +134,65 @@ fn prop_int_array(props: &[Property], name: &str) -> Vec<i32> {
     }
 }
 
+fn set_prop(props: &mut [Property], name: &str, value: PropertyValue) {
+    if let Some(p) = props.iter_mut().find(|p| p.name == name) {
+        p.value = value;
+    }
+}
+
+/// Channel counts and rates the engine's Vorbis decoder plays.
+const MAX_CHANNELS: u8 = 6;
+const SAMPLE_RATES: std::ops::RangeInclusive<u32> = 8000..=48000;
+
+struct VorbisInfo {
+    channels: u8,
+    sample_rate: u32,
+    duration: Option<f32>,
+}
+
+/// The identification header of an Ogg Vorbis stream, and its length
+/// from the last page's granule position.
+fn vorbis_info(bytes: &[u8]) -> Result<VorbisInfo> {
+    let bad = |msg: String| Error::new(ErrorKind::InvalidData, msg);
+    if !bytes.starts_with(b"OggS") {
+        return Err(bad(format!(
+            "not an Ogg file ({}); encode it as Ogg Vorbis",
+            AudioSniff::of(bytes).label()
+        )));
+    }
+    let segments = *bytes
+        .get(26)
+        .ok_or_else(|| bad("truncated Ogg page".into()))? as usize;
+    let packet = bytes
+        .get(27 + segments..)
+        .filter(|p| p.len() >= 16)
+        .ok_or_else(|| bad("truncated Ogg page".into()))?;
+    if packet.starts_with(b"OpusHead") {
+        return Err(bad(
+            "Ogg Opus isn't supported; encode it as Ogg Vorbis".into()
+        ));
+    }
+    if &packet[..7] != b"\x01vorbis" {
+        return Err(bad(
+            "Ogg stream doesn't start with a Vorbis header; encode it as Ogg Vorbis".into(),
+        ));
+    }
+    let channels = packet[11];
+    let sample_rate = u32::from_le_bytes([packet[12], packet[13], packet[14], packet[15]]);
+
+    let last = bytes.windows(4).rposition(|w| w == b"OggS").unwrap_or(0);
+    let granule = bytes
+        .get(last + 6..last + 14)
+        .map(|g| i64::from_le_bytes(g.try_into().unwrap()))
+        .unwrap_or(-1);
+    let duration = (granule > 0 && sample_rate > 0).then(|| granule as f32 / sample_rate as f32);
+    Ok(VorbisInfo {
+        channels,
+        sample_rate,
+        duration,
+    })
+}
+
 fn wrap_pcm_as_wav(
     pcm: &[u8],
     sample_rate: u32,
@@ -180,10 +246,13 @@ impl NativeSerializer for SoundNodeWaveSer {
             Vec::new()
         };
         if !trailing_raw.is_empty() {
-            eprintln!(
-                "  \x1b[33msnd\x1b[0m: {} trailing bytes after 4 bulk blocks (ver={}); preserved as raw",
-                trailing_raw.len(),
-                ctx.ver
+            lossy::warn(
+                Code::TrailingBytes,
+                format_args!(
+                    "{} trailing bytes after 4 bulk blocks (ver={}); preserved as raw",
+                    trailing_raw.len(),
+                    ctx.ver
+                ),
             );
         }
 
@@ -220,10 +289,11 @@ impl NativeSerializer for SoundNodeWaveSer {
             let sniff = AudioSniff::of(&p.compressed_pc.data);
             let ext = sniff.extension();
             let path = dir.join(format!("{stem}.{ext}"));
-            File::create(&path)?.write_all(&p.compressed_pc.data)?;
+            readonly::create(&path)?.write_all(&p.compressed_pc.data)?;
             println!(
-                "  \x1b[36msnd\x1b[0m → \x1b[32m{}\x1b[0m  ({} bytes, {})",
-                path.display(),
+                "  {} → {}  ({} bytes, {})",
+                paint(Color::Cyan, "snd"),
+                paint(Color::Green, path.display()),
                 p.compressed_pc.data.len(),
                 sniff.label()
             );
@@ -238,12 +308,15 @@ impl NativeSerializer for SoundNodeWaveSer {
                     let sr = p.sample_rate.unwrap_or(0).max(0) as u32;
                     let ch = p.num_channels.unwrap_or(1).max(1) as u16;
                     if sr == 0 {
-                        eprintln!(
-                            "  \x1b[33msnd\x1b[0m: {stem} RawData present but SampleRate=0; \
-                             writing .pcm with no header"
+                        lossy::warn(
+                            Code::PcmNoHeader,
+                            format_args!(
+                                "{stem} RawData present but SampleRate=0; \
+                                 writing .pcm with no header"
+                            ),
                         );
                         let path = dir.join(format!("{stem}.raw.pcm"));
-                        File::create(&path)?.write_all(&p.raw_data.data)?;
+                        readonly::create(&path)?.write_all(&p.raw_data.data)?;
                         out.push(path);
                         return Ok(out);
                     }
@@ -251,10 +324,11 @@ impl NativeSerializer for SoundNodeWaveSer {
                 }
             };
             let path = dir.join(format!("{stem}.raw.wav"));
-            File::create(&path)?.write_all(&bytes)?;
+            readonly::create(&path)?.write_all(&bytes)?;
             println!(
-                "  \x1b[36msnd\x1b[0m → \x1b[32m{}\x1b[0m  ({} bytes raw PCM)",
-                path.display(),
+                "  {} → {}  ({} bytes raw PCM)",
+                paint(Color::Cyan, "snd"),
+                paint(Color::Green, path.display()),
                 p.raw_data.data.len()
             );
             out.push(path);
@@ -270,10 +344,11 @@ impl NativeSerializer for SoundNodeWaveSer {
             let sniff = AudioSniff::of(&block.data);
             let ext = sniff.extension();
             let path = dir.join(format!("{stem}.{suffix}.{ext}"));
-            File::create(&path)?.write_all(&block.data)?;
+            readonly::create(&path)?.write_all(&block.data)?;
             println!(
-                "  \x1b[36msnd\x1b[0m → \x1b[32m{}\x1b[0m  ({} bytes, {})",
-                path.display(),
+                "  {} → {}  ({} bytes, {})",
+                paint(Color::Cyan, "snd"),
+                paint(Color::Green, path.display()),
                 block.data.len(),
                 sniff.label()
             );
@@ -282,4 +357,115 @@ impl NativeSerializer for SoundNodeWaveSer {
 
         Ok(out)
     }
+
+    /// Replaces the PC stream from an edited `.ogg`. `.raw.wav` is editor
+    /// source data and the console streams come from their cookers, so
+    /// neither is read back.
+    fn inject_external(&self, ctx: &mut NativeInjectCtx) -> Result<bool> {
+        let sidecar = ctx.sidecars.iter().find(|f| {
+            let l = f.to_ascii_lowercase();
+            l.ends_with(".ogg") && !l.ends_with(".xbox360.ogg") && !l.ends_with(".ps3.ogg")
+        });
+        let fname = match sidecar {
+            Some(f) => f,
+            None => return Ok(false),
+        };
+
+        let path = ctx.sidecar_dir.join(fname);
+        if !path.exists() {
+            term::warn(
+                "snd",
+                format_args!(
+                    "sidecar '{fname}' not found next to the .uo; \
+                     keeping original audio"
+                ),
+            );
+            return Ok(false);
+        }
+        let bytes = std::fs::read(&path)?;
+        let tail = &ctx.native_tail[..];
+        let mut c = Cursor::new(tail);
+        BulkBlock::read(&mut c)?;
+        let start = c.position() as usize;
+        let pc = BulkBlock::read(&mut c)?;
+        let end = c.position() as usize;
+        // Untouched since extract: keep the block as it was, flags and all.
+        if !pc.is_external() && inflate_bulk(pc.flags, &pc.data)? == bytes {
+            return Ok(false);
+        }
+
+        let ctx_err = |msg: String| Error::new(ErrorKind::InvalidData, format!("{fname}: {msg}"));
+        let info = vorbis_info(&bytes).map_err(|e| ctx_err(e.to_string()))?;
+        if info.channels == 0 || info.channels > MAX_CHANNELS {
+            return Err(ctx_err(format!(
+                "{} channel(s); the engine plays 1 to {MAX_CHANNELS}",
+                info.channels
+            )));
+        }
+        if !SAMPLE_RATES.contains(&info.sample_rate) {
+            return Err(ctx_err(format!(
+                "{} Hz; resample to between {} and {} Hz",
+                info.sample_rate,
+                SAMPLE_RATES.start(),
+                SAMPLE_RATES.end()
+            )));
+        }
+
+        let (flags, stored) = deflate_bulk(pc.flags, &bytes, ctx.bulk)?;
+        let pc = BulkBlock {
+            flags,
+            element_count: bytes.len() as i32,
+            size_on_disk: stored.len() as i32,
+            offset_in_file: 0,
+            data: stored,
+        };
+        let mut new_tail = tail[..start].to_vec();
+        pc.write(&mut new_tail)?;
+        new_tail.extend_from_slice(&tail[end..]);
+        *ctx.native_tail = new_tail;
+
+        // The engine sets up playback from these, not from the stream.
+        set_prop(
+            ctx.props,
+            "NumChannels",
+            PropertyValue::Int(info.channels as i32),
+        );
+        set_prop(
+            ctx.props,
+            "SampleRate",
+            PropertyValue::Int(info.sample_rate as i32),
+        );
+        if let Some(d) = info.duration {
+            set_prop(ctx.props, "Duration", PropertyValue::Float(d));
+        }
+
+        println!(
+            "  {} ← {}  ({} bytes, {} ch, {} Hz)",
+            paint(Color::Cyan, "snd"),
+            paint(Color::Green, fname),
+            bytes.len(),
+            info.channels,
+            info.sample_rate
+        );
+        Ok(true)
+    }
+
+    fn info(&self, payload: &NativePayload) -> Option<AssetInfo> {
+        let NativePayload::SoundNodeWave(p) = payload else {
+            return None;
+        };
+        let block = |b: &BulkBlock| BulkInfo {
+            bytes: b.data.len(),
+            format: AudioSniff::of(&b.data).label().to_string(),
+        };
+        Some(AssetInfo::SoundNodeWave(SoundWaveInfo {
+            num_channels: p.num_channels,
+            sample_rate: p.sample_rate,
+            duration: p.duration,
+            raw_data: block(&p.raw_data),
+            compressed_pc: block(&p.compressed_pc),
+            compressed_xbox360: block(&p.compressed_xbox360),
+            compressed_ps3: block(&p.compressed_ps3),
+        }))
+    }
 }
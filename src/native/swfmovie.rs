@@ -1,12 +1,18 @@
 use std::{
-    fs::File,
-    io::{Result, Write},
+    io::{Error, ErrorKind, Read, Result, Write},
     path::{Path, PathBuf},
 };
 
+use flate2::read::ZlibDecoder;
+
 use crate::{
-    native::{NativePayload, NativeRead, NativeReadCtx, NativeSerializer},
+    native::{AssetInfo, NativePayload, NativeRead, NativeReadCtx, NativeSerializer, SwfMovieInfo},
     upkprops::PropertyValue,
+    utils::{
+        lossy::{self, Code},
+        readonly,
+        term::{self, Color, paint},
+    },
 };
 
 use super::NativeInjectCtx;
@@ -17,6 +23,58 @@ pub struct SwfMoviePayload {
     pub recovered_via_schema: bool,
 }
 
+/// What the movie player loads: a `GFX` file, or `CFX` with the body
+/// zlib'd, whose header length is the whole uncompressed file.
+fn check_gfx(bytes: &[u8]) -> Result<()> {
+    if bytes.len() < 8 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} bytes is too short for a GFx header", bytes.len()),
+        ));
+    }
+    let declared = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as u64;
+    let actual = match &bytes[..3] {
+        b"GFX" => bytes.len() as u64,
+        b"CFX" => {
+            let mut body = Vec::new();
+            ZlibDecoder::new(&bytes[8..])
+                .read_to_end(&mut body)
+                .map_err(|e| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("compressed GFx body doesn't inflate: {e}"),
+                    )
+                })?;
+            8 + body.len() as u64
+        }
+        b"FWS" | b"CWS" | b"ZWS" => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "plain SWF, not GFx; convert it with gfxexport before importing",
+            ));
+        }
+        magic => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "not a GFx file (starts with {:02x} {:02x} {:02x})",
+                    magic[0], magic[1], magic[2]
+                ),
+            ));
+        }
+    };
+    if declared != actual {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "header says {declared} bytes but the movie is {actual}; \
+                 truncated, or patched without updating the header"
+            ),
+        ));
+    }
+    Ok(())
+}
+
 pub struct SwfMovieSer;
 
 impl NativeSerializer for SwfMovieSer {
@@ -73,9 +131,12 @@ impl NativeSerializer for SwfMovieSer {
             _ => return Ok(Vec::new()),
         };
         if p.raw_data.is_empty() {
-            eprintln!(
-                "  \x1b[33mgfx\x1b[0m: {stem} has no RawData payload — \
-                 check that the SwfMovie/GFxMovieInfo export actually carries Flash bytes"
+            term::warn(
+                "gfx",
+                format_args!(
+                    "{stem} has no RawData payload — \
+                     check that the SwfMovie/GFxMovieInfo export actually carries Flash bytes"
+                ),
             );
             return Ok(Vec::new());
         }
@@ -86,23 +147,38 @@ impl NativeSerializer for SwfMovieSer {
             || head.starts_with(b"FWS")
             || head.starts_with(b"ZWS"))
         {
-            eprintln!(
-                "  \x1b[33mgfx\x1b[0m: {stem} RawData magic 0x{:02x?} does not look like Flash; \
-                 writing anyway",
-                head
+            lossy::warn(
+                Code::NotFlash,
+                format_args!(
+                    "{stem} RawData magic 0x{:02x?} does not look like Flash; \
+                     writing anyway",
+                    head
+                ),
             );
         }
 
         let gfx_path = dir.join(format!("{stem}.gfx"));
-        File::create(&gfx_path)?.write_all(&p.raw_data)?;
+        readonly::create(&gfx_path)?.write_all(&p.raw_data)?;
         println!(
-            "  \x1b[36mgfx\x1b[0m → \x1b[32m{}\x1b[0m  ({} bytes)",
-            gfx_path.display(),
+            "  {} → {}  ({} bytes)",
+            paint(Color::Cyan, "gfx"),
+            paint(Color::Green, gfx_path.display()),
             p.raw_data.len()
         );
         Ok(vec![gfx_path])
     }
 
+    fn info(&self, payload: &NativePayload) -> Option<AssetInfo> {
+        let NativePayload::SwfMovie(p) = payload else {
+            return None;
+        };
+        let magic = &p.raw_data[..p.raw_data.len().min(3)];
+        Some(AssetInfo::SwfMovie(SwfMovieInfo {
+            bytes: p.raw_data.len(),
+            magic: String::from_utf8_lossy(magic).into_owned(),
+        }))
+    }
+
     fn inject_external(&self, ctx: &mut NativeInjectCtx) -> Result<bool> {
         let sidecar = ctx.sidecars.iter().find(|f| {
             let l = f.to_ascii_lowercase();
@@ -115,21 +191,28 @@ impl NativeSerializer for SwfMovieSer {
 
         let path = ctx.sidecar_dir.join(fname);
         if !path.exists() {
-            eprintln!(
-                "  \x1b[33mgfx\x1b[0m: sidecar '{fname}' not found next to the .uo; \
-                 keeping original RawData"
+            term::warn(
+                "gfx",
+                format_args!(
+                    "sidecar '{fname}' not found next to the .uo; \
+                     keeping original RawData"
+                ),
             );
             return Ok(false);
         }
         let bytes = std::fs::read(&path)?;
+        check_gfx(&bytes).map_err(|e| Error::new(e.kind(), format!("{fname}: {e}")))?;
 
         let target = ctx.externalized_prop.as_deref().unwrap_or("RawData");
         let prop = match ctx.props.iter_mut().find(|p| p.name == target) {
             Some(p) => p,
             None => {
-                eprintln!(
-                    "  \x1b[33mgfx\x1b[0m: property '{target}' is not in the original \
-                     export; cannot inject '{fname}'"
+                term::warn(
+                    "gfx",
+                    format_args!(
+                        "property '{target}' is not in the original \
+                         export; cannot inject '{fname}'"
+                    ),
                 );
                 return Ok(false);
             }
@@ -146,16 +229,21 @@ impl NativeSerializer for SwfMovieSer {
                 *buf = nb;
             }
             _ => {
-                eprintln!(
-                    "  \x1b[33mgfx\x1b[0m: property '{target}' is not a byte array; \
-                     cannot inject '{fname}'"
+                term::warn(
+                    "gfx",
+                    format_args!(
+                        "property '{target}' is not a byte array; \
+                         cannot inject '{fname}'"
+                    ),
                 );
                 return Ok(false);
             }
         }
 
         println!(
-            "  \x1b[36mgfx\x1b[0m ← \x1b[32m{fname}\x1b[0m  ({} bytes) → {target}",
+            "  {} ← {}  ({} bytes) → {target}",
+            paint(Color::Cyan, "gfx"),
+            paint(Color::Green, fname),
             bytes.len()
         );
         Ok(true)
@@ -9,10 +9,19 @@ use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    native::{NativePayload, NativeRead, NativeReadCtx, NativeSerializer},
+    native::{
+        AssetInfo, BulkCompression, NativePayload, NativeRead, NativeReadCtx, NativeSerializer,
+        Texture2DInfo, deflate_bulk, inflate_bulk, info::MipInfo,
+    },
     schemadb::SchemaDb,
     upkprops::{Property, PropertyValue},
-    utils::dds::{Dds, DdsMip, PixelFormat},
+    utils::{
+        dds::{Dds, DdsMip, PixelFormat, mip_to_rgba},
+        heuristics,
+        lossy::{self, Code},
+        png, readonly,
+        term::{self, Color, paint},
+    },
     versions::{
         BULKDATA_SERIALIZE_COMPRESSED, BULKDATA_STORE_IN_SEPARATE_FILE,
         VER_ADDED_CACHED_IPHONE_DATA, VER_ADDED_TEXTURE_FILECACHE_GUIDS, VER_ANDROID_ETC_SEPARATED,
@@ -72,12 +81,11 @@ fn read_bulk_data<R: Read + Seek>(r: &mut R) -> Result<(u32, i32, i32, i32, Vec<
 
 fn read_indirect_mips<R: Read + Seek>(r: &mut R) -> Result<Vec<Mip>> {
     let count = r.read_i32::<LittleEndian>()?;
-    if !(0..=64).contains(&count) {
-        return Err(Error::new(
-            ErrorKind::InvalidData,
-            format!("implausible mip count {count}"),
-        ));
-    }
+    heuristics::check(
+        "max-mips",
+        count as i64,
+        format_args!("implausible mip count {count}"),
+    )?;
     let mut mips = Vec::with_capacity(count as usize);
     for _ in 0..count {
         let (flags, element_count, size_on_disk, offset_in_file, data) = read_bulk_data(r)?;
@@ -119,6 +127,35 @@ fn skip_byte_bulk_data<R: Read + Seek>(r: &mut R) -> Result<()> {
 }
 
 impl Texture2DPayload {
+    /// Pixels of the largest loaded mip no wider or taller than `max_side`
+    /// (the smallest when all are bigger; the largest of all without a
+    /// limit). `None` when the format has no decoder or no mip is loaded.
+    pub fn preview_rgba(&self, max_side: Option<u32>) -> Result<Option<(u32, u32, Vec<u8>)>> {
+        let Some(pf) = self
+            .format_label
+            .as_deref()
+            .and_then(PixelFormat::from_pf_label)
+        else {
+            return Ok(None);
+        };
+        let mut loaded: Vec<&Mip> = self
+            .mips
+            .iter()
+            .filter(|m| !m.data.is_empty() && m.size_x > 0 && m.size_y > 0)
+            .collect();
+        loaded.sort_by_key(|m| m.size_x as u64 * m.size_y as u64);
+        let Some(mip) = loaded
+            .iter()
+            .rev()
+            .find(|m| max_side.is_none_or(|max| m.size_x.max(m.size_y) as u32 <= max))
+            .or(loaded.first())
+        else {
+            return Ok(None);
+        };
+        let (w, h) = (mip.size_x as u32, mip.size_y as u32);
+        Ok(Some((w, h, mip_to_rgba(pf, w, h, &mip.data)?)))
+    }
+
     fn parse_bytes(tail: &[u8], ver: i16) -> Result<Self> {
         let mut c = Cursor::new(tail);
         let _source_art = BulkBlock::read(&mut c)?;
@@ -152,10 +189,13 @@ impl Texture2DPayload {
             && (ver >= VER_VERSION_NUMBER_FIX_FOR_FLASH_TEXTURES
                 || ver >= VER_ANDROID_ETC_SEPARATED)
         {
-            eprintln!(
-                "  \x1b[33mtex\x1b[0m: {} trailing bytes after PVRTC mips (ver={}); preserved as raw",
-                trailing_raw.len(),
-                ver
+            lossy::warn(
+                Code::TrailingBytes,
+                format_args!(
+                    "{} trailing bytes after PVRTC mips (ver={}); preserved as raw",
+                    trailing_raw.len(),
+                    ver
+                ),
             );
         }
 
@@ -197,18 +237,13 @@ fn resolve_tfc_payload(mip: &Mip, tfc_stem: &str, db: &SchemaDb) -> Result<Optio
     if mip.flags & BULKDATA_STORE_IN_SEPARATE_FILE == 0 {
         return Ok(None);
     }
-    if mip.flags & BULKDATA_SERIALIZE_COMPRESSED != 0 {
-        eprintln!(
-            "  \x1b[33mtfc\x1b[0m: compressed payload in '{tfc_stem}.tfc' \
-             (flags=0x{:x}) — skipping inline extract",
-            mip.flags
-        );
-        return Ok(None);
-    }
     let path = match db.tfc_index.get(&tfc_stem.to_ascii_lowercase()) {
         Some(p) => p.clone(),
         None => {
-            eprintln!("  \x1b[33mtfc\x1b[0m: '{tfc_stem}.tfc' not in --game-root index");
+            lossy::warn(
+                Code::MipsUnresolved,
+                format_args!("'{tfc_stem}.tfc' not in --game-root index"),
+            );
             return Ok(None);
         }
     };
@@ -216,7 +251,7 @@ fn resolve_tfc_payload(mip: &Mip, tfc_stem: &str, db: &SchemaDb) -> Result<Optio
     f.seek(SeekFrom::Start(mip.offset_in_file as u64))?;
     let mut buf = vec![0u8; mip.size_on_disk as usize];
     f.read_exact(&mut buf)?;
-    Ok(Some(buf))
+    Ok(Some(inflate_bulk(mip.flags, &buf)?))
 }
 
 pub struct Texture2DSer;
@@ -228,6 +263,11 @@ impl NativeSerializer for Texture2DSer {
 
     fn read(&self, ctx: &NativeReadCtx) -> Result<NativeRead> {
         let mut payload = Texture2DPayload::parse_bytes(ctx.blob, ctx.ver)?;
+        for mip in payload.mips.iter_mut() {
+            if mip.source == MipSource::Inline && mip.flags & BULKDATA_SERIALIZE_COMPRESSED != 0 {
+                mip.data = inflate_bulk(mip.flags, &mip.data)?;
+            }
+        }
 
         payload.format_label = prop_enum_label(ctx.props, "Format").map(str::to_string);
         payload.tfc_name = prop_string_or_name(ctx.props, "TextureFileCacheName", ctx.pak)
@@ -272,9 +312,12 @@ impl NativeSerializer for Texture2DSer {
         {
             Some(pf) => pf,
             None => {
-                eprintln!(
-                    "  \x1b[33mtex\x1b[0m: unmapped pixel format '{}' for {stem}; no .dds emitted",
-                    p.format_label.as_deref().unwrap_or("?")
+                lossy::warn(
+                    Code::PixelFormatUnmapped,
+                    format_args!(
+                        "unmapped pixel format '{}' for {stem}; no .dds emitted",
+                        p.format_label.as_deref().unwrap_or("?")
+                    ),
                 );
                 return Ok(Vec::new());
             }
@@ -292,9 +335,12 @@ impl NativeSerializer for Texture2DSer {
             .collect();
 
         if dds_mips.is_empty() {
-            eprintln!(
-                "  \x1b[33mtex\x1b[0m: no resolvable mips for {stem} (TFC '{}'); no .dds emitted",
-                p.tfc_name.as_deref().unwrap_or("?")
+            lossy::warn(
+                Code::MipsUnresolved,
+                format_args!(
+                    "no resolvable mips for {stem} (TFC '{}'); no .dds emitted",
+                    p.tfc_name.as_deref().unwrap_or("?")
+                ),
             );
             return Ok(Vec::new());
         }
@@ -307,17 +353,70 @@ impl NativeSerializer for Texture2DSer {
         };
         let bytes = dds.encode()?;
         let dds_path = dir.join(format!("{stem}.dds"));
-        File::create(&dds_path)?.write_all(&bytes)?;
+        readonly::create(&dds_path)?.write_all(&bytes)?;
 
         println!(
-            "  \x1b[36mtexture\x1b[0m → \x1b[32m{}\x1b[0m  ({} mips, {})",
-            dds_path.display(),
+            "  {} → {}  ({} mips, {})",
+            paint(Color::Cyan, "texture"),
+            paint(Color::Green, dds_path.display()),
             dds.mips.len(),
-            pf.as_pf_label(),
+            pf.as_pf_label()
         );
         Ok(vec![dds_path])
     }
 
+    fn emit_preview(
+        &self,
+        payload: &NativePayload,
+        dir: &Path,
+        stem: &str,
+    ) -> Result<Option<PathBuf>> {
+        let NativePayload::Texture2D(p) = payload else {
+            return Ok(None);
+        };
+        let (w, h, rgba) = match p.preview_rgba(None) {
+            Ok(Some(px)) => px,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                term::warn("tex", format_args!("no .png for {stem}: {e}"));
+                return Ok(None);
+            }
+        };
+        let png_path = dir.join(format!("{stem}.png"));
+        readonly::write(&png_path, png::encode_rgba(w, h, &rgba)?)?;
+        println!(
+            "  {} → {}  ({w}x{h} preview)",
+            paint(Color::Cyan, "texture"),
+            paint(Color::Green, png_path.display())
+        );
+        Ok(Some(png_path))
+    }
+
+    fn info(&self, payload: &NativePayload) -> Option<AssetInfo> {
+        let NativePayload::Texture2D(p) = payload else {
+            return None;
+        };
+        let mips = p
+            .mips
+            .iter()
+            .map(|m| MipInfo {
+                width: m.size_x,
+                height: m.size_y,
+                bytes: m.data.len(),
+                source: match &m.source {
+                    MipSource::Inline => "inline".to_string(),
+                    MipSource::Tfc { stem_lc } => format!("tfc:{stem_lc}"),
+                    MipSource::Missing => "missing".to_string(),
+                },
+            })
+            .collect();
+        Some(AssetInfo::Texture2D(Texture2DInfo {
+            format: p.format_label.clone(),
+            texture_file_cache: p.tfc_name.clone(),
+            mips,
+        }))
+    }
+
     fn inject_external(&self, ctx: &mut NativeInjectCtx) -> Result<bool> {
         let sidecar = ctx
             .sidecars
@@ -330,23 +429,29 @@ impl NativeSerializer for Texture2DSer {
 
         let path = ctx.sidecar_dir.join(fname);
         if !path.exists() {
-            eprintln!(
-                "  \x1b[33mtex\x1b[0m: sidecar '{fname}' not found next to the .uo; \
-                 keeping original mips"
+            term::warn(
+                "tex",
+                format_args!(
+                    "sidecar '{fname}' not found next to the .uo; \
+                     keeping original mips"
+                ),
             );
             return Ok(false);
         }
         let bytes = std::fs::read(&path)?;
-        let dds = Dds::decode(&bytes)?;
+        let dds = Dds::decode(&bytes).map_err(|e| Error::new(e.kind(), format!("{fname}: {e}")))?;
 
         let expected = prop_enum_label(ctx.props, "Format").and_then(PixelFormat::from_pf_label);
-        let new_tail = reinject_mips_from_dds(ctx.native_tail, &dds, expected, ctx.ver)?;
+        let new_tail = reinject_mips_from_dds(ctx.native_tail, &dds, expected, ctx.bulk)
+            .map_err(|e| Error::new(e.kind(), format!("{fname}: {e}")))?;
         *ctx.native_tail = new_tail;
 
         println!(
-            "  \x1b[36mtexture\x1b[0m ← \x1b[32m{fname}\x1b[0m  ({} mip(s), {})",
+            "  {} ← {}  ({} mip(s), {})",
+            paint(Color::Cyan, "texture"),
+            paint(Color::Green, fname),
             dds.mips.len(),
-            dds.format.as_pf_label(),
+            dds.format.as_pf_label()
         );
         Ok(true)
     }
@@ -356,17 +461,20 @@ pub fn reinject_mips_from_dds(
     tail: &[u8],
     dds: &Dds,
     expected_format: Option<PixelFormat>,
-    _ver: i16,
+    bulk: BulkCompression,
 ) -> Result<Vec<u8>> {
-    if let Some(exp) = expected_format {
-        if exp != dds.format {
-            eprintln!(
-                "  \x1b[33mtex\x1b[0m: DDS is {} but the texture's Format is {}; \
-                 injecting anyway — make sure that's intended",
+    // The engine sizes mips by Format, so other data is read past its end.
+    if let Some(exp) = expected_format.filter(|&f| f != dds.format) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "DDS is {} but the texture's Format is {}; re-save the DDS as {} \
+                 or set Format in the .uo",
                 dds.format.as_pf_label(),
                 exp.as_pf_label(),
-            );
-        }
+                exp.as_pf_label(),
+            ),
+        ));
     }
 
     let mut c = Cursor::new(tail);
@@ -383,10 +491,11 @@ pub fn reinject_mips_from_dds(
     let mut matched = 0usize;
     for mip in mips.iter_mut() {
         if let Some(data) = by_dim.get(&(mip.size_x, mip.size_y)) {
-            mip.data = (*data).clone();
-            mip.flags &= !(BULKDATA_STORE_IN_SEPARATE_FILE | BULKDATA_SERIALIZE_COMPRESSED);
-            mip.size_on_disk = mip.data.len() as i32;
-            mip.element_count = mip.data.len() as i32;
+            let (flags, stored) = deflate_bulk(mip.flags, data, bulk)?;
+            mip.flags = flags;
+            mip.element_count = data.len() as i32;
+            mip.size_on_disk = stored.len() as i32;
+            mip.data = stored;
             mip.offset_in_file = 0;
             mip.source = MipSource::Inline;
             matched += 1;
@@ -408,10 +517,13 @@ pub fn reinject_mips_from_dds(
         ));
     }
     if matched < dds.mips.len() {
-        eprintln!(
-            "  \x1b[33mtex\x1b[0m: {} of {} DDS mip(s) had no matching slot and were ignored",
-            dds.mips.len() - matched,
-            dds.mips.len()
+        lossy::warn(
+            Code::DdsMipsIgnored,
+            format_args!(
+                "{} of {} DDS mip(s) had no matching slot and were ignored",
+                dds.mips.len() - matched,
+                dds.mips.len()
+            ),
         );
     }
 
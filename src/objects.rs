@@ -0,0 +1,209 @@
+//! Typed access to the exports `native` has parsers for, so library users
+//! get a `Texture2DPayload` rather than a property list and a byte tail:
+//!
+//! ```ignore
+//! let lp = open_package_file(path)?;
+//! for tex in lp.objects_of_class::<Texture2D>() {
+//!     let tex = tex?;
+//!     println!("{} {:?} {} mips", tex.name, tex.payload.format_label, tex.payload.mips.len());
+//! }
+//! ```
+//!
+//! Without a [`SchemaDb`] only exports whose class is exactly the one
+//! asked for match; with one (`objects_of_class_in`) subclasses do too,
+//! and texture mips stored in TFCs are loaded.
+
+use std::{
+    io::{Error, ErrorKind, Result},
+    marker::PhantomData,
+};
+
+use crate::{
+    native::{
+        NativePayload, NativeReadCtx, NativeSerializer, SoundNodeWavePayload, SoundNodeWaveSer,
+        SwfMoviePayload, SwfMovieSer, Texture2DPayload, Texture2DSer,
+    },
+    schemadb::{LazyPackage, ResolvedRef, SchemaDb},
+    upkprops::Property,
+    utils::lossy,
+};
+
+/// A class with a native parser and the payload type it produces.
+pub trait NativeClass {
+    type Payload;
+
+    /// Class names this matches; the first is the canonical one.
+    const CLASSES: &'static [&'static str];
+
+    fn serializer() -> &'static dyn NativeSerializer;
+
+    fn unwrap(payload: NativePayload) -> Option<Self::Payload>;
+}
+
+pub struct Texture2D;
+pub struct SwfMovie;
+pub struct SoundNodeWave;
+
+impl NativeClass for Texture2D {
+    type Payload = Texture2DPayload;
+    const CLASSES: &'static [&'static str] = &["Texture2D"];
+
+    fn serializer() -> &'static dyn NativeSerializer {
+        &Texture2DSer
+    }
+
+    fn unwrap(payload: NativePayload) -> Option<Self::Payload> {
+        match payload {
+            NativePayload::Texture2D(p) => Some(p),
+            _ => None,
+        }
+    }
+}
+
+impl NativeClass for SwfMovie {
+    type Payload = SwfMoviePayload;
+    const CLASSES: &'static [&'static str] = &["SwfMovie", "GFxMovieInfo"];
+
+    fn serializer() -> &'static dyn NativeSerializer {
+        &SwfMovieSer
+    }
+
+    fn unwrap(payload: NativePayload) -> Option<Self::Payload> {
+        match payload {
+            NativePayload::SwfMovie(p) => Some(p),
+            _ => None,
+        }
+    }
+}
+
+impl NativeClass for SoundNodeWave {
+    type Payload = SoundNodeWavePayload;
+    const CLASSES: &'static [&'static str] = &["SoundNodeWave"];
+
+    fn serializer() -> &'static dyn NativeSerializer {
+        &SoundNodeWaveSer
+    }
+
+    fn unwrap(payload: NativePayload) -> Option<Self::Payload> {
+        match payload {
+            NativePayload::SoundNodeWave(p) => Some(p),
+            _ => None,
+        }
+    }
+}
+
+/// One export, parsed by its class's native parser.
+#[derive(Debug, Clone)]
+pub struct TypedObject<P> {
+    /// 1-based export index.
+    pub index: i32,
+    /// Full object path, `Outer.Name`.
+    pub name: String,
+    /// The export's own class, which may be a subclass of the one asked for.
+    pub class: String,
+    pub props: Vec<Property>,
+    pub payload: P,
+}
+
+pub struct ObjectsOfClass<'a, T: NativeClass> {
+    lp: &'a LazyPackage,
+    db: Option<&'a SchemaDb>,
+    next: i32,
+    _class: PhantomData<T>,
+}
+
+impl<T: NativeClass> ObjectsOfClass<'_, T> {
+    fn matches(&self, i: i32) -> bool {
+        if T::CLASSES.contains(&self.lp.export_class_name(i).as_str()) {
+            return true;
+        }
+        let (Some(db), Some(cref)) = (self.db, self.lp.export_class_ref(i, self.db)) else {
+            return false;
+        };
+        db.class_chain(&cref).is_ok_and(|chain| {
+            chain.iter().any(|link| {
+                db.export_object_name(link)
+                    .is_some_and(|n| T::CLASSES.contains(&n.as_str()))
+            })
+        })
+    }
+
+    fn read(&self, i: i32) -> Result<TypedObject<T::Payload>> {
+        let lp = self.lp;
+        let _at = lossy::at(lp.export_full_name(i));
+        let blob = lp.export_blob(i)?;
+        let (props, end) = lp.export_props(i, self.db)?;
+        let read = T::serializer().read(&NativeReadCtx {
+            blob: blob.get(end..).unwrap_or_default(),
+            props: &props,
+            ver: lp.header.p_ver,
+            l_ver: lp.header.l_ver,
+            pak: &lp.pak,
+            db: self.db,
+            self_ref: Some(ResolvedRef {
+                stem_lc: lp.stem_lc.clone(),
+                export_idx: i,
+            }),
+            class_ref: lp.export_class_ref(i, self.db),
+        })?;
+        let name = lp.export_full_name(i);
+        let payload = T::unwrap(read.payload).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{name}: not a {} payload", T::CLASSES[0]),
+            )
+        })?;
+        Ok(TypedObject {
+            index: i,
+            name,
+            class: lp.export_class_name(i),
+            props,
+            payload,
+        })
+    }
+}
+
+impl<T: NativeClass> Iterator for ObjectsOfClass<'_, T> {
+    type Item = Result<TypedObject<T::Payload>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while (self.next as usize) <= self.lp.pak.export_table.len() {
+            let i = self.next;
+            self.next += 1;
+            if self.matches(i) {
+                return Some(self.read(i));
+            }
+        }
+        None
+    }
+}
+
+impl LazyPackage {
+    /// Exports of class `T`, parsed one at a time as the iterator advances.
+    pub fn objects_of_class<T: NativeClass>(&self) -> ObjectsOfClass<'_, T> {
+        self.objects_of_class_in(None)
+    }
+
+    /// Like `objects_of_class`, resolving classes and TFCs through `db`.
+    pub fn objects_of_class_in<'a, T: NativeClass>(
+        &'a self,
+        db: Option<&'a SchemaDb>,
+    ) -> ObjectsOfClass<'a, T> {
+        ObjectsOfClass {
+            lp: self,
+            db,
+            next: 1,
+            _class: PhantomData,
+        }
+    }
+
+    /// Export `i` parsed as a `T`; `None` when it isn't one.
+    pub fn object_in<T: NativeClass>(
+        &self,
+        i: i32,
+        db: Option<&SchemaDb>,
+    ) -> Option<Result<TypedObject<T::Payload>>> {
+        let it = self.objects_of_class_in::<T>(db);
+        it.matches(i).then(|| it.read(i))
+    }
+}
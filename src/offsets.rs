@@ -0,0 +1,299 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    path::Path,
+};
+
+use crate::{
+    disasm::{self, ScriptSpan},
+    schemadb::{LazyPackage, open_package_file},
+    upkreader::UpkHeader,
+    utils::{
+        decompress::{CompressionMethod, read_raw_header},
+        term,
+    },
+};
+
+pub fn parse_offset(s: &str) -> Result<u64> {
+    let t = s.trim();
+    let v = if let Some(h) = t.strip_prefix("0x").or_else(|| t.strip_prefix("0X")) {
+        u64::from_str_radix(h, 16)
+    } else {
+        t.parse::<u64>()
+    };
+    v.map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("'{s}' is not a valid offset"),
+        )
+    })
+}
+
+/// Accepts a 1-based export index (`123` / `#123`), a full name
+/// (`Function Actor.Touch`), a bare path (`Actor.Touch`) or a callstack-style
+/// path (`Engine.Actor:Touch`).
+pub fn find_export(lp: &LazyPackage, target: &str) -> Result<i32> {
+    let pak = &lp.pak;
+    let t = target.trim();
+    if let Ok(n) = t.trim_start_matches('#').parse::<i32>() {
+        if n >= 1 && (n as usize) <= pak.export_table.len() {
+            return Ok(n);
+        }
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("export #{n} out of range (1..={})", pak.export_table.len()),
+        ));
+    }
+    for i in 1..=pak.export_table.len() as i32 {
+        if pak.get_export_full_name(i).eq_ignore_ascii_case(t)
+            || pak.get_export_path_name(i).eq_ignore_ascii_case(t)
+        {
+            return Ok(i);
+        }
+    }
+
+    // Script callstacks print `Package.Class:Function`; exports don't carry
+    // the package name and use `.` throughout.
+    let norm = t.replace(':', ".").to_lowercase();
+    let short = norm
+        .strip_prefix(&format!("{}.", lp.stem_lc))
+        .unwrap_or(&norm);
+    for i in 1..=pak.export_table.len() as i32 {
+        let p = pak.get_export_path_name(i).replace(':', ".").to_lowercase();
+        if p == short {
+            return Ok(i);
+        }
+    }
+    Err(Error::new(
+        ErrorKind::NotFound,
+        format!("no export named '{t}' in {}", lp.stem_lc),
+    ))
+}
+
+pub fn export_at(lp: &LazyPackage, file_offset: u64) -> Option<i32> {
+    lp.pak
+        .export_table
+        .iter()
+        .position(|e| {
+            let start = e.serial_offset as u64;
+            e.serial_size > 0 && file_offset >= start && file_offset < start + e.serial_size as u64
+        })
+        .map(|i| i as i32 + 1)
+}
+
+fn table_region(h: &UpkHeader, file_offset: u64) -> Option<&'static str> {
+    let mut marks: Vec<(u64, &'static str)> = vec![
+        (0, "package summary"),
+        (h.name_offset as u64, "name table"),
+        (h.import_offset as u64, "import table"),
+        (h.export_offset as u64, "export table"),
+        (h.depends_offset as u64, "depends map"),
+    ];
+    if h.import_export_guids_offset > 0 {
+        marks.push((h.import_export_guids_offset as u64, "import/export guids"));
+    }
+    if h.thumbnail_table_offest > 0 {
+        marks.push((h.thumbnail_table_offest as u64, "thumbnail table"));
+    }
+    marks.sort_by_key(|m| m.0);
+    marks.iter().rev().find(|m| m.0 <= file_offset).map(|m| m.1)
+}
+
+fn export_script(lp: &LazyPackage, idx: i32) -> Option<ScriptSpan> {
+    let blob = lp.export_blob(idx).ok()?;
+    disasm::script_span(blob, lp.pak.script_kind(idx)?, &lp.pak, lp.header.p_ver)
+}
+
+fn script_code<'a>(blob: &'a [u8], span: &ScriptSpan, idx: i32) -> Result<&'a [u8]> {
+    let start = span.offset_in_blob as usize;
+    blob.get(start..start + span.disk_size as usize)
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("script of export #{idx} runs past its blob"),
+            )
+        })
+}
+
+fn print_export_line(lp: &LazyPackage, idx: i32) {
+    let exp = &lp.pak.export_table[(idx - 1) as usize];
+    println!(
+        "  export    #{} {}\n  span      0x{:08X}..0x{:08X} ({} bytes)",
+        idx,
+        lp.pak.get_export_full_name(idx),
+        exp.serial_offset,
+        exp.serial_offset as i64 + exp.serial_size as i64,
+        exp.serial_size
+    );
+}
+
+pub fn warn_if_compressed(path: &Path) -> Result<()> {
+    let raw = read_raw_header(path)?;
+    if raw.compression_method != CompressionMethod::None && raw.compressed_chunks_count > 0 {
+        term::warn(
+            "compressed",
+            format_args!(
+                "offsets refer to the decompressed image \
+                 (as written by `decompress`), not the file on disk"
+            ),
+        );
+    }
+    Ok(())
+}
+
+fn forward(lp: &LazyPackage, file_offset: u64) -> Result<()> {
+    if file_offset >= lp.bytes.len() as u64 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "offset 0x{file_offset:X} is past the end of the package (0x{:X} bytes)",
+                lp.bytes.len()
+            ),
+        ));
+    }
+
+    println!("0x{file_offset:08X}");
+    let Some(idx) = export_at(lp, file_offset) else {
+        match table_region(&lp.header, file_offset) {
+            Some(r) if file_offset < first_export_offset(lp) => println!("  region    {r}"),
+            _ => println!("  region    not covered by any export"),
+        }
+        return Ok(());
+    };
+    print_export_line(lp, idx);
+
+    let exp = &lp.pak.export_table[(idx - 1) as usize];
+    let rel = file_offset - exp.serial_offset as u64;
+    println!("  relative  +0x{rel:X} ({rel})");
+
+    let Some(span) = export_script(lp, idx) else {
+        return Ok(());
+    };
+    let script_end = span.offset_in_blob + span.disk_size as u64;
+    if rel < span.offset_in_blob || rel >= script_end {
+        println!(
+            "  script    +0x{:X}..+0x{:X} (offset is outside the bytecode)",
+            span.offset_in_blob, script_end
+        );
+        return Ok(());
+    }
+
+    let blob = lp.export_blob(idx)?;
+    let code = script_code(blob, &span, idx)?;
+    let dis = disasm::disassemble(code, &lp.pak, lp.header.p_ver);
+    let disk_off = (rel - span.offset_in_blob) as u32;
+    match dis.statement_at_disk(disk_off) {
+        Some(st) => {
+            println!("  script    disk +0x{disk_off:04X}");
+            println!(
+                "  statement disk 0x{:04X} / mem 0x{:04X}: {}",
+                st.disk_offset, st.mem_offset, st.text
+            );
+        }
+        None => println!("  script    disk +0x{disk_off:04X} (no statement decoded)"),
+    }
+    if let Some(e) = &dis.error
+        && dis
+            .statement_at_disk(disk_off)
+            .is_none_or(|st| disk_off >= st.disk_offset + st.disk_size)
+    {
+        term::warn("disasm", e);
+    }
+    Ok(())
+}
+
+fn first_export_offset(lp: &LazyPackage) -> u64 {
+    lp.pak
+        .export_table
+        .iter()
+        .filter(|e| e.serial_size > 0)
+        .map(|e| e.serial_offset as u64)
+        .min()
+        .unwrap_or(lp.bytes.len() as u64)
+}
+
+fn reverse(lp: &LazyPackage, idx: i32, rel: Option<u64>, script: Option<u64>) -> Result<()> {
+    let exp = &lp.pak.export_table[(idx - 1) as usize];
+    let base = exp.serial_offset as u64;
+    print_export_line(lp, idx);
+
+    if let Some(rel) = rel {
+        if rel >= exp.serial_size.max(0) as u64 {
+            term::warn(
+                "range",
+                format_args!("+0x{rel:X} is past the export's {} bytes", exp.serial_size),
+            );
+        }
+        println!("  relative  +0x{rel:X} → file 0x{:08X}", base + rel);
+    }
+
+    if let Some(mem) = script {
+        let span = export_script(lp, idx).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("export #{idx} has no bytecode"),
+            )
+        })?;
+        if span.mem_size > 0 && mem >= span.mem_size as u64 {
+            term::warn(
+                "range",
+                format_args!(
+                    "mem 0x{mem:04X} is past the bytecode (0x{:04X} bytes in memory)",
+                    span.mem_size
+                ),
+            );
+        }
+        let blob = lp.export_blob(idx)?;
+        let code = script_code(blob, &span, idx)?;
+        let dis = disasm::disassemble(code, &lp.pak, lp.header.p_ver);
+        let st = dis.statement_at_mem(mem as u32).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "could not decode bytecode up to mem 0x{mem:04X}: {}",
+                    dis.error.as_deref().unwrap_or("no statements")
+                ),
+            )
+        })?;
+        let file = base + span.offset_in_blob + st.disk_offset as u64;
+        if st.mem_offset as u64 == mem {
+            println!(
+                "  script    mem 0x{mem:04X} → disk 0x{:04X}",
+                st.disk_offset
+            );
+        } else {
+            println!(
+                "  script    mem 0x{mem:04X} is inside the statement at mem 0x{:04X} (disk 0x{:04X})",
+                st.mem_offset, st.disk_offset
+            );
+        }
+        println!("  statement {}", st.text);
+        println!("  file      0x{file:08X}");
+    }
+    Ok(())
+}
+
+pub fn where_cmd(
+    upk_path: &Path,
+    file_offset: Option<&str>,
+    export: Option<&str>,
+    rel: Option<&str>,
+    script: Option<&str>,
+) -> Result<()> {
+    let path = upk_path;
+    warn_if_compressed(path)?;
+    let lp = open_package_file(path)?;
+
+    match (file_offset, export) {
+        (Some(off), None) => forward(&lp, parse_offset(off)?),
+        (None, Some(exp)) => {
+            let idx = find_export(&lp, exp)?;
+            let rel = rel.map(parse_offset).transpose()?;
+            let script = script.map(parse_offset).transpose()?;
+            reverse(&lp, idx, rel, script)
+        }
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "give either a file offset or --export (with --rel / --script)",
+        )),
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind, Result},
+    path::Path,
+};
+
+use crate::{
+    disasm::{export_disassembly, opcode_name},
+    schemadb::open_package_file,
+    utils::{
+        deadline,
+        term::{self, Color, paint},
+        walk::package_files,
+    },
+};
+
+/// Locations kept per unknown opcode / decode error.
+const SAMPLES: usize = 5;
+
+#[derive(Default)]
+struct Stats {
+    packages: usize,
+    unreadable: usize,
+    scripts: usize,
+    /// Scripts that decoded to the end.
+    complete: usize,
+    /// By `opcode_name`, so all natives land on two lines.
+    counts: HashMap<&'static str, u64>,
+    unknown: HashMap<u8, (u64, Vec<String>)>,
+    /// Scripts that stopped on a known opcode: truncated operands, nesting.
+    errors: Vec<String>,
+    error_count: usize,
+}
+
+impl Stats {
+    fn package(&mut self, path: &Path) -> Result<()> {
+        deadline::arm();
+        let lp = open_package_file(path)?;
+        let file = path
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        for (idx, _) in lp.pak.functions() {
+            // No bytecode (native functions, script-less classes).
+            let Ok(dis) = export_disassembly(&lp, idx) else {
+                continue;
+            };
+            self.scripts += 1;
+            for &(_, op) in &dis.opcodes {
+                *self.counts.entry(opcode_name(op)).or_default() += 1;
+            }
+            let Some(err) = &dis.error else {
+                self.complete += 1;
+                continue;
+            };
+            let at = || format!("{file}: {}", lp.pak.get_export_full_name(idx));
+            match dis.opcodes.last() {
+                Some(&(off, op)) if opcode_name(op) == "Unknown" => {
+                    let (n, samples) = self.unknown.entry(op).or_default();
+                    *n += 1;
+                    if samples.len() < SAMPLES {
+                        samples.push(format!("{} @0x{off:04X}", at()));
+                    }
+                }
+                _ => {
+                    self.error_count += 1;
+                    if self.errors.len() < SAMPLES {
+                        self.errors.push(format!("{}: {err}", at()));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Disassembles every script in every package under `dir` and prints how
+/// often each opcode occurs, then every opcode the disassembler doesn't
+/// know with where it was found.
+pub fn opstats_cmd(dir: &Path) -> Result<()> {
+    let files = package_files(dir);
+    if files.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("no packages under {}", dir.display()),
+        ));
+    }
+    let mut st = Stats::default();
+    for f in &files {
+        st.packages += 1;
+        if let Err(e) = st.package(f) {
+            st.unreadable += 1;
+            term::warn("skip", format_args!("{}: {e}", f.display()));
+        }
+    }
+
+    let mut counts: Vec<_> = st.counts.iter().filter(|(n, _)| **n != "Unknown").collect();
+    counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    let total: u64 = counts.iter().map(|(_, n)| **n).sum();
+    let nw = term::column_width(counts.iter().map(|(name, _)| **name));
+    println!(
+        "{} script(s) in {} package(s), {} decoded to the end; {total} opcode(s)",
+        st.scripts,
+        st.packages - st.unreadable,
+        st.complete
+    );
+    for (name, n) in &counts {
+        println!(
+            "  {:<nw$}  {:>10}  {}",
+            name,
+            n,
+            paint(
+                Color::Gray,
+                format!("{:5.1}%", **n as f64 * 100.0 / total.max(1) as f64)
+            )
+        );
+    }
+
+    if !st.unknown.is_empty() {
+        let mut unknown: Vec<_> = st.unknown.iter().collect();
+        unknown.sort_by(|a, b| b.1.0.cmp(&a.1.0).then(a.0.cmp(b.0)));
+        println!();
+        println!("{}:", paint(Color::Red, "Unknown opcodes"));
+        for (op, (n, samples)) in unknown {
+            println!(
+                "  {}  stops {n} script(s)",
+                paint(Color::Highlight, format!("0x{op:02X}"))
+            );
+            for s in samples {
+                println!("    {s}");
+            }
+        }
+    }
+    if st.error_count > 0 {
+        println!();
+        println!(
+            "{} ({}):",
+            paint(Color::Yellow, "Other decode errors"),
+            st.error_count
+        );
+        for e in &st.errors {
+            println!("    {e}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use std::{io::Result, path::Path, rc::Rc};
+
+use crate::{
+    disasm::{disassemble, export_script},
+    schemadb::{LazyPackage, SchemaDb, open_package_file},
+    upkprops::{Property, PropertyValue},
+    utils::{heuristics, term},
+    versions::{RF_PUBLIC, RF_STANDALONE},
+};
+
+/// Classes whose exports are referenced from bytecode or by the engine
+/// itself; they are never reported.
+pub const SCRIPT_CLASSES: &[&str] = &[
+    "Class",
+    "Function",
+    "State",
+    "ScriptStruct",
+    "Enum",
+    "Const",
+    "Package",
+    "ObjectReferencer",
+];
+
+fn collect_refs(v: &PropertyValue, out: &mut Vec<i32>) {
+    match v {
+        PropertyValue::Object(i) if *i > 0 => out.push(*i),
+        PropertyValue::Array(items) => items.iter().for_each(|x| collect_refs(x, out)),
+        PropertyValue::Struct(props) => props_refs(props, out),
+        PropertyValue::AtomicStruct(fields) => {
+            fields.iter().for_each(|(_, x)| collect_refs(x, out))
+        }
+        // Untyped data (arrays without a schema, say) may hold references;
+        // counting every aligned int keeps false orphans out.
+        PropertyValue::Raw(bytes) => out.extend(
+            bytes
+                .chunks_exact(4)
+                .map(|c| i32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                .filter(|i| *i > 0),
+        ),
+        _ => {}
+    }
+}
+
+pub fn props_refs(props: &[Property], out: &mut Vec<i32>) {
+    for p in props {
+        collect_refs(&p.value, out);
+    }
+}
+
+/// Native data after the tagged properties has no schema here. Any int at
+/// any offset is taken as a possible reference, so an export is only
+/// reported when no bytes anywhere could point at it. Tails over the
+/// `native-scan-limit` heuristic (bulk payloads) are skipped, unrecorded:
+/// every texture hits it, and they would make every index look referenced.
+fn native_refs(tail: &[u8], out: &mut Vec<i32>) {
+    if tail.len() as u64 > heuristics::get().native_scan_limit {
+        return;
+    }
+    out.extend(
+        tail.windows(4)
+            .map(|c| i32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .filter(|i| *i > 0),
+    );
+}
+
+pub struct Orphan {
+    pub idx: i32,
+    pub name: String,
+    pub size: u64,
+}
+
+pub struct Analysis {
+    pub orphans: Vec<Orphan>,
+    /// Exports whose properties couldn't be read; anything they reference
+    /// may be missing from the reference set.
+    pub unparsed: usize,
+}
+
+/// An export counts as referenced when another export names it as outer,
+/// class, super or archetype, or points at it from a property or from
+/// bytecode.
+pub fn analyze(lp: &LazyPackage, db: Option<&SchemaDb>, include_public: bool) -> Analysis {
+    let n = lp.pak.export_table.len();
+    let mut referenced = vec![false; n + 1];
+    let mut mark = |i: i32| {
+        if i > 0 && (i as usize) <= n {
+            referenced[i as usize] = true;
+        }
+    };
+
+    let mut unparsed = 0;
+    for (k, e) in lp.pak.export_table.iter().enumerate() {
+        mark(e.outer_index);
+        mark(e.class_index);
+        mark(e.super_index);
+        mark(e.archetype);
+        for v in e.legacy_component_map.values() {
+            mark(*v);
+        }
+        let idx = k as i32 + 1;
+        if SCRIPT_CLASSES.contains(&lp.export_class_name(idx).as_str()) {
+            // Bytecode names objects too; whatever decoded before an
+            // unknown token still counts.
+            if let Ok(code) = export_script(lp, idx) {
+                let dis = disassemble(code, &lp.pak, lp.header.p_ver);
+                dis.objects(code).filter(|r| *r != idx).for_each(&mut mark);
+            }
+            continue;
+        }
+        match lp.export_props(idx, db) {
+            Ok((props, end)) => {
+                let mut refs = Vec::new();
+                props_refs(&props, &mut refs);
+                if let Ok(blob) = lp.export_blob(idx) {
+                    native_refs(&blob[end.min(blob.len())..], &mut refs);
+                }
+                refs.into_iter().filter(|r| *r != idx).for_each(&mut mark);
+            }
+            Err(_) => unparsed += 1,
+        }
+    }
+
+    let mut orphans = Vec::new();
+    for (k, e) in lp.pak.export_table.iter().enumerate() {
+        let idx = k as i32 + 1;
+        if referenced[idx as usize] || e.outer_index == 0 {
+            continue;
+        }
+        if !include_public && e.object_flags & (RF_PUBLIC | RF_STANDALONE) != 0 {
+            continue;
+        }
+        if SCRIPT_CLASSES.contains(&lp.export_class_name(idx).as_str()) {
+            continue;
+        }
+        orphans.push(Orphan {
+            idx,
+            name: lp.export_full_name(idx),
+            size: e.serial_size.max(0) as u64,
+        });
+    }
+    Analysis { orphans, unparsed }
+}
+
+pub fn orphans_cmd(
+    upk_path: &Path,
+    include_public: bool,
+    game_root: Option<&Path>,
+    verbose: bool,
+) -> Result<()> {
+    let lp = Rc::new(open_package_file(upk_path)?);
+    let db = match game_root.filter(|g| !g.as_os_str().is_empty()) {
+        Some(gr) => {
+            let db = SchemaDb::new(gr)?.with_verbose(verbose);
+            db.inject_package(lp.clone());
+            Some(db)
+        }
+        None => None,
+    };
+
+    let a = analyze(&lp, db.as_ref(), include_public);
+    for o in &a.orphans {
+        println!("  #{:<6} {:>10} bytes  {}", o.idx, o.size, o.name);
+    }
+    if a.unparsed > 0 {
+        term::warn(
+            "warning",
+            format_args!(
+                "{} export(s) without readable properties; their references are not counted (try --game-root)",
+                a.unparsed
+            ),
+        );
+    }
+    let total: u64 = a.orphans.iter().map(|o| o.size).sum();
+    println!(
+        "{} unreferenced export(s), {total} bytes of export data{}",
+        a.orphans.len(),
+        if include_public {
+            ""
+        } else {
+            " (public/standalone exports skipped; --include-public to list them)"
+        }
+    );
+    Ok(())
+}
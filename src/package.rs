@@ -0,0 +1,767 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{BufWriter, Cursor, Error, ErrorKind, Result, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{
+    hooks,
+    upkreader::{Export, Import, NameEntry, UPKPak, UpkHeader, read_name, write_name},
+    utils::{
+        decompress::read_package_image,
+        modarchive, readonly,
+        spill::PackageBytes,
+        stats::{self, Phase},
+    },
+    versions::{BULKDATA_STORE_IN_SEPARATE_FILE, VER_ADDED_LINKER_DEPENDENCIES},
+};
+
+const DEFAULT_NAME_FLAGS: u64 = 0x0007_0010_0000_0000;
+const WRITE_BUFFER: usize = 1 << 20;
+
+/// Editable package: tables are owned and can be written back.
+///
+/// Saving appends: replaced export blobs and a grown name table go to the end
+/// of the file and the summary / export table are patched in place. Untouched
+/// exports keep their original bytes and offsets, so absolute offsets stored
+/// inside blobs (bulk data) stay valid.
+///
+/// Exports are never renumbered. `add_export` puts new ones after the last
+/// export, and `save` refuses a table whose existing entries were removed or
+/// moved, so references elsewhere stay valid and bsdiff / xdelta patches
+/// against the original stay small. A grown export table (and the depends
+/// map, one entry per export) is written at the end like the name table.
+pub struct Package {
+    pub bytes: PackageBytes,
+    pub header: UpkHeader,
+    pub names: Vec<NameEntry>,
+    pub imports: Vec<Import>,
+    pub exports: Vec<Export>,
+    replaced: BTreeMap<i32, Vec<u8>>,
+    /// Replaced blobs whose inline bulk offsets are relative to the blob.
+    relative_bulk: BTreeSet<i32>,
+    original_names: Vec<NameEntry>,
+    original_exports: Vec<Export>,
+    original_imports: Vec<Import>,
+}
+
+#[derive(Debug, Default)]
+pub struct SaveStats {
+    pub replaced_exports: usize,
+    pub added_exports: usize,
+    pub added_names: usize,
+    pub bytes_written: u64,
+}
+
+impl Package {
+    pub fn open(path: &Path) -> Result<Self> {
+        let image = read_package_image(path)?;
+        let bytes = image.bytes;
+        let header = image.header;
+
+        let mut cur = Cursor::new(&*bytes);
+        let pak = UPKPak::parse_upk(&mut cur, &header)?;
+
+        cur.seek(SeekFrom::Start(header.name_offset as u64))?;
+        let mut names = Vec::with_capacity(header.name_count.max(0) as usize);
+        for _ in 0..header.name_count {
+            names.push(read_name(&mut cur)?);
+        }
+
+        Ok(Self {
+            original_names: names.clone(),
+            original_exports: pak.export_table.clone(),
+            original_imports: pak.import_table.clone(),
+            bytes,
+            header,
+            names,
+            imports: pak.import_table,
+            exports: pak.export_table,
+            replaced: BTreeMap::new(),
+            relative_bulk: BTreeSet::new(),
+        })
+    }
+
+    pub fn pak(&self) -> UPKPak {
+        UPKPak {
+            name_table: self.names.iter().map(|n| n.name.clone()).collect(),
+            import_table: self.imports.clone(),
+            export_table: self.exports.clone(),
+        }
+    }
+
+    fn export(&self, idx: i32) -> Result<&Export> {
+        self.exports.get((idx - 1) as usize).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("export #{idx} out of range"),
+            )
+        })
+    }
+
+    pub fn export_blob(&self, idx: i32) -> Result<&[u8]> {
+        match self.replaced.get(&idx) {
+            Some(b) => Ok(b),
+            None => self.original_blob(idx),
+        }
+    }
+
+    fn original_blob(&self, idx: i32) -> Result<&[u8]> {
+        let exp = self.export(idx)?;
+        if idx as usize > self.original_exports.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("export #{idx} was added and has no bytes on disk"),
+            ));
+        }
+        let s = exp.serial_offset as usize;
+        let e = s + exp.serial_size.max(0) as usize;
+        self.bytes.get(s..e).ok_or_else(|| {
+            Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("export #{idx} runs past the end of the package"),
+            )
+        })
+    }
+
+    /// Returns false (and keeps the original bytes) when `blob` is identical
+    /// to what the export held on disk.
+    pub fn set_export_blob(&mut self, idx: i32, blob: Vec<u8>) -> Result<bool> {
+        self.relative_bulk.remove(&idx);
+        if idx as usize > self.original_exports.len() {
+            self.export(idx)?;
+            self.replaced.insert(idx, blob);
+            return Ok(true);
+        }
+        if self.original_blob(idx)? == blob.as_slice() {
+            self.replaced.remove(&idx);
+            return Ok(false);
+        }
+        self.replaced.insert(idx, blob);
+        Ok(true)
+    }
+
+    /// `set_export_blob` for a blob taken from another package: its inline
+    /// bulk data offsets are given as if it sat at file offset 0 (see
+    /// `relocate_bulk`), and saving moves them to where it lands.
+    pub fn set_export_blob_relative(&mut self, idx: i32, blob: Vec<u8>) -> Result<bool> {
+        let changed = self.set_export_blob(idx, blob)?;
+        if changed {
+            self.relative_bulk.insert(idx);
+        }
+        Ok(changed)
+    }
+
+    /// Appends an export and returns its index, always one past the current
+    /// last; nothing existing moves. Serial offset and size are filled in
+    /// by `save`.
+    pub fn add_export(&mut self, exp: Export, blob: Vec<u8>) -> i32 {
+        self.exports.push(exp);
+        let idx = self.exports.len() as i32;
+        self.replaced.insert(idx, blob);
+        idx
+    }
+
+    fn name_text(&self, index: i32) -> &str {
+        self.names
+            .get(index as usize)
+            .map_or("", |n| n.name.as_str())
+    }
+
+    /// Existing exports still sit at their original indices. Object names
+    /// are compared by text, so pointing one at another entry with the
+    /// same string (merging duplicate names) is not a move.
+    fn check_stable(&self) -> Result<()> {
+        let n = self.original_exports.len();
+        if self.exports.len() < n {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "export table shrank from {n} to {}; exports are never removed or renumbered",
+                    self.exports.len()
+                ),
+            ));
+        }
+        for (i, (now, was)) in self.exports.iter().zip(&self.original_exports).enumerate() {
+            let same_name = now.object_name.name_instance == was.object_name.name_instance
+                && self.name_text(now.object_name.name_index)
+                    == self.name_text(was.object_name.name_index);
+            if !same_name
+                || now.outer_index != was.outer_index
+                || now.class_index != was.class_index
+            {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "export #{} no longer holds the object it had on disk; \
+                         exports are never renumbered, new ones go after #{n}",
+                        i + 1
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The depends map with an empty entry for each added export, or `None`
+    /// when the package has none.
+    fn grown_depends_map(&self) -> Result<Option<Vec<u8>>> {
+        let at = self.header.depends_offset;
+        if self.header.p_ver < VER_ADDED_LINKER_DEPENDENCIES || at <= 0 {
+            return Ok(None);
+        }
+        let mut cur = Cursor::new(&self.bytes[..]);
+        cur.seek(SeekFrom::Start(at as u64))?;
+        for _ in 0..self.original_exports.len() {
+            let n = cur.read_i32::<LittleEndian>()?;
+            if n < 0 {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("depends map at 0x{at:X} has a negative count"),
+                ));
+            }
+            cur.seek(SeekFrom::Current(n as i64 * 4))?;
+        }
+        let end = cur.position() as usize;
+        let mut map = self
+            .bytes
+            .get(at as usize..end)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!("depends map at 0x{at:X} runs past the end of the package"),
+                )
+            })?
+            .to_vec();
+        for _ in self.original_exports.len()..self.exports.len() {
+            map.write_i32::<LittleEndian>(0)?;
+        }
+        Ok(Some(map))
+    }
+
+    /// Flags for new names: whatever most existing names carry.
+    fn new_name_flags(&self) -> u64 {
+        let mut counts: BTreeMap<u64, usize> = BTreeMap::new();
+        for n in &self.names {
+            *counts.entry(n.flags).or_default() += 1;
+        }
+        counts
+            .into_iter()
+            .max_by_key(|(_, c)| *c)
+            .map(|(f, _)| f)
+            .unwrap_or(DEFAULT_NAME_FLAGS)
+    }
+
+    pub fn find_name(&self, name: &str) -> Option<i32> {
+        self.names
+            .iter()
+            .position(|n| n.name == name)
+            .map(|i| i as i32)
+    }
+
+    pub fn add_name(&mut self, name: &str) -> i32 {
+        if let Some(i) = self.find_name(name) {
+            return i;
+        }
+        let flags = self.new_name_flags();
+        self.names.push(NameEntry {
+            name: name.to_string(),
+            flags,
+        });
+        (self.names.len() - 1) as i32
+    }
+
+    fn names_changed(&self) -> bool {
+        self.names.len() != self.original_names.len()
+            || self
+                .names
+                .iter()
+                .zip(&self.original_names)
+                .any(|(a, b)| a.name != b.name || a.flags != b.flags)
+    }
+
+    /// Drops this package's mapping of `out` before it is replaced;
+    /// Windows won't rename over a mapped file, however long it waits.
+    fn release(&mut self, out: &Path) -> Result<()> {
+        if self.bytes.maps(out) {
+            self.bytes = self.bytes.detach()?;
+        }
+        Ok(())
+    }
+
+    /// Streams the result: unchanged regions are written straight from the
+    /// source bytes, so nothing the size of the package is built in memory.
+    /// The output goes to `<out>.part` first and is renamed over `out`, which
+    /// also makes saving over the source package safe.
+    pub fn save(&mut self, out: &Path) -> Result<SaveStats> {
+        modarchive::ensure_writable(out)?;
+        self.check_stable()?;
+        let mut header = self.header.clone();
+        let mut exports = self.exports.clone();
+        let mut stats = SaveStats::default();
+        let mut end = self.bytes.len();
+        let kept = self.original_exports.len();
+
+        for (&idx, blob) in &self.replaced {
+            let exp = &mut exports[(idx - 1) as usize];
+            exp.serial_offset = file_offset(end)?;
+            exp.serial_size = blob.len() as i32;
+            end += blob.len();
+            if idx as usize > kept {
+                stats.added_exports += 1;
+            } else {
+                stats.replaced_exports += 1;
+            }
+        }
+
+        let mut name_table = Vec::new();
+        if self.names_changed() {
+            header.name_offset = file_offset(end)?;
+            header.name_count = self.names.len() as i32;
+            for n in &self.names {
+                write_name(&mut name_table, n)?;
+            }
+            end += name_table.len();
+            stats.added_names = self.names.len().saturating_sub(self.original_names.len());
+        }
+
+        // A grown table can't be patched in place; the original stays where
+        // it is, unreferenced, so the bytes around it don't shift.
+        let mut moved_table = Vec::new();
+        let mut depends_map = Vec::new();
+        if exports.len() > kept {
+            header.export_offset = file_offset(end)?;
+            header.export_count = exports.len() as i32;
+            for e in &exports {
+                e.write(&mut moved_table, header.p_ver)?;
+            }
+            end += moved_table.len();
+            if let Some(map) = self.grown_depends_map()? {
+                header.depends_offset = file_offset(end)?;
+                end += map.len();
+                depends_map = map;
+            }
+            if let Some(g) = header.gens.last_mut() {
+                g.export_count = header.export_count;
+            }
+        }
+
+        let new_summary = summary_bytes(&header)?;
+        check_patch(&self.bytes, 0, &summary_bytes(&self.header)?, &new_summary)?;
+
+        let mut old_table = Cursor::new(Vec::new());
+        let mut new_table = Cursor::new(Vec::new());
+        let in_place = if moved_table.is_empty() {
+            &exports[..]
+        } else {
+            &self.original_exports[..]
+        };
+        for (old, new) in self.original_exports.iter().zip(in_place) {
+            old.write(&mut old_table, header.p_ver)?;
+            new.write(&mut new_table, header.p_ver)?;
+        }
+        let table_at = self.header.export_offset as usize;
+        check_patch(
+            &self.bytes,
+            table_at,
+            old_table.get_ref(),
+            new_table.get_ref(),
+        )?;
+        if table_at < new_summary.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("export table at 0x{table_at:X} overlaps the summary"),
+            ));
+        }
+
+        // Imports only change in place (same count, indices redirected).
+        let mut old_imports = Vec::new();
+        let mut new_imports = Vec::new();
+        if self.imports.len() != self.original_imports.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "import table changed length; imports are never added or removed on save",
+            ));
+        }
+        for (old, new) in self.original_imports.iter().zip(&self.imports) {
+            old.write(&mut old_imports)?;
+            new.write(&mut new_imports)?;
+        }
+        let imports_at = self.header.import_offset as usize;
+        check_patch(&self.bytes, imports_at, &old_imports, &new_imports)?;
+
+        let _t = stats::time(Phase::Output);
+        if let Some(parent) = out.parent() {
+            readonly::create_dir_all(parent)?;
+        }
+        let mut part = out.as_os_str().to_os_string();
+        part.push(".part");
+        let part = PathBuf::from(part);
+
+        let mut w = BufWriter::with_capacity(WRITE_BUFFER, readonly::create(&part)?);
+        let src = &self.bytes[..];
+        w.write_all(&new_summary)?;
+        // The two tables in file order, each patched over its original.
+        let mut patches = [
+            (table_at, new_table.get_ref().as_slice()),
+            (imports_at, new_imports.as_slice()),
+        ];
+        patches.sort_by_key(|(at, _)| *at);
+        let mut at = new_summary.len();
+        for (from, bytes) in patches {
+            w.write_all(&src[at..from])?;
+            w.write_all(bytes)?;
+            at = from + bytes.len();
+        }
+        w.write_all(&src[at..])?;
+        for (idx, blob) in &self.replaced {
+            if self.relative_bulk.contains(idx) {
+                let mut blob = blob.clone();
+                let at = exports[(idx - 1) as usize].serial_offset;
+                relocate_bulk(&mut blob, 0, at as i64);
+                w.write_all(&blob)?;
+            } else {
+                w.write_all(blob)?;
+            }
+        }
+        w.write_all(&name_table)?;
+        w.write_all(&moved_table)?;
+        w.write_all(&depends_map)?;
+        w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        self.release(out)?;
+        readonly::rename(&part, out)?;
+
+        stats.bytes_written = end as u64;
+        hooks::package_saved(out, &stats);
+        Ok(stats)
+    }
+
+    /// What the package on disk references, sorted by offset: the summary,
+    /// the tables the summary points at and every export's bytes. Bytes
+    /// outside all of them are dead. Reflects the file as opened, not
+    /// unsaved edits.
+    pub fn regions(&self) -> Result<Vec<Region>> {
+        let h = &self.header;
+        let mut out = vec![Region {
+            kind: RegionKind::Summary,
+            start: 0,
+            len: summary_bytes(h)?.len(),
+        }];
+        let mut table = |kind, at: i32, bytes: Vec<u8>| {
+            if at > 0 && !bytes.is_empty() {
+                out.push(Region {
+                    kind,
+                    start: at as usize,
+                    len: bytes.len(),
+                });
+            }
+        };
+        let mut names = Vec::new();
+        for n in &self.original_names {
+            write_name(&mut names, n)?;
+        }
+        table(RegionKind::Names, h.name_offset, names);
+        let mut imports = Vec::new();
+        for i in &self.original_imports {
+            i.write(&mut imports)?;
+        }
+        table(RegionKind::Imports, h.import_offset, imports);
+        let mut exports = Vec::new();
+        for e in &self.original_exports {
+            e.write(&mut exports, h.p_ver)?;
+        }
+        table(RegionKind::Exports, h.export_offset, exports);
+        table(
+            RegionKind::Depends,
+            h.depends_offset,
+            self.depends_map()?.unwrap_or_default(),
+        );
+        for (i, e) in self.original_exports.iter().enumerate() {
+            if e.serial_size > 0 {
+                out.push(Region {
+                    kind: RegionKind::Export(i as i32 + 1),
+                    start: e.serial_offset.max(0) as usize,
+                    len: e.serial_size as usize,
+                });
+            }
+        }
+        // Neither table is parsed; each is taken to run up to whatever
+        // comes next, which can only under-count dead bytes.
+        let unsized_tables = [
+            (
+                RegionKind::Guids,
+                h.import_export_guids_offset,
+                h.import_guids_count + h.export_guids_count > 0,
+            ),
+            (
+                RegionKind::Thumbnails,
+                h.thumbnail_table_offest as i32,
+                h.thumbnail_table_offest > 0,
+            ),
+        ];
+        for (kind, at, present) in unsized_tables {
+            if !present || at <= 0 {
+                continue;
+            }
+            let at = at as usize;
+            let next = out
+                .iter()
+                .map(|r| r.start)
+                .filter(|&s| s > at)
+                .min()
+                .unwrap_or(self.bytes.len());
+            out.push(Region {
+                kind,
+                start: at,
+                len: next - at,
+            });
+        }
+        out.sort_by_key(|r| (r.start, r.len));
+        Ok(out)
+    }
+
+    /// Saves with every table right after the summary and the exports
+    /// packed behind them in file order, so nothing dead is carried over:
+    /// not blobs replaced by earlier saves, not tables they moved, not a
+    /// chunk table left over from decompression. Inline bulk data records
+    /// its own file offset; offsets that point into an export's old bytes
+    /// are moved with it.
+    ///
+    /// Unlike `save` this moves every export, so a binary patch against
+    /// the original gets no smaller than the package.
+    pub fn save_compact(&mut self, out: &Path) -> Result<SaveStats> {
+        modarchive::ensure_writable(out)?;
+        self.check_stable()?;
+        let h = &self.header;
+        if h.thumbnail_table_offest > 0 || h.import_guids_count + h.export_guids_count > 0 {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "package has a thumbnail table or import / export GUIDs, which compacting \
+                 can't relocate",
+            ));
+        }
+        let mut header = h.clone();
+        let mut exports = self.exports.clone();
+        let mut stats = SaveStats {
+            replaced_exports: self
+                .replaced
+                .keys()
+                .filter(|&&i| i as usize <= self.original_exports.len())
+                .count(),
+            added_exports: self.exports.len() - self.original_exports.len(),
+            added_names: self.names.len().saturating_sub(self.original_names.len()),
+            bytes_written: 0,
+        };
+
+        let mut names = Vec::new();
+        for n in &self.names {
+            write_name(&mut names, n)?;
+        }
+        let mut imports = Vec::new();
+        for i in &self.imports {
+            i.write(&mut imports)?;
+        }
+        let depends = self.grown_depends_map()?;
+
+        // The export table's size doesn't depend on the offsets in it, so
+        // it can be measured before they are known.
+        let mut table_len = Vec::new();
+        for e in &exports {
+            e.write(&mut table_len, h.p_ver)?;
+        }
+        let summary_len = summary_bytes(&header)?.len();
+        let mut end = summary_len;
+        header.name_offset = file_offset(end)?;
+        header.name_count = self.names.len() as i32;
+        end += names.len();
+        header.import_offset = file_offset(end)?;
+        header.import_count = self.imports.len() as i32;
+        end += imports.len();
+        header.export_offset = file_offset(end)?;
+        header.export_count = exports.len() as i32;
+        end += table_len.len();
+        if let Some(map) = &depends {
+            header.depends_offset = file_offset(end)?;
+            end += map.len();
+        }
+        if header.import_export_guids_offset > 0 {
+            header.import_export_guids_offset = file_offset(end)?;
+        }
+        header.header_size = file_offset(end)?;
+        if let Some(g) = header.gens.last_mut() {
+            g.export_count = header.export_count;
+        }
+
+        // File order of the original bytes; added exports go last.
+        let mut order: Vec<i32> = (1..=exports.len() as i32).collect();
+        order.sort_by_key(|&i| match self.original_exports.get((i - 1) as usize) {
+            Some(e) => (0, e.serial_offset as i64),
+            None => (1, i as i64),
+        });
+        let mut blobs = Vec::with_capacity(order.len());
+        for &idx in &order {
+            let exp = &mut exports[(idx - 1) as usize];
+            let mut blob = self.export_blob(idx)?.to_vec();
+            if blob.is_empty() {
+                exp.serial_offset = 0;
+                exp.serial_size = 0;
+                continue;
+            }
+            if self.relative_bulk.contains(&idx) {
+                relocate_bulk(&mut blob, 0, end as i64);
+            } else if let Some(was) = self.original_exports.get((idx - 1) as usize) {
+                relocate_bulk(&mut blob, was.serial_offset as i64, end as i64);
+            }
+            exp.serial_offset = file_offset(end)?;
+            exp.serial_size = blob.len() as i32;
+            end += blob.len();
+            blobs.push(blob);
+        }
+        file_offset(end)?;
+
+        let mut table = Vec::with_capacity(table_len.len());
+        for e in &exports {
+            e.write(&mut table, header.p_ver)?;
+        }
+        let summary = summary_bytes(&header)?;
+        if summary.len() != summary_len {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "summary changed size while laying out the package",
+            ));
+        }
+
+        let _t = stats::time(Phase::Output);
+        if let Some(parent) = out.parent() {
+            readonly::create_dir_all(parent)?;
+        }
+        let mut part = out.as_os_str().to_os_string();
+        part.push(".part");
+        let part = PathBuf::from(part);
+        let mut w = BufWriter::with_capacity(WRITE_BUFFER, readonly::create(&part)?);
+        w.write_all(&summary)?;
+        w.write_all(&names)?;
+        w.write_all(&imports)?;
+        w.write_all(&table)?;
+        if let Some(map) = &depends {
+            w.write_all(map)?;
+        }
+        for blob in &blobs {
+            w.write_all(blob)?;
+        }
+        w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        self.release(out)?;
+        readonly::rename(&part, out)?;
+
+        stats.bytes_written = end as u64;
+        hooks::package_saved(out, &stats);
+        Ok(stats)
+    }
+
+    /// The depends map as on disk, one entry per original export.
+    fn depends_map(&self) -> Result<Option<Vec<u8>>> {
+        let Some(mut map) = self.grown_depends_map()? else {
+            return Ok(None);
+        };
+        map.truncate(map.len() - 4 * (self.exports.len() - self.original_exports.len()));
+        Ok(Some(map))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Summary,
+    Names,
+    Imports,
+    Exports,
+    Depends,
+    Guids,
+    Thumbnails,
+    /// An export's serialized bytes, by 1-based index.
+    Export(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub kind: RegionKind,
+    pub start: usize,
+    pub len: usize,
+}
+
+impl Region {
+    pub fn end(&self) -> usize {
+        self.start + self.len
+    }
+}
+
+/// Rewrites inline bulk data headers (flags, count, size, offset) whose
+/// offset is where their payload sat with the blob at `old_base`, for the
+/// blob at `new_base`.
+pub fn relocate_bulk(blob: &mut [u8], old_base: i64, new_base: i64) {
+    let field = |b: &[u8], at: usize| i32::from_le_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]]);
+    let mut p = 0;
+    while p + 16 <= blob.len() {
+        let flags = field(blob, p) as u32;
+        let size = field(blob, p + 8);
+        let offset = field(blob, p + 12) as i64;
+        let inline = flags & !0xFF == 0 && flags & BULKDATA_STORE_IN_SEPARATE_FILE == 0;
+        if inline
+            && size >= 0
+            && p + 16 + size as usize <= blob.len()
+            && offset == old_base + p as i64 + 16
+        {
+            let new = (new_base + p as i64 + 16) as i32;
+            blob[p + 12..p + 16].copy_from_slice(&new.to_le_bytes());
+            p += 16 + size as usize;
+            continue;
+        }
+        p += 1;
+    }
+}
+
+fn summary_bytes(h: &UpkHeader) -> Result<Vec<u8>> {
+    let mut w = Cursor::new(Vec::new());
+    h.write(&mut w)?;
+    Ok(w.into_inner())
+}
+
+fn file_offset(len: usize) -> Result<i32> {
+    i32::try_from(len).map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidData,
+            "package would exceed 2 GiB; offsets no longer fit",
+        )
+    })
+}
+
+/// Checks that a table re-serialized with the same layout can replace the
+/// original bytes. A size change means the append strategy can't be used
+/// for this edit; a mismatch with the old bytes means our writer doesn't
+/// round-trip this package.
+fn check_patch(buf: &[u8], at: usize, old: &[u8], new: &[u8]) -> Result<()> {
+    if old.len() != new.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "table at 0x{at:X} changed size ({} → {} bytes); cannot patch in place",
+                old.len(),
+                new.len()
+            ),
+        ));
+    }
+    let dst = buf.get(at..at + new.len()).ok_or_else(|| {
+        Error::new(
+            ErrorKind::UnexpectedEof,
+            format!("table at 0x{at:X} runs past the end of the package"),
+        )
+    })?;
+    if dst != old {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("table at 0x{at:X} does not re-serialize to its original bytes"),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,310 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+use clap::Subcommand;
+
+use crate::{
+    disasm::{disassemble, reserialize_script, script_span},
+    schemadb::{LazyPackage, open_package_file},
+    upkpacker::export_path_dotted,
+    upkreader::{FName, ScriptKind, UPKPak},
+    utils::{
+        readonly,
+        term::{self, Color, paint},
+        walk::stem,
+    },
+    versions::VER_USTRUCT_SERIALIZE_ONDISK_SCRIPTSIZE,
+};
+
+#[derive(Subcommand)]
+pub enum PatchCmd {
+    #[command(
+        about = "Rebuild pack-mod function overrides made against one package for a newer build of it"
+    )]
+    Retarget {
+        /// pack-mod output for one package: `<key>.bin` files and the
+        /// `<pkg>.namemap`.
+        patch_dir: PathBuf,
+        /// The package the patch was built against.
+        #[arg(long, value_name = "UPK")]
+        from: PathBuf,
+        /// The package to build it for.
+        #[arg(long, value_name = "UPK")]
+        to: PathBuf,
+        /// Written as `<DIR>/<pkg>/`, the layout pack-mod uses.
+        #[arg(long = "out", short = 'o', value_name = "DIR")]
+        out: PathBuf,
+    },
+}
+
+pub fn run(cmd: PatchCmd) -> Result<()> {
+    match cmd {
+        PatchCmd::Retarget {
+            patch_dir,
+            from,
+            to,
+            out,
+        } => retarget(&patch_dir, &from, &to, &out),
+    }
+}
+
+/// The old package's tables with the patch's name map in place of its
+/// names, so indices the patch added resolve.
+fn patch_view(lp: &LazyPackage, patch_dir: &Path) -> Result<UPKPak> {
+    let map_path = patch_dir.join(format!("{}.namemap", stem(&lp.path)));
+    let names = match std::fs::read_to_string(&map_path) {
+        Ok(text) => {
+            let names: Vec<String> = text.lines().map(str::to_string).collect();
+            if !names.starts_with(&lp.pak.name_table) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "{} doesn't extend the name table of {}; was the patch built against it?",
+                        map_path.display(),
+                        lp.path.display()
+                    ),
+                ));
+            }
+            names
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => lp.pak.name_table.to_vec(),
+        Err(e) => return Err(e),
+    };
+    Ok(UPKPak {
+        name_table: names.into(),
+        export_table: lp.pak.export_table.clone(),
+        import_table: lp.pak.import_table.clone(),
+    })
+}
+
+fn full_name(pak: &UPKPak, idx: i32) -> String {
+    if idx > 0 {
+        pak.get_export_full_name(idx)
+    } else {
+        pak.get_import_full_name(idx)
+    }
+}
+
+/// Where the two script size fields sit: memory size, then (from 639) the
+/// on-disk size, right before the bytecode.
+fn write_script_sizes(blob: &mut [u8], script_at: usize, p_ver: i16, disk: u32, mem: u32) {
+    if p_ver >= VER_USTRUCT_SERIALIZE_ONDISK_SCRIPTSIZE {
+        blob[script_at - 8..script_at - 4].copy_from_slice(&mem.to_le_bytes());
+        blob[script_at - 4..script_at].copy_from_slice(&disk.to_le_bytes());
+    } else {
+        blob[script_at - 4..script_at].copy_from_slice(&mem.to_le_bytes());
+    }
+}
+
+/// The new package's names, grown by whatever the patched bytecode uses
+/// that it doesn't have yet.
+struct NameMap {
+    names: Vec<String>,
+    index: HashMap<String, i32>,
+}
+
+impl NameMap {
+    fn new(names: Vec<String>) -> Self {
+        let mut index = HashMap::new();
+        for (i, n) in names.iter().enumerate() {
+            index.entry(n.clone()).or_insert(i as i32);
+        }
+        NameMap { names, index }
+    }
+
+    fn intern(&mut self, s: &str) -> i32 {
+        if let Some(&i) = self.index.get(s) {
+            return i;
+        }
+        let i = self.names.len() as i32;
+        self.names.push(s.to_string());
+        self.index.insert(s.to_string(), i);
+        i
+    }
+}
+
+struct Retargeter<'a> {
+    old: &'a UPKPak,
+    old_ver: i16,
+    new: &'a LazyPackage,
+    /// Full name (`Class Outer.Name`) to index, exports and imports alike.
+    objects: HashMap<String, i32>,
+    names: NameMap,
+}
+
+impl Retargeter<'_> {
+    fn object(old: &UPKPak, objects: &HashMap<String, i32>, idx: i32) -> Result<i32> {
+        if idx == 0 {
+            return Ok(0);
+        }
+        let full = full_name(old, idx);
+        objects.get(&full).copied().ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("references {full}, which the new package doesn't have"),
+            )
+        })
+    }
+
+    fn name(old: &UPKPak, names: &mut NameMap, n: FName) -> Result<FName> {
+        let s = old.name_table.get(n.name_index as usize).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("name index {} outside the patch's name map", n.name_index),
+            )
+        })?;
+        Ok(FName {
+            name_index: names.intern(s),
+            name_instance: n.name_instance,
+        })
+    }
+
+    /// The new package's export with the patched bytecode in it. Header
+    /// and flags come from the new build; only the script is carried over.
+    fn function(&mut self, key: &str, patched: &[u8]) -> Result<(i32, Vec<u8>)> {
+        let old_idx = (1..=self.old.export_table.len() as i32)
+            .find(|&i| export_path_dotted(self.old, i) == key)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("no export '{key}' in the package it was built against"),
+                )
+            })?;
+        if self.old.script_kind(old_idx) != Some(ScriptKind::Function) {
+            let class = self
+                .old
+                .get_class_name(self.old.export_table[(old_idx - 1) as usize].class_index);
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("{class} override; only functions can be retargeted"),
+            ));
+        }
+        let full = self.old.get_export_full_name(old_idx);
+        let new_idx = match self.objects.get(&full) {
+            Some(&i) if i > 0 => i,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("{full} isn't in the new package"),
+                ));
+            }
+        };
+
+        let span = script_span(patched, ScriptKind::Function, self.old, self.old_ver)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "patched export has no bytecode"))?;
+        let start = span.offset_in_blob as usize;
+        let script = patched
+            .get(start..start + span.disk_size as usize)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "script runs past the patched blob",
+                )
+            })?;
+        let dis = disassemble(script, self.old, self.old_ver);
+        let (old, objects, names) = (self.old, &self.objects, &mut self.names);
+        let script = reserialize_script(
+            script,
+            &dis,
+            |i| Self::object(old, objects, i),
+            |n| Self::name(old, names, n),
+        )?;
+
+        let new_blob = self.new.export_blob(new_idx)?;
+        let new_ver = self.new.header.p_ver;
+        let new_span = script_span(new_blob, ScriptKind::Function, &self.new.pak, new_ver)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{full} has no bytecode in the new package"),
+                )
+            })?;
+        let at = new_span.offset_in_blob as usize;
+        let mut blob = new_blob[..at].to_vec();
+        write_script_sizes(&mut blob, at, new_ver, span.disk_size, span.mem_size);
+        blob.extend_from_slice(&script);
+        blob.extend_from_slice(&new_blob[at + new_span.disk_size as usize..]);
+        Ok((new_idx, blob))
+    }
+}
+
+/// Rewrites every function override in `patch_dir` for the package `to`:
+/// each is found again by full name, its bytecode's object and name
+/// indices are translated, and it's spliced into the new build's export.
+fn retarget(patch_dir: &Path, from: &Path, to: &Path, out: &Path) -> Result<()> {
+    let old_lp = open_package_file(from)?;
+    let new_lp = open_package_file(to)?;
+    let old = patch_view(&old_lp, patch_dir)?;
+
+    let new = &new_lp.pak;
+    let mut objects = HashMap::new();
+    for i in 1..=new.import_table.len() as i32 {
+        objects.entry(new.get_import_full_name(-i)).or_insert(-i);
+    }
+    for i in 1..=new.export_table.len() as i32 {
+        objects.entry(new.get_export_full_name(i)).or_insert(i);
+    }
+    let mut rt = Retargeter {
+        old: &old,
+        old_ver: old_lp.header.p_ver,
+        new: &new_lp,
+        objects,
+        names: NameMap::new(new.name_table.to_vec()),
+    };
+
+    let mut bins: Vec<_> = std::fs::read_dir(patch_dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().and_then(|s| s.to_str()) == Some("bin"))
+        .collect();
+    bins.sort();
+    if bins.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("no .bin overrides in {}", patch_dir.display()),
+        ));
+    }
+
+    let pkg_name = stem(&new_lp.path);
+    let pkg_dir = out.join(&pkg_name);
+    readonly::create_dir_all(&pkg_dir)?;
+    let (mut written, mut failed) = (0usize, 0usize);
+    for bin in &bins {
+        let key = stem(bin);
+        match rt.function(&key, &std::fs::read(bin)?) {
+            Ok((idx, blob)) => {
+                readonly::write(pkg_dir.join(format!("{key}.bin")), blob)?;
+                written += 1;
+                println!(
+                    "  {}   {}",
+                    paint(Color::Green, "OK"),
+                    new.get_export_full_name(idx)
+                );
+            }
+            Err(e) => {
+                failed += 1;
+                term::error("FAIL", format_args!("{key} — {e}"));
+            }
+        }
+    }
+    if written > 0 {
+        readonly::write(
+            pkg_dir.join(format!("{pkg_name}.namemap")),
+            rt.names.names.join("\n"),
+        )?;
+    }
+
+    println!(
+        "patch retarget: {written} override(s) written to {} ({failed} failed)",
+        pkg_dir.display()
+    );
+    if failed > 0 {
+        return Err(Error::other(format!(
+            "{failed} of {} override(s) couldn't be retargeted",
+            bins.len()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use std::{io::Result, path::Path};
+
+use crate::{
+    disasm::{export_disassembly, print_statement},
+    doc::value_text,
+    offsets::find_export,
+    schemadb::{LazyPackage, SchemaDb, open_package_file},
+    utils::term::{self, Color, paint},
+};
+
+/// Statements shown when the export holds bytecode.
+const MAX_STATEMENTS: usize = 24;
+
+/// Properties shown before the rest is counted.
+const MAX_PROPS: usize = 40;
+
+/// `offset  hex (gap after 8)  ascii`, 16 bytes a row; `data` starts
+/// `start` bytes into the export, which is at file offset `base`.
+pub(crate) fn hexdump(data: &[u8], start: usize, base: u64) {
+    for (r, row) in data.chunks(16).enumerate() {
+        let mut hex = String::with_capacity(16 * 3 + 1);
+        for (i, b) in row.iter().enumerate() {
+            if i == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{b:02x} "));
+        }
+        for i in row.len()..16 {
+            if i == 8 {
+                hex.push(' ');
+            }
+            hex.push_str("   ");
+        }
+        let ascii: String = row
+            .iter()
+            .map(|&b| {
+                if (0x20..0x7f).contains(&b) {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        let off = start + r * 16;
+        println!(
+            "  {:04x} {}  {hex} {ascii}",
+            off,
+            paint(Color::Gray, format!("[{:08x}]", base + off as u64))
+        );
+    }
+}
+
+fn print_props(lp: &LazyPackage, idx: i32, db: Option<&SchemaDb>) {
+    let (props, end) = match lp.export_props(idx, db) {
+        Ok(p) => p,
+        Err(e) => {
+            println!("  tagged properties not readable: {e}");
+            return;
+        }
+    };
+    if props.is_empty() {
+        println!("  no tagged properties at the start; native or raw data");
+        return;
+    }
+    let nw = term::column_width(props.iter().take(MAX_PROPS).map(|p| p.name.as_str()));
+    for p in props.iter().take(MAX_PROPS) {
+        let name = if p.array_index > 0 {
+            format!("{}[{}]", p.name, p.array_index)
+        } else {
+            p.name.clone()
+        };
+        println!(
+            "  {:<nw$} {} = {}",
+            paint(Color::Cyan, name),
+            paint(Color::Gray, &p.prop_type),
+            value_text(lp, &p.value, 0)
+        );
+    }
+    if props.len() > MAX_PROPS {
+        println!("  … {} more", props.len() - MAX_PROPS);
+    }
+    let size = lp.export_blob(idx).map_or(0, |b| b.len());
+    if end < size {
+        println!(
+            "  {} byte(s) of native data follow at +0x{end:X}",
+            size - end
+        );
+    }
+}
+
+/// Hexdump of the first `bytes` of an export and a best guess at what it
+/// holds: bytecode for functions / states / classes, tagged properties
+/// otherwise.
+pub fn peek_cmd(
+    upk_path: &Path,
+    object: &str,
+    bytes: usize,
+    game_root: Option<&Path>,
+    verbose: bool,
+) -> Result<()> {
+    let lp = open_package_file(upk_path)?;
+    let idx = find_export(&lp, object)?;
+    let blob = lp.export_blob(idx)?;
+    let exp = &lp.pak.export_table[(idx - 1) as usize];
+
+    println!(
+        "#{idx} {} ({} bytes at 0x{:08X})",
+        paint(Color::Highlight, lp.pak.get_export_full_name(idx)),
+        blob.len(),
+        exp.serial_offset
+    );
+    let shown = &blob[..bytes.min(blob.len())];
+    hexdump(shown, 0, exp.serial_offset as u64);
+    if shown.len() < blob.len() {
+        println!("  … {} more byte(s)", blob.len() - shown.len());
+    }
+    println!();
+
+    if lp.pak.script_kind(idx).is_some() {
+        match export_disassembly(&lp, idx) {
+            Ok(dis) => {
+                println!("Bytecode ({} statement(s)):", dis.statements.len());
+                for st in dis.statements.iter().take(MAX_STATEMENTS) {
+                    print_statement(st, false);
+                }
+                if dis.statements.len() > MAX_STATEMENTS {
+                    println!("  … {} more", dis.statements.len() - MAX_STATEMENTS);
+                }
+                if let Some(e) = &dis.error {
+                    term::warn("disasm", e);
+                }
+                return Ok(());
+            }
+            Err(e) => {
+                println!("Bytecode not readable: {e}");
+                return Ok(());
+            }
+        }
+    }
+
+    let db = match game_root {
+        Some(gr) if !gr.as_os_str().is_empty() => Some(SchemaDb::new(gr)?.with_verbose(verbose)),
+        _ => None,
+    };
+    println!("Tagged properties:");
+    print_props(&lp, idx, db.as_ref());
+    Ok(())
+}
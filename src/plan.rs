@@ -0,0 +1,326 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    schemadb::open_package_file,
+    upkreader::UPKPak,
+    utils::{
+        deadline,
+        term::{self, Color, paint},
+        walk::{is_package, package_files, stem},
+    },
+    versions::{EF_FORCED_EXPORT, PKG_CONTAINS_MAP},
+};
+
+/// Copies listed per package without `--verbose`.
+const LISTED: usize = 8;
+
+/// A modified package, and which of its objects changed when known.
+struct Modified {
+    name: String,
+    /// `export_path_dotted` keys of edited exports, lowercased; `None`
+    /// is the whole package.
+    objects: Option<BTreeSet<String>>,
+}
+
+impl Modified {
+    /// `path` is inside the package (no package name), with subobjects
+    /// of an edited export counting as edited.
+    fn touches(&self, path: &str) -> bool {
+        let Some(keys) = &self.objects else {
+            return true;
+        };
+        let path = path.to_ascii_lowercase();
+        keys.iter().any(|k| {
+            path == *k
+                || path
+                    .strip_prefix(k.as_str())
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+    }
+}
+
+fn bin_keys(dir: &Path) -> Result<BTreeSet<String>> {
+    Ok(std::fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().and_then(|s| s.to_str()) == Some("bin"))
+        .filter_map(|p| {
+            p.file_stem()
+                .map(|s| s.to_string_lossy().to_ascii_lowercase())
+        })
+        .collect())
+}
+
+/// A package name or file (all of it changed), or pack-mod output: one
+/// package's directory of `.bin` overrides, or the directory above those.
+fn parse_modified(arg: &str) -> Result<Vec<Modified>> {
+    let p = Path::new(arg);
+    if !p.is_dir() {
+        let name = if is_package(p) {
+            stem(p)
+        } else {
+            arg.to_string()
+        };
+        return Ok(vec![Modified {
+            name,
+            objects: None,
+        }]);
+    }
+    let keys = bin_keys(p)?;
+    if !keys.is_empty() {
+        return Ok(vec![Modified {
+            name: stem(p),
+            objects: Some(keys),
+        }]);
+    }
+    let mut out = Vec::new();
+    for e in std::fs::read_dir(p)?.flatten() {
+        let sub = e.path();
+        if sub.is_dir() {
+            let keys = bin_keys(&sub)?;
+            if !keys.is_empty() {
+                out.push(Modified {
+                    name: stem(&sub),
+                    objects: Some(keys),
+                });
+            }
+        }
+    }
+    if out.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{arg} holds no pack-mod overrides (<pkg>/<object>.bin)"),
+        ));
+    }
+    out.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(out)
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum Kind {
+    /// Loaded at boot and kept resident, so its copies win everywhere.
+    Startup,
+    Map,
+    Other,
+}
+
+impl Kind {
+    fn label(self) -> &'static str {
+        match self {
+            Kind::Startup => "startup",
+            Kind::Map => "map",
+            Kind::Other => "package",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Found {
+    /// `Package.Path` of each cooked copy of an edited object.
+    copies: Vec<String>,
+    imports: usize,
+}
+
+/// The outermost export or import above `idx`, and the dotted path below
+/// it.
+fn top_and_path(pak: &UPKPak, idx: i32) -> (i32, String) {
+    let mut parts = Vec::new();
+    let mut cur = idx;
+    for _ in 0..64 {
+        let (name, outer) = if cur > 0 {
+            match pak.export_table.get((cur - 1) as usize) {
+                Some(e) => (&e.object_name, e.outer_index),
+                None => break,
+            }
+        } else {
+            match pak.import_table.get((-cur - 1) as usize) {
+                Some(i) => (&i.object_name, i.outer_index),
+                None => break,
+            }
+        };
+        if outer == 0 {
+            break;
+        }
+        parts.push(pak.fname_to_string(name));
+        cur = outer;
+    }
+    parts.reverse();
+    (cur, parts.join("."))
+}
+
+/// Exports seek-free cooking copied in from other packages: index, the
+/// package they came from, and their dotted path inside it.
+pub fn forced_copies(pak: &UPKPak) -> Vec<(i32, String, String)> {
+    let mut out = Vec::new();
+    for idx in 1..=pak.export_table.len() as i32 {
+        let (top, below) = top_and_path(pak, idx);
+        let Some(e) = pak
+            .export_table
+            .get((top - 1) as usize)
+            .filter(|_| top != idx)
+        else {
+            continue;
+        };
+        // They sit under an export standing for their package, flagged
+        // as forced.
+        if e.export_flags & EF_FORCED_EXPORT == 0 || pak.get_class_name(e.class_index) != "Package"
+        {
+            continue;
+        }
+        out.push((idx, pak.fname_to_string(&e.object_name), below));
+    }
+    out
+}
+
+/// Cooked copies of modified objects and imports of them in one package.
+fn scan(path: &Path, modified: &HashMap<String, &Modified>) -> Result<(Kind, Found)> {
+    deadline::arm();
+    let lp = open_package_file(path)?;
+    let pak = &lp.pak;
+    let kind = if stem(path).to_ascii_lowercase().starts_with("startup") {
+        Kind::Startup
+    } else if lp.header.pak_flags & PKG_CONTAINS_MAP != 0 {
+        Kind::Map
+    } else {
+        Kind::Other
+    };
+
+    let mut found = Found::default();
+    for (_, pkg, below) in forced_copies(pak) {
+        if modified
+            .get(&pkg.to_ascii_lowercase())
+            .is_some_and(|m| m.touches(&below))
+        {
+            found.copies.push(format!("{pkg}.{below}"));
+        }
+    }
+    for (i, imp) in pak.import_table.iter().enumerate() {
+        let (top, below) = top_and_path(pak, -(i as i32) - 1);
+        if pak.fname_to_string(&imp.class_name) == "Package" {
+            continue;
+        }
+        let Some(imp) = pak.import_table.get((-top - 1) as usize) else {
+            continue;
+        };
+        let pkg = pak.fname_to_string(&imp.object_name);
+        if modified
+            .get(&pkg.to_ascii_lowercase())
+            .is_some_and(|m| m.touches(&below))
+        {
+            found.imports += 1;
+        }
+    }
+    Ok((kind, found))
+}
+
+/// Prints which packages under `game_dir` have to be regenerated (or get
+/// patch entries) along with `modified`: seek-free packages cook copies
+/// of what they use into themselves, and those copies don't see edits
+/// made to the original.
+pub fn plan_cmd(game_dir: &Path, modified: &[String], verbose: bool) -> Result<()> {
+    let root = game_dir;
+    let mut mods: Vec<Modified> = Vec::new();
+    for m in modified {
+        mods.extend(parse_modified(m)?);
+    }
+    let files = package_files(root);
+    let by_stem: HashMap<String, &PathBuf> = files
+        .iter()
+        .map(|p| (stem(p).to_ascii_lowercase(), p))
+        .collect();
+    for m in &mods {
+        if !by_stem.contains_key(&m.name.to_ascii_lowercase()) {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("no package '{}' under {}", m.name, game_dir.display()),
+            ));
+        }
+    }
+    let index: HashMap<String, &Modified> = mods
+        .iter()
+        .map(|m| (m.name.to_ascii_lowercase(), m))
+        .collect();
+
+    let rel = |p: &Path| {
+        p.strip_prefix(root)
+            .unwrap_or(p)
+            .to_string_lossy()
+            .to_string()
+    };
+    let mut copies: BTreeMap<(Kind, String), Vec<String>> = BTreeMap::new();
+    let mut importers: Vec<(String, usize)> = Vec::new();
+    let mut unreadable = 0usize;
+    for f in &files {
+        if index.contains_key(&stem(f).to_ascii_lowercase()) {
+            continue;
+        }
+        match scan(f, &index) {
+            Ok((kind, found)) => {
+                if !found.copies.is_empty() {
+                    copies.insert((kind, rel(f)), found.copies);
+                } else if found.imports > 0 {
+                    importers.push((rel(f), found.imports));
+                }
+            }
+            Err(e) => {
+                unreadable += 1;
+                term::warn("skip", format_args!("{}: {e}", f.display()));
+            }
+        }
+    }
+
+    println!("Build plan");
+    let mut step = 0;
+    for m in &mods {
+        step += 1;
+        let what = match &m.objects {
+            Some(keys) => format!("{} edited object(s)", keys.len()),
+            None => "whole package".to_string(),
+        };
+        println!(
+            "  {step:>2}. {}  {}",
+            paint(Color::Highlight, rel(by_stem[&m.name.to_ascii_lowercase()])),
+            paint(Color::Gray, what)
+        );
+    }
+    for ((kind, path), list) in &copies {
+        step += 1;
+        println!(
+            "  {step:>2}. {}  {} {}",
+            paint(Color::Yellow, path),
+            paint(Color::Gray, format!("[{}]", kind.label())),
+            format_args!("{} cooked cop(ies)", list.len())
+        );
+        let shown = if verbose { list.len() } else { LISTED };
+        for c in list.iter().take(shown) {
+            println!("        {c}");
+        }
+        if list.len() > shown {
+            println!("        … {} more (--verbose)", list.len() - shown);
+        }
+    }
+    if !importers.is_empty() {
+        println!();
+        println!(
+            "{}",
+            paint(
+                Color::Gray,
+                "Import by name only; rebuild these if you renamed or removed an object:"
+            )
+        );
+        for (path, n) in &importers {
+            println!("      {path}  {n} import(s)");
+        }
+    }
+    println!();
+    println!(
+        "{} package(s) scanned ({unreadable} unreadable): {} hold cooked copies, {} only import",
+        files.len() - unreadable,
+        copies.len(),
+        importers.len()
+    );
+    Ok(())
+}
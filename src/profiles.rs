@@ -0,0 +1,267 @@
+use std::{
+    collections::BTreeMap,
+    io::{Error, ErrorKind, Result},
+    path::PathBuf,
+};
+
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{
+    config::config_dir,
+    hash::sha256_hex,
+    readonly,
+    term::{Color, paint},
+    walk::contained_path,
+};
+
+const MANIFEST_LIMIT: u64 = 1 << 20;
+const FILE_LIMIT: u64 = 64 << 20;
+
+#[derive(Subcommand)]
+pub enum ProfilesCmd {
+    #[command(about = "Fetch profile / native-table data from an index.json URL (remembered)")]
+    Update {
+        /// https:// URL of index.json, or a local file / file:// mirror.
+        #[arg(long)]
+        url: Option<String>,
+        /// SHA-256 the manifest must have, as published with it. Required
+        /// the first time and whenever the manifest changes; remembered
+        /// with the URL otherwise.
+        #[arg(long, value_name = "HEX")]
+        manifest_sha256: Option<String>,
+    },
+
+    #[command(about = "List installed profile data")]
+    List,
+}
+
+/// `index.json` at the update URL. Paths are relative to the profiles
+/// directory and, unless `url` is given, to the manifest URL.
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    version: u32,
+    files: Vec<ManifestFile>,
+}
+
+#[derive(Deserialize)]
+struct ManifestFile {
+    path: String,
+    sha256: String,
+    url: Option<String>,
+}
+
+/// `profiles.toml` in the config directory.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct State {
+    url: Option<String>,
+    /// SHA-256 of the manifest last installed from `url`.
+    manifest_sha256: Option<String>,
+    manifest_version: u32,
+    /// Installed path → SHA-256.
+    files: BTreeMap<String, String>,
+}
+
+fn profiles_dir() -> Result<PathBuf> {
+    Ok(config_dir()?.join("profiles"))
+}
+
+fn state_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("profiles.toml"))
+}
+
+fn load_state() -> Result<State> {
+    let p = state_path()?;
+    if !p.exists() {
+        return Ok(State::default());
+    }
+    let text = std::fs::read_to_string(&p)?;
+    toml::from_str(&text)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {e}", p.display())))
+}
+
+fn save_state(s: &State) -> Result<()> {
+    let p = state_path()?;
+    readonly::create_dir_all(p.parent().unwrap())?;
+    readonly::write(p, toml::to_string_pretty(s).map_err(Error::other)?)
+}
+
+/// `https://` goes over the network, `file://` and bare paths are read
+/// from disk (mirrors, testing). Plain `http://` and other schemes are
+/// refused: nothing here is worth fetching without transport security.
+fn fetch(url: &str, limit: u64) -> Result<Vec<u8>> {
+    if let Some((scheme, _)) = url.split_once("://")
+        && scheme != "https"
+        && scheme != "file"
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{url}: only https:// and file:// URLs are fetched"),
+        ));
+    }
+    if url.starts_with("https://") {
+        let mut resp = ureq::get(url)
+            .call()
+            .map_err(|e| Error::other(format!("{url}: {e}")))?;
+        return resp
+            .body_mut()
+            .with_config()
+            .limit(limit)
+            .read_to_vec()
+            .map_err(|e| Error::other(format!("{url}: {e}")));
+    }
+    let path = url.strip_prefix("file://").unwrap_or(url);
+    std::fs::read(path).map_err(|e| Error::new(e.kind(), format!("{path}: {e}")))
+}
+
+fn relative_url(manifest_url: &str, path: &str) -> String {
+    match manifest_url.rfind('/') {
+        Some(i) => format!("{}{path}", &manifest_url[..=i]),
+        None => path.to_string(),
+    }
+}
+
+/// Manifest paths must stay inside the profiles directory.
+fn update(url: Option<String>, manifest_sha256: Option<&str>) -> Result<()> {
+    let mut state = load_state()?;
+    let Some(url) = url.or_else(|| state.url.clone()) else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "no update URL configured; pass --url once",
+        ));
+    };
+    // The pin only carries over while the source stays the same.
+    let pinned = state
+        .manifest_sha256
+        .clone()
+        .filter(|_| state.url.as_ref() == Some(&url));
+    let Some(want) = manifest_sha256.map(str::to_ascii_lowercase).or(pinned) else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "no manifest hash pinned for {url}; pass --manifest-sha256 with the one published for it"
+            ),
+        ));
+    };
+    if want.len() != 64 || !want.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("--manifest-sha256 '{want}' isn't a SHA-256 in hex"),
+        ));
+    }
+
+    println!("Fetching {url}");
+    let raw = fetch(&url, MANIFEST_LIMIT)?;
+    let got = sha256_hex(&raw);
+    if got != want {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "manifest SHA-256 {got} does not match {want}; \
+                 pass --manifest-sha256 to pin a newly published manifest"
+            ),
+        ));
+    }
+    let manifest: Manifest = serde_json::from_slice(&raw)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{url}: {e}")))?;
+
+    // Everything is downloaded and verified before anything is written, so
+    // a bad file leaves the installed set as it was.
+    let dir = profiles_dir()?;
+    let mut pending = Vec::new();
+    let mut unchanged = 0usize;
+    for f in &manifest.files {
+        let rel = contained_path(&f.path, "the profiles directory")?;
+        let want = f.sha256.to_ascii_lowercase();
+        let dst = dir.join(rel);
+        if state.files.get(&f.path) == Some(&want)
+            && std::fs::read(&dst).is_ok_and(|d| sha256_hex(&d) == want)
+        {
+            unchanged += 1;
+            continue;
+        }
+        let src = f.url.clone().unwrap_or_else(|| relative_url(&url, &f.path));
+        let data = fetch(&src, FILE_LIMIT)?;
+        let got = sha256_hex(&data);
+        if got != want {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: SHA-256 {got} does not match manifest {want}", f.path),
+            ));
+        }
+        pending.push((f.path.clone(), dst, data, want));
+    }
+
+    for (name, dst, data, hash) in &pending {
+        readonly::create_dir_all(dst.parent().unwrap())?;
+        let tmp = dst.with_extension("part");
+        readonly::write(&tmp, data)?;
+        readonly::rename(&tmp, dst)?;
+        println!("  updated  {name}");
+        state.files.insert(name.clone(), hash.clone());
+    }
+
+    let listed: Vec<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+    let dropped: Vec<String> = state
+        .files
+        .keys()
+        .filter(|k| !listed.contains(&k.as_str()))
+        .cloned()
+        .collect();
+    for name in &dropped {
+        let p = dir.join(name);
+        if p.exists() {
+            readonly::remove_file(&p)?;
+        }
+        state.files.remove(name);
+        println!("  removed  {name}");
+    }
+
+    state.url = Some(url);
+    state.manifest_sha256 = Some(got);
+    state.manifest_version = manifest.version;
+    save_state(&state)?;
+    println!(
+        "Profiles v{}: {} updated, {unchanged} unchanged, {} removed ({})",
+        manifest.version,
+        pending.len(),
+        dropped.len(),
+        dir.display()
+    );
+    Ok(())
+}
+
+fn list() -> Result<()> {
+    let state = load_state()?;
+    let dir = profiles_dir()?;
+    match &state.url {
+        Some(u) => println!(
+            "Source: {u} (v{}, manifest SHA-256 {})",
+            state.manifest_version,
+            state.manifest_sha256.as_deref().unwrap_or("not pinned")
+        ),
+        None => println!("Source: none (run `profiles update --url <URL>`)"),
+    }
+    for (name, hash) in &state.files {
+        let status = match std::fs::read(dir.join(name)) {
+            Ok(d) if sha256_hex(&d) == *hash => paint(Color::Green, "ok"),
+            Ok(_) => paint(Color::Yellow, "modified"),
+            Err(_) => paint(Color::Red, "missing"),
+        };
+        println!("  {name:<48} {status}");
+    }
+    println!("{} file(s) in {}", state.files.len(), dir.display());
+    Ok(())
+}
+
+pub fn run(cmd: ProfilesCmd) -> Result<()> {
+    match cmd {
+        ProfilesCmd::Update {
+            url,
+            manifest_sha256,
+        } => update(url, manifest_sha256.as_deref()),
+        ProfilesCmd::List => list(),
+    }
+}
@@ -0,0 +1,528 @@
+use std::{
+    collections::HashMap,
+    io::{Cursor, Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    changes::{digest, shape},
+    native::{NativePayload, NativeReadCtx, NativeRegistry},
+    package::{Package, relocate_bulk},
+    plan::forced_copies,
+    schemadb::{LazyPackage, SchemaDb, open_package_file},
+    upkpacker::ensure_tag_names,
+    upkprops::{Property, PropertyValue},
+    upkreader::{FName, UPKPak},
+    utils::{
+        backup::{backup_original, original_of},
+        hash::ContentHash,
+        retry::{Backoff, with_backoff},
+        term::{self, Color, paint},
+        walk::{package_files, stem},
+    },
+    versions::VER_NETINDEX_STORED_AS_INT,
+};
+
+/// Full name as a cooked copy has it. Exports of a source package get
+/// `src_stem` in front; a copy's path already starts at its package.
+fn ref_name(pak: &UPKPak, src_stem: Option<&str>, idx: i32) -> String {
+    match src_stem {
+        Some(stem) if idx > 0 => format!(
+            "{} {stem}.{}",
+            pak.get_class_name(pak.export_table[(idx - 1) as usize].class_index),
+            pak.get_export_path_name(idx)
+        ),
+        _ if idx > 0 => pak.get_export_full_name(idx),
+        _ => pak.get_import_full_name(idx),
+    }
+}
+
+/// References spelled out, so the same object in two packages compares
+/// equal whatever its indices there.
+fn canonical(v: &PropertyValue, pak: &UPKPak, src_stem: Option<&str>) -> PropertyValue {
+    match v {
+        PropertyValue::Object(i) if *i != 0 => {
+            PropertyValue::ObjectRef(ref_name(pak, src_stem, *i).to_ascii_lowercase())
+        }
+        PropertyValue::Name(n) => PropertyValue::String(pak.fname_to_string(n)),
+        PropertyValue::Array(items) => {
+            PropertyValue::Array(items.iter().map(|x| canonical(x, pak, src_stem)).collect())
+        }
+        PropertyValue::Struct(props) => PropertyValue::Struct(
+            props
+                .iter()
+                .map(|p| Property {
+                    value: canonical(&p.value, pak, src_stem),
+                    ..p.clone()
+                })
+                .collect(),
+        ),
+        PropertyValue::AtomicStruct(fields) => PropertyValue::AtomicStruct(
+            fields
+                .iter()
+                .map(|(n, x)| (n.clone(), canonical(x, pak, src_stem)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// The native data after the properties, as far as it can be compared
+/// and carried between packages.
+fn native_key(
+    lp: &LazyPackage,
+    idx: i32,
+    props: &[Property],
+    tail: &[u8],
+    registry: &NativeRegistry,
+) -> (String, bool) {
+    let class = lp.export_class_name(idx);
+    let payload = registry.for_class(None, None, &class).and_then(|ser| {
+        ser.read(&NativeReadCtx {
+            blob: tail,
+            props,
+            ver: lp.header.p_ver,
+            l_ver: lp.header.l_ver,
+            pak: &lp.pak,
+            db: None,
+            self_ref: None,
+            class_ref: None,
+        })
+        .ok()
+    });
+    match payload.map(|r| r.payload) {
+        // Index-free apart from inline bulk offsets, which are left out
+        // here and relocated when the tail is copied.
+        Some(
+            p @ (NativePayload::Texture2D(_)
+            | NativePayload::SoundNodeWave(_)
+            | NativePayload::SwfMovie(_)),
+        ) => {
+            let mut key = shape(&p);
+            for b in p.bulk() {
+                key.push_str(&format!(
+                    " {:x}/{}/{}/{}",
+                    b.flags,
+                    b.element_count,
+                    b.size_on_disk,
+                    digest(b.data)
+                ));
+                if b.is_external() {
+                    key.push_str(&format!("@{}", b.offset_in_file));
+                }
+            }
+            (key, true)
+        }
+        // Whatever else is there may hold package-local indices, which
+        // can't be told apart from data.
+        _ if tail.is_empty() => (String::new(), true),
+        _ => (format!("opaque {}", digest(tail)), false),
+    }
+}
+
+/// Export contents without the net index.
+struct Contents {
+    props: Vec<Property>,
+    tail: Vec<u8>,
+    /// File offset of `tail`, which inline bulk offsets are relative to.
+    tail_at: i64,
+    native: String,
+    /// `tail` can be copied into another package (with bulk relocated).
+    portable: bool,
+}
+
+impl Contents {
+    fn read(lp: &LazyPackage, idx: i32, db: &SchemaDb, registry: &NativeRegistry) -> Result<Self> {
+        let (props, end) = lp.export_props(idx, Some(db))?;
+        let tail = lp.export_blob(idx)?[end..].to_vec();
+        let (native, portable) = native_key(lp, idx, &props, &tail, registry);
+        let serial_offset = lp.pak.export_table[(idx - 1) as usize].serial_offset;
+        Ok(Contents {
+            props,
+            tail,
+            tail_at: serial_offset as i64 + end as i64,
+            native,
+            portable,
+        })
+    }
+
+    /// `src_stem` is set when `pak` is the object's own package, `None`
+    /// for a cooked copy.
+    fn hash(&self, pak: &UPKPak, src_stem: Option<&str>) -> String {
+        let canon = canonical(&PropertyValue::Struct(self.props.clone()), pak, src_stem);
+        let mut h = ContentHash::new();
+        h.update(&serde_json::to_vec(&canon).unwrap_or_default());
+        h.update(self.native.as_bytes());
+        h.hex()
+    }
+}
+
+/// An export that differs between the original and edited source.
+struct Edit {
+    /// `Class Path`, lowercased, as the copies are matched.
+    key: String,
+    display: String,
+    original_hash: String,
+    edited_hash: String,
+    /// The native data is the same before and after the edit.
+    same_native: bool,
+    edited: Contents,
+}
+
+struct Translator<'a> {
+    src: &'a UPKPak,
+    src_stem: &'a str,
+    /// Lowercased full names of the target's exports and imports.
+    objects: HashMap<String, i32>,
+}
+
+impl Translator<'_> {
+    fn value(&self, v: &PropertyValue, dst: &mut Package) -> Result<PropertyValue> {
+        Ok(match v {
+            PropertyValue::Object(i) if *i != 0 => {
+                let full = ref_name(self.src, Some(self.src_stem), *i);
+                match self.objects.get(&full.to_ascii_lowercase()) {
+                    Some(&t) => PropertyValue::Object(t),
+                    None => {
+                        return Err(Error::new(
+                            ErrorKind::NotFound,
+                            format!("references {full}, which isn't in this package"),
+                        ));
+                    }
+                }
+            }
+            PropertyValue::Name(n) => PropertyValue::Name(FName {
+                name_index: dst.add_name(&self.src.fname_to_string(n)),
+                name_instance: n.name_instance,
+            }),
+            PropertyValue::Array(items) => PropertyValue::Array(
+                items
+                    .iter()
+                    .map(|x| self.value(x, dst))
+                    .collect::<Result<_>>()?,
+            ),
+            PropertyValue::Struct(props) => PropertyValue::Struct(self.props(props, dst)?),
+            PropertyValue::AtomicStruct(fields) => PropertyValue::AtomicStruct(
+                fields
+                    .iter()
+                    .map(|(n, x)| Ok((n.clone(), self.value(x, dst)?)))
+                    .collect::<Result<_>>()?,
+            ),
+            other => other.clone(),
+        })
+    }
+
+    fn props(&self, props: &[Property], dst: &mut Package) -> Result<Vec<Property>> {
+        props
+            .iter()
+            .map(|p| {
+                Ok(Property {
+                    value: self.value(&p.value, dst)?,
+                    ..p.clone()
+                })
+            })
+            .collect()
+    }
+
+    /// The edited export rewritten against `dst`'s tables, keeping the
+    /// copy's own net index. Inline bulk offsets come out relative to the
+    /// blob, for `set_export_blob_relative`. Native data that may hold
+    /// indices is only kept when the edit left it alone, as the copy's
+    /// own.
+    fn blob(
+        &self,
+        edit: &Edit,
+        copy: &Contents,
+        dst: &mut Package,
+        copy_idx: i32,
+    ) -> Result<Vec<u8>> {
+        let tail = if edit.edited.portable {
+            &edit.edited
+        } else if edit.same_native {
+            copy
+        } else {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "native data changed in a form that may hold package-local indices; \
+                 it can't be carried over",
+            ));
+        };
+        let edit = &edit.edited;
+        let props = self.props(&edit.props, dst)?;
+        let mut names: Vec<String> = dst.names.iter().map(|n| n.name.clone()).collect();
+        let known = names.len();
+        ensure_tag_names(&props, &mut names);
+        for n in &names[known..] {
+            dst.add_name(n);
+        }
+
+        let p_ver = dst.header.p_ver;
+        let pak = dst.pak();
+        let mut body = Vec::new();
+        if p_ver >= VER_NETINDEX_STORED_AS_INT {
+            body.extend_from_slice(dst.export_blob(copy_idx)?.get(..4).unwrap_or(&[0; 4]));
+        }
+        let mut w = Cursor::new(&mut body);
+        w.set_position(w.get_ref().len() as u64);
+        for p in &props {
+            p.write(&mut w, &pak, p_ver)?;
+        }
+        let mut native = tail.tail.clone();
+        relocate_bulk(&mut native, tail.tail_at, body.len() as i64);
+        body.extend_from_slice(&native);
+        Ok(body)
+    }
+}
+
+fn edits(
+    orig: &LazyPackage,
+    edited: &LazyPackage,
+    db: &SchemaDb,
+    registry: &NativeRegistry,
+) -> Result<Vec<Edit>> {
+    if orig.pak.export_table.len() != edited.pak.export_table.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{} has {} export(s), {} has {}; propagate carries edits to existing objects only",
+                orig.path.display(),
+                orig.pak.export_table.len(),
+                edited.path.display(),
+                edited.pak.export_table.len()
+            ),
+        ));
+    }
+    let stem = stem(&edited.path);
+    let mut out = Vec::new();
+    for idx in 1..=edited.pak.export_table.len() as i32 {
+        if orig.export_blob(idx)? == edited.export_blob(idx)? {
+            continue;
+        }
+        let pak = &edited.pak;
+        let class = pak.get_class_name(pak.export_table[(idx - 1) as usize].class_index);
+        let path = pak.get_export_path_name(idx).replace(':', ".");
+        let contents = Contents::read(edited, idx, db, registry)?;
+        let original = Contents::read(orig, idx, db, registry)?;
+        out.push(Edit {
+            key: format!("{class} {path}").to_ascii_lowercase(),
+            display: format!("{class} {path}"),
+            original_hash: original.hash(&orig.pak, Some(&stem)),
+            edited_hash: contents.hash(pak, Some(&stem)),
+            same_native: original.native == contents.native,
+            edited: contents,
+        });
+    }
+    Ok(out)
+}
+
+/// Applies the edits made to `edited` (against `original`, or its `.bak`)
+/// to every cooked copy of those objects in packages under `game_dir`.
+/// A copy is only replaced when it matches the original object by full
+/// name and by content, so copies cooked differently are left alone.
+///
+/// A package that stays locked through `backoff` is reported at the end
+/// and the rest are still written; `throttle` pauses after each write.
+pub fn propagate_cmd(
+    edited: &Path,
+    game_dir: &Path,
+    original: Option<&Path>,
+    dry_run: bool,
+    backoff: &Backoff,
+    throttle: Duration,
+) -> Result<()> {
+    let edited_path = edited;
+    let original_path = match original {
+        Some(o) => o.to_path_buf(),
+        None => original_of(edited_path)?,
+    };
+    let db = SchemaDb::new(game_dir)?;
+    let registry = NativeRegistry::standard();
+    let src = open_package_file(edited_path)?;
+    let orig = open_package_file(&original_path)?;
+    let src_stem = stem(edited_path);
+    let edits = edits(&orig, &src, &db, &registry)?;
+    if edits.is_empty() {
+        println!(
+            "No export of {} differs from {}",
+            edited_path.display(),
+            original_path.display()
+        );
+        return Ok(());
+    }
+    let by_key: HashMap<&str, &Edit> = edits.iter().map(|e| (e.key.as_str(), e)).collect();
+
+    let (mut replaced, mut current, mut diverged, mut failed, mut packages) =
+        (0usize, 0usize, 0usize, 0usize, 0usize);
+    let mut unwritten: Vec<(PathBuf, usize, Error)> = Vec::new();
+    for file in package_files(game_dir) {
+        if stem(&file).eq_ignore_ascii_case(&src_stem) {
+            continue;
+        }
+        let lp = match open_package_file(&file) {
+            Ok(lp) => lp,
+            Err(e) => {
+                term::warn("skip", format_args!("{}: {e}", file.display()));
+                continue;
+            }
+        };
+        let mut targets = Vec::new();
+        for (idx, pkg, below) in forced_copies(&lp.pak) {
+            if !pkg.eq_ignore_ascii_case(&src_stem) {
+                continue;
+            }
+            let class = lp
+                .pak
+                .get_class_name(lp.pak.export_table[(idx - 1) as usize].class_index);
+            let key = format!("{class} {below}").to_ascii_lowercase();
+            let Some(edit) = by_key.get(key.as_str()) else {
+                continue;
+            };
+            match Contents::read(&lp, idx, &db, &registry).map(|c| (c.hash(&lp.pak, None), c)) {
+                Ok((h, copy)) if h == edit.original_hash => targets.push((idx, *edit, copy)),
+                Ok((h, _)) if h == edit.edited_hash => current += 1,
+                Ok(_) => {
+                    diverged += 1;
+                    term::warn(
+                        "skip",
+                        format_args!(
+                            "{}: {} differs from the original; not replaced",
+                            file.display(),
+                            edit.display
+                        ),
+                    );
+                }
+                Err(e) => {
+                    failed += 1;
+                    term::error(
+                        "FAIL",
+                        format_args!("{}: {} — {e}", file.display(), edit.display),
+                    );
+                }
+            }
+        }
+        if targets.is_empty() {
+            continue;
+        }
+        if lp.header.p_ver != src.header.p_ver {
+            failed += targets.len();
+            term::error(
+                "FAIL",
+                format_args!(
+                    "{}: package version {} isn't the source's {}",
+                    file.display(),
+                    lp.header.p_ver,
+                    src.header.p_ver
+                ),
+            );
+            continue;
+        }
+
+        let mut pkg = match with_backoff(backoff, &file, || Package::open(&file)) {
+            Ok(p) => p,
+            Err(e) => {
+                unwritten.push((file.clone(), targets.len(), e));
+                continue;
+            }
+        };
+        let pak = pkg.pak();
+        let mut objects = HashMap::new();
+        for i in 1..=pak.import_table.len() as i32 {
+            objects
+                .entry(pak.get_import_full_name(-i).to_ascii_lowercase())
+                .or_insert(-i);
+        }
+        for i in 1..=pak.export_table.len() as i32 {
+            objects
+                .entry(pak.get_export_full_name(i).to_ascii_lowercase())
+                .or_insert(i);
+        }
+        let tr = Translator {
+            src: &src.pak,
+            src_stem: &src_stem,
+            objects,
+        };
+        let mut changed = 0;
+        for (idx, edit, copy) in targets {
+            match tr
+                .blob(edit, &copy, &mut pkg, idx)
+                .and_then(|b| pkg.set_export_blob_relative(idx, b))
+            {
+                Ok(_) => {
+                    changed += 1;
+                    println!(
+                        "  {}   {} in {}",
+                        paint(Color::Green, "OK"),
+                        edit.display,
+                        file.display()
+                    );
+                }
+                Err(e) => {
+                    failed += 1;
+                    term::error(
+                        "FAIL",
+                        format_args!("{}: {} — {e}", file.display(), edit.display),
+                    );
+                }
+            }
+        }
+        if changed == 0 {
+            continue;
+        }
+        // Its mapping of `file` would keep the save from replacing it.
+        drop(lp);
+        if !dry_run {
+            let written = with_backoff(backoff, &file, || {
+                let bak = backup_original(&file)?;
+                pkg.save(&file)?;
+                Ok(bak)
+            });
+            match written {
+                Ok(Some(bak)) => println!("  Backup: {}", bak.display()),
+                Ok(None) => {}
+                Err(e) => {
+                    let mut part = file.as_os_str().to_os_string();
+                    part.push(".part");
+                    let _ = std::fs::remove_file(part);
+                    unwritten.push((file.clone(), changed, e));
+                    continue;
+                }
+            }
+            if !throttle.is_zero() {
+                std::thread::sleep(throttle);
+            }
+        }
+        replaced += changed;
+        packages += 1;
+    }
+
+    println!(
+        "propagate: {} edited export(s); {replaced} cop(ies) replaced in {packages} package(s){}, {current} already up to date, {diverged} left as they differ, {failed} failed",
+        edits.len(),
+        if dry_run {
+            " (dry run, nothing written)"
+        } else {
+            ""
+        }
+    );
+    if !unwritten.is_empty() {
+        println!(
+            "{} package(s) couldn't be written and were left as they were:",
+            unwritten.len()
+        );
+        for (file, n, e) in &unwritten {
+            println!(
+                "  {} {} ({n} cop(ies)) — {e}",
+                paint(Color::Red, "FAIL"),
+                file.display()
+            );
+        }
+        failed += unwritten.iter().map(|(_, n, _)| n).sum::<usize>();
+    }
+    if failed > 0 {
+        return Err(Error::other(format!(
+            "{failed} cop(ies) couldn't be replaced"
+        )));
+    }
+    Ok(())
+}
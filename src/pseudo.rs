@@ -2,15 +2,16 @@ use std::{
     collections::HashMap,
     fmt::Write as FmtWrite,
     io::{Result, Write},
-    path::{Path, PathBuf},
+    path::Path,
 };
 
 use crate::{
-    native::{Mip, MipSource, NativePayload},
+    native::{Mip, MipSource, NativePayload, Sidecar},
     schema::{PropertyKind, SchemaEntry},
     schemadb::{ResolvedRef, SchemaDb},
     upkprops::{Property, PropertyValue},
     upkreader::{FName, UPKPak},
+    utils::readonly,
 };
 
 const INDENT: &str = "    ";
@@ -113,7 +114,7 @@ pub struct EmitInput<'a> {
     pub props: &'a [Property],
     pub consumed_props: &'a [String],
     pub payload: &'a NativePayload,
-    pub sidecars: &'a [PathBuf],
+    pub sidecars: &'a [Sidecar],
     pub pak: &'a UPKPak,
     pub pkg_stem: &'a str,
     pub p_ver: i16,
@@ -121,7 +122,7 @@ pub struct EmitInput<'a> {
 
 pub fn write_uo_file(path: &Path, input: &EmitInput) -> Result<()> {
     let s = render(input);
-    let mut f = std::fs::File::create(path)?;
+    let mut f = readonly::create(path)?;
     f.write_all(s.as_bytes())?;
     Ok(())
 }
@@ -192,8 +193,8 @@ pub fn render(input: &EmitInput) -> String {
     if !input.sidecars.is_empty() {
         out.push('\n');
         for s in input.sidecars {
-            let fname = s.file_name().and_then(|n| n.to_str()).unwrap_or("?");
-            let _ = writeln!(out, "{INDENT}@sidecar = \"{fname}\"");
+            let fname = s.path.file_name().and_then(|n| n.to_str()).unwrap_or("?");
+            let _ = writeln!(out, "{INDENT}@sidecar({}) = \"{fname}\"", s.role);
         }
     }
 
@@ -516,7 +517,7 @@ pub fn render_const_def(
     Some(out)
 }
 
-fn leaf_name(pak: &UPKPak, idx: i32) -> String {
+pub fn leaf_name(pak: &UPKPak, idx: i32) -> String {
     if idx > 0 {
         pak.export_table
             .get((idx - 1) as usize)
@@ -532,7 +533,7 @@ fn leaf_name(pak: &UPKPak, idx: i32) -> String {
     }
 }
 
-fn type_of(db: &SchemaDb, pak: &UPKPak, stem_lc: &str, kind: &PropertyKind) -> String {
+pub fn type_of(db: &SchemaDb, pak: &UPKPak, stem_lc: &str, kind: &PropertyKind) -> String {
     use PropertyKind::*;
     match kind {
         Int { .. } => "int".to_string(),
@@ -1,5 +1,7 @@
 use std::fmt;
 
+use crate::native::SidecarRole;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum PseudoValue {
     Null,
@@ -27,7 +29,11 @@ pub struct PseudoFile {
     pub object_name: String,
     pub fields: Vec<(String, PseudoValue)>,
     pub native_fields: Vec<(String, PseudoValue)>,
+    /// Files re-import rebuilds the export from (`@sidecar(source)`, or no
+    /// role in files written before roles existed).
     pub sidecars: Vec<String>,
+    /// Previews and info files: recorded, never read back.
+    pub derived_sidecars: Vec<String>,
 }
 
 #[derive(Debug)]
@@ -81,6 +87,7 @@ struct Parser<'a> {
     i: usize,
     native_fields: Vec<(String, PseudoValue)>,
     sidecars: Vec<String>,
+    derived_sidecars: Vec<String>,
 }
 
 impl<'a> Parser<'a> {
@@ -91,6 +98,7 @@ impl<'a> Parser<'a> {
             i: 0,
             native_fields: Vec::new(),
             sidecars: Vec::new(),
+            derived_sidecars: Vec::new(),
         }
     }
 
@@ -211,6 +219,7 @@ impl<'a> Parser<'a> {
         out.fields = self.parse_object_fields()?;
         out.native_fields = std::mem::take(&mut self.native_fields);
         out.sidecars = std::mem::take(&mut self.sidecars);
+        out.derived_sidecars = std::mem::take(&mut self.derived_sidecars);
         Ok(out)
     }
 
@@ -249,9 +258,19 @@ impl<'a> Parser<'a> {
                 }
                 Some(b'@') => {
                     let label = self.rest_of_line().to_string();
-                    if label.starts_with("@sidecar") {
+                    if let Some(rest) = label.strip_prefix("@sidecar") {
+                        let role = match rest.strip_prefix('(').and_then(|r| r.split_once(')')) {
+                            Some((role, _)) => match SidecarRole::from_label(role.trim()) {
+                                Some(r) => r,
+                                None => return self.err(format!("unknown sidecar role '{role}'")),
+                            },
+                            None => SidecarRole::Source,
+                        };
                         if let Some(name) = extract_quoted(&label) {
-                            self.sidecars.push(name);
+                            match role {
+                                SidecarRole::Source => self.sidecars.push(name),
+                                _ => self.derived_sidecars.push(name),
+                            }
                         }
                         continue;
                     }
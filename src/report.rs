@@ -0,0 +1,295 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Error, Result, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{
+    upkreader::{PackageFlags, UpkHeader},
+    utils::{
+        deadline,
+        decompress::{
+            CompressionMethod, decompress_fully, fully_compressed_method, is_fully_compressed,
+            read_package_image,
+        },
+        pool::{self, par_map},
+        readonly,
+        term::{Color, epaint},
+        walk::{package_files, rel_key},
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageRow {
+    pub path: String,
+    pub file_size: u64,
+    pub uncompressed_size: u64,
+    pub p_ver: i16,
+    pub l_ver: i16,
+    pub engine_ver: i32,
+    pub cooker_ver: i32,
+    pub flags: u32,
+    pub flag_names: String,
+    pub compression: CompressionMethod,
+    pub compressed_chunks: u32,
+    pub fully_compressed: bool,
+    pub name_count: i32,
+    pub import_count: i32,
+    pub export_count: i32,
+    pub header_size: i32,
+}
+
+const CSV_COLUMNS: &[&str] = &[
+    "path",
+    "file_size",
+    "uncompressed_size",
+    "p_ver",
+    "l_ver",
+    "engine_ver",
+    "cooker_ver",
+    "flags",
+    "flag_names",
+    "compression",
+    "compressed_chunks",
+    "fully_compressed",
+    "name_count",
+    "import_count",
+    "export_count",
+    "header_size",
+];
+
+impl PackageRow {
+    pub fn read(path: &Path, root: &Path) -> Result<Self> {
+        let file_size = std::fs::metadata(path)?.len();
+        let rel = rel_key(root, path);
+        if is_fully_compressed(path)? {
+            let method = fully_compressed_method(path)?;
+            return Ok(Self::fully_compressed(rel, file_size, method));
+        }
+        let h = UpkHeader::read(&mut BufReader::new(File::open(path)?))?;
+        let uncompressed_size = h
+            .compressed_chunks
+            .iter()
+            .map(|c| c.decompressed_offset as u64 + c.decompressed_size as u64)
+            .max()
+            .unwrap_or(file_size);
+        let flag_names = PackageFlags::from_bits_retain(h.pak_flags)
+            .iter_names()
+            .map(|(n, _)| n)
+            .collect::<Vec<_>>()
+            .join("|");
+        Ok(Self {
+            path: rel,
+            file_size,
+            uncompressed_size,
+            p_ver: h.p_ver,
+            l_ver: h.l_ver,
+            engine_ver: h.engine_ver,
+            cooker_ver: h.cooker_ver,
+            flags: h.pak_flags,
+            flag_names,
+            compression: h.compression_method,
+            compressed_chunks: h.compressed_chunks_count,
+            fully_compressed: false,
+            name_count: h.name_count,
+            import_count: h.import_count,
+            export_count: h.export_count,
+            header_size: h.header_size,
+        })
+    }
+
+    fn fully_compressed(path: String, file_size: u64, compression: CompressionMethod) -> Self {
+        Self {
+            path,
+            file_size,
+            uncompressed_size: 0,
+            p_ver: 0,
+            l_ver: 0,
+            engine_ver: 0,
+            cooker_ver: 0,
+            flags: PackageFlags::StoreFullyCompressed.bits(),
+            flag_names: "StoreFullyCompressed".into(),
+            compression,
+            compressed_chunks: 1,
+            fully_compressed: true,
+            name_count: 0,
+            import_count: 0,
+            export_count: 0,
+            header_size: 0,
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        let flags = PackageFlags::from_bits_retain(self.flags);
+        self.fully_compressed
+            || flags.intersects(PackageFlags::StoreCompressed | PackageFlags::StoreFullyCompressed)
+            || (self.compression != CompressionMethod::None && self.compressed_chunks > 0)
+    }
+
+    fn csv_fields(&self) -> Vec<String> {
+        vec![
+            self.path.clone(),
+            self.file_size.to_string(),
+            self.uncompressed_size.to_string(),
+            self.p_ver.to_string(),
+            self.l_ver.to_string(),
+            self.engine_ver.to_string(),
+            self.cooker_ver.to_string(),
+            format!("0x{:08X}", self.flags),
+            self.flag_names.clone(),
+            format!("{:?}", self.compression),
+            self.compressed_chunks.to_string(),
+            self.fully_compressed.to_string(),
+            self.name_count.to_string(),
+            self.import_count.to_string(),
+            self.export_count.to_string(),
+            self.header_size.to_string(),
+        ]
+    }
+}
+
+pub fn csv_escape(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// A row per readable package under `game_dir`, and how many weren't.
+pub fn collect_rows(game_dir: &Path) -> (Vec<PackageRow>, usize) {
+    let mut rows = Vec::new();
+    let mut skipped = 0;
+    for p in package_files(game_dir) {
+        match PackageRow::read(&p, game_dir) {
+            Ok(r) => rows.push(r),
+            Err(e) => {
+                skipped += 1;
+                eprintln!("  {} {}: {e}", epaint(Color::Yellow, "skip"), p.display());
+            }
+        }
+    }
+    (rows, skipped)
+}
+
+pub fn write_rows(rows: &[PackageRow], format: ReportFormat, w: &mut dyn Write) -> Result<()> {
+    match format {
+        ReportFormat::Csv => {
+            writeln!(w, "{}", CSV_COLUMNS.join(","))?;
+            for r in rows {
+                let line: Vec<String> = r.csv_fields().iter().map(|f| csv_escape(f)).collect();
+                writeln!(w, "{}", line.join(","))?;
+            }
+        }
+        ReportFormat::Json => {
+            serde_json::to_writer_pretty(&mut *w, rows).map_err(Error::other)?;
+            writeln!(w)?;
+        }
+    }
+    Ok(())
+}
+
+fn decompress_one(root: &Path, row: &PackageRow, out_dir: &Path) -> Result<(u64, u64)> {
+    let src = root.join(&row.path);
+    let bytes = if row.fully_compressed {
+        decompress_fully(&src, row.compression)?.into()
+    } else {
+        read_package_image(&src)?.bytes
+    };
+    let dst = out_dir.join(&row.path);
+    if let Some(parent) = dst.parent() {
+        readonly::create_dir_all(parent)?;
+    }
+    readonly::write(&dst, &*bytes)?;
+    Ok((row.file_size, bytes.len() as u64))
+}
+
+/// Inflates every compressed row into `out_dir`, keeping paths relative to
+/// `root`. Work is spread over `jobs` threads; results print in input order.
+pub fn decompress_all(root: &Path, rows: &[&PackageRow], out_dir: &Path, jobs: usize) -> usize {
+    let results = par_map(rows, jobs, |row| {
+        // The deadline is per thread and per package.
+        deadline::arm();
+        decompress_one(root, row, out_dir)
+    });
+
+    let mut failed = 0;
+    for (row, r) in rows.iter().zip(results) {
+        match r {
+            Ok((a, b)) => println!("  {}  {a} → {b} bytes", row.path),
+            Err(e) => {
+                failed += 1;
+                eprintln!("  {} {}: {e}", epaint(Color::Red, "failed"), row.path);
+            }
+        }
+    }
+    failed
+}
+
+pub fn report_cmd(
+    game_dir: &Path,
+    format: ReportFormat,
+    out: Option<&Path>,
+    compressed_only: bool,
+    decompress_to: Option<&Path>,
+    jobs: Option<usize>,
+) -> Result<()> {
+    let root = game_dir;
+    let (mut rows, skipped) = collect_rows(root);
+    if compressed_only || decompress_to.is_some() {
+        rows.retain(|r| r.is_compressed());
+    }
+
+    let mut failed = 0;
+    if let Some(dir) = decompress_to {
+        let jobs = pool::jobs(jobs);
+        let targets: Vec<&PackageRow> = rows.iter().collect();
+        println!(
+            "Decompressing {} package(s) into {} ({jobs} job(s))",
+            targets.len(),
+            dir.display()
+        );
+        failed = decompress_all(root, &targets, dir, jobs);
+        println!(
+            "\nSummary: {} decompressed, {} failed",
+            targets.len() - failed,
+            failed
+        );
+    }
+    match out {
+        Some(o) => {
+            let mut w = BufWriter::new(readonly::create(o)?);
+            write_rows(&rows, format, &mut w)?;
+            w.flush()?;
+            let compressed = rows.iter().filter(|r| r.is_compressed()).count();
+            println!(
+                "{} package(s), {} compressed → {}",
+                rows.len(),
+                compressed,
+                o.display()
+            );
+        }
+        None if decompress_to.is_some() => {}
+        None => {
+            let stdout = std::io::stdout();
+            let mut w = stdout.lock();
+            write_rows(&rows, format, &mut w)?;
+        }
+    }
+    // The report is written either way; the exit code says it's partial.
+    if skipped + failed > 0 {
+        return Err(Error::other(format!(
+            "{skipped} package(s) unreadable, {failed} failed to decompress"
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,282 @@
+//! Extract → repack → compare: every export of a package goes through the
+//! same `.uo` text and `pack-mod` path a mod would, and the result is
+//! spliced back with the `Package` model. Nothing was edited, so anything
+//! but the original bytes is a bug in extraction, packing or saving.
+//!
+//! The intermediate tree lives in a scratch directory under the system
+//! temp dir and is removed afterwards unless `--keep` names one.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    exit::validation_failed,
+    native::{BulkCompression, Convert},
+    package::Package,
+    peek::hexdump,
+    pseudo_parse,
+    upkpacker::{self, PackOptions, export_path_dotted},
+    utils::{
+        decompress::read_package_image,
+        readonly,
+        term::{Color, paint},
+    },
+};
+
+/// Exports whose divergence is shown in full; the rest are counted.
+const MAX_SHOWN: usize = 8;
+/// Bytes of context either side of a divergence.
+const CONTEXT: usize = 32;
+
+fn first_difference(a: &[u8], b: &[u8]) -> Option<usize> {
+    a.iter()
+        .zip(b)
+        .position(|(x, y)| x != y)
+        .or_else(|| (a.len() != b.len()).then(|| a.len().min(b.len())))
+}
+
+/// Rows around `at` from both sides; `base` is where `a` sits in the file.
+fn show_context(a: &[u8], b: &[u8], at: usize, base: u64) {
+    let from = at.saturating_sub(CONTEXT) & !15;
+    let window = |d: &[u8]| {
+        let end = (at + CONTEXT).min(d.len());
+        d.get(from..end).unwrap_or_default().to_vec()
+    };
+    println!("    {}", paint(Color::Gray, "original:"));
+    hexdump(&window(a), from, base);
+    println!("    {}", paint(Color::Gray, "repacked:"));
+    hexdump(&window(b), from, base);
+}
+
+/// What sits at file offset `at`: an export's bytes, the summary, or the
+/// tables.
+fn region_at(pkg: &Package, at: usize) -> String {
+    for (i, e) in pkg.exports.iter().enumerate() {
+        let s = e.serial_offset.max(0) as usize;
+        if (s..s + e.serial_size.max(0) as usize).contains(&at) {
+            let idx = i as i32 + 1;
+            return format!("export #{idx} {}", pkg.pak().get_export_full_name(idx));
+        }
+    }
+    if at < pkg.header.name_offset.max(0) as usize {
+        "the summary".to_string()
+    } else {
+        "the package tables".to_string()
+    }
+}
+
+/// `.uo` files under `dir` that `pack-mod` didn't turn into an override,
+/// definitions (classes, structs) aside since those never repack.
+fn unpacked(dir: &Path, packed: &BTreeSet<PathBuf>) -> Result<Vec<PathBuf>> {
+    let mut out = Vec::new();
+    for p in upkpacker::find_uo_files(dir)? {
+        if packed.contains(&p) {
+            continue;
+        }
+        let text = std::fs::read_to_string(&p)?;
+        if pseudo_parse::parse(&text).is_ok_and(|u| u.is_definition) {
+            continue;
+        }
+        out.push(p);
+    }
+    Ok(out)
+}
+
+pub fn roundtrip_cmd(
+    upk_path: &Path,
+    game_root: Option<&Path>,
+    keep: Option<&Path>,
+    verbose: bool,
+) -> Result<()> {
+    let path = upk_path;
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("{}: no file name", upk_path.display()),
+            )
+        })?;
+    let scratch = match keep {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            std::env::temp_dir().join(format!("ue3-tools-roundtrip-{}-{stem}", std::process::id()))
+        }
+    };
+    let result = roundtrip(path, &stem, &scratch, game_root, verbose);
+    if keep.is_none() {
+        let _ = std::fs::remove_dir_all(&scratch);
+    } else {
+        println!("Intermediate files kept in {}", scratch.display());
+    }
+    result
+}
+
+fn roundtrip(
+    path: &Path,
+    stem: &str,
+    scratch: &Path,
+    game_root: Option<&Path>,
+    verbose: bool,
+) -> Result<()> {
+    let extracted = scratch.join("extracted");
+    let overrides = scratch.join("overrides");
+    readonly::create_dir_all(scratch)?;
+
+    crate::extract_file(
+        path,
+        &[],
+        &extracted,
+        game_root,
+        verbose,
+        &[Convert::Native],
+    )?;
+
+    let package_paths = HashMap::from([(stem.to_lowercase(), path.to_path_buf())]);
+    let packed = upkpacker::pack_mod(&PackOptions {
+        extracted_dir: &extracted,
+        game_root,
+        out_dir: Some(&overrides),
+        verbose,
+        only_files: None,
+        package_paths: Some(&package_paths),
+        keep_names: false,
+        bulk: BulkCompression::Original,
+    })?;
+
+    let mut pkg = Package::open(path)?;
+    let pak = pkg.pak();
+    let keys: HashMap<String, i32> = (1..=pak.export_table.len() as i32)
+        .map(|i| (export_path_dotted(&pak, i), i))
+        .collect();
+
+    let mut failures = 0usize;
+    let mut diverged = 0usize;
+    let mut identical = 0usize;
+    for p in &packed {
+        let bin = overrides.join(&p.pkg_name).join(format!("{}.bin", p.key));
+        let Some(&idx) = keys.get(&p.key) else {
+            failures += 1;
+            println!(
+                "  {} {}: packed as '{}', which is no export of {stem}",
+                paint(Color::Red, "FAIL"),
+                p.uo.display(),
+                p.key
+            );
+            continue;
+        };
+        let blob = std::fs::read(&bin)?;
+        let original = pkg.export_blob(idx)?.to_vec();
+        if !pkg.set_export_blob(idx, blob.clone())? {
+            identical += 1;
+            continue;
+        }
+        diverged += 1;
+        if diverged > MAX_SHOWN {
+            continue;
+        }
+        let at = first_difference(&original, &blob).unwrap_or(0);
+        println!(
+            "  {} #{idx} {}: first difference at +0x{at:X} ({} bytes → {})",
+            paint(Color::Red, "DIFF"),
+            paint(Color::Highlight, pak.get_export_full_name(idx)),
+            original.len(),
+            blob.len()
+        );
+        show_context(
+            &original,
+            &blob,
+            at,
+            pak.export_table[(idx - 1) as usize].serial_offset.max(0) as u64,
+        );
+    }
+    if diverged > MAX_SHOWN {
+        println!("  … {} more export(s) differ", diverged - MAX_SHOWN);
+    }
+
+    let names_before = pak.name_table.len();
+    let map_path = overrides.join(stem).join(format!("{stem}.namemap"));
+    let added_names = std::fs::read_to_string(&map_path)
+        .map(|t| t.lines().count().saturating_sub(names_before))
+        .unwrap_or(0);
+    if added_names > 0 {
+        failures += 1;
+        println!(
+            "  {} packing added {added_names} name(s) the package didn't have",
+            paint(Color::Red, "FAIL")
+        );
+    }
+
+    let packed_uo: BTreeSet<PathBuf> = packed.iter().map(|p| p.uo.clone()).collect();
+    let left_out = unpacked(&extracted, &packed_uo)?;
+    for p in &left_out {
+        println!(
+            "  {} {}: extracted but not repacked",
+            paint(Color::Red, "FAIL"),
+            p.strip_prefix(&extracted).unwrap_or(p).display()
+        );
+    }
+    failures += left_out.len();
+
+    let not_covered = pak.export_table.len().saturating_sub(packed.len());
+    if not_covered > 0 {
+        println!(
+            "  {}",
+            paint(
+                Color::Gray,
+                format!(
+                    "{not_covered} export(s) had nothing to repack (definitions, or not extracted); only the file comparison covers them"
+                )
+            )
+        );
+    }
+
+    // Saving with nothing replaced must reproduce the image exactly, and
+    // with replacements the file comparison points at the first one.
+    let out = scratch.join(format!("{stem}.roundtrip.upk"));
+    pkg.save(&out)?;
+    let image = read_package_image(path)?;
+    let input = &image.bytes[..];
+    let output = std::fs::read(&out)?;
+    let file_diff = first_difference(input, &output);
+    if let Some(at) = file_diff {
+        println!(
+            "  {} output differs from the input at 0x{at:X}, in {} ({} bytes → {})",
+            paint(Color::Red, "DIFF"),
+            region_at(&pkg, at),
+            input.len(),
+            output.len()
+        );
+        show_context(input, &output, at, 0);
+    }
+    if image.was_compressed() {
+        println!(
+            "  {}",
+            paint(
+                Color::Gray,
+                "the input is compressed; compared against its decompressed image"
+            )
+        );
+    }
+
+    println!(
+        "roundtrip: {} export(s) repacked, {identical} identical, {diverged} different, {failures} failure(s)",
+        packed.len()
+    );
+    if failures > 0 || diverged > 0 || file_diff.is_some() {
+        return Err(validation_failed(format!(
+            "{} did not survive extract → repack unchanged",
+            path.display()
+        )));
+    }
+    println!(
+        "roundtrip: {} {} bytes reproduced exactly",
+        paint(Color::Green, "OK"),
+        input.len()
+    );
+    Ok(())
+}
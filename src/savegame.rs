@@ -0,0 +1,60 @@
+use std::{
+    fs::File,
+    io::{BufReader, Error, Result, Seek},
+    path::{Path, PathBuf},
+};
+
+use clap::Subcommand;
+
+use crate::{
+    archive::Schema,
+    utils::{readonly, term},
+};
+
+#[derive(Subcommand)]
+pub enum SavegameCmd {
+    #[command(about = "Decode an FArchive blob (save file etc.) with a RON schema, as JSON")]
+    Dump {
+        file: PathBuf,
+        #[arg(long, value_name = "RON")]
+        schema: PathBuf,
+        #[arg(long = "out", short = 'o', value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+}
+
+fn dump(path: &Path, schema_path: &Path, out: Option<&Path>) -> Result<()> {
+    let text = std::fs::read_to_string(schema_path)
+        .map_err(|e| Error::new(e.kind(), format!("{}: {e}", schema_path.display())))?;
+    let schema = Schema::from_ron(&text)?;
+    let file = path.display();
+    let f = File::open(path).map_err(|e| Error::new(e.kind(), format!("{file}: {e}")))?;
+    let len = f.metadata()?.len();
+    let mut r = BufReader::new(f);
+    let value = schema
+        .decode(&mut r)
+        .map_err(|e| Error::new(e.kind(), format!("{file}: {e}")))?;
+    let end = r.stream_position()?;
+    if end < len {
+        term::warn(
+            "trailing",
+            format_args!(
+                "{file}: schema ends at 0x{end:X}, {} byte(s) left undecoded",
+                len - end
+            ),
+        );
+    }
+
+    let json = serde_json::to_string_pretty(&value).map_err(Error::other)?;
+    match out {
+        Some(p) => readonly::write(p, json + "\n")?,
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+pub fn run(cmd: SavegameCmd) -> Result<()> {
+    match cmd {
+        SavegameCmd::Dump { file, schema, out } => dump(&file, &schema, out.as_deref()),
+    }
+}
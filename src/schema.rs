@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::upkprops::parse_property;
 use crate::upkreader::{FName, UPKPak, read_fstring_stream};
+use crate::utils::heuristics;
 use crate::versions::*;
 
 fn tag<T>(c: &Cursor<&Vec<u8>>, what: &str, r: Result<T>) -> Result<T> {
@@ -835,12 +836,11 @@ fn read_fname(c: &mut Cursor<&Vec<u8>>) -> Result<FName> {
 
 fn read_fname_array(c: &mut Cursor<&Vec<u8>>) -> Result<Vec<FName>> {
     let n = c.read_i32::<LittleEndian>()?;
-    if !(0..=0x10_0000).contains(&n) {
-        return Err(Error::new(
-            ErrorKind::InvalidData,
-            format!("FName TArray: implausible count {}", n),
-        ));
-    }
+    heuristics::check(
+        "max-count",
+        n as i64,
+        format_args!("FName TArray: implausible count {n}"),
+    )?;
     let mut v = Vec::with_capacity(n as usize);
     for _ in 0..n {
         v.push(read_fname(c)?);
@@ -850,12 +850,11 @@ fn read_fname_array(c: &mut Cursor<&Vec<u8>>) -> Result<Vec<FName>> {
 
 fn read_fname_to_object_map(c: &mut Cursor<&Vec<u8>>) -> Result<Vec<(FName, i32)>> {
     let n = c.read_i32::<LittleEndian>()?;
-    if !(0..=0x10_0000).contains(&n) {
-        return Err(Error::new(
-            ErrorKind::InvalidData,
-            format!("TMap<FName,Object*>: implausible count {}", n),
-        ));
-    }
+    heuristics::check(
+        "max-count",
+        n as i64,
+        format_args!("TMap<FName,Object*>: implausible count {n}"),
+    )?;
     let mut v = Vec::with_capacity(n as usize);
     for _ in 0..n {
         let k = read_fname(c)?;
@@ -867,12 +866,11 @@ fn read_fname_to_object_map(c: &mut Cursor<&Vec<u8>>) -> Result<Vec<(FName, i32)
 
 fn read_implemented_interfaces(c: &mut Cursor<&Vec<u8>>) -> Result<Vec<ImplementedInterface>> {
     let n = c.read_i32::<LittleEndian>()?;
-    if !(0..=0x10_0000).contains(&n) {
-        return Err(Error::new(
-            ErrorKind::InvalidData,
-            format!("TArray<FImplementedInterface>: implausible count {}", n),
-        ));
-    }
+    heuristics::check(
+        "max-count",
+        n as i64,
+        format_args!("TArray<FImplementedInterface>: implausible count {n}"),
+    )?;
     let mut v = Vec::with_capacity(n as usize);
     for _ in 0..n {
         let class = c.read_i32::<LittleEndian>()?;
@@ -888,12 +886,11 @@ fn read_implemented_interfaces(c: &mut Cursor<&Vec<u8>>) -> Result<Vec<Implement
 #[allow(dead_code)]
 fn read_object_to_fname_map(c: &mut Cursor<&Vec<u8>>) -> Result<Vec<(i32, FName)>> {
     let n = c.read_i32::<LittleEndian>()?;
-    if !(0..=0x10_0000).contains(&n) {
-        return Err(Error::new(
-            ErrorKind::InvalidData,
-            format!("TMap<Object*,FName>: implausible count {}", n),
-        ));
-    }
+    heuristics::check(
+        "max-count",
+        n as i64,
+        format_args!("TMap<Object*,FName>: implausible count {n}"),
+    )?;
     let mut v = Vec::with_capacity(n as usize);
     for _ in 0..n {
         let k = c.read_i32::<LittleEndian>()?;
@@ -906,12 +903,11 @@ fn read_object_to_fname_map(c: &mut Cursor<&Vec<u8>>) -> Result<Vec<(i32, FName)
 #[allow(dead_code)]
 fn read_object_to_object_map(c: &mut Cursor<&Vec<u8>>) -> Result<Vec<(i32, i32)>> {
     let n = c.read_i32::<LittleEndian>()?;
-    if !(0..=0x10_0000).contains(&n) {
-        return Err(Error::new(
-            ErrorKind::InvalidData,
-            format!("TMap<Object*,Object*>: implausible count {}", n),
-        ));
-    }
+    heuristics::check(
+        "max-count",
+        n as i64,
+        format_args!("TMap<Object*,Object*>: implausible count {n}"),
+    )?;
     let mut v = Vec::with_capacity(n as usize);
     for _ in 0..n {
         let k = c.read_i32::<LittleEndian>()?;
@@ -924,12 +920,11 @@ fn read_object_to_object_map(c: &mut Cursor<&Vec<u8>>) -> Result<Vec<(i32, i32)>
 #[allow(dead_code)]
 fn read_object_array(c: &mut Cursor<&Vec<u8>>) -> Result<Vec<i32>> {
     let n = c.read_i32::<LittleEndian>()?;
-    if !(0..=0x10_0000).contains(&n) {
-        return Err(Error::new(
-            ErrorKind::InvalidData,
-            format!("TArray<Object*>: implausible count {}", n),
-        ));
-    }
+    heuristics::check(
+        "max-count",
+        n as i64,
+        format_args!("TArray<Object*>: implausible count {n}"),
+    )?;
     let mut v = Vec::with_capacity(n as usize);
     for _ in 0..n {
         v.push(c.read_i32::<LittleEndian>()?);
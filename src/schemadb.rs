@@ -1,8 +1,7 @@
 use std::{
     cell::RefCell,
     collections::{HashMap, HashSet, VecDeque},
-    fs::File,
-    io::{BufReader, Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom},
+    io::{Cursor, Error, ErrorKind, Result, Seek, SeekFrom},
     path::{Path, PathBuf},
     rc::Rc,
 };
@@ -13,9 +12,10 @@ use crate::{
     schema::{
         PropertyKind, SchemaEntry, SchemaParseCtx, parse_export_schema, parse_opaque_field_next,
     },
-    upkreader::{FName, PackageFlags, UPKPak, UpkHeader},
-    utils::decompress::{CompressionMethod, upk_decompress},
-    versions::VER_BYTEPROP_SERIALIZE_ENUM,
+    upkprops::Property,
+    upkreader::{FName, PackageFlags, UPKPak, UpkHeader, get_obj_props_with_db},
+    utils::{decompress::read_package_image, heuristics, spill::PackageBytes},
+    versions::{VER_BYTEPROP_SERIALIZE_ENUM, VER_NETINDEX_STORED_AS_INT},
 };
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -33,7 +33,7 @@ impl ResolvedRef {
 pub struct LazyPackage {
     pub stem_lc: String,
     pub path: PathBuf,
-    pub bytes: Vec<u8>,
+    pub bytes: PackageBytes,
     pub header: UpkHeader,
     pub pak: UPKPak,
 }
@@ -100,6 +100,40 @@ impl LazyPackage {
     pub fn is_cooked(&self) -> bool {
         self.header.pak_flags & PackageFlags::Cooked.bits() != 0
     }
+
+    /// The class of export `i`; an imported class needs `db` to resolve.
+    pub fn export_class_ref(&self, i: i32, db: Option<&SchemaDb>) -> Option<ResolvedRef> {
+        let class_index = self
+            .pak
+            .export_table
+            .get((i - 1) as usize)
+            .map(|e| e.class_index)
+            .unwrap_or(0);
+        if class_index > 0 {
+            Some(ResolvedRef {
+                stem_lc: self.stem_lc.clone(),
+                export_idx: class_index,
+            })
+        } else if class_index < 0 {
+            db.and_then(|d| d.resolve_index(self, class_index).ok().flatten())
+        } else {
+            None
+        }
+    }
+
+    /// Tagged properties at the start of export `i` and the blob offset where
+    /// they end; the owner class is resolved through `db` when imported.
+    pub fn export_props(&self, i: i32, db: Option<&SchemaDb>) -> Result<(Vec<Property>, usize)> {
+        let owner = self.export_class_ref(i, db);
+        let blob = self.export_blob(i)?.to_vec();
+        let mut cur = Cursor::new(&blob);
+        if self.header.p_ver >= VER_NETINDEX_STORED_AS_INT {
+            cur.read_i32::<LittleEndian>()?;
+        }
+        let (props, end) =
+            get_obj_props_with_db(&mut cur, &self.pak, false, self.header.p_ver, db, owner)?;
+        Ok((props, end as usize))
+    }
 }
 
 pub struct SchemaDb {
@@ -398,11 +432,10 @@ impl SchemaDb {
         let mut guard = 0;
         while cur != 0 {
             guard += 1;
-            if guard > 8192 {
-                self.note_miss(format!(
-                    "children walk runaway at {} (cur={cur})",
-                    r.display()
-                ));
+            if guard > heuristics::get().max_children {
+                let what = format!("children walk runaway at {} (cur={cur})", r.display());
+                heuristics::hit("max-children", &what);
+                self.note_miss(what);
                 break;
             }
             let child_ref = match self.resolve_index(&pkg, cur)? {
@@ -432,8 +465,10 @@ impl SchemaDb {
         let mut guard = 0;
         loop {
             guard += 1;
-            if guard > 64 {
-                self.note_miss(format!("class_chain runaway at {}", r.display()));
+            if guard > heuristics::get().max_class_chain {
+                let what = format!("class_chain runaway at {}", r.display());
+                heuristics::hit("max-class-chain", &what);
+                self.note_miss(what);
                 break;
             }
             let pkg = self.open_package(&cur.stem_lc)?;
@@ -655,61 +690,27 @@ fn walk_index(
 }
 
 pub fn open_package_at(path: &Path, stem_lc: &str) -> Result<LazyPackage> {
-    let file = File::open(path)?;
-    let mut reader = BufReader::new(file);
-
-    let filesize = reader.seek(SeekFrom::End(0))?;
-    reader.seek(SeekFrom::Start(0))?;
-
-    let raw_header = UpkHeader::read(&mut reader)?;
-
-    let (bytes, header_kept) = if raw_header.compression_method == CompressionMethod::None
-        || raw_header.compressed_chunks_count == 0
-    {
-        reader.seek(SeekFrom::Start(0))?;
-        let mut buf = Vec::with_capacity(filesize as usize);
-        reader.read_to_end(&mut buf)?;
-        (buf, raw_header)
-    } else {
-        let mut cloned = raw_header.clone();
-        cloned.compression_method = CompressionMethod::None;
-        cloned.compressed_chunks_count = 0;
-        cloned.compressed_chunks.clear();
-        cloned.pak_flags = raw_header.pak_flags & !PackageFlags::StoreCompressed.bits();
-
-        let mut chunks = raw_header.compressed_chunks.clone();
-        chunks.sort_by_key(|c| c.decompressed_offset);
-
-        let dec_data = upk_decompress(&mut reader, raw_header.compression_method, &chunks)
-            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("decompress: {e}")))?;
-
-        let mut buf: Vec<u8> = Vec::with_capacity(filesize as usize);
-        {
-            let mut w = Cursor::new(&mut buf);
-            cloned.write(&mut w)?;
-        }
-        for (i, dec) in dec_data.iter().enumerate() {
-            let target = chunks[i].decompressed_offset as usize;
-            if buf.len() < target {
-                buf.resize(target, 0);
-            }
-            buf.extend_from_slice(dec);
-        }
-        (buf, cloned)
-    };
-
-    let mut cur = Cursor::new(&bytes);
-    let pak = UPKPak::parse_upk(&mut cur, &header_kept)?;
+    let image = read_package_image(path)?;
+    let mut cur = Cursor::new(&*image.bytes);
+    let pak = UPKPak::parse_upk(&mut cur, &image.header)?;
 
     Ok(LazyPackage {
         stem_lc: stem_lc.to_string(),
         path: path.to_path_buf(),
-        bytes,
-        header: header_kept,
+        bytes: image.bytes,
+        header: image.header,
         pak,
     })
 }
 
+pub fn open_package_file(path: &Path) -> Result<LazyPackage> {
+    let stem_lc = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    open_package_at(path, &stem_lc)
+}
+
 fn top_package_name(pak: &UPKPak, idx: i32) -> Option<String> {
     let mut cur = idx;
     let mut guard = 0;
@@ -886,14 +887,11 @@ fn read_redirector_destination(pkg: &LazyPackage, eidx: i32) -> Result<Option<i3
             Ok(v) => v,
             Err(_) => return Ok(None),
         };
-        let _inst = c.read_i32::<LittleEndian>().unwrap_or(0);
-        let nm = pkg
-            .pak
-            .name_table
-            .get(name_idx as usize)
-            .cloned()
-            .unwrap_or_default();
-        if nm == "None" {
+        let inst = c.read_i32::<LittleEndian>().unwrap_or(0);
+        if pkg.pak.name_table.is_none(&FName {
+            name_index: name_idx,
+            name_instance: inst,
+        }) {
             break;
         }
         c.set_position(before);
@@ -0,0 +1,293 @@
+use std::{
+    io::{Error, ErrorKind, Read, Result},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    disasm::export_disassembly,
+    exit::validation_failed,
+    schema::parse_export_schema,
+    schemadb::open_package_file,
+    utils::{
+        readonly, sniff,
+        term::{self, Color, paint},
+    },
+};
+
+/// Mutations applied to one copy, at most.
+const MAX_MUTATIONS: u64 = 8;
+
+const INTERESTING_U8: [u8; 5] = [0x00, 0x01, 0x7F, 0x80, 0xFF];
+const INTERESTING_I32: [i32; 7] = [0, 1, -1, 0x7FFF, 0x10000, i32::MAX, i32::MIN];
+
+/// splitmix64: small, and the same sequence for a seed everywhere.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+}
+
+/// Bytes written at one offset, or XORed in for a bit flip.
+#[derive(Debug, Clone)]
+struct Mutation {
+    offset: usize,
+    bytes: Vec<u8>,
+    xor: bool,
+}
+
+fn mutations(rng: &mut Rng, len: usize, header_size: usize) -> Vec<Mutation> {
+    let n = 1 + rng.below(MAX_MUTATIONS);
+    (0..n)
+        .map(|_| {
+            // Half of them land in the summary and tables, where a
+            // single byte changes how everything after is read.
+            let span = if rng.below(2) == 0 { header_size } else { len };
+            let offset = rng.below(span as u64) as usize;
+            let kind = rng.below(4);
+            let bytes = match kind {
+                0 => vec![1u8 << rng.below(8)],
+                1 => vec![rng.next() as u8],
+                2 => vec![INTERESTING_U8[rng.below(INTERESTING_U8.len() as u64) as usize]],
+                _ => INTERESTING_I32[rng.below(INTERESTING_I32.len() as u64) as usize]
+                    .to_le_bytes()
+                    .to_vec(),
+            };
+            Mutation {
+                offset,
+                bytes,
+                xor: kind == 0,
+            }
+        })
+        .collect()
+}
+
+fn apply(original: &[u8], muts: &[Mutation]) -> Vec<u8> {
+    let mut data = original.to_vec();
+    for m in muts {
+        for (i, b) in m.bytes.iter().enumerate() {
+            if let Some(slot) = data.get_mut(m.offset + i) {
+                *slot = if m.xor { *slot ^ b } else { *b };
+            }
+        }
+    }
+    data
+}
+
+/// What a child run of `selftest --exercise` ended in, when it didn't
+/// exit normally.
+#[derive(Debug, Clone, PartialEq)]
+enum Failure {
+    Crash(String),
+    Hang,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Failure::Crash(msg) => write!(f, "crashed: {msg}"),
+            Failure::Hang => write!(f, "still running at the timeout"),
+        }
+    }
+}
+
+/// Every parser a command can reach for, with errors ignored: only
+/// panics, aborts and hangs matter here.
+pub fn exercise(path: &Path) -> Result<()> {
+    sniff::set_force(true);
+    let Ok(lp) = open_package_file(path) else {
+        return Ok(());
+    };
+    let ctx = lp.schema_ctx();
+    for idx in 1..=lp.pak.export_table.len() as i32 {
+        let class = lp.export_class_name(idx);
+        let _ = lp.pak.get_export_full_name(idx);
+        let _ = lp.export_props(idx, None);
+        if let Ok(blob) = lp.export_blob(idx) {
+            let _ = parse_export_schema(blob, &class, &lp.pak, ctx);
+        }
+        if lp.pak.script_kind(idx).is_some() {
+            let _ = export_disassembly(&lp, idx);
+        }
+    }
+    for i in 1..=lp.pak.import_table.len() as i32 {
+        let _ = lp.pak.get_import_full_name(-i);
+    }
+    Ok(())
+}
+
+/// Runs `exercise` on `path` in a child process, so aborts (allocation
+/// failure, stack overflow) and hangs are caught too.
+fn run_child(path: &Path, timeout: Duration) -> Result<Option<Failure>> {
+    let mut child = Command::new(std::env::current_exe()?)
+        .arg("selftest")
+        .arg("--exercise")
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Drained as it comes, or a chatty parse fills the pipe and blocks.
+    let drain = child.stderr.take().map(|mut e| {
+        std::thread::spawn(move || {
+            let mut s = String::new();
+            let _ = e.read_to_string(&mut s);
+            s
+        })
+    });
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() > timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(Some(Failure::Hang));
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    };
+    let stderr = drain.and_then(|t| t.join().ok()).unwrap_or_default();
+    if status.success() {
+        return Ok(None);
+    }
+    // `thread 'main' panicked at file:line:col:` then the message.
+    let lines: Vec<&str> = stderr.lines().collect();
+    let msg = match lines.iter().position(|l| l.contains("panicked at")) {
+        Some(i) => lines[i..lines.len().min(i + 2)].join(" "),
+        None => lines
+            .iter()
+            .find(|l| l.contains("overflow") || l.contains("memory"))
+            .map(|l| l.to_string())
+            .unwrap_or_else(|| format!("exit status {status}")),
+    };
+    Ok(Some(Failure::Crash(msg.trim().to_string())))
+}
+
+/// Drops mutations one at a time while the same kind of failure remains.
+fn minimize(
+    original: &[u8],
+    mut muts: Vec<Mutation>,
+    failure: &Failure,
+    scratch: &Path,
+    timeout: Duration,
+) -> Result<Vec<Mutation>> {
+    let mut i = 0;
+    while i < muts.len() && muts.len() > 1 {
+        let mut fewer = muts.clone();
+        fewer.remove(i);
+        readonly::write(scratch, apply(original, &fewer))?;
+        let same = matches!(
+            (run_child(scratch, timeout)?, failure),
+            (Some(Failure::Hang), Failure::Hang) | (Some(Failure::Crash(_)), Failure::Crash(_))
+        );
+        if same {
+            muts = fewer;
+        } else {
+            i += 1;
+        }
+    }
+    Ok(muts)
+}
+
+/// Parses `iterations` randomly mutated copies of `upk_path` and fails on
+/// the first one that panics, aborts or hangs instead of returning an
+/// error, printing the smallest set of mutations that still does.
+pub fn mutate_cmd(
+    upk_path: &Path,
+    iterations: u64,
+    seed: u64,
+    timeout_secs: u64,
+    out: Option<&Path>,
+) -> Result<()> {
+    let path = upk_path;
+    let original = std::fs::read(path)?;
+    if original.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} is empty", upk_path.display()),
+        ));
+    }
+    // The unmutated file has to parse, or every iteration tests the same
+    // early error.
+    let header_size = open_package_file(path)?
+        .header
+        .header_size
+        .clamp(1, original.len() as i32) as usize;
+    let timeout = Duration::from_secs(timeout_secs.max(1));
+    let name = path
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "package.upk".into());
+    let scratch: PathBuf =
+        std::env::temp_dir().join(format!("ue3-tools-selftest-{}-{name}", std::process::id()));
+
+    let mut rng = Rng(seed);
+    let mut found = None;
+    for iter in 0..iterations {
+        let muts = mutations(&mut rng, original.len(), header_size);
+        readonly::write(&scratch, apply(&original, &muts))?;
+        if let Some(f) = run_child(&scratch, timeout)? {
+            found = Some((iter, muts, f));
+            break;
+        }
+        if (iter + 1) % 100 == 0 {
+            eprintln!("  {} / {iterations}", iter + 1);
+        }
+    }
+    let Some((iter, muts, failure)) = found else {
+        let _ = std::fs::remove_file(&scratch);
+        println!(
+            "selftest: {} {iterations} mutated cop(ies) of {} (seed {seed}) parsed without a crash or hang",
+            paint(Color::Green, "OK"),
+            upk_path.display()
+        );
+        return Ok(());
+    };
+
+    term::error(
+        "FAIL",
+        format_args!("iteration {iter} (seed {seed}) {failure}"),
+    );
+    let muts = minimize(&original, muts, &failure, &scratch, timeout)?;
+    let _ = std::fs::remove_file(&scratch);
+    println!("Reproducer: {} with", upk_path.display());
+    for m in &muts {
+        let was: Vec<String> = original
+            .iter()
+            .skip(m.offset)
+            .take(m.bytes.len())
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        let now: Vec<String> = apply(&original, std::slice::from_ref(m))
+            .iter()
+            .skip(m.offset)
+            .take(m.bytes.len())
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        println!(
+            "  0x{:08X}: {} -> {}",
+            m.offset,
+            was.join(" "),
+            paint(Color::Highlight, now.join(" "))
+        );
+    }
+    if let Some(out) = out {
+        readonly::write(out, apply(&original, &muts))?;
+        println!("Written to {}", out.display());
+    }
+    Err(validation_failed(format!(
+        "iteration {iter} of seed {seed} didn't end in an error"
+    )))
+}
@@ -0,0 +1,653 @@
+//! A read-only view of an indexed game directory over HTTP, for a modding
+//! team to browse packages without everyone installing the tools:
+//!
+//! | route                   | returns                                    |
+//! |-------------------------|--------------------------------------------|
+//! | `/`                     | HTML list of the indexed packages          |
+//! | `/pkg?p=REL`            | HTML export list of one package            |
+//! | `/export?p=REL&i=N`     | HTML properties / bytecode / thumbnail     |
+//! | `/thumb?p=REL&i=N`      | PNG thumbnail of a texture                 |
+//! | `/api/packages`         | the same as JSON                           |
+//! | `/api/package?p=REL`    |                                            |
+//! | `/api/export?p=REL&i=N` |                                            |
+//!
+//! `REL` is a package path as the index records it; nothing outside the
+//! index is reachable. Requests are handled one at a time, and the index
+//! is re-read when `index build` rewrites it.
+
+use std::{
+    fmt::Write as _,
+    io::{Error, ErrorKind, Read, Result, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, SystemTime},
+};
+
+use serde_json::{Value, json};
+
+use crate::{
+    disasm::export_disassembly,
+    doc::value_text,
+    index::{self, PackageIndex},
+    schemadb::{LazyPackage, SchemaDb, open_package_file},
+    upkpacker::export_path_dotted,
+    utils::{
+        deadline, png,
+        term::{self, Color, paint},
+    },
+};
+use ue3_tools::objects::Texture2D;
+
+/// Longest request head read before giving up on a client.
+const MAX_HEAD: usize = 16 * 1024;
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest side of a thumbnail; the nearest smaller mip is used.
+const THUMB_SIDE: u32 = 256;
+/// Bytecode statements shown per export.
+const MAX_STATEMENTS: usize = 2000;
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn html(title: &str, body: &str) -> Self {
+        Self {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            body: page(title, body).into_bytes(),
+        }
+    }
+
+    fn json(v: &Value) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/json",
+            body: serde_json::to_vec_pretty(v).unwrap_or_default(),
+        }
+    }
+
+    fn error(status: u16, api: bool, msg: &str) -> Self {
+        let mut r = if api {
+            Self::json(&json!({ "error": msg }))
+        } else {
+            Self::html("Error", &format!("<p>{}</p>", esc(msg)))
+        };
+        r.status = status;
+        r
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+fn esc(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn url_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~/".contains(&b) {
+            out.push(b as char);
+        } else {
+            let _ = write!(out, "%{b:02X}");
+        }
+    }
+    out
+}
+
+fn url_decode(s: &str) -> String {
+    let hex = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+    let b = s.as_bytes();
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        match b[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < b.len() => match (hex(b[i + 1]), hex(b[i + 2])) {
+                (Some(hi), Some(lo)) => {
+                    out.push(hi << 4 | lo);
+                    i += 2;
+                }
+                _ => out.push(b'%'),
+            },
+            c => out.push(c),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn query_param(query: &str, key: &str) -> Option<String> {
+    query.split('&').find_map(|kv| {
+        let (k, v) = kv.split_once('=').unwrap_or((kv, ""));
+        (url_decode(k) == key).then(|| url_decode(v))
+    })
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!doctype html><html><head><meta charset=\"utf-8\"><title>{} — ue3-tools</title>\
+         <style>body{{font:14px sans-serif;margin:1.5em}}table{{border-collapse:collapse}}\
+         td,th{{padding:2px 10px;text-align:left;vertical-align:top}}tr:nth-child(even){{background:#f3f3f3}}\
+         pre{{background:#f6f6f6;padding:.6em;overflow-x:auto}}.dim{{color:#888}}</style></head>\
+         <body><p><a href=\"/\">packages</a></p><h2>{}</h2>{body}</body></html>",
+        esc(title),
+        esc(title)
+    )
+}
+
+struct Server {
+    root: PathBuf,
+    index_path: PathBuf,
+    index: PackageIndex,
+    index_mtime: Option<SystemTime>,
+    db: Option<SchemaDb>,
+    /// The last package opened, since browsing stays in one for a while.
+    last: Option<(String, Option<SystemTime>, Rc<LazyPackage>)>,
+}
+
+fn mtime(p: &Path) -> Option<SystemTime> {
+    std::fs::metadata(p).and_then(|m| m.modified()).ok()
+}
+
+impl Server {
+    fn refresh_index(&mut self) {
+        let now = mtime(&self.index_path);
+        if now == self.index_mtime {
+            return;
+        }
+        match PackageIndex::load(&self.index_path) {
+            Ok(idx) => {
+                println!("Index changed; reloaded {} package(s)", idx.packages.len());
+                self.index = idx;
+                self.index_mtime = now;
+            }
+            Err(e) => term::warn("index", format_args!("{e}; keeping the old one")),
+        }
+    }
+
+    fn package(&mut self, rel: &str) -> Result<Rc<LazyPackage>> {
+        if !self.index.packages.contains_key(rel) {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("'{rel}' is not in the index"),
+            ));
+        }
+        let path = self.root.join(rel);
+        let when = mtime(&path);
+        if let Some((r, t, lp)) = &self.last
+            && r == rel
+            && *t == when
+        {
+            return Ok(lp.clone());
+        }
+        let lp = Rc::new(open_package_file(&path)?);
+        self.last = Some((rel.to_string(), when, lp.clone()));
+        Ok(lp)
+    }
+
+    fn export_arg(lp: &LazyPackage, query: &str) -> Result<i32> {
+        let i = query_param(query, "i")
+            .and_then(|s| s.parse::<i32>().ok())
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "missing export index i=N"))?;
+        if i < 1 || i as usize > lp.pak.export_table.len() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("export #{i} out of range"),
+            ));
+        }
+        Ok(i)
+    }
+
+    fn rel_arg(query: &str) -> Result<String> {
+        query_param(query, "p")
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "missing package p=PATH"))
+    }
+
+    fn route(&mut self, path: &str, query: &str) -> Result<Response> {
+        match path {
+            "/" => Ok(self.packages_html()),
+            "/api/packages" => Ok(Response::json(&self.packages_json())),
+            "/pkg" | "/api/package" => {
+                let rel = Self::rel_arg(query)?;
+                let lp = self.package(&rel)?;
+                Ok(if path == "/pkg" {
+                    package_html(&rel, &lp)
+                } else {
+                    Response::json(&package_json(&rel, &lp))
+                })
+            }
+            "/export" | "/api/export" => {
+                let rel = Self::rel_arg(query)?;
+                let lp = self.package(&rel)?;
+                let i = Self::export_arg(&lp, query)?;
+                let ex = ExportView::read(&lp, i, self.db.as_ref());
+                Ok(if path == "/export" {
+                    ex.html(&rel, &lp)
+                } else {
+                    Response::json(&ex.json(&rel, &lp))
+                })
+            }
+            "/thumb" => {
+                let rel = Self::rel_arg(query)?;
+                let lp = self.package(&rel)?;
+                let i = Self::export_arg(&lp, query)?;
+                let body = thumbnail(&lp, i, self.db.as_ref())?.ok_or_else(|| {
+                    Error::new(ErrorKind::NotFound, format!("export #{i} has no thumbnail"))
+                })?;
+                Ok(Response {
+                    status: 200,
+                    content_type: "image/png",
+                    body,
+                })
+            }
+            _ => Err(Error::new(ErrorKind::NotFound, format!("no route {path}"))),
+        }
+    }
+
+    fn packages_json(&self) -> Value {
+        let list: Vec<Value> = self
+            .index
+            .packages
+            .iter()
+            .map(|(rel, e)| {
+                json!({
+                    "path": rel,
+                    "size": e.size,
+                    "hash": e.hash,
+                    "p_ver": e.p_ver,
+                    "l_ver": e.l_ver,
+                    "exports": e.exports.len(),
+                    "error": e.error,
+                })
+            })
+            .collect();
+        json!({ "game_dir": self.index.game_dir, "packages": list })
+    }
+
+    fn packages_html(&self) -> Response {
+        let mut b = format!(
+            "<p class=\"dim\">{} — {} package(s)</p><table><tr><th>package</th><th>size</th>\
+             <th>version</th><th>exports</th></tr>",
+            esc(&self.index.game_dir),
+            self.index.packages.len()
+        );
+        for (rel, e) in &self.index.packages {
+            let _ = write!(
+                b,
+                "<tr><td><a href=\"/pkg?p={}\">{}</a></td><td>{}</td><td>{}/{}</td><td>{}</td></tr>",
+                url_encode(rel),
+                esc(rel),
+                e.size,
+                e.p_ver,
+                e.l_ver,
+                match &e.error {
+                    Some(err) => format!("<span class=\"dim\">{}</span>", esc(err)),
+                    None => e.exports.len().to_string(),
+                }
+            );
+        }
+        b.push_str("</table>");
+        Response::html("Packages", &b)
+    }
+}
+
+fn package_json(rel: &str, lp: &LazyPackage) -> Value {
+    let exports: Vec<Value> = (1..=lp.pak.export_table.len() as i32)
+        .map(|i| {
+            let e = &lp.pak.export_table[(i - 1) as usize];
+            json!({
+                "index": i,
+                "class": lp.export_class_name(i),
+                "path": export_path_dotted(&lp.pak, i),
+                "size": e.serial_size,
+                "offset": e.serial_offset,
+            })
+        })
+        .collect();
+    json!({
+        "path": rel,
+        "p_ver": lp.header.p_ver,
+        "l_ver": lp.header.l_ver,
+        "flags": lp.header.pak_flags,
+        "names": lp.pak.name_table.len(),
+        "imports": lp.pak.import_table.len(),
+        "exports": exports,
+    })
+}
+
+fn package_html(rel: &str, lp: &LazyPackage) -> Response {
+    let mut b = format!(
+        "<p class=\"dim\">version {}/{}, {} names, {} imports, {} exports — \
+         <a href=\"/api/package?p={}\">json</a></p>\
+         <table><tr><th>#</th><th>class</th><th>object</th><th>size</th></tr>",
+        lp.header.p_ver,
+        lp.header.l_ver,
+        lp.pak.name_table.len(),
+        lp.pak.import_table.len(),
+        lp.pak.export_table.len(),
+        url_encode(rel)
+    );
+    for i in 1..=lp.pak.export_table.len() as i32 {
+        let _ = write!(
+            b,
+            "<tr><td class=\"dim\">{i}</td><td>{}</td><td><a href=\"/export?p={}&amp;i={i}\">{}</a></td><td>{}</td></tr>",
+            esc(&lp.export_class_name(i)),
+            url_encode(rel),
+            esc(&lp.pak.get_export_path_name(i)),
+            lp.pak.export_table[(i - 1) as usize].serial_size
+        );
+    }
+    b.push_str("</table>");
+    Response::html(rel, &b)
+}
+
+/// What the export page shows; parts that fail to read carry the error.
+struct ExportView {
+    index: i32,
+    props: std::result::Result<Vec<(String, String, String)>, String>,
+    native_bytes: usize,
+    /// `(memory offset, text)` per statement; `None` for non-code exports.
+    code: Option<std::result::Result<Vec<(u32, String)>, String>>,
+    is_texture: bool,
+}
+
+impl ExportView {
+    fn read(lp: &LazyPackage, i: i32, db: Option<&SchemaDb>) -> Self {
+        let size = lp.export_blob(i).map_or(0, |b| b.len());
+        let mut native_bytes = 0;
+        let props = lp
+            .export_props(i, db)
+            .map(|(props, end)| {
+                native_bytes = size.saturating_sub(end);
+                props
+                    .iter()
+                    .map(|p| {
+                        let name = if p.array_index > 0 {
+                            format!("{}[{}]", p.name, p.array_index)
+                        } else {
+                            p.name.clone()
+                        };
+                        (name, p.prop_type.clone(), value_text(lp, &p.value, 0))
+                    })
+                    .collect()
+            })
+            .map_err(|e| e.to_string());
+        let code = lp.pak.script_kind(i).map(|_| {
+            export_disassembly(lp, i)
+                .map(|d| {
+                    d.statements
+                        .iter()
+                        .take(MAX_STATEMENTS)
+                        .map(|s| (s.mem_offset, s.text.clone()))
+                        .collect()
+                })
+                .map_err(|e| e.to_string())
+        });
+        Self {
+            index: i,
+            props,
+            native_bytes,
+            code,
+            is_texture: lp.object_in::<Texture2D>(i, db).is_some(),
+        }
+    }
+
+    fn json(&self, rel: &str, lp: &LazyPackage) -> Value {
+        let i = self.index;
+        let e = &lp.pak.export_table[(i - 1) as usize];
+        let props = match &self.props {
+            Ok(list) => json!(
+                list.iter()
+                    .map(|(n, t, v)| json!({ "name": n, "type": t, "value": v }))
+                    .collect::<Vec<_>>()
+            ),
+            Err(err) => json!({ "error": err }),
+        };
+        let code = match &self.code {
+            None => Value::Null,
+            Some(Ok(st)) => json!(
+                st.iter()
+                    .map(|(at, text)| json!({ "offset": at, "text": text }))
+                    .collect::<Vec<_>>()
+            ),
+            Some(Err(err)) => json!({ "error": err }),
+        };
+        json!({
+            "package": rel,
+            "index": i,
+            "name": lp.export_full_name(i),
+            "class": lp.export_class_name(i),
+            "size": e.serial_size,
+            "offset": e.serial_offset,
+            "object_flags": e.object_flags,
+            "export_flags": e.export_flags,
+            "props": props,
+            "native_bytes": self.native_bytes,
+            "disassembly": code,
+            "thumbnail": self
+                .is_texture
+                .then(|| format!("/thumb?p={}&i={i}", url_encode(rel))),
+        })
+    }
+
+    fn html(&self, rel: &str, lp: &LazyPackage) -> Response {
+        let i = self.index;
+        let e = &lp.pak.export_table[(i - 1) as usize];
+        let mut b = format!(
+            "<p class=\"dim\"><a href=\"/pkg?p={0}\">{1}</a> #{i}: {2} bytes at 0x{3:08X} — \
+             <a href=\"/api/export?p={0}&amp;i={i}\">json</a></p>",
+            url_encode(rel),
+            esc(rel),
+            e.serial_size,
+            e.serial_offset
+        );
+        if self.is_texture {
+            let _ = write!(
+                b,
+                "<p><img src=\"/thumb?p={}&amp;i={i}\" alt=\"no decodable mip\"></p>",
+                url_encode(rel)
+            );
+        }
+        b.push_str("<h3>Properties</h3>");
+        match &self.props {
+            Ok(list) if list.is_empty() => {
+                b.push_str("<p class=\"dim\">no tagged properties</p>");
+            }
+            Ok(list) => {
+                b.push_str("<table>");
+                for (n, t, v) in list {
+                    let _ = write!(
+                        b,
+                        "<tr><td>{}</td><td class=\"dim\">{}</td><td>{}</td></tr>",
+                        esc(n),
+                        esc(t),
+                        esc(v)
+                    );
+                }
+                b.push_str("</table>");
+            }
+            Err(err) => {
+                let _ = write!(b, "<p class=\"dim\">not readable: {}</p>", esc(err));
+            }
+        }
+        if self.native_bytes > 0 {
+            let _ = write!(
+                b,
+                "<p class=\"dim\">{} byte(s) of native data follow</p>",
+                self.native_bytes
+            );
+        }
+        match &self.code {
+            None => {}
+            Some(Ok(st)) => {
+                b.push_str("<h3>Bytecode</h3><pre>");
+                for (at, text) in st {
+                    let _ = writeln!(b, "0x{at:04X}  {}", esc(text));
+                }
+                b.push_str("</pre>");
+            }
+            Some(Err(err)) => {
+                let _ = write!(b, "<h3>Bytecode</h3><p class=\"dim\">{}</p>", esc(err));
+            }
+        }
+        Response::html(&lp.export_full_name(i), &b)
+    }
+}
+
+fn thumbnail(lp: &LazyPackage, i: i32, db: Option<&SchemaDb>) -> Result<Option<Vec<u8>>> {
+    let Some(tex) = lp.object_in::<Texture2D>(i, db) else {
+        return Ok(None);
+    };
+    match tex?.payload.preview_rgba(Some(THUMB_SIDE))? {
+        Some((w, h, rgba)) => Ok(Some(png::encode_rgba(w, h, &rgba)?)),
+        None => Ok(None),
+    }
+}
+
+/// The request line's method and target, once the head has arrived.
+fn read_request(stream: &mut TcpStream) -> Result<(String, String)> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 2048];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..n]);
+        if head.len() > MAX_HEAD {
+            return Err(Error::new(ErrorKind::InvalidData, "request head too long"));
+        }
+    }
+    let text = String::from_utf8_lossy(&head);
+    let mut parts = text.lines().next().unwrap_or_default().split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some(m), Some(t)) => Ok((m.to_string(), t.to_string())),
+        _ => Err(Error::new(ErrorKind::InvalidData, "malformed request line")),
+    }
+}
+
+fn handle(server: &mut Server, mut stream: TcpStream, verbose: bool) -> Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let (method, target) = read_request(&mut stream)?;
+    // `--deadline` bounds each request, not the server's lifetime.
+    deadline::arm();
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let api = path.starts_with("/api/");
+    let resp = if method != "GET" && method != "HEAD" {
+        Response::error(405, api, "read-only: only GET and HEAD")
+    } else {
+        server.refresh_index();
+        server.route(path, query).unwrap_or_else(|e| {
+            let status = match e.kind() {
+                ErrorKind::NotFound => 404,
+                ErrorKind::InvalidInput => 400,
+                _ => 500,
+            };
+            Response::error(status, api, &e.to_string())
+        })
+    };
+    if verbose || resp.status >= 500 {
+        println!(
+            "{} {method} {target} {}",
+            paint(
+                if resp.status < 400 {
+                    Color::Gray
+                } else {
+                    Color::Yellow
+                },
+                resp.status
+            ),
+            paint(Color::Gray, format!("{} bytes", resp.body.len()))
+        );
+    }
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        resp.status,
+        reason(resp.status),
+        resp.content_type,
+        resp.body.len()
+    )?;
+    if method != "HEAD" {
+        stream.write_all(&resp.body)?;
+    }
+    stream.flush()
+}
+
+pub fn serve_cmd(
+    root: &Path,
+    bind: &str,
+    port: u16,
+    index: Option<&Path>,
+    verbose: bool,
+) -> Result<()> {
+    let (index_path, idx) = index::open(root, index)?;
+    let db = match SchemaDb::new(root) {
+        Ok(db) => Some(db.with_verbose(verbose)),
+        Err(e) => {
+            term::warn(
+                "schema",
+                format_args!("{e}; imported classes won't resolve"),
+            );
+            None
+        }
+    };
+    let listener = TcpListener::bind((bind, port))
+        .map_err(|e| Error::new(e.kind(), format!("{bind}:{port}: {e}")))?;
+    println!(
+        "Serving {} package(s) from {} on {}",
+        idx.packages.len(),
+        root.display(),
+        paint(
+            Color::Highlight,
+            format!("http://{}", listener.local_addr()?)
+        )
+    );
+    let mut server = Server {
+        root: root.to_path_buf(),
+        index_mtime: mtime(&index_path),
+        index_path,
+        index: idx,
+        db,
+        last: None,
+    };
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                term::warn("accept", e);
+                continue;
+            }
+        };
+        if let Err(e) = handle(&mut server, stream, verbose) {
+            term::warn("client", e);
+        }
+    }
+    Ok(())
+}
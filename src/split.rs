@@ -0,0 +1,340 @@
+//! Planning how to split a package that has outgrown a size limit (console
+//! memory, a streaming budget) into several, each under it.
+//!
+//! An export moves together with everything inside it, so the units are
+//! top-level objects with their subobjects. They are packed largest first,
+//! each into the part it references most that still has room. References
+//! that end up crossing parts become imports of the sibling package, which
+//! the estimate of each part's size includes.
+//!
+//! The plan is advisory: moving an export renumbers every reference to it,
+//! including ones inside native data this tool can't see, so the parts are
+//! meant to be cooked from the listed groups. `--json` writes the plan for
+//! build scripts to do that.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::{Cursor, Error, ErrorKind, Result},
+    path::Path,
+};
+
+use serde_json::json;
+
+use crate::{
+    exit::validation_failed,
+    orphans::props_refs,
+    schemadb::{LazyPackage, open_package_file},
+    upkpacker::export_path_dotted,
+    utils::{
+        readonly,
+        spill::parse_size,
+        term::{self, Color, paint},
+    },
+};
+
+/// Serialized size of an import table entry.
+const IMPORT_ENTRY: u64 = 28;
+/// Groups listed per part without `--verbose`.
+const LISTED: usize = 8;
+
+/// A top-level export and its subobjects.
+struct Unit {
+    root: i32,
+    exports: Vec<i32>,
+    size: u64,
+    /// Exports of other units this one references.
+    refs: BTreeSet<i32>,
+}
+
+struct Part {
+    units: Vec<usize>,
+    data: u64,
+    exports: usize,
+}
+
+/// Serialized sizes of the summary and the tables.
+struct Overhead {
+    summary: u64,
+    names: u64,
+    imports: u64,
+    /// Export table bytes per entry, on average.
+    per_export: f64,
+}
+
+impl Overhead {
+    fn of(lp: &LazyPackage) -> Result<Self> {
+        let mut summary = Cursor::new(Vec::new());
+        lp.header.write(&mut summary)?;
+        // FString plus 64-bit flags; UTF-16 when not plain ASCII.
+        let names = lp
+            .pak
+            .name_table
+            .iter()
+            .map(|n| {
+                let chars = n.chars().count() as u64 + 1;
+                4 + 8 + if n.is_ascii() { chars } else { 2 * chars }
+            })
+            .sum();
+        let mut imports = Vec::new();
+        for i in &lp.pak.import_table {
+            i.write(&mut imports)?;
+        }
+        let mut exports = Vec::new();
+        for e in &lp.pak.export_table {
+            e.write(&mut exports, lp.header.p_ver)?;
+        }
+        Ok(Self {
+            summary: summary.into_inner().len() as u64,
+            names,
+            imports: imports.len() as u64,
+            per_export: exports.len() as f64 / lp.pak.export_table.len().max(1) as f64,
+        })
+    }
+
+    /// A part keeps the whole name and import tables (an upper bound), its
+    /// own export entries and depends map, and one import per object of a
+    /// sibling it references plus one per sibling package.
+    fn estimate(&self, exports: usize, cross: usize, siblings: usize) -> u64 {
+        self.summary
+            + self.names
+            + self.imports
+            + (self.per_export * exports as f64).ceil() as u64
+            + 4 * exports as u64
+            + IMPORT_ENTRY * (cross + siblings) as u64
+    }
+}
+
+fn top_level(lp: &LazyPackage, mut i: i32) -> i32 {
+    let mut hops = 0;
+    while let Some(e) = lp.pak.export_table.get((i - 1) as usize) {
+        if e.outer_index <= 0 || hops > lp.pak.export_table.len() {
+            break;
+        }
+        i = e.outer_index;
+        hops += 1;
+    }
+    i
+}
+
+/// Units and how many exports' properties couldn't be read.
+fn units(lp: &LazyPackage) -> (Vec<Unit>, usize) {
+    let n = lp.pak.export_table.len() as i32;
+    let mut by_root: BTreeMap<i32, usize> = BTreeMap::new();
+    let mut units: Vec<Unit> = Vec::new();
+    let mut unit_of = vec![0usize; n as usize + 1];
+    for i in 1..=n {
+        let root = top_level(lp, i);
+        let u = *by_root.entry(root).or_insert_with(|| {
+            units.push(Unit {
+                root,
+                exports: Vec::new(),
+                size: 0,
+                refs: BTreeSet::new(),
+            });
+            units.len() - 1
+        });
+        unit_of[i as usize] = u;
+        units[u].exports.push(i);
+        units[u].size += lp.pak.export_table[(i - 1) as usize].serial_size.max(0) as u64;
+    }
+
+    let mut unparsed = 0;
+    for i in 1..=n {
+        let e = &lp.pak.export_table[(i - 1) as usize];
+        let mut refs = vec![e.class_index, e.super_index, e.archetype];
+        refs.extend(e.legacy_component_map.values());
+        match lp.export_props(i, None) {
+            Ok((props, _)) => props_refs(&props, &mut refs),
+            Err(_) => unparsed += 1,
+        }
+        let u = unit_of[i as usize];
+        for r in refs {
+            if r > 0 && r <= n && unit_of[r as usize] != u {
+                units[u].refs.insert(r);
+            }
+        }
+    }
+    (units, unparsed)
+}
+
+/// Exports of other parts that `part`'s units reference.
+fn cross_refs(
+    units: &[Unit],
+    part: &Part,
+    unit_part: &HashMap<i32, usize>,
+    me: usize,
+) -> BTreeSet<i32> {
+    part.units
+        .iter()
+        .flat_map(|&u| units[u].refs.iter().copied())
+        .filter(|r| unit_part.get(r).is_some_and(|p| *p != me))
+        .collect()
+}
+
+pub fn split_cmd(
+    upk_path: &Path,
+    budget: &str,
+    json_out: Option<&Path>,
+    verbose: bool,
+) -> Result<()> {
+    let budget = parse_size(budget)?;
+    let path = upk_path;
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("{}: no file name", upk_path.display()),
+            )
+        })?;
+    let lp = open_package_file(path)?;
+    let overhead = Overhead::of(&lp)?;
+    let (mut units, unparsed) = units(&lp);
+    units.sort_by(|a, b| b.size.cmp(&a.size).then(a.root.cmp(&b.root)));
+
+    let mut parts: Vec<Part> = Vec::new();
+    // Export → part, filled in as units are placed.
+    let mut placed: HashMap<i32, usize> = HashMap::new();
+    let mut oversized = Vec::new();
+    for (u, unit) in units.iter().enumerate() {
+        let fits = |p: &Part| {
+            let exports = p.exports + unit.exports.len();
+            // Every outgoing reference may become an import; cheap and
+            // never under the real count.
+            let cross =
+                unit.refs.len() + p.units.iter().map(|&v| units[v].refs.len()).sum::<usize>();
+            p.data + unit.size + overhead.estimate(exports, cross, parts.len()) <= budget
+        };
+        let affinity = |k: usize| {
+            unit.refs
+                .iter()
+                .filter(|r| placed.get(r) == Some(&k))
+                .count()
+        };
+        let best = (0..parts.len())
+            .filter(|&k| fits(&parts[k]))
+            .max_by_key(|&k| (affinity(k), std::cmp::Reverse(budget - parts[k].data)));
+        let k = match best {
+            Some(k) => k,
+            None => {
+                let alone = unit.size + overhead.estimate(unit.exports.len(), unit.refs.len(), 0);
+                if alone > budget {
+                    oversized.push((u, alone));
+                }
+                parts.push(Part {
+                    units: Vec::new(),
+                    data: 0,
+                    exports: 0,
+                });
+                parts.len() - 1
+            }
+        };
+        parts[k].units.push(u);
+        parts[k].data += unit.size;
+        parts[k].exports += unit.exports.len();
+        for &e in &unit.exports {
+            placed.insert(e, k);
+        }
+    }
+
+    let total: u64 = units.iter().map(|u| u.size).sum();
+    println!(
+        "{}: {total} bytes of export data in {} export(s), {} top-level group(s); \
+         budget {budget} → {} package(s)",
+        path.display(),
+        lp.pak.export_table.len(),
+        units.len(),
+        paint(Color::Highlight, parts.len())
+    );
+
+    let name_of = |k: usize| format!("{stem}_{}", k + 1);
+    let mut plan = Vec::new();
+    for (k, p) in parts.iter().enumerate() {
+        let cross = cross_refs(&units, p, &placed, k);
+        let siblings: BTreeSet<usize> = cross.iter().map(|r| placed[r]).collect();
+        let size = p.data + overhead.estimate(p.exports, cross.len(), siblings.len());
+        let over = size > budget;
+        println!(
+            "  {} ~{} bytes, {} group(s), {} export(s){}",
+            paint(Color::Cyan, name_of(k)),
+            paint(if over { Color::Red } else { Color::Green }, size),
+            p.units.len(),
+            p.exports,
+            if siblings.is_empty() {
+                String::new()
+            } else {
+                let from: Vec<String> = siblings.iter().map(|&s| name_of(s)).collect();
+                format!(", {} import(s) from {}", cross.len(), from.join(", "))
+            }
+        );
+        let shown = if verbose { p.units.len() } else { LISTED };
+        for &u in p.units.iter().take(shown) {
+            println!(
+                "      {:>10}  {}",
+                units[u].size,
+                lp.export_full_name(units[u].root)
+            );
+        }
+        if p.units.len() > shown {
+            println!("      … {} more group(s)", p.units.len() - shown);
+        }
+
+        plan.push(json!({
+            "package": name_of(k),
+            "estimated_size": size,
+            "groups": p.units.iter()
+                .map(|&u| export_path_dotted(&lp.pak, units[u].root))
+                .collect::<Vec<_>>(),
+            "imports": cross.iter()
+                .map(|&r| json!({
+                    "package": name_of(placed[&r]),
+                    "object": export_path_dotted(&lp.pak, r),
+                    "class": lp.export_class_name(r),
+                }))
+                .collect::<Vec<_>>(),
+        }));
+    }
+
+    if unparsed > 0 {
+        term::warn(
+            "refs",
+            format_args!(
+                "{unparsed} export(s) without readable properties; their references aren't counted"
+            ),
+        );
+    }
+    println!(
+        "  {}",
+        paint(
+            Color::Gray,
+            "references held in native data aren't seen; check the parts load before shipping them"
+        )
+    );
+
+    if let Some(out) = json_out {
+        let doc = json!({
+            "source": upk_path,
+            "budget": budget,
+            "parts": plan,
+        });
+        let text = serde_json::to_string_pretty(&doc).map_err(Error::other)?;
+        readonly::write(out, text)?;
+        println!("Plan: {}", out.display());
+    }
+
+    if !oversized.is_empty() {
+        for (u, size) in &oversized {
+            println!(
+                "  {} {} alone needs ~{size} bytes",
+                paint(Color::Red, "FAIL"),
+                lp.export_full_name(units[*u].root)
+            );
+        }
+        return Err(validation_failed(format!(
+            "{} group(s) don't fit in {budget} bytes even on their own",
+            oversized.len()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use std::{
+    io::{BufRead, Result},
+    path::Path,
+};
+
+use crate::{
+    disasm::{self, print_statement},
+    offsets::find_export,
+    schemadb::open_package_file,
+    utils::term::{self, Color, paint},
+};
+
+/// A script frame as printed in UE3 logs, e.g.
+/// `Function Engine.Actor:Touch:00A3` or `Engine.Actor.Touch + 0xA3`.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub path: String,
+    pub offset: u32,
+}
+
+pub fn parse_frame(line: &str) -> Option<Frame> {
+    // Log lines carry timestamps / categories in front of the frame.
+    let t = match line.rfind("Function ") {
+        Some(i) => &line[i + "Function ".len()..],
+        None => line,
+    }
+    .trim();
+    let (path, off) = match t.rsplit_once('+') {
+        Some((p, o)) => (p.trim(), o.trim()),
+        None => {
+            let (p, o) = t.rsplit_once(':')?;
+            (p.trim(), o.trim())
+        }
+    };
+    let off = off
+        .strip_prefix("0x")
+        .or_else(|| off.strip_prefix("0X"))
+        .unwrap_or(off);
+    let offset = u32::from_str_radix(off, 16).ok()?;
+    if path.is_empty() || path.contains(char::is_whitespace) {
+        return None;
+    }
+    Some(Frame {
+        path: path.to_string(),
+        offset,
+    })
+}
+
+pub fn symbolicate_cmd(upk_path: &Path, frames: &[String], context: usize) -> Result<()> {
+    let lp = open_package_file(upk_path)?;
+
+    let mut lines = Vec::new();
+    for f in frames {
+        if f == "-" {
+            for l in std::io::stdin().lock().lines() {
+                lines.push(l?);
+            }
+        } else {
+            lines.push(f.clone());
+        }
+    }
+
+    for line in &lines {
+        let Some(frame) = parse_frame(line) else {
+            continue;
+        };
+        let idx = match find_export(&lp, &frame.path) {
+            Ok(i) => i,
+            Err(e) => {
+                println!(
+                    "{}\n  {}: {e}\n",
+                    line.trim(),
+                    paint(Color::Yellow, "skipped")
+                );
+                continue;
+            }
+        };
+        let dis = match disasm::export_disassembly(&lp, idx) {
+            Ok(d) => d,
+            Err(e) => {
+                println!(
+                    "{}\n  {}: {e}\n",
+                    line.trim(),
+                    paint(Color::Yellow, "skipped")
+                );
+                continue;
+            }
+        };
+
+        println!(
+            "{} → {} (#{idx}) @ 0x{:04X}",
+            line.trim(),
+            lp.pak.get_export_full_name(idx),
+            frame.offset
+        );
+        let Some(hit) = dis
+            .statements
+            .iter()
+            .rposition(|s| s.mem_offset <= frame.offset)
+        else {
+            println!(
+                "  offset is before the first statement{}\n",
+                dis.error
+                    .as_deref()
+                    .map(|e| format!(" ({e})"))
+                    .unwrap_or_default()
+            );
+            continue;
+        };
+        let lo = hit.saturating_sub(context);
+        let hi = (hit + context + 1).min(dis.statements.len());
+        for (i, st) in dis.statements[lo..hi].iter().enumerate() {
+            print_statement(st, lo + i == hit);
+        }
+        if hit + 1 == dis.statements.len()
+            && let Some(e) = &dis.error
+        {
+            term::warn("disasm", format_args!("stopped early, {e}"));
+        }
+        println!();
+    }
+    Ok(())
+}
@@ -0,0 +1,266 @@
+//! Symbol files for loading a package into a disassembler as a flat binary
+//! (base 0): every table and export as a named span, functions' bytecode
+//! as its own label, and decoded statements as comments. Reverse engineers
+//! can then line up what the game binary reads with what the package holds.
+
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    io::{Error, Result, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{
+    disasm::{export_disassembly, script_span},
+    offsets::warn_if_compressed,
+    package::{Package, RegionKind},
+    schemadb::{LazyPackage, open_package_file},
+    upkpacker::export_path_dotted,
+    utils::{readonly, term},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SymbolFormat {
+    /// IDC script: File → Script file in IDA.
+    Idc,
+    /// `name address l` lines for Ghidra's ImportSymbolsScript.py.
+    Ghidra,
+    Json,
+}
+
+#[derive(Serialize)]
+struct Statement {
+    offset: u64,
+    mem_offset: u32,
+    text: String,
+}
+
+#[derive(Serialize)]
+struct Script {
+    name: String,
+    offset: u64,
+    disk_size: u32,
+    mem_size: u32,
+    statements: Vec<Statement>,
+}
+
+#[derive(Serialize)]
+struct Symbol {
+    name: String,
+    kind: &'static str,
+    offset: u64,
+    size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    object: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    script: Option<Script>,
+}
+
+/// Characters both IDA and Ghidra take in a label; anything else becomes
+/// `_`.
+fn label(s: &str) -> String {
+    let mut out: String = s
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$' | '@' | '?') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if out.is_empty() || out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+    out
+}
+
+fn table_label(kind: RegionKind) -> &'static str {
+    match kind {
+        RegionKind::Summary => "__summary",
+        RegionKind::Names => "__name_table",
+        RegionKind::Imports => "__import_table",
+        RegionKind::Exports => "__export_table",
+        RegionKind::Depends => "__depends_map",
+        RegionKind::Guids => "__import_export_guids",
+        RegionKind::Thumbnails => "__thumbnail_table",
+        RegionKind::Export(_) => "__export",
+    }
+}
+
+fn collect(pkg: &Package, lp: &LazyPackage) -> Result<Vec<Symbol>> {
+    let mut out = Vec::new();
+    let mut taken = HashSet::new();
+    let mut unique = |name: String, i: i32| {
+        if taken.insert(name.clone()) {
+            name
+        } else {
+            format!("{name}_{i}")
+        }
+    };
+    let mut undecoded = 0;
+    for r in pkg.regions()? {
+        let RegionKind::Export(i) = r.kind else {
+            out.push(Symbol {
+                name: table_label(r.kind).to_string(),
+                kind: "table",
+                offset: r.start as u64,
+                size: r.len as u64,
+                object: None,
+                script: None,
+            });
+            continue;
+        };
+        let name = unique(label(&export_path_dotted(&lp.pak, i)), i);
+        let script = lp
+            .pak
+            .script_kind(i)
+            .zip(lp.export_blob(i).ok())
+            .and_then(|(kind, blob)| script_span(blob, kind, &lp.pak, lp.header.p_ver))
+            .map(|span| {
+                let start = r.start as u64 + span.offset_in_blob;
+                let statements = match export_disassembly(lp, i) {
+                    Ok(dis) => {
+                        if dis.error.is_some() {
+                            undecoded += 1;
+                        }
+                        dis.statements
+                            .into_iter()
+                            .map(|st| Statement {
+                                offset: start + st.disk_offset as u64,
+                                mem_offset: st.mem_offset,
+                                text: st.text,
+                            })
+                            .collect()
+                    }
+                    Err(_) => {
+                        undecoded += 1;
+                        Vec::new()
+                    }
+                };
+                Script {
+                    name: format!("{name}$script"),
+                    offset: start,
+                    disk_size: span.disk_size,
+                    mem_size: span.mem_size,
+                    statements,
+                }
+            });
+        out.push(Symbol {
+            name,
+            kind: "export",
+            offset: r.start as u64,
+            size: r.len as u64,
+            object: Some(lp.export_full_name(i)),
+            script,
+        });
+    }
+    if undecoded > 0 {
+        term::warn(
+            "disasm",
+            format_args!(
+                "{undecoded} script(s) only partly decoded; their statements are cut short"
+            ),
+        );
+    }
+    out.sort_by_key(|s| s.offset);
+    Ok(out)
+}
+
+fn idc_string(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_idc(package: &Path, symbols: &[Symbol]) -> String {
+    let mut s = String::new();
+    let _ = writeln!(
+        s,
+        "// Symbols for {}; load the (decompressed) package at base 0.",
+        package.display()
+    );
+    let _ = writeln!(s, "#include <idc.idc>\n\nstatic main() {{");
+    for sym in symbols {
+        let _ = writeln!(
+            s,
+            "    set_name(0x{:X}, \"{}\", SN_NOWARN | SN_NOCHECK);",
+            sym.offset, sym.name
+        );
+        let what = sym.object.as_deref().unwrap_or(&sym.name);
+        let _ = writeln!(
+            s,
+            "    set_cmt(0x{:X}, \"{} ({} bytes)\", 0);",
+            sym.offset,
+            idc_string(what),
+            sym.size
+        );
+        let Some(script) = &sym.script else {
+            continue;
+        };
+        let _ = writeln!(
+            s,
+            "    set_name(0x{:X}, \"{}\", SN_NOWARN | SN_NOCHECK);",
+            script.offset, script.name
+        );
+        for st in &script.statements {
+            let _ = writeln!(
+                s,
+                "    set_cmt(0x{:X}, \"[mem 0x{:04X}] {}\", 0);",
+                st.offset,
+                st.mem_offset,
+                idc_string(&st.text)
+            );
+        }
+    }
+    s.push_str("}\n");
+    s
+}
+
+fn write_ghidra(symbols: &[Symbol]) -> String {
+    let mut s = String::new();
+    for sym in symbols {
+        let _ = writeln!(s, "{} 0x{:X} l", sym.name, sym.offset);
+        if let Some(script) = &sym.script {
+            let _ = writeln!(s, "{} 0x{:X} l", script.name, script.offset);
+        }
+    }
+    s
+}
+
+pub fn symbols_cmd(upk_path: &Path, format: SymbolFormat, out: Option<&Path>) -> Result<()> {
+    let path = upk_path;
+    warn_if_compressed(path)?;
+    let pkg = Package::open(path)?;
+    let lp = open_package_file(path)?;
+    let symbols = collect(&pkg, &lp)?;
+
+    let text = match format {
+        SymbolFormat::Idc => write_idc(upk_path, &symbols),
+        SymbolFormat::Ghidra => write_ghidra(&symbols),
+        SymbolFormat::Json => {
+            let doc = serde_json::json!({
+                "package": upk_path,
+                "size": pkg.bytes.len(),
+                "symbols": symbols,
+            });
+            serde_json::to_string_pretty(&doc).map_err(Error::other)? + "\n"
+        }
+    };
+    match out {
+        Some(o) => {
+            readonly::write(o, text)?;
+            let scripts = symbols.iter().filter(|s| s.script.is_some()).count();
+            println!(
+                "Wrote {} symbol(s), {scripts} with bytecode → {}",
+                symbols.len(),
+                o.display()
+            );
+        }
+        None => std::io::stdout().lock().write_all(text.as_bytes())?,
+    }
+    Ok(())
+}
@@ -0,0 +1,474 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+use clap::Subcommand;
+
+use crate::{
+    delta,
+    exit::validation_failed,
+    schemadb::open_package_file,
+    upkreader::{Export, Import, UPKPak, UpkHeader},
+    utils::{
+        backup::backup_original,
+        decompress::{CompressionMethod, read_raw_header},
+        readonly, sniff, term,
+    },
+    versions::{
+        VER_FOBJECTEXPORT_EXPORTFLAGS, VER_LINKERFREE_PACKAGEMAP,
+        VER_MOVED_EXPORTIMPORTMAPS_ADDED_TOTALHEADERSIZE, VER_REMOVED_COMPONENT_MAP,
+    },
+};
+
+#[derive(Subcommand)]
+pub enum TableCmd {
+    #[command(about = "Print the fields of one export / import table entry with their offsets")]
+    Show {
+        upk_path: PathBuf,
+        #[arg(long, conflicts_with = "import", required_unless_present = "import")]
+        export: Option<i32>,
+        #[arg(long)]
+        import: Option<i32>,
+    },
+
+    #[command(about = "Set fields of one export / import table entry, e.g. --set outer_index=-3")]
+    Edit {
+        upk_path: PathBuf,
+        #[arg(long, conflicts_with = "import", required_unless_present = "import")]
+        export: Option<i32>,
+        #[arg(long)]
+        import: Option<i32>,
+        #[arg(long = "set", value_name = "FIELD=VALUE", required = true)]
+        set: Vec<String>,
+        #[arg(long)]
+        force: bool,
+        #[arg(long = "out", short = 'o', value_name = "FILE")]
+        out: Option<PathBuf>,
+        #[arg(long, value_name = "FILE")]
+        emit_delta: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    I32,
+    U32,
+    U64,
+}
+
+/// What a field's value points at, for range checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Plain,
+    Object,
+    Name,
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: &'static str,
+    pub offset: usize,
+    pub width: Width,
+    pub target: Target,
+}
+
+const fn field(name: &'static str, offset: usize, width: Width, target: Target) -> Field {
+    Field {
+        name,
+        offset,
+        width,
+        target,
+    }
+}
+
+/// Field positions inside a serialized export entry for package version
+/// `ver`; mirrors `Export::read`.
+pub fn export_layout(exp: &Export, ver: i16) -> Vec<Field> {
+    use Target::*;
+    use Width::*;
+    let mut f = vec![
+        field("class_index", 0, I32, Object),
+        field("super_index", 4, I32, Object),
+        field("outer_index", 8, I32, Object),
+        field("name_index", 12, I32, Name),
+        field("name_instance", 16, I32, Plain),
+        field("archetype", 20, I32, Object),
+        field("object_flags", 24, U64, Plain),
+        field("serial_size", 32, I32, Plain),
+    ];
+    let mut at = 36;
+    if exp.serial_size != 0 || ver >= VER_MOVED_EXPORTIMPORTMAPS_ADDED_TOTALHEADERSIZE {
+        f.push(field("serial_offset", at, I32, Plain));
+        at += 4;
+    }
+    if ver < VER_REMOVED_COMPONENT_MAP {
+        at += 4 + 12 * exp.legacy_component_map.len();
+    }
+    if ver >= VER_FOBJECTEXPORT_EXPORTFLAGS {
+        f.push(field("export_flags", at, U32, Plain));
+        at += 4;
+    }
+    if ver >= VER_LINKERFREE_PACKAGEMAP {
+        at += 4 + 4 * exp.generation_net_object_count.len() + 16;
+    }
+    if ver >= VER_REMOVED_COMPONENT_MAP {
+        f.push(field("package_flags", at, U32, Plain));
+    }
+    f
+}
+
+pub fn import_layout() -> Vec<Field> {
+    use Target::*;
+    use Width::*;
+    vec![
+        field("class_package", 0, I32, Name),
+        field("class_package_instance", 4, I32, Plain),
+        field("class_name", 8, I32, Name),
+        field("class_name_instance", 12, I32, Plain),
+        field("outer_index", 16, I32, Object),
+        field("name_index", 20, I32, Name),
+        field("name_instance", 24, I32, Plain),
+    ]
+}
+
+/// Friendlier spellings accepted by `--set`.
+fn canonical(name: &str) -> &str {
+    match name {
+        "class" | "class_ref" => "class_index",
+        "super" | "super_ref" => "super_index",
+        "outer" | "owner" | "owner_ref" | "outer_ref" => "outer_index",
+        "name" | "object_name" => "name_index",
+        "archetype_ref" => "archetype",
+        "flags" => "object_flags",
+        other => other,
+    }
+}
+
+enum Entry {
+    Export(i32),
+    Import(i32),
+}
+
+impl Entry {
+    fn from_args(export: Option<i32>, import: Option<i32>) -> Result<Self> {
+        match (export, import) {
+            (Some(e), None) => Ok(Entry::Export(e)),
+            (None, Some(i)) => Ok(Entry::Import(i)),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "pass exactly one of --export / --import",
+            )),
+        }
+    }
+}
+
+/// Where the entry lives in the file, its layout and its current bytes.
+struct Located {
+    label: String,
+    offset: usize,
+    fields: Vec<Field>,
+}
+
+fn serialized_export(e: &Export, ver: i16) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    e.write(&mut buf, ver)?;
+    Ok(buf)
+}
+
+const IMPORT_SIZE: usize = 28;
+
+fn locate(bytes: &[u8], h: &UpkHeader, pak: &UPKPak, entry: &Entry) -> Result<Located> {
+    let out_of_range = |what: &str, i: i32, n: usize| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("{what} #{i} out of range (1..={n})"),
+        )
+    };
+    let located = match *entry {
+        Entry::Export(i) => {
+            let n = pak.export_table.len();
+            if i < 1 || i as usize > n {
+                return Err(out_of_range("export", i, n));
+            }
+            let mut at = h.export_offset as usize;
+            for e in &pak.export_table[..(i - 1) as usize] {
+                at += serialized_export(e, h.p_ver)?.len();
+            }
+            let exp = &pak.export_table[(i - 1) as usize];
+            let expected = serialized_export(exp, h.p_ver)?;
+            if bytes.get(at..at + expected.len()) != Some(expected.as_slice()) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("export #{i} not found at 0x{at:X}; table layout not understood"),
+                ));
+            }
+            Located {
+                label: format!("export #{i} {}", pak.get_export_full_name(i)),
+                offset: at,
+                fields: export_layout(exp, h.p_ver),
+            }
+        }
+        Entry::Import(i) => {
+            let n = pak.import_table.len();
+            if i < 1 || i as usize > n {
+                return Err(out_of_range("import", i, n));
+            }
+            let at = h.import_offset as usize + (i - 1) as usize * IMPORT_SIZE;
+            let imp: &Import = &pak.import_table[(i - 1) as usize];
+            let name = read_field(bytes, at, &import_layout()[5]);
+            if name != Some(imp.object_name.name_index as i64) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("import #{i} not found at 0x{at:X}; table layout not understood"),
+                ));
+            }
+            Located {
+                label: format!("import #{i} {}", pak.fname_to_string(&imp.object_name)),
+                offset: at,
+                fields: import_layout(),
+            }
+        }
+    };
+    Ok(located)
+}
+
+fn read_field(bytes: &[u8], base: usize, f: &Field) -> Option<i64> {
+    let at = base + f.offset;
+    Some(match f.width {
+        Width::I32 => i32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?) as i64,
+        Width::U32 => u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?) as i64,
+        Width::U64 => u64::from_le_bytes(bytes.get(at..at + 8)?.try_into().ok()?) as i64,
+    })
+}
+
+fn encode(f: &Field, v: i64) -> Result<Vec<u8>> {
+    let range = |ok: bool| {
+        if ok {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{v} does not fit {} ({:?})", f.name, f.width),
+            ))
+        }
+    };
+    Ok(match f.width {
+        Width::I32 => {
+            range(i32::try_from(v).is_ok())?;
+            (v as i32).to_le_bytes().to_vec()
+        }
+        Width::U32 => {
+            range(u32::try_from(v).is_ok() || i32::try_from(v).is_ok())?;
+            (v as u32).to_le_bytes().to_vec()
+        }
+        Width::U64 => (v as u64).to_le_bytes().to_vec(),
+    })
+}
+
+fn parse_value(f: &Field, raw: &str, pak: &UPKPak) -> Result<i64> {
+    let raw = raw.trim();
+    let (neg, digits) = match raw.strip_prefix('-') {
+        Some(d) => (true, d),
+        None => (false, raw),
+    };
+    let parsed = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16).ok().map(|v| v as i64),
+        None => digits.parse::<i64>().ok(),
+    };
+    if let Some(v) = parsed {
+        return Ok(if neg { -v } else { v });
+    }
+    // Name fields also take the name text itself.
+    if f.target == Target::Name
+        && let Some(i) = pak.name_table.iter().position(|n| n == raw)
+    {
+        return Ok(i as i64);
+    }
+    Err(Error::new(
+        ErrorKind::InvalidInput,
+        format!("{}: can't parse value '{raw}'", f.name),
+    ))
+}
+
+fn check_target(f: &Field, v: i64, pak: &UPKPak) -> Option<String> {
+    match f.target {
+        Target::Plain => None,
+        Target::Object => {
+            let (ne, ni) = (pak.export_table.len() as i64, pak.import_table.len() as i64);
+            (v > ne || v < -ni).then(|| {
+                format!(
+                    "{} = {v} is outside the tables (exports 1..={ne}, imports -1..=-{ni})",
+                    f.name
+                )
+            })
+        }
+        Target::Name => {
+            let n = pak.name_table.len() as i64;
+            (v < 0 || v >= n)
+                .then(|| format!("{} = {v} is outside the name table (0..{n})", f.name))
+        }
+    }
+}
+
+fn describe_value(f: &Field, v: i64, pak: &UPKPak) -> String {
+    match f.target {
+        Target::Plain => match f.width {
+            Width::I32 => v.to_string(),
+            Width::U32 => format!("0x{v:08X}"),
+            Width::U64 => format!("0x{:016X}", v as u64),
+        },
+        Target::Name => match pak.name_table.get(v as usize) {
+            Some(n) if v >= 0 => format!("{v} ({n})"),
+            _ => v.to_string(),
+        },
+        Target::Object if v > 0 => format!("{v} ({})", pak.get_export_full_name(v as i32)),
+        Target::Object if v < 0 => match pak.import_table.get((-v - 1) as usize) {
+            Some(imp) => format!("{v} ({})", pak.fname_to_string(&imp.object_name)),
+            None => v.to_string(),
+        },
+        Target::Object => "0 (none)".into(),
+    }
+}
+
+fn ensure_uncompressed(path: &Path) -> Result<()> {
+    let raw = read_raw_header(path)?;
+    if raw.compression_method != CompressionMethod::None && raw.compressed_chunks_count > 0 {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "tables are inside compressed chunks; decompress the package first",
+        ));
+    }
+    Ok(())
+}
+
+fn show(upk_path: &Path, entry: Entry) -> Result<()> {
+    let lp = open_package_file(upk_path)?;
+    let loc = locate(&lp.bytes, &lp.header, &lp.pak, &entry)?;
+    println!("{} @ 0x{:X}", loc.label, loc.offset);
+    for f in &loc.fields {
+        let v = read_field(&lp.bytes, loc.offset, f).unwrap_or_default();
+        println!(
+            "  +0x{:02X}  {:<24} {}",
+            f.offset,
+            f.name,
+            describe_value(f, v, &lp.pak)
+        );
+    }
+    Ok(())
+}
+
+fn edit(
+    src: &Path,
+    entry: Entry,
+    sets: &[String],
+    force: bool,
+    out: Option<&Path>,
+    emit_delta: Option<&Path>,
+) -> Result<()> {
+    // This command's own --force shadows the global one.
+    if force {
+        sniff::set_force(true);
+    }
+    ensure_uncompressed(src)?;
+    let lp = open_package_file(src)?;
+    let loc = locate(&lp.bytes, &lp.header, &lp.pak, &entry)?;
+
+    let mut patches = Vec::new();
+    let mut problems = Vec::new();
+    for s in sets {
+        let (k, v) = s.split_once('=').ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("--set {s}: expected FIELD=VALUE"),
+            )
+        })?;
+        let key = canonical(k.trim());
+        let f = loc.fields.iter().find(|f| f.name == key).ok_or_else(|| {
+            let known: Vec<&str> = loc.fields.iter().map(|f| f.name).collect();
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "no field '{k}' in this entry (fields: {})",
+                    known.join(", ")
+                ),
+            )
+        })?;
+        let new = parse_value(f, v, &lp.pak)?;
+        let bytes = encode(f, new)?;
+        if let Some(p) = check_target(f, new, &lp.pak) {
+            problems.push(p);
+        }
+        let old = read_field(&lp.bytes, loc.offset, f).unwrap_or_default();
+        patches.push((f.clone(), old, new, bytes));
+    }
+
+    println!("{} @ 0x{:X}", loc.label, loc.offset);
+    for (f, old, new, _) in &patches {
+        println!(
+            "  {:<24} {} → {}",
+            f.name,
+            describe_value(f, *old, &lp.pak),
+            describe_value(f, *new, &lp.pak)
+        );
+    }
+    for p in &problems {
+        term::error("error", p);
+    }
+    if !problems.is_empty() && !force {
+        return Err(validation_failed(format!(
+            "{} out-of-range value(s); rerun with --force to write anyway",
+            problems.len()
+        )));
+    }
+
+    let dst = match out {
+        Some(o) => o.to_path_buf(),
+        None => {
+            if let Some(bak) = backup_original(src)? {
+                println!("Backup: {}", bak.display());
+            }
+            src.to_path_buf()
+        }
+    };
+    let mut bytes = std::fs::read(src)?;
+    for (f, _, _, b) in &patches {
+        let at = loc.offset + f.offset;
+        bytes[at..at + b.len()].copy_from_slice(b);
+    }
+    readonly::write(&dst, &bytes)?;
+    println!("Wrote {}", dst.display());
+    if let Some(d) = emit_delta {
+        delta::emit_delta(&delta::original_for(src), &dst, d)?;
+    }
+    Ok(())
+}
+
+pub fn run(cmd: TableCmd) -> Result<()> {
+    match cmd {
+        TableCmd::Show {
+            upk_path,
+            export,
+            import,
+        } => show(&upk_path, Entry::from_args(export, import)?),
+        TableCmd::Edit {
+            upk_path,
+            export,
+            import,
+            set,
+            force,
+            out,
+            emit_delta,
+        } => edit(
+            &upk_path,
+            Entry::from_args(export, import)?,
+            &set,
+            force,
+            out.as_deref(),
+            emit_delta.as_deref(),
+        ),
+    }
+}
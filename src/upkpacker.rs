@@ -1,31 +1,70 @@
-use crate::native::{NativeInjectCtx, NativeRegistry};
+use crate::native::{BulkCompression, NativeInjectCtx, NativeRegistry};
 use crate::pseudo_parse::{self, PseudoFile, PseudoValue};
 use crate::schemadb::{LazyPackage, ResolvedRef, SchemaDb, open_package_at};
 use crate::upkprops::{Property, PropertyValue, read_native_props};
 use crate::upkreader::{FName, UPKPak, get_obj_props_with_db};
 use crate::versions::VER_NETINDEX_STORED_AS_INT;
 
+use crate::utils::{decompress::CompressionMethod, readonly};
+
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use clap::ValueEnum;
 use std::collections::HashMap;
 use std::io::{Cursor, Error, ErrorKind, Result};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
+/// `--compress-bulk`: compress re-imported bulk data like the block it
+/// replaces, or with a given method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BulkArg {
+    Original,
+    Zlib,
+    Lzo,
+}
+
+/// Uncompressed when the flag is absent.
+pub fn bulk_compression(arg: Option<BulkArg>) -> BulkCompression {
+    match arg {
+        None => BulkCompression::None,
+        Some(BulkArg::Original) => BulkCompression::Original,
+        Some(BulkArg::Zlib) => BulkCompression::Method(CompressionMethod::Zlib),
+        Some(BulkArg::Lzo) => BulkCompression::Method(CompressionMethod::Lzo),
+    }
+}
+
 pub struct PackOptions<'a> {
     pub extracted_dir: &'a Path,
     pub game_root: Option<&'a Path>,
     pub out_dir: Option<&'a Path>,
     pub verbose: bool,
-}
-
-pub fn pack_mod(opts: &PackOptions) -> Result<()> {
-    let uo_files = find_uo_files(opts.extracted_dir)?;
+    pub only_files: Option<&'a [PathBuf]>,
+    pub package_paths: Option<&'a HashMap<String, PathBuf>>,
+    /// Continue an existing `<pkg>.namemap` in the output dir so overrides
+    /// from earlier runs keep their name indices.
+    pub keep_names: bool,
+    /// Storage for bulk data (texture mips) replaced from sidecars.
+    pub bulk: BulkCompression,
+}
+
+/// One override written by `pack_mod`: `<out>/<pkg_name>/<key>.bin`.
+pub struct Packed {
+    pub uo: PathBuf,
+    pub pkg_name: String,
+    pub key: String,
+}
+
+pub fn pack_mod(opts: &PackOptions) -> Result<Vec<Packed>> {
+    let uo_files = match opts.only_files {
+        Some(files) => files.to_vec(),
+        None => find_uo_files(opts.extracted_dir)?,
+    };
     if uo_files.is_empty() {
         eprintln!(
             "pack-mod: no .uo files found under {}",
             opts.extracted_dir.display()
         );
-        return Ok(());
+        return Ok(Vec::new());
     }
 
     let mut by_pkg: HashMap<String, Vec<(PathBuf, PseudoFile)>> = HashMap::new();
@@ -57,9 +96,9 @@ pub fn pack_mod(opts: &PackOptions) -> Result<()> {
         Some(dir) => dir.to_path_buf(),
         None => overrides_dir(opts.extracted_dir),
     };
-    std::fs::create_dir_all(&out_dir)?;
+    readonly::create_dir_all(&out_dir)?;
 
-    let mut written = 0usize;
+    let mut packed = Vec::new();
     let mut failed = 0usize;
     for (stem, targets) in &by_pkg {
         let lp = match load_package(stem, opts) {
@@ -82,19 +121,40 @@ pub fn pack_mod(opts: &PackOptions) -> Result<()> {
             .unwrap_or(stem.as_str())
             .to_string();
         let pkg_dir = out_dir.join(&pkg_name);
-        std::fs::create_dir_all(&pkg_dir)?;
-        let mut names = lp.pak.name_table.clone();
+        readonly::create_dir_all(&pkg_dir)?;
+        let map_path = pkg_dir.join(format!("{pkg_name}.namemap"));
+        let mut names = lp.pak.name_table.to_vec();
+        if opts.keep_names
+            && let Ok(text) = std::fs::read_to_string(&map_path)
+        {
+            let prev: Vec<String> = text.lines().map(str::to_string).collect();
+            if prev.starts_with(&names) {
+                names = prev;
+            }
+        }
 
         let mut pkg_ok = 0usize;
 
         for (src_path, uo) in targets {
-            match pack_one(&lp, db.as_ref(), uo, src_path, &pkg_dir, &mut names) {
+            match pack_one(
+                &lp,
+                db.as_ref(),
+                uo,
+                src_path,
+                &pkg_dir,
+                &mut names,
+                opts.bulk,
+            ) {
                 Ok(key) => {
-                    written += 1;
                     pkg_ok += 1;
                     if opts.verbose {
                         println!("  OK   {key}  <-  {}", src_path.display());
                     }
+                    packed.push(Packed {
+                        uo: src_path.clone(),
+                        pkg_name: pkg_name.clone(),
+                        key,
+                    });
                 }
                 Err(e) => {
                     failed += 1;
@@ -104,16 +164,16 @@ pub fn pack_mod(opts: &PackOptions) -> Result<()> {
         }
 
         if pkg_ok > 0 {
-            let map_path = pkg_dir.join(format!("{pkg_name}.namemap"));
-            std::fs::write(&map_path, names.join("\n"))?;
+            readonly::write(&map_path, names.join("\n"))?;
         }
     }
 
     println!(
-        "pack-mod: {written} override(s) written to {}  ({failed} failed, {skipped_defs} definition(s) skipped)",
+        "pack-mod: {} override(s) written to {}  ({failed} failed, {skipped_defs} definition(s) skipped)",
+        packed.len(),
         out_dir.display()
     );
-    Ok(())
+    Ok(packed)
 }
 
 fn pack_one(
@@ -123,6 +183,7 @@ fn pack_one(
     uo_path: &Path,
     pkg_dir: &Path,
     names: &mut Vec<String>,
+    bulk: BulkCompression,
 ) -> Result<String> {
     let pak = &lp.pak;
     let p_ver = lp.header.p_ver;
@@ -212,12 +273,13 @@ fn pack_one(
         owner.as_ref(),
         class_index,
         p_ver,
+        bulk,
     )?;
 
     ensure_tag_names(&props, names);
 
     let working = UPKPak {
-        name_table: names.clone(),
+        name_table: names.clone().into(),
         export_table: pak.export_table.clone(),
         import_table: pak.import_table.clone(),
     };
@@ -240,7 +302,7 @@ fn pack_one(
 
     let key = export_path_dotted(pak, export_idx);
     let bin_path = pkg_dir.join(format!("{key}.bin"));
-    std::fs::write(&bin_path, &body)?;
+    readonly::write(&bin_path, &body)?;
     Ok(key)
 }
 
@@ -254,6 +316,7 @@ fn inject_sidecars(
     owner: Option<&ResolvedRef>,
     class_index: i32,
     p_ver: i16,
+    bulk: BulkCompression,
 ) -> Result<()> {
     if uo.sidecars.is_empty() {
         return Ok(());
@@ -288,6 +351,7 @@ fn inject_sidecars(
         externalized_prop,
         ver: p_ver,
         pak,
+        bulk,
     };
     ser.inject_external(&mut ictx)?;
     Ok(())
@@ -342,6 +406,8 @@ fn overlay_value(
         (PV::String(slot), PseudoValue::Str(s)) => *slot = s.clone(),
 
         (PV::Name(slot), PseudoValue::Name(s)) => *slot = intern_fname(s, names),
+        // The .uo writes a None name as a bare `None`.
+        (PV::Name(slot), PseudoValue::Null) => *slot = intern_fname("None", names),
 
         (PV::EnumLabel(slot), PseudoValue::Enum(s)) => {
             let val = s.rsplit("::").next().unwrap_or(s);
@@ -467,7 +533,7 @@ fn ensure_name(name: &str, names: &mut Vec<String>) -> i32 {
     (names.len() - 1) as i32
 }
 
-fn ensure_tag_names(props: &[Property], names: &mut Vec<String>) {
+pub fn ensure_tag_names(props: &[Property], names: &mut Vec<String>) {
     ensure_name("None", names);
     for p in props {
         ensure_name(&p.name, names);
@@ -560,7 +626,7 @@ fn label_matches_index(pak: &UPKPak, idx: i32, label: &str) -> bool {
     false
 }
 
-fn export_path_dotted(pak: &UPKPak, export_index: i32) -> String {
+pub fn export_path_dotted(pak: &UPKPak, export_index: i32) -> String {
     let mut parts = Vec::new();
     let mut cur = export_index;
     let mut guard = 0;
@@ -632,6 +698,9 @@ fn build_db(opts: &PackOptions, lp: &LazyPackage) -> Result<Option<SchemaDb>> {
 
 fn find_package_file(stem: &str, opts: &PackOptions) -> Option<PathBuf> {
     let want = stem.to_lowercase();
+    if let Some(p) = opts.package_paths.and_then(|m| m.get(&want)) {
+        return Some(p.clone());
+    }
     let mut dirs: Vec<PathBuf> = Vec::new();
 
     if let Some(gr) = opts.game_root {
@@ -676,7 +745,7 @@ fn overrides_dir(extracted_dir: &Path) -> PathBuf {
     extracted_dir.join("overrides")
 }
 
-fn find_uo_files(root: &Path) -> Result<Vec<PathBuf>> {
+pub(crate) fn find_uo_files(root: &Path) -> Result<Vec<PathBuf>> {
     let mut out = Vec::new();
     walk(root, &mut out)?;
     out.sort();
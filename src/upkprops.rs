@@ -7,6 +7,10 @@ use crate::{
     schema::{PropertyKind, SchemaEntry},
     schemadb::{ResolvedRef, SchemaDb},
     upkreader::{FName, UPKPak, read_string, write_fstring},
+    utils::{
+        deadline,
+        lossy::{self, Code},
+    },
     versions::{
         VER_BYTEPROP_SERIALIZE_ENUM as V_BYTE_ENUM, VER_PROPERTYTAG_BOOL_OPTIMIZATION as V_BOOL_OPT,
     },
@@ -202,8 +206,7 @@ impl PropertyValue {
                     p.write(w, pak, ver)?;
                 }
 
-                let none_idx = find_name(pak, "None")?;
-                w.write_i32::<LittleEndian>(none_idx)?;
+                w.write_i32::<LittleEndian>(pak.name_table.require_none()?)?;
                 w.write_i32::<LittleEndian>(0)?;
             }
             AtomicStruct(fields) => {
@@ -218,12 +221,14 @@ impl PropertyValue {
 
 impl Property {
     pub fn write<W: Write + Seek>(&self, w: &mut W, pak: &UPKPak, ver: i16) -> Result<()> {
-        let name_idx = find_name(pak, &self.name)?;
-        w.write_i32::<LittleEndian>(name_idx)?;
-        w.write_i32::<LittleEndian>(0)?;
         if self.name == "None" {
+            w.write_i32::<LittleEndian>(pak.name_table.require_none()?)?;
+            w.write_i32::<LittleEndian>(0)?;
             return Ok(());
         }
+        let name_idx = find_name(pak, &self.name)?;
+        w.write_i32::<LittleEndian>(name_idx)?;
+        w.write_i32::<LittleEndian>(0)?;
 
         let type_idx = find_name(pak, &self.prop_type)?;
         w.write_i32::<LittleEndian>(type_idx)?;
@@ -293,6 +298,7 @@ pub fn parse_property(
 }
 
 pub fn parse_property_ctx(r: &mut Cursor<&Vec<u8>>, ctx: &PropertyCtx) -> Result<Option<Property>> {
+    deadline::check("parsing properties")?;
     let name_pos = r.position();
     let end = {
         let e = r.seek(SeekFrom::End(0))?;
@@ -307,11 +313,7 @@ pub fn parse_property_ctx(r: &mut Cursor<&Vec<u8>>, ctx: &PropertyCtx) -> Result
     if prop_fname.name_index < 0 || prop_fname.name_index as usize >= ctx.pak.name_table.len() {
         return Ok(None);
     }
-    let prop_name = match resolve_fname(&prop_fname, ctx.pak) {
-        Some(n) => n,
-        None => return Ok(None),
-    };
-    if prop_name == "None" {
+    if ctx.pak.name_table.is_none(&prop_fname) {
         return Ok(Some(Property {
             name: "None".into(),
             prop_type: "None".into(),
@@ -322,6 +324,10 @@ pub fn parse_property_ctx(r: &mut Cursor<&Vec<u8>>, ctx: &PropertyCtx) -> Result
             struct_name: None,
         }));
     }
+    let prop_name = match resolve_fname(&prop_fname, ctx.pak) {
+        Some(n) => n,
+        None => return Ok(None),
+    };
 
     let type_fname = read_fname(r)?;
     if type_fname.name_index < 0 || type_fname.name_index as usize >= ctx.pak.name_table.len() {
@@ -514,9 +520,12 @@ fn parse_array_ctx(
             if consumed_exactly {
                 return Ok(PropertyValue::Array(elems));
             }
-            eprintln!(
-                "  \x1b[33marr\x1b[0m '{prop_name}': {count} elements did not match \
-                 tag size ({size} bytes); emitted as Raw"
+            lossy::warn(
+                Code::ArrayAsRaw,
+                format_args!(
+                    "'{prop_name}': {count} elements did not match \
+                     tag size ({size} bytes); emitted as Raw"
+                ),
             );
             let mut buf = vec![0u8; (end - value_start) as usize];
             r.seek(SeekFrom::Start(value_start))?;
@@ -530,14 +539,20 @@ fn parse_array_ctx(
     r.seek(SeekFrom::Start(value_start))?;
     r.read_exact(&mut buf)?;
     if ctx.db.is_none() {
-        eprintln!(
-            "  \x1b[33marr\x1b[0m '{prop_name}': no schema (--game-root); \
-             {count} elements emitted as Raw"
+        lossy::warn(
+            Code::ArrayUntyped,
+            format_args!(
+                "'{prop_name}': no schema (--game-root); \
+                 {count} elements emitted as Raw"
+            ),
         );
     } else {
-        eprintln!(
-            "  \x1b[33marr\x1b[0m '{prop_name}': schema lookup failed; \
-             {count} elements emitted as Raw"
+        lossy::warn(
+            Code::ArrayUntyped,
+            format_args!(
+                "'{prop_name}': schema lookup failed; \
+                 {count} elements emitted as Raw"
+            ),
         );
     }
     Ok(PropertyValue::Raw(buf))
@@ -625,10 +640,13 @@ fn parse_struct_ctx(
             }
             let v = read_struct_value(r, ctx, &sref, &sentry, ctx.pak)?;
             if r.position() > end {
-                eprintln!(
-                    "  \x1b[33mstruct\x1b[0m '{prop_name}' ({struct_name}): \
-                     overran by {} bytes; realigning to tag size",
-                    r.position() - end
+                lossy::warn(
+                    Code::StructRealigned,
+                    format_args!(
+                        "'{prop_name}' ({struct_name}): \
+                         overran by {} bytes; realigning to tag size",
+                        r.position() - end
+                    ),
                 );
             }
             if r.position() != end {
@@ -993,12 +1011,15 @@ fn try_read_native_list(
 
 fn native_tail_miss(out: &[Property], consumed: u64, total: usize) -> Option<Vec<Property>> {
     if !out.is_empty() {
-        eprintln!(
-            "  \x1b[33mnative\x1b[0m schema parse read {} CPF_Native field(s) but \
-             consumed {} of {} tail bytes; emitting Raw",
-            out.len(),
-            consumed,
-            total
+        lossy::warn(
+            Code::NativeTailRaw,
+            format_args!(
+                "schema parse read {} CPF_Native field(s) but \
+                 consumed {} of {} tail bytes; emitting Raw",
+                out.len(),
+                consumed,
+                total
+            ),
         );
     }
     None
@@ -2,21 +2,31 @@ use std::{
     collections::HashMap,
     fmt,
     fs::File,
-    io::{Cursor, Error, ErrorKind, Read, Result, Seek, Write},
+    io::{Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
 };
 
 use crate::{
-    native::{NativePayload, NativeRead, NativeReadCtx, NativeRegistry},
+    hooks::{self, ExportParsed, FileKind},
+    native::{
+        AssetInfo, Convert, NativePayload, NativeRead, NativeReadCtx, NativeRegistry,
+        info::{ASSETS_FILE, ASSETS_VERSION, AssetEntry, AssetManifest, font_info},
+    },
     pseudo::EmitInput,
     schemadb::{ResolvedRef, SchemaDb},
     upkprops::{self, Property, PropertyCtx, PropertyValue, parse_property_ctx},
-    utils::decompress::{CompressedChunk, CompressionMethod},
+    utils::{
+        decompress::{CompressedChunk, CompressionMethod},
+        fsname, heuristics,
+        lossy::{self, Code},
+        readonly, sniff,
+        stats::{self, Phase},
+        term::{Color, paint},
+    },
     versions::{
-        PACKAGE_FILE_TAG, PKG_FILTER_EDITOR_ONLY, VER_ADDED_CROSSLEVEL_REFERENCES,
-        VER_ADDED_LINKER_DEPENDENCIES, VER_ADDED_PACKAGE_COMPRESSION_SUPPORT,
-        VER_ADDITIONAL_COOK_PACKAGE_SUMMARY, VER_ASSET_THUMBNAILS_IN_PACKAGES,
-        VER_FOBJECTEXPORT_EXPORTFLAGS, VER_LINKERFREE_PACKAGEMAP,
+        PKG_FILTER_EDITOR_ONLY, VER_ADDED_CROSSLEVEL_REFERENCES, VER_ADDED_LINKER_DEPENDENCIES,
+        VER_ADDED_PACKAGE_COMPRESSION_SUPPORT, VER_ADDITIONAL_COOK_PACKAGE_SUMMARY,
+        VER_ASSET_THUMBNAILS_IN_PACKAGES, VER_FOBJECTEXPORT_EXPORTFLAGS, VER_LINKERFREE_PACKAGEMAP,
         VER_MOVED_EXPORTIMPORTMAPS_ADDED_TOTALHEADERSIZE, VER_NETINDEX_STORED_AS_INT,
         VER_PACKAGEFILESUMMARY_CHANGE, VER_PACKAGEFILESUMMARY_CHANGE_COOK_VER_ADDED,
         VER_REMOVED_COMPONENT_MAP, VER_TEXTURE_PREALLOCATION,
@@ -83,7 +93,7 @@ impl PackageFlags {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NameEntry {
     pub name: String,
     pub flags: u64,
@@ -153,7 +163,7 @@ fn resolve_value(val: &mut PropertyValue, pkg: &UPKPak) {
 }
 
 impl Export {
-    pub fn read(cursor: &mut Cursor<&Vec<u8>>, ver: i16) -> Result<Self> {
+    pub fn read<T: AsRef<[u8]>>(cursor: &mut Cursor<T>, ver: i16) -> Result<Self> {
         let class_index = cursor.read_i32::<LittleEndian>()?;
         let super_index = cursor.read_i32::<LittleEndian>()?;
         let outer_index = cursor.read_i32::<LittleEndian>()?;
@@ -233,6 +243,48 @@ impl Export {
             package_flags,
         })
     }
+
+    pub fn write<W: Write>(&self, w: &mut W, ver: i16) -> Result<()> {
+        w.write_i32::<LittleEndian>(self.class_index)?;
+        w.write_i32::<LittleEndian>(self.super_index)?;
+        w.write_i32::<LittleEndian>(self.outer_index)?;
+        w.write_i32::<LittleEndian>(self.object_name.name_index)?;
+        w.write_i32::<LittleEndian>(self.object_name.name_instance)?;
+        w.write_i32::<LittleEndian>(self.archetype)?;
+        w.write_u64::<LittleEndian>(self.object_flags)?;
+        w.write_i32::<LittleEndian>(self.serial_size)?;
+        if self.serial_size != 0 || ver >= VER_MOVED_EXPORTIMPORTMAPS_ADDED_TOTALHEADERSIZE {
+            w.write_i32::<LittleEndian>(self.serial_offset)?;
+        }
+
+        if ver < VER_REMOVED_COMPONENT_MAP {
+            w.write_i32::<LittleEndian>(self.legacy_component_map.len() as i32)?;
+            for (k, v) in &self.legacy_component_map {
+                w.write_i32::<LittleEndian>(k.name_index)?;
+                w.write_i32::<LittleEndian>(k.name_instance)?;
+                w.write_i32::<LittleEndian>(*v)?;
+            }
+        }
+
+        if ver >= VER_FOBJECTEXPORT_EXPORTFLAGS {
+            w.write_u32::<LittleEndian>(self.export_flags)?;
+        }
+
+        if ver >= VER_LINKERFREE_PACKAGEMAP {
+            w.write_i32::<LittleEndian>(self.generation_net_object_count.len() as i32)?;
+            for n in &self.generation_net_object_count {
+                w.write_i32::<LittleEndian>(*n)?;
+            }
+            for g in &self.package_guid {
+                w.write_i32::<LittleEndian>(*g)?;
+            }
+        }
+
+        if ver >= VER_REMOVED_COMPONENT_MAP {
+            w.write_u32::<LittleEndian>(self.package_flags)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -244,7 +296,7 @@ pub struct Import {
 }
 
 impl Import {
-    pub fn read(cursor: &mut Cursor<&Vec<u8>>) -> Result<Self> {
+    pub fn read<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> Result<Self> {
         Ok(Self {
             class_package: FName {
                 name_index: cursor.read_i32::<LittleEndian>()?,
@@ -261,11 +313,22 @@ impl Import {
             },
         })
     }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        for n in [&self.class_package, &self.class_name] {
+            w.write_i32::<LittleEndian>(n.name_index)?;
+            w.write_i32::<LittleEndian>(n.name_instance)?;
+        }
+        w.write_i32::<LittleEndian>(self.outer_index)?;
+        w.write_i32::<LittleEndian>(self.object_name.name_index)?;
+        w.write_i32::<LittleEndian>(self.object_name.name_instance)?;
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct GenerationInfo {
-    export_count: i32,
+    pub(crate) export_count: i32,
     name_count: i32,
     net_obj_count: i32,
 }
@@ -293,12 +356,11 @@ impl FTextureType {
         let format = r.read_u32::<LittleEndian>()?;
         let tex_create_flags = r.read_u32::<LittleEndian>()?;
         let n = r.read_i32::<LittleEndian>()?;
-        if n < 0 || n > 0x10_0000 {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("FTextureType: implausible ExportIndices count {}", n),
-            ));
-        }
+        heuristics::check(
+            "max-count",
+            n as i64,
+            format_args!("FTextureType: implausible ExportIndices count {n}"),
+        )?;
         let mut export_indices = Vec::with_capacity(n as usize);
         for _ in 0..n {
             export_indices.push(r.read_i32::<LittleEndian>()?);
@@ -330,12 +392,11 @@ impl FTextureType {
 impl FTextureAllocations {
     fn read<R: Read>(r: &mut R) -> Result<Self> {
         let n = r.read_i32::<LittleEndian>()?;
-        if n < 0 || n > 0x10_0000 {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("FTextureAllocations: implausible TextureTypes count {}", n),
-            ));
-        }
+        heuristics::check(
+            "max-count",
+            n as i64,
+            format_args!("FTextureAllocations: implausible TextureTypes count {n}"),
+        )?;
         let mut texture_types = Vec::with_capacity(n as usize);
         for _ in 0..n {
             texture_types.push(FTextureType::read(r)?);
@@ -408,15 +469,107 @@ pub struct UpkHeader {
     pub texture_allocs: FTextureAllocations,
 }
 
+/// Package names, with the index of `None` looked up once. `None` ends tag
+/// lists, struct values and label tables; packages don't agree on where it
+/// sits, so nothing should assume index 0.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<String>", into = "Vec<String>")]
+pub struct NameTable {
+    names: Vec<String>,
+    none: Option<i32>,
+}
+
+impl NameTable {
+    pub fn none_index(&self) -> Option<i32> {
+        self.none
+    }
+
+    /// `none_index`, for writers that can't do without it.
+    pub fn require_none(&self) -> Result<i32> {
+        self.none.ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                "name table has no 'None' to terminate properties with",
+            )
+        })
+    }
+
+    pub fn is_none(&self, f: &FName) -> bool {
+        f.name_instance == 0 && Some(f.name_index) == self.none
+    }
+
+    pub fn push(&mut self, name: String) {
+        if self.none.is_none() && name == "None" {
+            self.none = Some(self.names.len() as i32);
+        }
+        self.names.push(name);
+    }
+}
+
+impl std::ops::Deref for NameTable {
+    type Target = [String];
+
+    fn deref(&self) -> &[String] {
+        &self.names
+    }
+}
+
+impl From<Vec<String>> for NameTable {
+    fn from(names: Vec<String>) -> Self {
+        let none = names.iter().position(|n| n == "None").map(|i| i as i32);
+        Self { names, none }
+    }
+}
+
+impl From<NameTable> for Vec<String> {
+    fn from(t: NameTable) -> Self {
+        t.names
+    }
+}
+
+impl FromIterator<String> for NameTable {
+    fn from_iter<I: IntoIterator<Item = String>>(iter: I) -> Self {
+        Vec::from_iter(iter).into()
+    }
+}
+
+/// The Core classes whose exports carry bytecode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptKind {
+    Function,
+    State,
+    Class,
+}
+
+impl ScriptKind {
+    fn from_class(name: &str) -> Option<Self> {
+        match name {
+            "Function" => Some(Self::Function),
+            "State" => Some(Self::State),
+            "Class" => Some(Self::Class),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Function => "Function",
+            Self::State => "State",
+            Self::Class => "Class",
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UPKPak {
-    pub name_table: Vec<String>,
+    pub name_table: NameTable,
     pub export_table: Vec<Export>,
     pub import_table: Vec<Import>,
 }
 
 impl UPKPak {
-    pub fn parse_upk(cursor: &mut Cursor<&Vec<u8>>, header: &UpkHeader) -> Result<Self> {
+    pub fn parse_upk<T: AsRef<[u8]>>(cursor: &mut Cursor<T>, header: &UpkHeader) -> Result<Self> {
+        let _t = stats::time(Phase::Tables);
         let name_count = header.name_count;
         let name_offset = header.name_offset;
         let export_count = header.export_count;
@@ -424,7 +577,7 @@ impl UPKPak {
         let import_count = header.import_count;
         let import_offset = header.import_offset;
 
-        let mut name_table = Vec::new();
+        let mut name_table = NameTable::default();
         cursor.set_position(name_offset as u64);
         for _ in 0..name_count {
             let name = read_name(cursor)?;
@@ -603,6 +756,45 @@ impl UPKPak {
         }
     }
 
+    /// Which script object export `export_index` is, by resolving its class:
+    /// an import of `Core.Function` / `Core.State` / `Core.Class`, a
+    /// top-level class of that name defined in this package (Core itself),
+    /// or no class at all, which is `Class`. The class name alone isn't
+    /// enough; a game package is free to define its own `State`.
+    pub fn script_kind(&self, export_index: i32) -> Option<ScriptKind> {
+        let export = self.export_table.get((export_index - 1) as usize)?;
+        let class_index = export.class_index;
+        if class_index == 0 {
+            return Some(ScriptKind::Class);
+        }
+        if class_index > 0 {
+            let class = self.export_table.get((class_index - 1) as usize)?;
+            if class.class_index != 0 || class.outer_index != 0 {
+                return None;
+            }
+            return ScriptKind::from_class(&self.fname_to_string(&class.object_name));
+        }
+        let class = self.import_table.get((-class_index - 1) as usize)?;
+        if self.fname_to_string(&class.class_name) != "Class" || class.outer_index >= 0 {
+            return None;
+        }
+        let package = self.import_table.get((-class.outer_index - 1) as usize)?;
+        if package.outer_index != 0
+            || !self
+                .fname_to_string(&package.object_name)
+                .eq_ignore_ascii_case("Core")
+        {
+            return None;
+        }
+        ScriptKind::from_class(&self.fname_to_string(&class.object_name))
+    }
+
+    /// Exports that carry bytecode, in table order: functions, and the
+    /// states and classes whose bodies are script as well.
+    pub fn functions(&self) -> impl Iterator<Item = (i32, ScriptKind)> + '_ {
+        (1..=self.export_table.len() as i32).filter_map(|i| self.script_kind(i).map(|k| (i, k)))
+    }
+
     fn is_package_outer(&self, outer_index: i32) -> bool {
         if outer_index == 0 {
             return true;
@@ -615,28 +807,34 @@ impl UPKPak {
         }
     }
 
-    fn ue_name_to_path(full_name: &str) -> String {
+    /// Relative file path for an export's full name, each component made
+    /// safe for the filesystem (`fsname::component`).
+    pub fn ue_name_to_path(full_name: &str) -> String {
         let parts: Vec<&str> = full_name.splitn(2, ' ').collect();
 
         if parts.len() != 2 {
-            return full_name.replace(&[':', '.'][..], "/");
+            return full_name
+                .split(&[':', '.'][..])
+                .map(fsname::component)
+                .collect::<Vec<_>>()
+                .join("/");
         }
 
         let class_name = parts[0];
         let path_name = parts[1];
         let mut path_parts: Vec<String> = path_name
             .split(&['.', ':'][..])
-            .map(|s| s.to_string())
+            .map(fsname::component)
             .collect();
 
         if let Some(last) = path_parts.last_mut() {
-            *last = format!("{}.{}", last, class_name);
+            *last = format!("{}.{}", last, fsname::component(class_name));
         }
 
         path_parts.join("/")
     }
 
-    fn ue_name_to_path_class_first(full_name: &str) -> String {
+    pub fn ue_name_to_path_class_first(full_name: &str) -> String {
         let parts: Vec<&str> = full_name.splitn(2, ' ').collect();
 
         if parts.len() != 2 {
@@ -799,11 +997,12 @@ pub fn write_extracted_file(
     export_index: i32,
     export_full_path: &str,
     registry: &NativeRegistry,
-) -> Result<PathBuf> {
+) -> Result<(PathBuf, Option<AssetInfo>)> {
     let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("obj");
     let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("bin");
+    let _t = stats::time(Phase::Output);
     let dir = path.parent().unwrap();
-    std::fs::create_dir_all(dir)?;
+    readonly::create_dir_all(dir)?;
 
     if ext == "Class" {
         if let (Some(db), Some(self_ref)) = (db, self_ref.as_ref()) {
@@ -850,8 +1049,9 @@ pub fn write_extracted_file(
                 &cdo_props,
             ) {
                 let uo_path = dir.join(format!("{name}.uo"));
-                std::fs::write(&uo_path, text.as_bytes())?;
-                return Ok(uo_path);
+                readonly::write(&uo_path, text.as_bytes())?;
+                hooks::file_written(&uo_path, export_full_path, FileKind::Object);
+                return Ok((uo_path, None));
             }
         }
     }
@@ -870,8 +1070,9 @@ pub fn write_extracted_file(
                 export_full_path,
             ) {
                 let uo_path = dir.join(format!("{name}.uo"));
-                std::fs::write(&uo_path, text.as_bytes())?;
-                return Ok(uo_path);
+                readonly::write(&uo_path, text.as_bytes())?;
+                hooks::file_written(&uo_path, export_full_path, FileKind::Object);
+                return Ok((uo_path, None));
             }
         }
     }
@@ -920,10 +1121,21 @@ pub fn write_extracted_file(
         }
     };
 
+    hooks::export_parsed(&ExportParsed {
+        index: export_index,
+        object: export_full_path,
+        class: ext,
+        props: &props,
+        payload: &read.payload,
+    });
+
     let sidecars = match &ser {
-        Some(s) => s.emit_external(&read.payload, dir, name)?,
+        Some(s) => registry.emit(s.as_ref(), &read.payload, dir, name)?,
         None => Vec::new(),
     };
+    for s in &sidecars {
+        hooks::file_written(&s.path, export_full_path, FileKind::Sidecar(s.role));
+    }
 
     let uo_path = dir.join(format!("{name}.uo"));
     crate::pseudo::write_uo_file(
@@ -943,36 +1155,86 @@ pub fn write_extracted_file(
             p_ver,
         },
     )?;
+    hooks::file_written(&uo_path, export_full_path, FileKind::Object);
 
-    Ok(uo_path)
+    let info = match &ser {
+        Some(s) => s.info(&read.payload),
+        None if ext == "Font" => Some(AssetInfo::Font(font_info(&props, pkg))),
+        None => None,
+    };
+    Ok((uo_path, info))
+}
+
+/// Which object each file with a changed name holds, kept next to the
+/// extracted files; earlier entries are kept.
+pub const RENAMED_FILE: &str = "object-names.json";
+
+fn record_renamed(out_dir: &Path, renamed: Vec<(String, String)>) -> Result<()> {
+    let path = out_dir.join(RENAMED_FILE);
+    let mut map: std::collections::BTreeMap<String, String> = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|t| serde_json::from_str(&t).ok())
+        .unwrap_or_default();
+    map.extend(renamed);
+    let text = serde_json::to_string_pretty(&map).map_err(Error::other)?;
+    readonly::write(&path, text)?;
+    hooks::file_written(&path, "", FileKind::Manifest);
+    Ok(())
+}
+
+/// Adds `assets` to the output's `assets.json`; entries from earlier runs
+/// stay unless the same file was written again.
+fn record_assets(out_dir: &Path, assets: Vec<(String, AssetEntry)>) -> Result<()> {
+    let path = out_dir.join(ASSETS_FILE);
+    let mut manifest: AssetManifest = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|t| serde_json::from_str(&t).ok())
+        .filter(|m: &AssetManifest| m.version == ASSETS_VERSION)
+        .unwrap_or_default();
+    manifest.assets.extend(assets);
+    let text = serde_json::to_string_pretty(&manifest).map_err(Error::other)?;
+    readonly::write(&path, text)?;
+    hooks::file_written(&path, "", FileKind::Manifest);
+    Ok(())
 }
 
-pub fn extract_by_name(
-    cursor: &mut Cursor<Vec<u8>>,
+/// Extracts every export whose name or path contains one of `paths`, or
+/// all of them when `paths` is empty. An export matched by several paths
+/// is written once; paths that match nothing are a `NotFound` error after
+/// the rest were extracted. `convert` adds sidecars (previews, info)
+/// next to the native one every payload gets.
+pub fn extract_by_name<T: AsRef<[u8]>>(
+    cursor: &mut Cursor<T>,
     pkg: &UPKPak,
-    path: &str,
+    paths: &[&str],
     out_dir: &Path,
-    all: bool,
     ver: i16,
     db: Option<&SchemaDb>,
     pkg_stem_lc: &str,
+    convert: &[Convert],
 ) -> Result<()> {
-    let registry = NativeRegistry::standard();
-    let mut found = false;
+    let registry = NativeRegistry::standard().with_convert(convert);
+    let mut matched = vec![false; paths.len()];
+    // Files whose path doesn't spell out the object's name.
+    let mut renamed: Vec<(String, String)> = Vec::new();
+    let mut assets: Vec<(String, AssetEntry)> = Vec::new();
 
     for (idx, exp) in pkg.export_table.iter().enumerate() {
         let export_idx_1 = (idx + 1) as i32;
         let full_name = pkg.get_export_full_name(export_idx_1);
         let fs_path = UPKPak::ue_name_to_path(&full_name);
 
-        if !(fs_path.contains(path) || full_name.contains(path) || all) {
-            continue;
+        let mut hit = paths.is_empty();
+        for (p, m) in paths.iter().zip(matched.iter_mut()) {
+            if fs_path.contains(p) || full_name.contains(p) {
+                *m = true;
+                hit = true;
+            }
         }
-
-        let file_path = out_dir.join(&fs_path);
-        if let Some(parent) = file_path.parent() {
-            std::fs::create_dir_all(parent)?;
+        if !hit {
+            continue;
         }
+        let _at = lossy::at(full_name.clone());
 
         cursor.seek(std::io::SeekFrom::Start(exp.serial_offset as u64))?;
         let mut buffer = vec![0u8; exp.serial_size as usize];
@@ -998,55 +1260,129 @@ pub fn extract_by_name(
             export_idx: export_idx_1,
         });
 
-        let out_path = write_extracted_file(
-            &file_path,
-            &buffer,
-            pkg,
-            pkg_stem_lc,
-            ver,
-            db,
-            class_ref,
-            self_ref,
-            export_idx_1,
-            &full_name,
-            &registry,
-        )?;
+        // Deep trees can pass Windows' MAX_PATH; std switches such paths to
+        // the `\\?\` form itself, so they need nothing special here.
+        let write = |rel: &str| {
+            let file_path = out_dir.join(rel);
+            if let Some(parent) = file_path.parent() {
+                readonly::create_dir_all(parent)?;
+            }
+            write_extracted_file(
+                &file_path,
+                &buffer,
+                pkg,
+                pkg_stem_lc,
+                ver,
+                db,
+                class_ref.clone(),
+                self_ref.clone(),
+                export_idx_1,
+                &full_name,
+                &registry,
+            )
+        };
+        let (out_path, info) = match write(&fs_path) {
+            Err(e) if fsname::rejected(&e) && !fs_path.is_ascii() => {
+                let ascii = fsname::ascii(&fs_path);
+                lossy::warn(
+                    Code::AsciiFilename,
+                    format_args!("{e}; writing {full_name} as {ascii}"),
+                );
+                write(&ascii)?
+            }
+            r => r?,
+        };
+        if let Some((class, path)) = full_name.split_once(' ')
+            && fs_path != format!("{}.{class}", path.replace(['.', ':'], "/"))
+            && let Ok(rel) = out_path.strip_prefix(out_dir)
+        {
+            renamed.push((rel.to_string_lossy().replace('\\', "/"), full_name.clone()));
+        }
+        if let Some(info) = info
+            && let Ok(rel) = out_path.strip_prefix(out_dir)
+        {
+            assets.push((
+                rel.to_string_lossy().replace('\\', "/"),
+                AssetEntry {
+                    object: full_name.clone(),
+                    info,
+                },
+            ));
+        }
 
         println!(
-            "Exported \x1b[93m{}\x1b[0m (\x1b[33m{}\x1b[0m bytes) → \x1b[32m{}\x1b[0m",
-            full_name,
-            buffer.len(),
-            out_path.display()
+            "Exported {} ({} bytes) → {}",
+            paint(Color::Highlight, full_name),
+            paint(Color::Yellow, buffer.len()),
+            paint(Color::Green, out_path.display())
         );
-        found = true;
     }
-    if !found {
-        println!("File {path} not exists in package.");
+    if !renamed.is_empty() {
+        record_renamed(out_dir, renamed)?;
+    }
+    if !assets.is_empty() {
+        record_assets(out_dir, assets)?;
+    }
+    let missing: Vec<&str> = paths
+        .iter()
+        .zip(&matched)
+        .filter(|(_, m)| !**m)
+        .map(|(p, _)| *p)
+        .collect();
+    if !missing.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("no export matching '{}' in package", missing.join("', '")),
+        ));
     }
     Ok(())
 }
 
-pub fn read_name(cursor: &mut Cursor<&Vec<u8>>) -> Result<NameEntry> {
+pub fn write_name<W: Write>(w: &mut W, entry: &NameEntry) -> Result<()> {
+    write_fstring(w, &entry.name)?;
+    w.write_u64::<LittleEndian>(entry.flags)?;
+    Ok(())
+}
+
+/// ISO-8859-1 to `String`. Nearly all names and strings are plain ASCII,
+/// which is checked a word at a time and reused without re-encoding.
+pub fn latin1_to_string(bytes: Vec<u8>) -> String {
+    if bytes.is_ascii() {
+        return String::from_utf8(bytes).expect("ASCII is valid UTF-8");
+    }
+    let mut s = String::with_capacity(bytes.len() + bytes.len() / 2);
+    s.extend(bytes.iter().map(|&b| b as char));
+    s
+}
+
+/// Little-endian UTF-16 to `String`, without an intermediate `u16` buffer.
+/// `None` on unpaired surrogates.
+pub fn utf16le_to_string(bytes: &[u8]) -> Option<String> {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]));
+    let mut s = String::with_capacity(bytes.len() / 2);
+    for c in char::decode_utf16(units) {
+        s.push(c.ok()?);
+    }
+    Some(s)
+}
+
+pub fn read_name<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> Result<NameEntry> {
     let length = cursor.read_i32::<LittleEndian>()?;
 
     let name = if length < 0 {
-        let abs_length = (-length) as usize;
-        let mut u16_chars = vec![0u16; abs_length];
-        for i in 0..abs_length {
-            u16_chars[i] = cursor.read_u16::<LittleEndian>()?;
-        }
-        String::from_utf16(&u16_chars[..abs_length.saturating_sub(1)])
-            .unwrap_or_else(|_| String::from("<invalid_utf16>"))
+        let abs_length = length.unsigned_abs() as usize;
+        let mut bytes = vec![0u8; abs_length * 2];
+        cursor.read_exact(&mut bytes)?;
+        utf16le_to_string(&bytes[..abs_length.saturating_sub(1) * 2])
+            .unwrap_or_else(|| String::from("<invalid_utf16>"))
     } else {
         let length = length as usize;
         let mut bytes = vec![0u8; length];
         cursor.read_exact(&mut bytes)?;
-        let name: String = bytes[..length.saturating_sub(1)]
-            .iter()
-            .map(|&b| b as char)
-            .collect();
-
-        name
+        bytes.truncate(length.saturating_sub(1));
+        latin1_to_string(bytes)
     };
 
     let flags = cursor.read_u64::<LittleEndian>()?;
@@ -1084,24 +1420,17 @@ pub fn read_string(cursor: &mut Cursor<&Vec<u8>>) -> Result<String> {
             buf.pop();
         }
 
-        Ok(buf.iter().map(|&b| b as char).collect::<String>()) // not utf8 but ISO-8859-1
+        Ok(latin1_to_string(buf)) // not utf8 but ISO-8859-1
     } else {
         let wchar_count = -len;
         let mut buf = vec![0u8; (wchar_count * 2) as usize];
         cursor.read_exact(&mut buf)?;
 
-        let utf16: Vec<u16> = buf
-            .chunks_exact(2)
-            .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
-            .collect();
-
-        let utf16_trimmed = match utf16.last() {
-            Some(&0) => &utf16[..utf16.len() - 1],
-            _ => &utf16[..],
-        };
+        if buf.ends_with(&[0, 0]) {
+            buf.truncate(buf.len() - 2);
+        }
 
-        String::from_utf16(utf16_trimmed)
-            .map_err(|_| Error::new(ErrorKind::InvalidData, "Invalid UTF16"))
+        utf16le_to_string(&buf).ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid UTF16"))
     }
 }
 
@@ -1111,34 +1440,30 @@ pub fn read_fstring_stream<R: Read>(r: &mut R) -> Result<String> {
         return Ok(String::new());
     }
     if len > 0 {
-        if len > 0x10_0000 {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("FString: implausible ANSI length {}", len),
-            ));
-        }
+        heuristics::check(
+            "max-count",
+            len as i64,
+            format_args!("FString: implausible ANSI length {len}"),
+        )?;
         let mut buf = vec![0u8; len as usize];
         r.read_exact(&mut buf)?;
         if buf.last() == Some(&0) {
             buf.pop();
         }
-        Ok(buf.iter().map(|&b| b as char).collect())
+        Ok(latin1_to_string(buf))
     } else {
         let n = (-len) as usize;
-        if n > 0x10_0000 {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("FString: implausible UTF-16 length {}", n),
-            ));
-        }
-        let mut u = vec![0u16; n];
-        for slot in &mut u {
-            *slot = r.read_u16::<LittleEndian>()?;
-        }
-        if u.last() == Some(&0) {
-            u.pop();
+        heuristics::check(
+            "max-count",
+            n as i64,
+            format_args!("FString: implausible UTF-16 length {n}"),
+        )?;
+        let mut buf = vec![0u8; n * 2];
+        r.read_exact(&mut buf)?;
+        if buf.ends_with(&[0, 0]) {
+            buf.truncate(buf.len() - 2);
         }
-        String::from_utf16(&u).map_err(|_| Error::new(ErrorKind::InvalidData, "bad UTF-16"))
+        utf16le_to_string(&buf).ok_or_else(|| Error::new(ErrorKind::InvalidData, "bad UTF-16"))
     }
 }
 
@@ -1148,6 +1473,7 @@ pub fn get_obj_props(
     print_out: bool,
     ver: i16,
 ) -> Result<(Vec<Property>, u64)> {
+    let _t = stats::time(Phase::Properties);
     let mut props = Vec::new();
     let mut last_pos = cursor.position();
 
@@ -1157,6 +1483,7 @@ pub fn get_obj_props(
         let result = upkprops::parse_property(cursor, upk, ver);
 
         match result {
+            Err(e) if e.kind() == ErrorKind::TimedOut => return Err(e),
             Err(_) => {
                 cursor.set_position(last_pos);
                 break;
@@ -1190,6 +1517,7 @@ pub fn get_obj_props_with_db(
     db: Option<&SchemaDb>,
     owner: Option<ResolvedRef>,
 ) -> Result<(Vec<Property>, u64)> {
+    let _t = stats::time(Phase::Properties);
     let ctx = PropertyCtx {
         pak: upk,
         ver,
@@ -1202,6 +1530,7 @@ pub fn get_obj_props_with_db(
         let before = cursor.position();
         let result = parse_property_ctx(cursor, &ctx);
         match result {
+            Err(e) if e.kind() == ErrorKind::TimedOut => return Err(e),
             Err(_) => {
                 cursor.set_position(last_pos);
                 break;
@@ -1338,13 +1667,13 @@ impl fmt::Display for UpkHeader {
 
 impl UpkHeader {
     pub fn read<R: Read + Seek>(mut reader: R) -> Result<Self> {
+        let start = reader.stream_position()?;
+        let mut head = Vec::with_capacity(1024);
+        reader.by_ref().take(1024).read_to_end(&mut head)?;
+        sniff::check(&head)?;
+        reader.seek(SeekFrom::Start(start))?;
+
         let sign = reader.read_u32::<LittleEndian>()?;
-        if sign != PACKAGE_FILE_TAG {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("Invalid file signature, sig=0x{:X}", sign),
-            ));
-        }
 
         let p_ver = reader.read_i16::<LittleEndian>()?;
         let l_ver = reader.read_i16::<LittleEndian>()?;
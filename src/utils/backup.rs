@@ -0,0 +1,42 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+use crate::utils::{modarchive, readonly};
+
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+/// Copies `path` to `<path>.bak` unless a backup already exists, so the
+/// `.bak` keeps the original file across repeated in-place edits.
+pub fn backup_original(path: &Path) -> Result<Option<PathBuf>> {
+    readonly::check(path)?;
+    modarchive::ensure_writable(path)?;
+    let bak = backup_path(path);
+    if bak.exists() {
+        return Ok(None);
+    }
+    readonly::copy(path, &bak)?;
+    Ok(Some(bak))
+}
+
+/// The untouched copy of an in-place edited `path`, for comparing against
+/// when only the modified file is given.
+pub fn original_of(path: &Path) -> Result<PathBuf> {
+    let bak = backup_path(path);
+    if bak.exists() {
+        return Ok(bak);
+    }
+    Err(Error::new(
+        ErrorKind::NotFound,
+        format!(
+            "no {} to compare {} against; name the original explicitly",
+            bak.display(),
+            path.display()
+        ),
+    ))
+}
@@ -0,0 +1,175 @@
+use std::io::{Cursor, Error, ErrorKind, Result, Write};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use flate2::{Compression, write::ZlibEncoder};
+
+use crate::{
+    upkreader::{PackageFlags, UpkHeader},
+    utils::{
+        decompress::{CHUNK_SIZE, CompressedChunk, CompressionMethod},
+        pool::par_map,
+    },
+    versions::PACKAGE_FILE_TAG,
+};
+
+/// Uncompressed bytes per chunk-table entry; each entry is stored as
+/// `CHUNK_SIZE` blocks.
+pub const PACKAGE_CHUNK_SIZE: usize = 1 << 20;
+
+/// Size / speed tradeoff. `zlib_level` is 1..=9 (6 when unset); `fast`
+/// picks the quickest setting of either method.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tuning {
+    pub zlib_level: Option<u32>,
+    pub fast: bool,
+}
+
+impl Tuning {
+    fn lzo(&self) -> lzo1x::CompressLevel {
+        if self.fast {
+            lzo1x::CompressLevel::MIN
+        } else {
+            lzo1x::CompressLevel::default()
+        }
+    }
+
+    fn zlib(&self) -> Compression {
+        match (self.zlib_level, self.fast) {
+            (Some(l), _) => Compression::new(l.clamp(1, 9)),
+            (None, true) => Compression::fast(),
+            (None, false) => Compression::default(),
+        }
+    }
+}
+
+pub fn compress_block(data: &[u8], mode: CompressionMethod, tuning: Tuning) -> Result<Vec<u8>> {
+    match mode {
+        CompressionMethod::Lzo => Ok(lzo1x::compress(data, tuning.lzo())),
+        CompressionMethod::Zlib => {
+            let mut e = ZlibEncoder::new(Vec::new(), tuning.zlib());
+            e.write_all(data)?;
+            e.finish()
+        }
+        other => Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("{other:?} compression is not supported"),
+        )),
+    }
+}
+
+/// Compresses `blocks` on `jobs` threads. Each block is compressed on its
+/// own and results come back in input order, so the output doesn't depend
+/// on the thread count or scheduling.
+pub fn compress_blocks(
+    blocks: &[&[u8]],
+    mode: CompressionMethod,
+    tuning: Tuning,
+    jobs: usize,
+) -> Result<Vec<Vec<u8>>> {
+    par_map(blocks, jobs, |block| compress_block(block, mode, tuning))
+        .into_iter()
+        .collect()
+}
+
+/// One chunk in the layout `upk_decompress` reads: tag, block size, packed
+/// and unpacked totals, a size pair per block, then the blocks.
+pub fn write_chunk<W: Write>(w: &mut W, raw: &[&[u8]], packed: &[Vec<u8>]) -> Result<()> {
+    let packed_total: usize = packed.iter().map(Vec::len).sum();
+    let raw_total: usize = raw.iter().map(|b| b.len()).sum();
+    w.write_u32::<LittleEndian>(PACKAGE_FILE_TAG)?;
+    w.write_u32::<LittleEndian>(CHUNK_SIZE)?;
+    w.write_u32::<LittleEndian>(packed_total as u32)?;
+    w.write_u32::<LittleEndian>(raw_total as u32)?;
+    for (r, p) in raw.iter().zip(packed) {
+        w.write_u32::<LittleEndian>(p.len() as u32)?;
+        w.write_u32::<LittleEndian>(r.len() as u32)?;
+    }
+    for p in packed {
+        w.write_all(p)?;
+    }
+    Ok(())
+}
+
+fn chunk_stream_len(packed: &[Vec<u8>]) -> usize {
+    16 + packed.len() * 8 + packed.iter().map(Vec::len).sum::<usize>()
+}
+
+/// Writes `image` (an uncompressed package, e.g. `PackageImage::bytes`) as
+/// a StoreCompressed package: the summary stays plain and everything from
+/// the name table on goes into chunks. Returns the summary written.
+pub fn compress_package<W: Write>(
+    image: &[u8],
+    header: &UpkHeader,
+    mode: CompressionMethod,
+    tuning: Tuning,
+    jobs: usize,
+    w: &mut W,
+) -> Result<UpkHeader> {
+    if header.compressed_chunks_count > 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "package is already compressed; decompress it first",
+        ));
+    }
+    let start = header.name_offset as usize;
+    if start == 0 || start > image.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("name table offset 0x{start:X} is outside the package"),
+        ));
+    }
+
+    let chunks: Vec<(usize, &[u8])> = image[start..]
+        .chunks(PACKAGE_CHUNK_SIZE)
+        .enumerate()
+        .map(|(i, c)| (start + i * PACKAGE_CHUNK_SIZE, c))
+        .collect();
+    let blocks: Vec<&[u8]> = chunks
+        .iter()
+        .flat_map(|(_, c)| c.chunks(CHUNK_SIZE as usize))
+        .collect();
+    let mut packed = compress_blocks(&blocks, mode, tuning, jobs)?.into_iter();
+
+    let mut out = header.clone();
+    out.compression_method = mode;
+    out.pak_flags |= PackageFlags::StoreCompressed.bits();
+    out.compressed_chunks_count = chunks.len() as u32;
+    out.compressed_chunks = vec![
+        CompressedChunk {
+            decompressed_offset: 0,
+            decompressed_size: 0,
+            compressed_offset: 0,
+            compressed_size: 0,
+        };
+        chunks.len()
+    ];
+    // Offsets don't change the summary's size, so measure it first.
+    let mut offset = {
+        let mut probe = Vec::new();
+        out.write(Cursor::new(&mut probe))?;
+        probe.len()
+    };
+
+    let mut streams = Vec::with_capacity(chunks.len());
+    for (i, (at, data)) in chunks.iter().enumerate() {
+        let raw: Vec<&[u8]> = data.chunks(CHUNK_SIZE as usize).collect();
+        let p: Vec<Vec<u8>> = packed.by_ref().take(raw.len()).collect();
+        let len = chunk_stream_len(&p);
+        out.compressed_chunks[i] = CompressedChunk {
+            decompressed_offset: *at as u32,
+            decompressed_size: data.len() as u32,
+            compressed_offset: offset as u32,
+            compressed_size: len as u32,
+        };
+        offset += len;
+        streams.push((raw, p));
+    }
+
+    let mut summary = Vec::new();
+    out.write(Cursor::new(&mut summary))?;
+    w.write_all(&summary)?;
+    for (raw, p) in &streams {
+        write_chunk(w, raw, p)?;
+    }
+    Ok(out)
+}
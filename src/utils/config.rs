@@ -0,0 +1,25 @@
+use std::{
+    env,
+    io::{Error, ErrorKind, Result},
+    path::PathBuf,
+};
+
+/// `$XDG_CONFIG_HOME/ue3-tools`, `%APPDATA%\ue3-tools` or
+/// `~/.config/ue3-tools`; `UE3_TOOLS_CONFIG` overrides all of them.
+pub fn config_dir() -> Result<PathBuf> {
+    let var = |k: &str| env::var_os(k).filter(|v| !v.is_empty()).map(PathBuf::from);
+    if let Some(d) = var("UE3_TOOLS_CONFIG") {
+        return Ok(d);
+    }
+    let base = if cfg!(windows) {
+        var("APPDATA")
+    } else {
+        var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|h| h.join(".config")))
+    };
+    base.map(|b| b.join("ue3-tools")).ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            "no config directory (set UE3_TOOLS_CONFIG)",
+        )
+    })
+}
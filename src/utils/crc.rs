@@ -0,0 +1,80 @@
+//! The engine's CRCs. `GCRCTable` is CRC-32 with the 0x04C11DB7
+//! polynomial fed MSB-first, which is not the zlib CRC-32; `zip_crc` is
+//! that one, for the integrity lists that use it instead.
+
+use flate2::Crc;
+
+const POLY: u32 = 0x04C1_1DB7;
+
+/// FName's hash table size; `name_hash` is masked to it.
+pub const NAME_HASH_BUCKETS: u32 = 4096;
+
+pub static TABLE: [u32; 256] = {
+    let mut t = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = (i as u32) << 24;
+        let mut j = 0;
+        while j < 8 {
+            c = if c & 0x8000_0000 != 0 {
+                (c << 1) ^ POLY
+            } else {
+                c << 1
+            };
+            j += 1;
+        }
+        t[i] = c;
+        i += 1;
+    }
+    t
+};
+
+fn step(crc: u32, b: u8) -> u32 {
+    (crc << 8) ^ TABLE[((crc >> 24) ^ b as u32) as usize]
+}
+
+/// `appMemCrc`; `crc` is 0 to start, or a previous result to continue.
+pub fn mem_crc(data: &[u8], crc: u32) -> u32 {
+    !data.iter().fold(!crc, |c, &b| step(c, b))
+}
+
+/// `appToUpper`: ASCII and the Latin-1 lowercase letters only, as the
+/// engine does it.
+fn to_upper(c: u16) -> u16 {
+    match c {
+        255 => 159,
+        156 => 140,
+        215 | 247 => c,
+        0x61..=0x7A | 224..=254 => c - 32,
+        _ => c,
+    }
+}
+
+/// `appStrCrc`: over the UTF-16 code units, low byte first.
+pub fn str_crc(s: &str) -> u32 {
+    !s.encode_utf16()
+        .fold(!0, |c, u| step(step(c, u as u8), (u >> 8) as u8))
+}
+
+/// `appStrCrcCaps`: `str_crc` of the string upper-cased the engine's way.
+pub fn str_crc_caps(s: &str) -> u32 {
+    !s.encode_utf16()
+        .map(to_upper)
+        .fold(!0, |c, u| step(step(c, u as u8), (u >> 8) as u8))
+}
+
+/// `appStrihash`, which FName buckets names by: case-insensitive,
+/// reflected table lookups, no final inversion.
+pub fn name_hash(s: &str) -> u32 {
+    s.encode_utf16().map(to_upper).fold(0u32, |h, u| {
+        let h = (h >> 8) ^ TABLE[((h ^ (u & 0xFF) as u32) & 0xFF) as usize];
+        (h >> 8) ^ TABLE[((h ^ (u >> 8) as u32) & 0xFF) as usize]
+    })
+}
+
+/// The zlib / PNG CRC-32.
+pub fn zip_crc(data: &[u8]) -> u32 {
+    let mut c = Crc::new();
+    c.update(data);
+    c.sum()
+}
@@ -275,14 +275,28 @@ impl Dds {
         if mip_count == 0 {
             mip_count = 1;
         }
+        if width == 0 || height == 0 || mip_count > 32 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("DDS header claims {width}x{height} with {mip_count} mip(s)"),
+            ));
+        }
 
         let mut mips = Vec::with_capacity(mip_count as usize);
         let mut w = width;
         let mut h = height;
-        for _ in 0..mip_count {
+        for i in 0..mip_count {
             let n = format.mip_size(w, h) as usize;
             let mut buf = vec![0u8; n];
-            c.read_exact(&mut buf)?;
+            c.read_exact(&mut buf).map_err(|_| {
+                Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!(
+                        "DDS ends inside mip {i} ({w}x{h}) of the {mip_count} its header lists; \
+                         re-save it with a complete mip chain"
+                    ),
+                )
+            })?;
             mips.push(DdsMip {
                 width: w,
                 height: h,
@@ -348,3 +362,141 @@ fn write_pixelformat(w: &mut Cursor<&mut Vec<u8>>, p: PixelFormat) -> Result<()>
     }
     Ok(())
 }
+
+fn rgb565(c: u16) -> [u8; 3] {
+    let (r, g, b) = ((c >> 11) & 0x1F, (c >> 5) & 0x3F, c & 0x1F);
+    [
+        ((r << 3) | (r >> 2)) as u8,
+        ((g << 2) | (g >> 4)) as u8,
+        ((b << 3) | (b >> 2)) as u8,
+    ]
+}
+
+/// The 4x4 colors of a BC1 block, alpha 0 for the punch-through index
+/// when `one_bit_alpha`.
+fn bc1_colors(block: &[u8], one_bit_alpha: bool) -> [[u8; 4]; 16] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (a, b) = (rgb565(c0), rgb565(c1));
+    let mix = |wa: u16, wb: u16, d: u16| -> [u8; 4] {
+        let ch = |i: usize| ((a[i] as u16 * wa + b[i] as u16 * wb) / d) as u8;
+        [ch(0), ch(1), ch(2), 255]
+    };
+    let palette = if c0 > c1 || !one_bit_alpha {
+        [
+            [a[0], a[1], a[2], 255],
+            [b[0], b[1], b[2], 255],
+            mix(2, 1, 3),
+            mix(1, 2, 3),
+        ]
+    } else {
+        [
+            [a[0], a[1], a[2], 255],
+            [b[0], b[1], b[2], 255],
+            mix(1, 1, 2),
+            [0, 0, 0, 0],
+        ]
+    };
+    let bits = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    std::array::from_fn(|i| palette[((bits >> (i * 2)) & 3) as usize])
+}
+
+/// One 8-byte interpolated channel block (BC3 alpha, BC5 red / green).
+fn bc4_values(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u16, block[1] as u16);
+    let palette: [u8; 8] = std::array::from_fn(|i| match i {
+        0 => a0 as u8,
+        1 => a1 as u8,
+        _ if a0 > a1 => ((a0 * (8 - i as u16) + a1 * (i as u16 - 1)) / 7) as u8,
+        6 => 0,
+        7 => 255,
+        _ => ((a0 * (6 - i as u16) + a1 * (i as u16 - 1)) / 5) as u8,
+    });
+    let mut bits = 0u64;
+    for (i, b) in block[2..8].iter().enumerate() {
+        bits |= (*b as u64) << (i * 8);
+    }
+    std::array::from_fn(|i| palette[((bits >> (i * 3)) & 7) as usize])
+}
+
+fn half_to_unit(h: u16) -> u8 {
+    let exp = (h >> 10) & 0x1F;
+    let frac = (h & 0x3FF) as f32;
+    let v = match exp {
+        0 => frac / 1024.0 * 2f32.powi(-14),
+        31 => f32::INFINITY,
+        e => (1.0 + frac / 1024.0) * 2f32.powi(e as i32 - 15),
+    };
+    let v = if h & 0x8000 != 0 { -v } else { v };
+    (v.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+}
+
+/// Decodes one mip to tightly packed RGBA8, for previews.
+pub fn mip_to_rgba(format: PixelFormat, w: u32, h: u32, data: &[u8]) -> Result<Vec<u8>> {
+    let need = format.mip_size(w, h) as usize;
+    if data.len() < need {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            format!(
+                "{}x{h} {} mip needs {need} bytes, has {}",
+                w,
+                format.as_pf_label(),
+                data.len()
+            ),
+        ));
+    }
+    let (w, h) = (w as usize, h as usize);
+    let mut out = vec![0u8; w * h * 4];
+    if !format.is_block_compressed() {
+        let unit = format.unit_bytes() as usize;
+        for (i, px) in data[..need].chunks_exact(unit).enumerate() {
+            out[i * 4..i * 4 + 4].copy_from_slice(&match format {
+                // Stored as B, G, R, A.
+                PixelFormat::A8R8G8B8 => [px[2], px[1], px[0], px[3]],
+                PixelFormat::G8 => [px[0], px[0], px[0], 255],
+                _ => {
+                    let ch = |k: usize| half_to_unit(u16::from_le_bytes([px[k], px[k + 1]]));
+                    [ch(0), ch(2), ch(4), ch(6)]
+                }
+            });
+        }
+        return Ok(out);
+    }
+
+    let unit = format.unit_bytes() as usize;
+    let bw = w.max(4).div_ceil(4);
+    for (n, block) in data[..need].chunks_exact(unit).enumerate() {
+        let texels: [[u8; 4]; 16] = match format {
+            PixelFormat::Dxt1 => bc1_colors(block, true),
+            PixelFormat::Dxt3 => {
+                let mut c = bc1_colors(&block[8..], false);
+                for (i, t) in c.iter_mut().enumerate() {
+                    let nib = (block[i / 2] >> ((i % 2) * 4)) & 0xF;
+                    t[3] = nib * 17;
+                }
+                c
+            }
+            PixelFormat::Dxt5 => {
+                let mut c = bc1_colors(&block[8..], false);
+                let alpha = bc4_values(&block[..8]);
+                for (t, a) in c.iter_mut().zip(alpha) {
+                    t[3] = a;
+                }
+                c
+            }
+            // Two-channel normal maps; blue is left at the midpoint.
+            _ => {
+                let (r, g) = (bc4_values(&block[..8]), bc4_values(&block[8..]));
+                std::array::from_fn(|i| [r[i], g[i], 128, 255])
+            }
+        };
+        let (bx, by) = (n % bw * 4, n / bw * 4);
+        for (i, t) in texels.iter().enumerate() {
+            let (x, y) = (bx + i % 4, by + i / 4);
+            if x < w && y < h {
+                out[(y * w + x) * 4..(y * w + x) * 4 + 4].copy_from_slice(t);
+            }
+        }
+    }
+    Ok(out)
+}
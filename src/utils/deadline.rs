@@ -0,0 +1,84 @@
+use std::{
+    cell::Cell,
+    fmt::Display,
+    io::{Error, ErrorKind, Result},
+    sync::{
+        OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+const UNSET: u64 = u64::MAX;
+
+/// Milliseconds; 0 is no limit.
+static LIMIT: AtomicU64 = AtomicU64::new(UNSET);
+
+thread_local! {
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// `30s`, `500ms`, `2m`, `1.5`; a bare number is seconds.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let t = s.trim();
+    let split = t.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(t.len());
+    let (num, unit) = t.split_at(split);
+    let secs: f64 = match unit.to_ascii_lowercase().as_str() {
+        "" | "s" => 1.0,
+        "ms" => 0.001,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => -1.0,
+    };
+    match num.trim().parse::<f64>() {
+        Ok(v) if secs > 0.0 && v >= 0.0 => Ok(Duration::from_secs_f64(v * secs)),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("'{s}' is not a duration (e.g. 30s, 500ms, 2m)"),
+        )),
+    }
+}
+
+/// How long one operation may parse; `None` removes the limit.
+pub fn set_limit(limit: Option<Duration>) {
+    LIMIT.store(
+        limit.map_or(0, |d| (d.as_millis() as u64).max(1)),
+        Ordering::Relaxed,
+    );
+}
+
+/// The limit set by `set_limit`, else `UE3_TOOLS_DEADLINE`.
+pub fn limit() -> Option<Duration> {
+    static FROM_ENV: OnceLock<u64> = OnceLock::new();
+    let ms = match LIMIT.load(Ordering::Relaxed) {
+        UNSET => *FROM_ENV.get_or_init(|| {
+            std::env::var("UE3_TOOLS_DEADLINE")
+                .ok()
+                .and_then(|v| parse_duration(&v).ok())
+                .map_or(0, |d| (d.as_millis() as u64).max(1))
+        }),
+        ms => ms,
+    };
+    (ms != 0).then(|| Duration::from_millis(ms))
+}
+
+/// Starts the clock for an operation on this thread: a command, or one
+/// package of a batch scan so a single bad file doesn't stop the rest.
+pub fn arm() {
+    DEADLINE.with(|d| d.set(limit().map(|l| Instant::now() + l)));
+}
+
+/// Called from parse loops that data can make arbitrarily long; a
+/// `TimedOut` error once the armed deadline has passed.
+pub fn check(what: impl Display) -> Result<()> {
+    match DEADLINE.with(Cell::get) {
+        Some(at) if Instant::now() > at => Err(Error::new(
+            ErrorKind::TimedOut,
+            format!(
+                "gave up {what} after {:.1?} (--deadline); the data may be corrupt or adversarial",
+                limit().unwrap_or_default()
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
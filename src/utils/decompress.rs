@@ -1,9 +1,22 @@
-use std::io::{self, Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::{
+    fs::File,
+    io::{self, BufReader, Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom},
+    path::Path,
+};
 
 use byteorder::{LittleEndian, ReadBytesExt};
 use serde::{Deserialize, Serialize};
 
-use crate::versions::PACKAGE_FILE_TAG;
+use crate::{
+    upkreader::{PackageFlags, UpkHeader},
+    utils::{
+        lossy::{self, Code},
+        modarchive,
+        spill::{ImageWriter, PackageBytes, fits_in_memory},
+        stats::{self, Phase},
+    },
+    versions::PACKAGE_FILE_TAG,
+};
 
 pub const CHUNK_SIZE: u32 = 131072; // default in Unreal Engine 3
 
@@ -39,13 +52,28 @@ impl TryFrom<u32> for CompressionMethod {
 }
 
 pub fn upk_decompress<R: Read + Seek>(
-    mut reader: R,
+    reader: R,
     mode: CompressionMethod,
-    chunks: &Vec<CompressedChunk>,
+    chunks: &[CompressedChunk],
 ) -> Result<Vec<Vec<u8>>> {
     let mut dec_data = Vec::new();
+    upk_decompress_with(reader, mode, chunks, |_, data| {
+        dec_data.push(data);
+        Ok(())
+    })?;
+    Ok(dec_data)
+}
 
-    for chunk in chunks {
+/// Like `upk_decompress`, but hands each chunk to `sink` as soon as it is
+/// inflated instead of keeping them all.
+pub fn upk_decompress_with<R: Read + Seek>(
+    mut reader: R,
+    mode: CompressionMethod,
+    chunks: &[CompressedChunk],
+    mut sink: impl FnMut(usize, Vec<u8>) -> Result<()>,
+) -> Result<()> {
+    let _t = stats::time(Phase::Decompress);
+    for (i, chunk) in chunks.iter().enumerate() {
         reader.seek(SeekFrom::Start(chunk.compressed_offset as u64))?;
 
         let tag = reader.read_u32::<LittleEndian>()?;
@@ -101,10 +129,10 @@ pub fn upk_decompress<R: Read + Seek>(
             rchunk_data.resize(chunk.decompressed_size as usize, 0);
         }
 
-        dec_data.push(rchunk_data);
+        sink(i, rchunk_data)?;
     }
 
-    Ok(dec_data)
+    Ok(())
 }
 
 pub fn decompress_chunk(
@@ -117,7 +145,12 @@ pub fn decompress_chunk(
 
     match mode {
         CompressionMethod::Lzo => {
-            lzo1x::decompress(&compressed, &mut out).unwrap();
+            lzo1x::decompress(&compressed, &mut out).map_err(|e| {
+                Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("LZO decompression failed: {e:?}"),
+                )
+            })?;
 
             if out_len > expected_decompress_size {
                 return Err(Error::new(
@@ -133,8 +166,274 @@ pub fn decompress_chunk(
                 out[out_len..expected_decompress_size].fill(0);
             }
         }
-        _ => unimplemented!(),
+        CompressionMethod::Zlib => {
+            flate2::read::ZlibDecoder::new(&compressed[..])
+                .read_exact(&mut out)
+                .map_err(|e| {
+                    Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("zlib decompression failed: {e}"),
+                    )
+                })?;
+        }
+        other => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("{other:?} decompression is not supported"),
+            ));
+        }
     }
 
     Ok(out)
 }
+
+/// A package as the engine sees it after load: compressed chunks inflated in
+/// place, summary rewritten to say "not compressed". Past the memory budget
+/// the image is paged from disk instead (see `utils::spill`).
+pub struct PackageImage {
+    pub bytes: PackageBytes,
+    pub header: UpkHeader,
+    pub raw_header: UpkHeader,
+}
+
+impl PackageImage {
+    pub fn was_compressed(&self) -> bool {
+        self.raw_header.compression_method != CompressionMethod::None
+            && self.raw_header.compressed_chunks_count > 0
+    }
+}
+
+/// Loads `path`, which may also name a package inside a mod archive
+/// (`mod.zip://Path/Package.upk`, see `utils::modarchive`).
+pub fn read_package_image(path: &Path) -> Result<PackageImage> {
+    let _at = lossy::at(path.display().to_string());
+    if let Some(member) = modarchive::split(path) {
+        let data = modarchive::read(&member)?;
+        let filesize = data.len() as u64;
+        let mut reader = Cursor::new(data);
+        let header = UpkHeader::read(&mut reader)?;
+        if !is_compressed(&header) {
+            return Ok(PackageImage {
+                bytes: reader.into_inner().into(),
+                header: header.clone(),
+                raw_header: header,
+            });
+        }
+        return inflate_image(&mut reader, header, filesize);
+    }
+
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    let filesize = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    let header = UpkHeader::read(&mut reader)?;
+
+    if !is_compressed(&header) {
+        let bytes = if fits_in_memory(filesize) {
+            reader.seek(SeekFrom::Start(0))?;
+            let mut buf = Vec::with_capacity(filesize as usize);
+            reader.read_to_end(&mut buf)?;
+            buf.into()
+        } else {
+            PackageBytes::map_file(path)?
+        };
+        return Ok(PackageImage {
+            bytes,
+            header: header.clone(),
+            raw_header: header,
+        });
+    }
+    inflate_image(&mut reader, header, filesize)
+}
+
+fn is_compressed(header: &UpkHeader) -> bool {
+    header.compression_method != CompressionMethod::None && header.compressed_chunks_count > 0
+}
+
+fn inflate_image<R: Read + Seek>(
+    reader: &mut R,
+    header: UpkHeader,
+    filesize: u64,
+) -> Result<PackageImage> {
+    let mut cloned_header = header.clone();
+    cloned_header.compression_method = CompressionMethod::None;
+    cloned_header.compressed_chunks_count = 0;
+    cloned_header.compressed_chunks.clear();
+    cloned_header.pak_flags = header.pak_flags & !PackageFlags::StoreCompressed.bits();
+
+    let mut chunks = header.compressed_chunks.clone();
+    chunks.sort_by_key(|c| c.decompressed_offset);
+
+    let dec_total = chunks
+        .iter()
+        .map(|c| c.decompressed_offset as u64 + c.decompressed_size as u64)
+        .max()
+        .unwrap_or(0);
+
+    // Uncompressed bytes between chunks are read up front so the chunks can
+    // be streamed into the image one at a time.
+    let mut gaps = Vec::with_capacity(chunks.len());
+    for i in 0..chunks.len() {
+        let mut gap_buf = Vec::new();
+        if i != 0 {
+            let prev = chunks[i - 1].compressed_offset + chunks[i - 1].compressed_size;
+            let gap = chunks[i].compressed_offset.saturating_sub(prev);
+            if gap > 0 {
+                reader.seek(SeekFrom::Start(prev as u64))?;
+                gap_buf.resize(gap as usize, 0);
+                reader.read_exact(&mut gap_buf)?;
+            }
+        }
+        gaps.push(gap_buf);
+    }
+
+    let mut img = ImageWriter::new(dec_total.max(filesize))?;
+    {
+        let mut head = Vec::new();
+        cloned_header.write(&mut std::io::Cursor::new(&mut head))?;
+        img.extend(&head)?;
+    }
+
+    upk_decompress_with(
+        &mut *reader,
+        header.compression_method,
+        &chunks,
+        |i, dec| {
+            img.extend(&gaps[i])?;
+            let target = chunks[i].decompressed_offset as u64;
+            if img.len() < target {
+                lossy::warn(
+                    Code::ChunkPadded,
+                    format_args!(
+                        "chunk {i} starts at 0x{target:X}, {} bytes past the data before it; \
+                         zero-filled",
+                        target - img.len()
+                    ),
+                );
+                img.pad_to(target)?;
+            } else if img.len() > target {
+                return img.write_at(target, &dec);
+            }
+            img.extend(&dec)
+        },
+    )?;
+
+    let last_compressed_end = chunks
+        .last()
+        .map(|c| (c.compressed_offset + c.compressed_size) as u64)
+        .unwrap_or(0);
+    if filesize > last_compressed_end {
+        reader.seek(SeekFrom::Start(last_compressed_end))?;
+        let mut tail = Vec::with_capacity((filesize - last_compressed_end) as usize);
+        reader.read_to_end(&mut tail)?;
+        img.extend(&tail)?;
+    }
+
+    Ok(PackageImage {
+        bytes: img.finish()?,
+        header: cloned_header,
+        raw_header: header,
+    })
+}
+
+/// The summary as stored, before any decompression; archive members are
+/// read like `read_package_image` does.
+pub fn read_raw_header(path: &Path) -> Result<UpkHeader> {
+    match modarchive::split(path) {
+        Some(member) => UpkHeader::read(&mut Cursor::new(modarchive::read(&member)?)),
+        None => UpkHeader::read(&mut BufReader::new(File::open(path)?)),
+    }
+}
+
+/// StoreFullyCompressed packages are one chunk stream from byte 0, so the
+/// "summary" starts with the chunk header: tag, then block size where the
+/// package version would be (giving p_ver 0, which no real package has).
+pub fn is_fully_compressed(path: &Path) -> Result<bool> {
+    let mut head = [0u8; 8];
+    if let Some(member) = modarchive::split(path) {
+        let data = modarchive::read(&member)?;
+        if data.len() < 8 {
+            return Ok(false);
+        }
+        head.copy_from_slice(&data[..8]);
+    } else if File::open(path)?.read(&mut head)? < 8 {
+        return Ok(false);
+    }
+    let tag = u32::from_le_bytes([head[0], head[1], head[2], head[3]]);
+    let p_ver = i16::from_le_bytes([head[4], head[5]]);
+    Ok((tag == PACKAGE_FILE_TAG || tag.swap_bytes() == PACKAGE_FILE_TAG) && p_ver == 0)
+}
+
+/// The method of a StoreFullyCompressed stream, which no summary records:
+/// its first block is inflated as zlib, then as LZO, and whichever takes it
+/// whole is the answer.
+pub fn fully_compressed_method(path: &Path) -> Result<CompressionMethod> {
+    let (block, decompressed_size) = match modarchive::split(path) {
+        Some(member) => first_block(Cursor::new(modarchive::read(&member)?)),
+        None => first_block(BufReader::new(File::open(path)?)),
+    }
+    .map_err(|e| Error::new(ErrorKind::InvalidData, format!("bad chunk stream: {e}")))?;
+    for mode in [CompressionMethod::Zlib, CompressionMethod::Lzo] {
+        if decompress_chunk(block.clone(), mode, decompressed_size).is_ok() {
+            return Ok(mode);
+        }
+    }
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        "chunk stream is neither zlib nor LZO",
+    ))
+}
+
+/// The first compressed block of a chunk stream and its inflated size.
+fn first_block<R: Read>(mut r: R) -> Result<(Vec<u8>, usize)> {
+    let tag = r.read_u32::<LittleEndian>()?;
+    let bswap = tag != PACKAGE_FILE_TAG;
+    let mut word = || -> Result<u32> {
+        let v = r.read_u32::<LittleEndian>()?;
+        Ok(if bswap { v.swap_bytes() } else { v })
+    };
+    let mut chunk_size = word()?;
+    if chunk_size == PACKAGE_FILE_TAG {
+        chunk_size = CHUNK_SIZE;
+    }
+    let (_summary, total) = (word()?, word()?);
+    let count = total.div_ceil(chunk_size.max(1));
+    if count == 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "no blocks"));
+    }
+    let (compressed_size, decompressed_size) = (word()?, word()?);
+    if decompressed_size > chunk_size {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("block of {decompressed_size} bytes in {chunk_size}-byte chunks"),
+        ));
+    }
+    for _ in 1..count {
+        word()?;
+        word()?;
+    }
+    let mut block = Vec::new();
+    r.take(compressed_size as u64).read_to_end(&mut block)?;
+    if block.len() != compressed_size as usize {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            "first block is cut short",
+        ));
+    }
+    Ok((block, decompressed_size as usize))
+}
+
+pub fn decompress_fully(path: &Path, mode: CompressionMethod) -> Result<Vec<u8>> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len() as u32;
+    let chunk = CompressedChunk {
+        decompressed_offset: 0,
+        decompressed_size: 0,
+        compressed_offset: 0,
+        compressed_size: size,
+    };
+    let mut out = upk_decompress(BufReader::new(file), mode, &[chunk])?;
+    Ok(out.pop().unwrap_or_default())
+}
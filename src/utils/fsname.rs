@@ -0,0 +1,176 @@
+//! Object names as file names. Names come from the package (Latin-1 or
+//! UTF-16) and may hold anything; the result is a UTF-8 path component
+//! every common filesystem accepts. The original name always stays in the
+//! `.uo` header, which is what packing goes by.
+
+use std::io::{Error, ErrorKind};
+
+use crate::utils::hash::ContentHash;
+
+/// Bytes per component; 255 is the usual limit, and the class suffix and
+/// sidecar extensions still have to fit.
+const MAX_COMPONENT: usize = 200;
+
+/// Windows device names, refused with any extension.
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Linux errno when a filesystem refuses the encoding of a name.
+const EILSEQ: i32 = 84;
+
+/// `name` as one path component: characters Windows reserves and control
+/// characters become `_`, as do trailing dots and spaces; device names
+/// get a `_` in front. Overlong names are cut. Either way the result ends
+/// in `~` and a hash of the original name, so `A:B` and `A?B` (or `A_B`
+/// itself) don't land on the same file.
+pub fn component(name: &str) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let kept = out.trim_end_matches(['.', ' ']).len();
+    let trailing = out.len() - kept;
+    out.truncate(kept);
+    out.extend(std::iter::repeat_n('_', trailing));
+    if out.is_empty() {
+        out.push('_');
+    }
+    let stem = out.split('.').next().unwrap_or("");
+    if RESERVED.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        out.insert(0, '_');
+    }
+    if out != name || out.len() > MAX_COMPONENT {
+        let mut h = ContentHash::new();
+        h.update(name.as_bytes());
+        let mut cut = out.len().min(MAX_COMPONENT - 17);
+        while !out.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        out.truncate(cut);
+        out.push('~');
+        out.push_str(&h.hex());
+    }
+    out
+}
+
+fn latin(c: char) -> Option<&'static str> {
+    Some(match c {
+        'À'..='Å' => "A",
+        'Æ' => "AE",
+        'Ç' | 'Ć' | 'Č' => "C",
+        'È'..='Ë' | 'Ę' | 'Ě' => "E",
+        'Ì'..='Ï' => "I",
+        'Ð' | 'Ď' => "D",
+        'Ñ' | 'Ń' | 'Ň' => "N",
+        'Ò'..='Ö' | 'Ø' | 'Ő' => "O",
+        'Ù'..='Ü' | 'Ů' | 'Ű' => "U",
+        'Ý' | 'Ÿ' => "Y",
+        'Þ' => "TH",
+        'ß' => "ss",
+        'à'..='å' => "a",
+        'æ' => "ae",
+        'ç' | 'ć' | 'č' => "c",
+        'è'..='ë' | 'ę' | 'ě' => "e",
+        'ì'..='ï' => "i",
+        'ð' | 'ď' => "d",
+        'ñ' | 'ń' | 'ň' => "n",
+        'ò'..='ö' | 'ø' | 'ő' => "o",
+        'ù'..='ü' | 'ů' | 'ű' => "u",
+        'ý' | 'ÿ' => "y",
+        'þ' => "th",
+        'Ł' => "L",
+        'ł' => "l",
+        'Ř' => "R",
+        'ř' => "r",
+        'Ś' | 'Š' => "S",
+        'ś' | 'š' => "s",
+        'Ť' => "T",
+        'ť' => "t",
+        'Ź' | 'Ż' | 'Ž' => "Z",
+        'ź' | 'ż' | 'ž' => "z",
+        _ => return None,
+    })
+}
+
+/// Ukrainian and Russian letters, one spelling each (the in-word forms
+/// of the Ukrainian national table).
+fn cyrillic(c: char) -> Option<&'static str> {
+    let lower = match c.to_lowercase().next()? {
+        'а' => "a",
+        'б' => "b",
+        'в' => "v",
+        'г' => "h",
+        'ґ' => "g",
+        'д' => "d",
+        'е' | 'э' => "e",
+        'є' => "ie",
+        'ё' => "io",
+        'ж' => "zh",
+        'з' => "z",
+        'и' => "y",
+        'і' => "i",
+        'ї' => "i",
+        'й' => "i",
+        'к' => "k",
+        'л' => "l",
+        'м' => "m",
+        'н' => "n",
+        'о' => "o",
+        'п' => "p",
+        'р' => "r",
+        'с' => "s",
+        'т' => "t",
+        'у' => "u",
+        'ф' => "f",
+        'х' => "kh",
+        'ц' => "ts",
+        'ч' => "ch",
+        'ш' => "sh",
+        'щ' => "shch",
+        'ъ' | 'ь' => "",
+        'ы' => "y",
+        'ю' => "iu",
+        'я' => "ia",
+        _ => return None,
+    };
+    Some(lower)
+}
+
+/// ASCII spelling of `name` for filesystems that refuse its characters:
+/// accented Latin and Cyrillic letters are transliterated, anything else
+/// is written as `_uXXXX`.
+pub fn ascii(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii() {
+            out.push(c);
+        } else if let Some(s) = latin(c) {
+            out.push_str(s);
+        } else if let Some(s) = cyrillic(c) {
+            // Capitalised like the letter it stands for.
+            let mut chars = s.chars();
+            match chars.next() {
+                Some(first) if c.is_uppercase() => {
+                    out.push(first.to_ascii_uppercase());
+                    out.extend(chars);
+                }
+                _ => out.push_str(s),
+            }
+        } else {
+            out.push_str(&format!("_u{:04X}", c as u32));
+        }
+    }
+    out
+}
+
+/// Whether `e` is a filesystem refusing a name's characters, so that the
+/// ASCII spelling is worth a try.
+pub fn rejected(e: &Error) -> bool {
+    e.kind() == ErrorKind::InvalidFilename || e.raw_os_error() == Some(EILSEQ)
+}
@@ -0,0 +1,175 @@
+//! Sanity limits that decide when data is taken as garbage rather than
+//! read. The defaults suit shipped UE3 games; packages from unusual builds
+//! (huge localisation tables, deep script) can exceed them, so each is a
+//! setting: `heuristics.toml` in the config directory, then
+//! `--heuristic key=value` on the command line.
+//!
+//! Hitting a limit is recorded as a `limit-hit` warning naming the key, so
+//! a failed read says which one to raise.
+
+use std::{
+    fmt::Display,
+    io::{Error, ErrorKind, Result},
+    sync::OnceLock,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{
+    config::config_dir,
+    lossy::{self, Code},
+    spill::parse_size,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct HeuristicsConfig {
+    /// Length a string or count a table / TArray in package metadata may
+    /// claim.
+    pub max_count: u64,
+    /// Elements a TArray in a saved game or other FArchive blob may claim.
+    pub max_archive_array: u64,
+    /// Mips a texture may claim.
+    pub max_mips: u64,
+    /// Nesting of a bytecode expression.
+    pub max_script_depth: u64,
+    /// Nesting of structs inside an FArchive blob.
+    pub max_struct_depth: u64,
+    /// Fields followed walking a class's children.
+    pub max_children: u64,
+    /// Superclasses followed walking a class chain.
+    pub max_class_chain: u64,
+    /// Native data larger than this isn't scanned for object references
+    /// by `orphans`.
+    pub native_scan_limit: u64,
+}
+
+impl Default for HeuristicsConfig {
+    fn default() -> Self {
+        Self {
+            max_count: 0x10_0000,
+            max_archive_array: 1 << 24,
+            max_mips: 64,
+            max_script_depth: 256,
+            max_struct_depth: 64,
+            max_children: 8192,
+            max_class_chain: 64,
+            native_scan_limit: 64 * 1024,
+        }
+    }
+}
+
+impl HeuristicsConfig {
+    pub const KEYS: &[&str] = &[
+        "max-count",
+        "max-archive-array",
+        "max-mips",
+        "max-script-depth",
+        "max-struct-depth",
+        "max-children",
+        "max-class-chain",
+        "native-scan-limit",
+    ];
+
+    pub fn value(&self, key: &str) -> Option<u64> {
+        Some(match key {
+            "max-count" => self.max_count,
+            "max-archive-array" => self.max_archive_array,
+            "max-mips" => self.max_mips,
+            "max-script-depth" => self.max_script_depth,
+            "max-struct-depth" => self.max_struct_depth,
+            "max-children" => self.max_children,
+            "max-class-chain" => self.max_class_chain,
+            "native-scan-limit" => self.native_scan_limit,
+            _ => return None,
+        })
+    }
+
+    fn field_mut(&mut self, key: &str) -> Option<&mut u64> {
+        Some(match key {
+            "max-count" => &mut self.max_count,
+            "max-archive-array" => &mut self.max_archive_array,
+            "max-mips" => &mut self.max_mips,
+            "max-script-depth" => &mut self.max_script_depth,
+            "max-struct-depth" => &mut self.max_struct_depth,
+            "max-children" => &mut self.max_children,
+            "max-class-chain" => &mut self.max_class_chain,
+            "native-scan-limit" => &mut self.native_scan_limit,
+            _ => return None,
+        })
+    }
+
+    /// `heuristics.toml` from the config directory when there is one, with
+    /// `key=value` overrides applied; values take size suffixes (`4M`).
+    pub fn load(overrides: &[String]) -> Result<Self> {
+        let mut cfg = match config_dir().map(|d| d.join("heuristics.toml")) {
+            Ok(p) if p.exists() => {
+                let text = std::fs::read_to_string(&p)?;
+                toml::from_str(&text).map_err(|e| {
+                    Error::new(ErrorKind::InvalidData, format!("{}: {e}", p.display()))
+                })?
+            }
+            _ => Self::default(),
+        };
+        for o in overrides {
+            let (key, value) = o.split_once('=').ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("--heuristic '{o}': expected key=value"),
+                )
+            })?;
+            let key = key.trim();
+            let slot = cfg.field_mut(key).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "--heuristic: unknown key '{key}' (one of {})",
+                        Self::KEYS.join(", ")
+                    ),
+                )
+            })?;
+            *slot = parse_size(value)?;
+        }
+        Ok(cfg)
+    }
+}
+
+static CONFIG: OnceLock<HeuristicsConfig> = OnceLock::new();
+
+/// Installs the limits for the run; only the first call counts.
+pub fn set(cfg: HeuristicsConfig) {
+    let _ = CONFIG.set(cfg);
+}
+
+/// The limits installed by `set`, else the defaults.
+pub fn get() -> &'static HeuristicsConfig {
+    CONFIG.get_or_init(HeuristicsConfig::default)
+}
+
+/// Records that `what` went over the `key` limit.
+pub fn hit(key: &str, what: impl Display) {
+    lossy::warn(
+        Code::LimitHit,
+        format_args!("{what}; over {key}, raise with --heuristic {key}=N"),
+    );
+}
+
+/// An error for a negative `value`, or one over the `key` limit (which
+/// is recorded); `what` says what was read.
+pub fn check(key: &str, value: i64, what: impl Display) -> Result<()> {
+    let limit = get().value(key).unwrap_or(u64::MAX);
+    if value < 0 {
+        Err(Error::new(ErrorKind::InvalidData, what.to_string()))
+    } else if value as u64 > limit {
+        Err(exceeded(key, what))
+    } else {
+        Ok(())
+    }
+}
+
+/// `hit`, and the error to give up with.
+pub fn exceeded(key: &str, what: impl Display) -> Error {
+    let message = what.to_string();
+    hit(key, &message);
+    Error::new(ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,132 @@
+//! Lossy and heuristic decisions made while reading or converting. Each
+//! is printed like `term::warn`, tagged with its code, and kept for the
+//! run so `--warnings-json` can hand automation the full list.
+//!
+//! Codes are stable: scripts match on them, so add new ones rather than
+//! renaming.
+
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    io::{Error, Result},
+    path::Path,
+    sync::Mutex,
+};
+
+use serde::Serialize;
+
+use crate::utils::{readonly, term};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Code {
+    /// Array elements didn't add up to the tag size; kept as bytes.
+    ArrayAsRaw,
+    /// No schema for an array's element type; kept as bytes.
+    ArrayUntyped,
+    /// A struct read past its tag size and was cut back to it.
+    StructRealigned,
+    /// Native fields didn't consume the export's tail; kept as bytes.
+    NativeTailRaw,
+    /// Bytes after the known payload, kept but not understood.
+    TrailingBytes,
+    /// A gap before a compressed chunk was filled with zeros.
+    ChunkPadded,
+    /// Texture mips in a TFC that couldn't be found or read.
+    MipsUnresolved,
+    /// A pixel format with no DDS equivalent; no DDS written.
+    PixelFormatUnmapped,
+    /// Mips in an imported DDS that fit no slot of the texture.
+    DdsMipsIgnored,
+    /// A movie payload that doesn't start like a GFx/SWF file.
+    NotFlash,
+    /// Raw audio without a sample rate, written with no header.
+    PcmNoHeader,
+    /// A name the filesystem refused, written transliterated.
+    AsciiFilename,
+    /// Data over a sanity limit (`--heuristic`) was taken as garbage.
+    LimitHit,
+}
+
+impl Code {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Code::ArrayAsRaw => "array-as-raw",
+            Code::ArrayUntyped => "array-untyped",
+            Code::StructRealigned => "struct-realigned",
+            Code::NativeTailRaw => "native-tail-raw",
+            Code::TrailingBytes => "trailing-bytes",
+            Code::ChunkPadded => "chunk-padded",
+            Code::MipsUnresolved => "mips-unresolved",
+            Code::PixelFormatUnmapped => "pixel-format-unmapped",
+            Code::DdsMipsIgnored => "dds-mips-ignored",
+            Code::NotFlash => "not-flash",
+            Code::PcmNoHeader => "pcm-no-header",
+            Code::AsciiFilename => "ascii-filename",
+            Code::LimitHit => "limit-hit",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Warning {
+    pub code: Code,
+    /// The object being read (`Class Outer.Name`) or the package, when
+    /// known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    pub message: String,
+}
+
+static WARNINGS: Mutex<Vec<Warning>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Restores the previous location when dropped.
+pub struct LocationGuard(Option<String>);
+
+impl Drop for LocationGuard {
+    fn drop(&mut self) {
+        let prev = self.0.take();
+        LOCATION.with(|l| *l.borrow_mut() = prev);
+    }
+}
+
+/// Attributes warnings on this thread to `location` until the guard is
+/// dropped.
+pub fn at(location: impl Into<String>) -> LocationGuard {
+    let prev = LOCATION.with(|l| l.borrow_mut().replace(location.into()));
+    LocationGuard(prev)
+}
+
+pub fn warn(code: Code, msg: impl Display) {
+    let message = msg.to_string();
+    term::warn(code.as_str(), &message);
+    let location = LOCATION.with(|l| l.borrow().clone());
+    WARNINGS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(Warning {
+            code,
+            location,
+            message,
+        });
+}
+
+/// Everything recorded so far as `{"count": n, "warnings": [...]}`, to
+/// `path` or stdout for `-`.
+pub fn write_json(path: &Path) -> Result<()> {
+    let list = WARNINGS.lock().unwrap_or_else(|e| e.into_inner());
+    let text = serde_json::to_string_pretty(&serde_json::json!({
+        "count": list.len(),
+        "warnings": *list,
+    }))
+    .map_err(Error::other)?;
+    if path == Path::new("-") {
+        println!("{text}");
+        return Ok(());
+    }
+    readonly::write(path, text)
+}
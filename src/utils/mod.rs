@@ -1,2 +1,22 @@
+pub mod backup;
+pub mod compress;
+pub mod config;
+pub mod crc;
 pub mod dds;
+pub mod deadline;
 pub mod decompress;
+pub mod fsname;
+pub mod hash;
+pub mod heuristics;
+pub mod lossy;
+pub mod modarchive;
+pub mod png;
+pub mod pool;
+pub mod readonly;
+pub mod retry;
+pub mod sniff;
+pub mod spill;
+pub mod stats;
+pub mod term;
+pub mod vcdiff;
+pub mod walk;
@@ -0,0 +1,441 @@
+//! Packages inside mod archives, named `mod.zip://Path/In/Archive.upk`.
+//! Zip (stored or deflated, zip64 included) is read here; 7z needs the
+//! `sevenz` feature. Members are read whole into memory and are inputs
+//! only: nothing is ever written back into an archive.
+
+use std::{
+    fs::File,
+    io::{BufReader, Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use flate2::read::DeflateDecoder;
+
+use crate::utils::{crc::zip_crc, walk::is_package};
+
+/// Between the archive path and the member path.
+pub const SEPARATOR: &str = "://";
+
+const EOCD_SIG: u32 = 0x0605_4b50;
+const ZIP64_LOCATOR_SIG: u32 = 0x0706_4b50;
+const ZIP64_EOCD_SIG: u32 = 0x0606_4b50;
+const CENTRAL_SIG: u32 = 0x0201_4b50;
+const LOCAL_SIG: u32 = 0x0403_4b50;
+/// End-of-central-directory record without its comment.
+const EOCD_LEN: u64 = 22;
+const ZIP64_EXTRA: u16 = 0x0001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    SevenZ,
+}
+
+/// A file inside an archive.
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub archive: PathBuf,
+    /// `/`-separated, no leading slash.
+    pub inner: String,
+    pub kind: ArchiveKind,
+}
+
+impl std::fmt::Display for Member {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{SEPARATOR}{}", self.archive.display(), self.inner)
+    }
+}
+
+fn kind_of(archive: &str) -> Option<ArchiveKind> {
+    let lower = archive.to_ascii_lowercase();
+    if lower.ends_with(".zip") {
+        Some(ArchiveKind::Zip)
+    } else if lower.ends_with(".7z") {
+        Some(ArchiveKind::SevenZ)
+    } else {
+        None
+    }
+}
+
+/// The archive member `path` names, if it is of the `mod.zip://inner`
+/// form; plain paths give `None`.
+pub fn split(path: &Path) -> Option<Member> {
+    let s = path.to_str()?;
+    let mut from = 0;
+    while let Some(at) = s[from..].find(SEPARATOR).map(|i| from + i) {
+        if let Some(kind) = kind_of(&s[..at]) {
+            let inner = s[at + SEPARATOR.len()..]
+                .replace('\\', "/")
+                .trim_start_matches('/')
+                .to_string();
+            return Some(Member {
+                archive: PathBuf::from(&s[..at]),
+                inner,
+                kind,
+            });
+        }
+        from = at + SEPARATOR.len();
+    }
+    None
+}
+
+pub fn is_member(path: &Path) -> bool {
+    split(path).is_some()
+}
+
+/// Refuses to write to `path` when it names an archive member.
+pub fn ensure_writable(path: &Path) -> Result<()> {
+    match split(path) {
+        Some(m) => Err(Error::new(
+            ErrorKind::PermissionDenied,
+            format!("{m} is inside an archive and can't be written; give an output file with -o"),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Which of `names` `want` means: the exact path (case-insensitive, either
+/// slash), else the only entry with that file name, so `mod.zip://X.upk`
+/// works whatever folder the mod put it in.
+fn pick(names: &[String], member: &Member) -> Result<usize> {
+    let norm = |s: &str| {
+        s.replace('\\', "/")
+            .trim_start_matches('/')
+            .to_ascii_lowercase()
+    };
+    let want = norm(&member.inner);
+    if let Some(i) = names.iter().position(|n| norm(n) == want) {
+        return Ok(i);
+    }
+    let base = |s: &str| s.rsplit('/').next().unwrap_or("").to_string();
+    let hits: Vec<usize> = if want.contains('/') {
+        Vec::new()
+    } else {
+        (0..names.len())
+            .filter(|&i| base(&norm(&names[i])) == want)
+            .collect()
+    };
+    match hits.as_slice() {
+        [i] => Ok(*i),
+        [] => {
+            let packages: Vec<&str> = names
+                .iter()
+                .map(String::as_str)
+                .filter(|n| is_package(Path::new(n)))
+                .collect();
+            let listed = if packages.is_empty() {
+                "it holds no packages".to_string()
+            } else {
+                format!("packages in it: {}", packages.join(", "))
+            };
+            Err(Error::new(
+                ErrorKind::NotFound,
+                format!("not in the archive; {listed}"),
+            ))
+        }
+        many => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "ambiguous, could be {}; give the full path",
+                many.iter()
+                    .map(|&i| names[i].as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        )),
+    }
+}
+
+/// The bytes of `member`, decompressed and checked against the archive's
+/// CRC.
+pub fn read(member: &Member) -> Result<Vec<u8>> {
+    let at = |e: Error| Error::new(e.kind(), format!("{member}: {e}"));
+    match member.kind {
+        ArchiveKind::Zip => read_zip(member).map_err(at),
+        ArchiveKind::SevenZ => read_7z(member).map_err(at),
+    }
+}
+
+struct ZipEntry {
+    name: String,
+    flags: u16,
+    method: u16,
+    crc: u32,
+    compressed: u64,
+    size: u64,
+    local_offset: u64,
+}
+
+fn bad(msg: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.into())
+}
+
+/// The central directory's offset, size and entry count, from the
+/// end-of-central-directory record (and its zip64 form when a field is
+/// saturated).
+fn zip_directory<R: Read + Seek>(r: &mut R) -> Result<(u64, u64, u64)> {
+    let len = r.seek(SeekFrom::End(0))?;
+    if len < EOCD_LEN {
+        return Err(bad("not a zip archive"));
+    }
+    // The record ends in a comment of up to 64 KiB.
+    let tail_len = len.min(EOCD_LEN + u16::MAX as u64);
+    r.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = vec![0u8; tail_len as usize];
+    r.read_exact(&mut tail)?;
+    let pos = (0..=tail.len() - EOCD_LEN as usize)
+        .rev()
+        .find(|&i| tail[i..i + 4] == EOCD_SIG.to_le_bytes())
+        .ok_or_else(|| bad("not a zip archive (no end of central directory)"))?;
+    let eocd_at = len - tail_len + pos as u64;
+
+    let mut c = Cursor::new(&tail[pos + 4..]);
+    let disk = c.read_u16::<LittleEndian>()?;
+    let cd_disk = c.read_u16::<LittleEndian>()?;
+    let _entries_here = c.read_u16::<LittleEndian>()?;
+    let entries = c.read_u16::<LittleEndian>()?;
+    let cd_size = c.read_u32::<LittleEndian>()?;
+    let cd_offset = c.read_u32::<LittleEndian>()?;
+    if entries != u16::MAX && cd_size != u32::MAX && cd_offset != u32::MAX {
+        if disk != 0 || cd_disk != 0 {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "split (multi-volume) zip archives are not supported",
+            ));
+        }
+        return Ok((cd_offset as u64, cd_size as u64, entries as u64));
+    }
+
+    if eocd_at < 20 {
+        return Err(bad("zip64 locator missing"));
+    }
+    r.seek(SeekFrom::Start(eocd_at - 20))?;
+    if r.read_u32::<LittleEndian>()? != ZIP64_LOCATOR_SIG {
+        return Err(bad("zip64 locator missing"));
+    }
+    let _disk = r.read_u32::<LittleEndian>()?;
+    let eocd64 = r.read_u64::<LittleEndian>()?;
+    r.seek(SeekFrom::Start(eocd64))?;
+    if r.read_u32::<LittleEndian>()? != ZIP64_EOCD_SIG {
+        return Err(bad("zip64 end of central directory missing"));
+    }
+    let _record_size = r.read_u64::<LittleEndian>()?;
+    let _made_by = r.read_u16::<LittleEndian>()?;
+    let _needed = r.read_u16::<LittleEndian>()?;
+    let disk = r.read_u32::<LittleEndian>()?;
+    let cd_disk = r.read_u32::<LittleEndian>()?;
+    let _entries_here = r.read_u64::<LittleEndian>()?;
+    let entries = r.read_u64::<LittleEndian>()?;
+    let cd_size = r.read_u64::<LittleEndian>()?;
+    let cd_offset = r.read_u64::<LittleEndian>()?;
+    if disk != 0 || cd_disk != 0 {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "split (multi-volume) zip archives are not supported",
+        ));
+    }
+    Ok((cd_offset, cd_size, entries))
+}
+
+fn zip_entries<R: Read + Seek>(r: &mut R) -> Result<Vec<ZipEntry>> {
+    let (offset, size, count) = zip_directory(r)?;
+    r.seek(SeekFrom::Start(offset))?;
+    let mut cd = Vec::new();
+    r.take(size).read_to_end(&mut cd)?;
+    let mut c = Cursor::new(cd.as_slice());
+
+    let mut out = Vec::with_capacity(count.min(1 << 16) as usize);
+    for _ in 0..count {
+        if c.read_u32::<LittleEndian>()? != CENTRAL_SIG {
+            return Err(bad("corrupt central directory"));
+        }
+        let _made_by = c.read_u16::<LittleEndian>()?;
+        let _needed = c.read_u16::<LittleEndian>()?;
+        let flags = c.read_u16::<LittleEndian>()?;
+        let method = c.read_u16::<LittleEndian>()?;
+        let _time = c.read_u16::<LittleEndian>()?;
+        let _date = c.read_u16::<LittleEndian>()?;
+        let crc = c.read_u32::<LittleEndian>()?;
+        let mut compressed = c.read_u32::<LittleEndian>()? as u64;
+        let mut size = c.read_u32::<LittleEndian>()? as u64;
+        let name_len = c.read_u16::<LittleEndian>()? as usize;
+        let extra_len = c.read_u16::<LittleEndian>()? as usize;
+        let comment_len = c.read_u16::<LittleEndian>()? as usize;
+        let _disk = c.read_u16::<LittleEndian>()?;
+        let _internal = c.read_u16::<LittleEndian>()?;
+        let _external = c.read_u32::<LittleEndian>()?;
+        let mut local_offset = c.read_u32::<LittleEndian>()? as u64;
+
+        let mut name = vec![0u8; name_len];
+        c.read_exact(&mut name)?;
+        let mut extra = vec![0u8; extra_len];
+        c.read_exact(&mut extra)?;
+        c.seek(SeekFrom::Current(comment_len as i64))?;
+
+        // Saturated fields are in the zip64 extra, in this order.
+        let mut x = Cursor::new(extra.as_slice());
+        while (x.position() as usize) + 4 <= extra.len() {
+            let id = x.read_u16::<LittleEndian>()?;
+            let len = x.read_u16::<LittleEndian>()? as u64;
+            let next = x.position() + len;
+            if id == ZIP64_EXTRA {
+                for field in [&mut size, &mut compressed, &mut local_offset] {
+                    if *field == u32::MAX as u64 {
+                        *field = x.read_u64::<LittleEndian>()?;
+                    }
+                }
+            }
+            x.set_position(next);
+        }
+
+        // Bit 11: UTF-8 name. Older tools wrote the OEM code page; only its
+        // ASCII part is worth trusting.
+        let name = if flags & 0x0800 != 0 {
+            String::from_utf8_lossy(&name).into_owned()
+        } else {
+            name.iter()
+                .map(|&b| if b.is_ascii() { b as char } else { '_' })
+                .collect()
+        };
+        out.push(ZipEntry {
+            name,
+            flags,
+            method,
+            crc,
+            compressed,
+            size,
+            local_offset,
+        });
+    }
+    Ok(out)
+}
+
+fn zip_method_name(method: u16) -> String {
+    match method {
+        9 => "Deflate64".to_string(),
+        12 => "bzip2".to_string(),
+        14 => "LZMA".to_string(),
+        93 => "Zstandard".to_string(),
+        95 => "XZ".to_string(),
+        99 => "AES".to_string(),
+        m => format!("method {m}"),
+    }
+}
+
+fn read_zip(member: &Member) -> Result<Vec<u8>> {
+    let mut r = BufReader::new(File::open(&member.archive)?);
+    let entries = zip_entries(&mut r)?;
+    let names: Vec<String> = entries.iter().map(|e| e.name.clone()).collect();
+    let e = &entries[pick(&names, member)?];
+    if e.flags & 0x0001 != 0 {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("{} is encrypted", e.name),
+        ));
+    }
+
+    r.seek(SeekFrom::Start(e.local_offset))?;
+    if r.read_u32::<LittleEndian>()? != LOCAL_SIG {
+        return Err(bad(format!("{}: corrupt local header", e.name)));
+    }
+    // The local copy of the name and extra may differ in length from the
+    // central one; everything else is taken from the central directory.
+    r.seek(SeekFrom::Current(22))?;
+    let name_len = r.read_u16::<LittleEndian>()? as i64;
+    let extra_len = r.read_u16::<LittleEndian>()? as i64;
+    r.seek(SeekFrom::Current(name_len + extra_len))?;
+
+    // Sizes come from the archive, so nothing is allocated up front and
+    // output is cut off one byte past the stated size: a member inflating
+    // to more (a zip bomb) fails the check below instead of filling memory.
+    let mut data = Vec::new();
+    let raw = (&mut r).take(e.compressed);
+    match e.method {
+        0 => {
+            raw.take(e.size.saturating_add(1)).read_to_end(&mut data)?;
+        }
+        8 => {
+            DeflateDecoder::new(raw)
+                .take(e.size.saturating_add(1))
+                .read_to_end(&mut data)?;
+        }
+        m => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!(
+                    "{} is compressed with {}; only stored and deflated members are supported",
+                    e.name,
+                    zip_method_name(m)
+                ),
+            ));
+        }
+    }
+    if data.len() as u64 > e.size {
+        return Err(bad(format!(
+            "{}: inflates past its stated {} bytes; refusing it",
+            e.name, e.size
+        )));
+    }
+    if data.len() as u64 != e.size || zip_crc(&data) != e.crc {
+        return Err(bad(format!(
+            "{}: CRC mismatch, the archive is damaged",
+            e.name
+        )));
+    }
+    Ok(data)
+}
+
+#[cfg(feature = "sevenz")]
+fn read_7z(member: &Member) -> Result<Vec<u8>> {
+    use sevenz_rust::{Password, SevenZReader};
+
+    let to_io = |e: sevenz_rust::Error| match e {
+        sevenz_rust::Error::Io(e, _) => e,
+        e => bad(e.to_string()),
+    };
+    let mut reader = SevenZReader::open(&member.archive, Password::empty()).map_err(to_io)?;
+    let names: Vec<String> = reader
+        .archive()
+        .files
+        .iter()
+        .map(|f| f.name().to_string())
+        .collect();
+    let want = names[pick(&names, member)?].clone();
+
+    // Solid blocks only decode front to back, so the entries before the
+    // wanted one are read through and dropped.
+    let mut found = None;
+    reader
+        .for_each_entries(|entry, data| {
+            if entry.name() != want {
+                std::io::copy(data, &mut std::io::sink())?;
+                return Ok(true);
+            }
+            // Same bound as for zip members.
+            let mut buf = Vec::new();
+            data.take(entry.size.saturating_add(1))
+                .read_to_end(&mut buf)?;
+            if buf.len() as u64 > entry.size {
+                return Err(sevenz_rust::Error::other(format!(
+                    "{want}: unpacks past its stated {} bytes; refusing it",
+                    entry.size
+                )));
+            }
+            found = Some(buf);
+            Ok(false)
+        })
+        .map_err(to_io)?;
+    found.ok_or_else(|| bad(format!("{want} has no data")))
+}
+
+#[cfg(not(feature = "sevenz"))]
+fn read_7z(member: &Member) -> Result<Vec<u8>> {
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        format!(
+            "reading {} needs 7z support; rebuild with `--features sevenz`",
+            member.archive.display()
+        ),
+    ))
+}
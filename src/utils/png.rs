@@ -0,0 +1,40 @@
+use std::io::{Result, Write};
+
+use flate2::{Compression, Crc, write::ZlibEncoder};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+/// 8-bit RGBA, no filtering; enough for previews, not for small files.
+pub fn encode_rgba(width: u32, height: u32, rgba: &[u8]) -> Result<Vec<u8>> {
+    let row = width as usize * 4;
+    let mut z = ZlibEncoder::new(Vec::new(), Compression::default());
+    for line in rgba.chunks(row).take(height as usize) {
+        // Filter type 0 per scanline.
+        z.write_all(&[0])?;
+        z.write_all(line)?;
+    }
+    let idat = z.finish()?;
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    // Bit depth 8, color type 6 (RGBA), deflate, no filter method, no interlace.
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut out = Vec::with_capacity(idat.len() + 64);
+    out.extend_from_slice(&SIGNATURE);
+    chunk(&mut out, b"IHDR", &ihdr);
+    chunk(&mut out, b"IDAT", &idat);
+    chunk(&mut out, b"IEND", &[]);
+    Ok(out)
+}
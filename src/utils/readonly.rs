@@ -0,0 +1,140 @@
+//! `--read-only` / `UE3_TOOLS_READONLY`: writes only go where the command
+//! line said output goes, so the tool can be pointed at an original game
+//! install without touching it. Every write path calls `check` first;
+//! in-place saves, `.bak` copies, sidecar files next to the input and the
+//! caches in the config directory are all refused.
+//!
+//! Allowed: the paths given as output arguments (`allow`), anything under
+//! them when they are directories, their `<out>.part` staging files, and
+//! the temp directory (spill files, scratch copies).
+
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicU8, Ordering},
+    },
+};
+
+use crate::utils::stats::{self, Phase};
+
+const UNSET: u8 = u8::MAX;
+
+static ENABLED: AtomicU8 = AtomicU8::new(UNSET);
+static ALLOWED: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+pub fn set_enabled(on: bool) {
+    ENABLED.store(on as u8, Ordering::Relaxed);
+}
+
+/// As set by `set_enabled`, else `UE3_TOOLS_READONLY` (anything but
+/// empty, `0` or `false`).
+pub fn enabled() -> bool {
+    static FROM_ENV: OnceLock<bool> = OnceLock::new();
+    match ENABLED.load(Ordering::Relaxed) {
+        UNSET => *FROM_ENV.get_or_init(|| {
+            std::env::var("UE3_TOOLS_READONLY").is_ok_and(|v| {
+                let v = v.trim();
+                !v.is_empty() && v != "0" && !v.eq_ignore_ascii_case("false")
+            })
+        }),
+        on => on != 0,
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Lets writes go to `path` (an output file or directory named on the
+/// command line).
+pub fn allow(path: impl AsRef<Path>) {
+    ALLOWED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(absolute(path.as_ref()));
+}
+
+fn allowed(path: &Path) -> bool {
+    let path = absolute(path);
+    if path.starts_with(absolute(&std::env::temp_dir())) {
+        return true;
+    }
+    ALLOWED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .any(|root| {
+            let mut part = OsString::from(root.as_os_str());
+            part.push(".part");
+            path.starts_with(root) || path.as_os_str() == part
+        })
+}
+
+/// A `PermissionDenied` error in read-only mode unless `path` may be
+/// written.
+pub fn check(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    if !enabled() || allowed(path) {
+        return Ok(());
+    }
+    Err(Error::new(
+        ErrorKind::PermissionDenied,
+        format!(
+            "{}: not writing in read-only mode; name an output with -o / --out-dir",
+            path.display()
+        ),
+    ))
+}
+
+/// `File::create`, after `check`.
+pub fn create(path: impl AsRef<Path>) -> Result<File> {
+    check(&path)?;
+    File::create(path)
+}
+
+/// `fs::write`, after `check`.
+pub fn write(path: impl AsRef<Path>, data: impl AsRef<[u8]>) -> Result<()> {
+    check(&path)?;
+    let _t = stats::time(Phase::Output);
+    std::fs::write(path, data)
+}
+
+/// `fs::create_dir_all`, after `check`.
+pub fn create_dir_all(path: impl AsRef<Path>) -> Result<()> {
+    check(&path)?;
+    std::fs::create_dir_all(path)
+}
+
+/// `fs::rename`, after checking the destination.
+pub fn rename(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
+    check(&to)?;
+    std::fs::rename(from, to)
+}
+
+/// `fs::copy`, after checking the destination.
+pub fn copy(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<u64> {
+    check(&to)?;
+    std::fs::copy(from, to)
+}
+
+/// `fs::hard_link`, after checking the new link.
+pub fn hard_link(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
+    check(&to)?;
+    std::fs::hard_link(from, to)
+}
+
+/// `fs::remove_file`, after `check`.
+pub fn remove_file(path: impl AsRef<Path>) -> Result<()> {
+    check(&path)?;
+    std::fs::remove_file(path)
+}
+
+/// `fs::remove_dir_all`, after `check`.
+pub fn remove_dir_all(path: impl AsRef<Path>) -> Result<()> {
+    check(&path)?;
+    std::fs::remove_dir_all(path)
+}
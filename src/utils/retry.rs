@@ -0,0 +1,78 @@
+//! Retrying writes that fail because something else has the file open.
+//! On Windows a virus scanner or the game's launcher briefly holding a
+//! package is common while a batch edit runs; the lock is usually gone a
+//! moment later.
+
+use std::{
+    io::{Error, ErrorKind, Result},
+    path::Path,
+    time::Duration,
+};
+
+use crate::utils::term;
+
+/// `ERROR_SHARING_VIOLATION` and `ERROR_LOCK_VIOLATION`.
+#[cfg(windows)]
+const LOCK_ERRORS: &[i32] = &[32, 33];
+
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    /// Tries after the first; 0 never retries.
+    pub retries: u32,
+    pub first_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            retries: 5,
+            first_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Whether `e` looks like another process holding the file, rather than
+/// something retrying won't fix.
+pub fn is_transient(e: &Error) -> bool {
+    #[cfg(windows)]
+    {
+        if e.raw_os_error().is_some_and(|c| LOCK_ERRORS.contains(&c)) {
+            return true;
+        }
+    }
+    matches!(
+        e.kind(),
+        ErrorKind::ResourceBusy | ErrorKind::ExecutableFileBusy | ErrorKind::WouldBlock
+    )
+}
+
+/// Runs `f`, retrying transient failures with a doubling delay. Other
+/// errors, and the last transient one, are returned as they are.
+pub fn with_backoff<T>(
+    backoff: &Backoff,
+    path: &Path,
+    mut f: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    let mut delay = backoff.first_delay;
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Err(e) if attempt < backoff.retries && is_transient(&e) => {
+                attempt += 1;
+                term::warn(
+                    "retry",
+                    format_args!(
+                        "{}: {e}; try {attempt} of {} in {delay:?}",
+                        path.display(),
+                        backoff.retries
+                    ),
+                );
+                std::thread::sleep(delay);
+                delay = (delay * 2).min(backoff.max_delay);
+            }
+            r => return r,
+        }
+    }
+}
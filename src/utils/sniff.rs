@@ -0,0 +1,153 @@
+use std::{
+    fmt,
+    io::{Error, ErrorKind, Result},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{utils::term, versions::PACKAGE_FILE_TAG};
+
+static FORCE: AtomicBool = AtomicBool::new(false);
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Lets files that don't look like UE3 packages through to the parser.
+pub fn set_force(force: bool) {
+    FORCE.store(force, Ordering::Relaxed);
+}
+
+pub fn forced() -> bool {
+    FORCE.load(Ordering::Relaxed)
+}
+
+/// Oldest and newest package versions seen from UE3 games. UE1/UE2 sit
+/// below, UE4 writes a negative legacy version where UE3 has its own.
+const UE3_VERSIONS: std::ops::RangeInclusive<i16> = 150..=900;
+
+/// Bits per byte above which a header is taken for ciphertext or a
+/// compressed stream rather than a summary.
+const ENCRYPTED_ENTROPY: f64 = 7.2;
+
+/// Best guess at why a file isn't readable as a UE3 package.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Suspect {
+    TooShort,
+    FullyCompressed,
+    BigEndian,
+    Encrypted,
+    OtherEngine(String),
+    OtherFormat(&'static str),
+    NotAPackage(u32),
+}
+
+impl fmt::Display for Suspect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Suspect::TooShort => write!(f, "file is too short for a package summary"),
+            Suspect::FullyCompressed => write!(
+                f,
+                "looks like a StoreFullyCompressed wrapper (the whole file is one chunk stream); \
+                 inflate it first, e.g. `report <dir> --decompress-all <out>`"
+            ),
+            Suspect::BigEndian => write!(
+                f,
+                "byte-swapped signature: a big-endian (console) package, which isn't supported"
+            ),
+            Suspect::Encrypted => write!(
+                f,
+                "no package signature and a high-entropy header: probably encrypted"
+            ),
+            Suspect::OtherEngine(what) => write!(f, "{what}"),
+            Suspect::OtherFormat(what) => write!(f, "not a package but {what}"),
+            Suspect::NotAPackage(sig) => {
+                write!(f, "not an Unreal package (signature 0x{sig:08X})")
+            }
+        }
+    }
+}
+
+impl Suspect {
+    /// Big-endian and other-engine packages are real packages this tool
+    /// doesn't handle; everything else is data it can't parse.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Suspect::BigEndian | Suspect::OtherEngine(_) => ErrorKind::Unsupported,
+            _ => ErrorKind::InvalidData,
+        }
+    }
+}
+
+fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for b in data {
+        counts[*b as usize] += 1;
+    }
+    let n = data.len() as f64;
+    counts
+        .iter()
+        .filter(|c| **c > 0)
+        .map(|c| {
+            let p = *c as f64 / n;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Looks at the first bytes of a file (a few hundred are plenty) and says
+/// what's wrong with it, or `None` when it looks like a UE3 package.
+pub fn sniff(head: &[u8]) -> Option<Suspect> {
+    if head.len() < 8 {
+        return Some(Suspect::TooShort);
+    }
+    let sig = u32::from_le_bytes([head[0], head[1], head[2], head[3]]);
+    let p_ver = i16::from_le_bytes([head[4], head[5]]);
+    let legacy = i32::from_le_bytes([head[4], head[5], head[6], head[7]]);
+
+    if sig.swap_bytes() == PACKAGE_FILE_TAG {
+        return Some(Suspect::BigEndian);
+    }
+    if sig != PACKAGE_FILE_TAG {
+        return Some(match head {
+            [b'P', b'K', 3, 4, ..] => Suspect::OtherFormat("a zip archive"),
+            [0x1F, 0x8B, ..] => Suspect::OtherFormat("a gzip stream"),
+            [0x78, 0x01 | 0x5E | 0x9C | 0xDA, ..] => Suspect::OtherFormat("a raw zlib stream"),
+            _ if head.len() >= 256 && entropy(head) > ENCRYPTED_ENTROPY => Suspect::Encrypted,
+            _ => Suspect::NotAPackage(sig),
+        });
+    }
+    if p_ver == 0 {
+        return Some(Suspect::FullyCompressed);
+    }
+    if legacy < 0 {
+        return Some(Suspect::OtherEngine(format!(
+            "Unreal Engine 4 or later package (legacy version {legacy})"
+        )));
+    }
+    if p_ver < *UE3_VERSIONS.start() {
+        return Some(Suspect::OtherEngine(format!(
+            "Unreal Engine 1/2 package (version {p_ver})"
+        )));
+    }
+    if p_ver > *UE3_VERSIONS.end() {
+        return Some(Suspect::OtherEngine(format!(
+            "package version {p_ver} is outside UE3's range; another engine or a scrambled summary"
+        )));
+    }
+    None
+}
+
+/// `sniff`, turned into an error unless `--force` was given.
+pub fn check(head: &[u8]) -> Result<()> {
+    match sniff(head) {
+        Some(s) if !forced() => Err(Error::new(
+            s.kind(),
+            format!("{s}; pass --force to try parsing it anyway"),
+        )),
+        Some(s) => {
+            // The summary is often read more than once per command.
+            if !WARNED.swap(true, Ordering::Relaxed) {
+                term::warn("force", format_args!("{s}; parsing anyway"));
+            }
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
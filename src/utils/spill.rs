@@ -0,0 +1,325 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Error, ErrorKind, Result, Seek, SeekFrom, Write},
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use memmap2::Mmap;
+
+const UNSET: u64 = u64::MAX;
+
+static BUDGET: AtomicU64 = AtomicU64::new(UNSET);
+/// Package bytes currently held in memory by `PackageBytes`.
+static RESIDENT: AtomicU64 = AtomicU64::new(0);
+static SPILL_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// `4G`, `512M`, `1.5G`, `65536`; suffixes are binary.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let t = s.trim();
+    let (num, mul) = match t.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => {
+            let mul: u64 = match c.to_ascii_uppercase() {
+                'K' => 1 << 10,
+                'M' => 1 << 20,
+                'G' => 1 << 30,
+                'T' => 1 << 40,
+                _ => 0,
+            };
+            (&t[..i], mul)
+        }
+        _ => (t, 1),
+    };
+    match num.trim().parse::<f64>() {
+        Ok(v) if mul != 0 && v >= 0.0 => Ok((v * mul as f64) as u64),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("'{s}' is not a size (e.g. 512M, 4G)"),
+        )),
+    }
+}
+
+/// Caps the package data kept in memory; `None` removes the cap.
+pub fn set_memory_budget(bytes: Option<u64>) {
+    BUDGET.store(bytes.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// The cap set by `set_memory_budget`, else `UE3_TOOLS_MEM_BUDGET`.
+pub fn memory_budget() -> Option<u64> {
+    static FROM_ENV: OnceLock<u64> = OnceLock::new();
+    let b = match BUDGET.load(Ordering::Relaxed) {
+        UNSET => *FROM_ENV.get_or_init(|| {
+            std::env::var("UE3_TOOLS_MEM_BUDGET")
+                .ok()
+                .and_then(|v| parse_size(&v).ok())
+                .unwrap_or(0)
+        }),
+        b => b,
+    };
+    (b != 0).then_some(b)
+}
+
+/// Whether another `extra` bytes fit next to what is already resident.
+pub fn fits_in_memory(extra: u64) -> bool {
+    memory_budget().is_none_or(|b| RESIDENT.load(Ordering::Relaxed).saturating_add(extra) <= b)
+}
+
+pub fn resident_bytes() -> u64 {
+    RESIDENT.load(Ordering::Relaxed)
+}
+
+fn spill_dir() -> PathBuf {
+    std::env::var_os("UE3_TOOLS_SPILL_DIR")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+struct Resident(Vec<u8>);
+
+impl Resident {
+    fn new(v: Vec<u8>) -> Self {
+        RESIDENT.fetch_add(v.len() as u64, Ordering::Relaxed);
+        Self(v)
+    }
+}
+
+impl Drop for Resident {
+    fn drop(&mut self) {
+        RESIDENT.fetch_sub(self.0.len() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Removes the spill file when dropped.
+struct TempPath(PathBuf);
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// A mapping of the package file itself, or of a spill file holding the
+/// decompressed image. Fields drop in order, so the map is gone before the
+/// spill file is removed.
+struct Mapped {
+    map: Mmap,
+    /// The package file, when that is what is mapped.
+    source: Option<PathBuf>,
+    _temp: Option<TempPath>,
+}
+
+enum Repr {
+    Mem(Resident),
+    Mapped(Mapped),
+}
+
+/// Package data, either in memory or paged in from disk by the OS. Reads
+/// go through `Deref<Target = [u8]>` either way; clones share the data.
+#[derive(Clone)]
+pub struct PackageBytes(Arc<Repr>);
+
+impl PackageBytes {
+    /// Maps `path` read-only. The file must not change while mapped.
+    pub fn map_file(path: &Path) -> Result<Self> {
+        let f = File::open(path)?;
+        // SAFETY: read-only mapping; the tools never write a package they
+        // have open through a mapping.
+        let map = unsafe { Mmap::map(&f)? };
+        Ok(Self(Arc::new(Repr::Mapped(Mapped {
+            map,
+            source: Some(path.to_path_buf()),
+            _temp: None,
+        }))))
+    }
+
+    /// True when this is a mapping of the file at `path`. Windows refuses
+    /// to replace a file that is mapped, so whoever saves over it has to
+    /// `detach` first.
+    pub fn maps(&self, path: &Path) -> bool {
+        let Repr::Mapped(Mapped {
+            source: Some(src), ..
+        }) = &*self.0
+        else {
+            return false;
+        };
+        match (std::fs::canonicalize(src), std::fs::canonicalize(path)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => src == path,
+        }
+    }
+
+    /// The same bytes, no longer backed by the package file: copied into
+    /// memory, or into a spill file when that doesn't fit the budget.
+    /// Other clones keep the old mapping until they drop.
+    pub fn detach(&self) -> Result<Self> {
+        if !matches!(
+            &*self.0,
+            Repr::Mapped(Mapped {
+                source: Some(_),
+                ..
+            })
+        ) {
+            return Ok(self.clone());
+        }
+        let mut w = ImageWriter::new(self.len() as u64)?;
+        for chunk in self.chunks(1 << 20) {
+            w.extend(chunk)?;
+        }
+        w.finish()
+    }
+
+    /// True when the data lives on disk rather than in memory.
+    pub fn is_mapped(&self) -> bool {
+        matches!(&*self.0, Repr::Mapped(_))
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl From<Vec<u8>> for PackageBytes {
+    fn from(v: Vec<u8>) -> Self {
+        Self(Arc::new(Repr::Mem(Resident::new(v))))
+    }
+}
+
+impl Deref for PackageBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &*self.0 {
+            Repr::Mem(r) => &r.0,
+            Repr::Mapped(m) => &m.map,
+        }
+    }
+}
+
+impl AsRef<[u8]> for PackageBytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+/// Builds a package image in memory, or in a temp file when `size_hint`
+/// doesn't fit the memory budget.
+pub struct ImageWriter(Sink);
+
+enum Sink {
+    Mem(Vec<u8>),
+    File {
+        w: BufWriter<File>,
+        temp: TempPath,
+        len: u64,
+    },
+}
+
+impl ImageWriter {
+    pub fn new(size_hint: u64) -> Result<Self> {
+        if fits_in_memory(size_hint) {
+            return Ok(Self(Sink::Mem(Vec::with_capacity(size_hint as usize))));
+        }
+        let path = spill_dir().join(format!(
+            "ue3-tools-{}-{}.spill",
+            std::process::id(),
+            SPILL_SEQ.fetch_add(1, Ordering::Relaxed)
+        ));
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self(Sink::File {
+            w: BufWriter::new(f),
+            temp: TempPath(path),
+            len: 0,
+        }))
+    }
+
+    pub fn is_spilling(&self) -> bool {
+        matches!(self.0, Sink::File { .. })
+    }
+
+    pub fn len(&self) -> u64 {
+        match &self.0 {
+            Sink::Mem(v) => v.len() as u64,
+            Sink::File { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn extend(&mut self, data: &[u8]) -> Result<()> {
+        match &mut self.0 {
+            Sink::Mem(v) => v.extend_from_slice(data),
+            Sink::File { w, len, .. } => {
+                w.write_all(data)?;
+                *len += data.len() as u64;
+            }
+        }
+        Ok(())
+    }
+
+    /// Zero-fills up to `n` bytes; does nothing if already longer.
+    pub fn pad_to(&mut self, n: u64) -> Result<()> {
+        match &mut self.0 {
+            Sink::Mem(v) => {
+                if (v.len() as u64) < n {
+                    v.resize(n as usize, 0);
+                }
+            }
+            Sink::File { w, len, .. } => {
+                while *len < n {
+                    let step = (n - *len).min(1 << 16) as usize;
+                    w.write_all(&vec![0u8; step])?;
+                    *len += step as u64;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Overwrites already-written bytes at `at`.
+    pub fn write_at(&mut self, at: u64, data: &[u8]) -> Result<()> {
+        let end = at + data.len() as u64;
+        if end > self.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("write at 0x{at:X} past the end of the image"),
+            ));
+        }
+        match &mut self.0 {
+            Sink::Mem(v) => v[at as usize..end as usize].copy_from_slice(data),
+            Sink::File { w, len, .. } => {
+                w.seek(SeekFrom::Start(at))?;
+                w.write_all(data)?;
+                w.seek(SeekFrom::Start(*len))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<PackageBytes> {
+        match self.0 {
+            Sink::Mem(v) => Ok(v.into()),
+            Sink::File { w, temp, .. } => {
+                let f = w.into_inner().map_err(|e| e.into_error())?;
+                // SAFETY: the spill file is private to this process and only
+                // removed once the mapping is dropped.
+                let map = unsafe { Mmap::map(&f)? };
+                Ok(PackageBytes(Arc::new(Repr::Mapped(Mapped {
+                    map,
+                    source: None,
+                    _temp: Some(temp),
+                }))))
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! `--stats`: wall time per phase of a run and the peak resident set, for
+//! performance reports that say where the time went.
+//!
+//! Phases nest (an extract parses properties while writing files); time is
+//! charged to the innermost one only, so the rows add up to at most the
+//! wall time. Work on several threads is summed, which can exceed it.
+
+use std::{
+    cell::RefCell,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::utils::term::{self, Color};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Decompress,
+    Tables,
+    Properties,
+    Output,
+}
+
+impl Phase {
+    const ALL: [Phase; 4] = [
+        Phase::Decompress,
+        Phase::Tables,
+        Phase::Properties,
+        Phase::Output,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::Decompress => "decompress",
+            Phase::Tables => "parse tables",
+            Phase::Properties => "parse properties",
+            Phase::Output => "write output",
+        }
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static NANOS: [AtomicU64; 4] = [const { AtomicU64::new(0) }; 4];
+static CALLS: [AtomicU64; 4] = [const { AtomicU64::new(0) }; 4];
+
+thread_local! {
+    /// Open phases on this thread, innermost last, with when each was
+    /// last charged.
+    static OPEN: RefCell<Vec<(Phase, Instant)>> = const { RefCell::new(Vec::new()) };
+}
+
+pub fn set_enabled(on: bool) {
+    ENABLED.store(on, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn charge(phase: Phase, since: Instant, now: Instant) {
+    NANOS[phase as usize].fetch_add((now - since).as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// Charges the time until the guard drops to `phase`; nothing when
+/// `--stats` is off.
+#[must_use]
+pub struct Timer {
+    on: bool,
+}
+
+pub fn time(phase: Phase) -> Timer {
+    if !enabled() {
+        return Timer { on: false };
+    }
+    CALLS[phase as usize].fetch_add(1, Ordering::Relaxed);
+    let now = Instant::now();
+    OPEN.with_borrow_mut(|open| {
+        if let Some((outer, since)) = open.last_mut() {
+            charge(*outer, *since, now);
+        }
+        open.push((phase, now));
+    });
+    Timer { on: true }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if !self.on {
+            return;
+        }
+        let now = Instant::now();
+        OPEN.with_borrow_mut(|open| {
+            if let Some((phase, since)) = open.pop() {
+                charge(phase, since, now);
+            }
+            if let Some((_, since)) = open.last_mut() {
+                *since = now;
+            }
+        });
+    }
+}
+
+/// Peak resident set size of the process in bytes, where the OS says.
+pub fn peak_rss() -> Option<u64> {
+    #[cfg(unix)]
+    {
+        let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+        // SAFETY: getrusage only writes the struct it is given.
+        if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        // SAFETY: initialised by the successful call above.
+        let max = unsafe { usage.assume_init() }.ru_maxrss.max(0) as u64;
+        // Bytes on macOS, KiB everywhere else.
+        Some(if cfg!(target_os = "macos") {
+            max
+        } else {
+            max * 1024
+        })
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+fn mib(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Prints the table to stderr, so it stays out of piped output.
+pub fn report(wall: Duration) {
+    let mut charged = Duration::ZERO;
+    eprintln!(
+        "{} {:.3}s wall, peak RSS {}",
+        term::epaint(Color::Highlight, "stats:"),
+        wall.as_secs_f64(),
+        peak_rss().map_or_else(|| "unknown".to_string(), mib)
+    );
+    for phase in Phase::ALL {
+        let spent = Duration::from_nanos(NANOS[phase as usize].load(Ordering::Relaxed));
+        let calls = CALLS[phase as usize].load(Ordering::Relaxed);
+        charged += spent;
+        eprintln!(
+            "  {:<18} {:>9.3}s  {calls} call(s)",
+            phase.as_str(),
+            spent.as_secs_f64()
+        );
+    }
+    eprintln!(
+        "  {:<18} {:>9.3}s",
+        "other",
+        wall.saturating_sub(charged).as_secs_f64()
+    );
+}
@@ -0,0 +1,118 @@
+use std::{
+    fmt::{self, Display},
+    io::IsTerminal,
+    sync::{
+        OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+static NO_COLOR: AtomicBool = AtomicBool::new(false);
+
+/// `--no-color`; `NO_COLOR` and output that isn't a terminal turn colors
+/// off without it.
+pub fn set_no_color(off: bool) {
+    NO_COLOR.store(off, Ordering::Relaxed);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+pub fn color_enabled(stream: Stream) -> bool {
+    static ENV: OnceLock<bool> = OnceLock::new();
+    static STDOUT: OnceLock<bool> = OnceLock::new();
+    static STDERR: OnceLock<bool> = OnceLock::new();
+    if NO_COLOR.load(Ordering::Relaxed)
+        || *ENV.get_or_init(|| std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()))
+    {
+        return false;
+    }
+    match stream {
+        Stream::Stdout => *STDOUT.get_or_init(|| std::io::stdout().is_terminal()),
+        Stream::Stderr => *STDERR.get_or_init(|| std::io::stderr().is_terminal()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Cyan,
+    Gray,
+    Highlight,
+}
+
+impl Color {
+    fn code(self) -> u8 {
+        match self {
+            Color::Red => 31,
+            Color::Green => 32,
+            Color::Yellow => 33,
+            Color::Cyan => 36,
+            Color::Gray => 90,
+            Color::Highlight => 93,
+        }
+    }
+}
+
+/// A value printed in color when its stream takes colors. Width and
+/// alignment apply to the text, not the escape codes, so `{:<12}` still
+/// lines columns up.
+pub struct Paint<T> {
+    value: T,
+    color: Color,
+    stream: Stream,
+}
+
+impl<T: Display> Display for Paint<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = self.value.to_string();
+        if !color_enabled(self.stream) {
+            return f.pad(&text);
+        }
+        write!(f, "\x1b[{}m", self.color.code())?;
+        f.pad(&text)?;
+        f.write_str("\x1b[0m")
+    }
+}
+
+/// For `println!`.
+pub fn paint<T: Display>(color: Color, value: T) -> Paint<T> {
+    Paint {
+        value,
+        color,
+        stream: Stream::Stdout,
+    }
+}
+
+/// For `eprintln!`.
+pub fn epaint<T: Display>(color: Color, value: T) -> Paint<T> {
+    Paint {
+        value,
+        color,
+        stream: Stream::Stderr,
+    }
+}
+
+/// `  tag: msg` on stderr, tag in yellow.
+pub fn warn(tag: &str, msg: impl Display) {
+    eprintln!("  {}: {msg}", epaint(Color::Yellow, tag));
+}
+
+/// `  tag: msg` on stderr, tag in red.
+pub fn error(tag: &str, msg: impl Display) {
+    eprintln!("  {}: {msg}", epaint(Color::Red, tag));
+}
+
+/// Width of the widest cell, for lining up a column.
+pub fn column_width<'a>(cells: impl IntoIterator<Item = &'a str>) -> usize {
+    cells
+        .into_iter()
+        .map(|c| c.chars().count())
+        .max()
+        .unwrap_or(0)
+}
@@ -0,0 +1,493 @@
+//! VCDIFF (RFC 3284), the format xdelta3 reads and writes. Encoding uses
+//! the default code table with explicit sizes and only ADD and COPY from
+//! the source, which any conforming decoder accepts; no secondary
+//! compression.
+
+use std::{
+    borrow::Cow,
+    io::{Error, ErrorKind, Result},
+};
+
+const MAGIC: [u8; 4] = [0xD6, 0xC3, 0xC4, 0x00];
+const VCD_APPHEADER: u8 = 0x04;
+const VCD_SOURCE: u8 = 0x01;
+
+/// Default code table entries: ADD and COPY (mode 0, VCD_SELF) with the
+/// size given separately.
+const ADD: u8 = 1;
+const COPY_SELF: u8 = 19;
+
+/// Target bytes per window, xdelta3's default.
+const WINDOW: usize = 1 << 23;
+/// Shortest match worth a COPY, and the length hashed to find one.
+const BLOCK: usize = 32;
+/// Source positions indexed at most; beyond this the index gets sparser.
+const MAX_INDEX: usize = 1 << 22;
+const HASH_MUL: u32 = 0x0100_0193;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DeltaStats {
+    pub copied: u64,
+    pub added: u64,
+    pub windows: usize,
+}
+
+fn put_int(out: &mut Vec<u8>, mut v: u64) {
+    let mut tmp = [0u8; 10];
+    let mut i = tmp.len() - 1;
+    tmp[i] = (v & 0x7F) as u8;
+    v >>= 7;
+    while v > 0 {
+        i -= 1;
+        tmp[i] = 0x80 | (v & 0x7F) as u8;
+        v >>= 7;
+    }
+    out.extend_from_slice(&tmp[i..]);
+}
+
+fn block_hash(b: &[u8]) -> u32 {
+    b.iter().fold(0u32, |h, &x| {
+        h.wrapping_mul(HASH_MUL).wrapping_add(x as u32)
+    })
+}
+
+/// Open-addressed table of source positions by block hash; later
+/// positions don't displace earlier ones.
+struct SourceIndex {
+    slots: Vec<u32>,
+    mask: usize,
+}
+
+impl SourceIndex {
+    const EMPTY: u32 = u32::MAX;
+
+    fn build(src: &[u8]) -> Self {
+        let blocks = src.len() / BLOCK;
+        let step = BLOCK * blocks.div_ceil(MAX_INDEX).max(1);
+        let entries = src.len() / step;
+        let size = (entries * 2).next_power_of_two().max(16);
+        let mut idx = Self {
+            slots: vec![Self::EMPTY; size],
+            mask: size - 1,
+        };
+        let mut at = 0;
+        while at + BLOCK <= src.len() {
+            let mut s = block_hash(&src[at..at + BLOCK]) as usize & idx.mask;
+            // Linear probing, bounded so a flood of equal blocks stays cheap.
+            for _ in 0..8 {
+                if idx.slots[s] == Self::EMPTY {
+                    idx.slots[s] = at as u32;
+                    break;
+                }
+                s = (s + 1) & idx.mask;
+            }
+            at += step;
+        }
+        idx
+    }
+
+    fn find(&self, src: &[u8], hash: u32, block: &[u8]) -> Option<usize> {
+        let mut s = hash as usize & self.mask;
+        for _ in 0..8 {
+            let p = self.slots[s];
+            if p == Self::EMPTY {
+                return None;
+            }
+            let p = p as usize;
+            if src.get(p..p + BLOCK) == Some(block) {
+                return Some(p);
+            }
+            s = (s + 1) & self.mask;
+        }
+        None
+    }
+}
+
+/// One window's three sections.
+#[derive(Default)]
+struct Sections {
+    data: Vec<u8>,
+    inst: Vec<u8>,
+    addr: Vec<u8>,
+}
+
+impl Sections {
+    fn add(&mut self, bytes: &[u8], stats: &mut DeltaStats) {
+        if bytes.is_empty() {
+            return;
+        }
+        self.inst.push(ADD);
+        put_int(&mut self.inst, bytes.len() as u64);
+        self.data.extend_from_slice(bytes);
+        stats.added += bytes.len() as u64;
+    }
+
+    fn copy(&mut self, from: usize, len: usize, stats: &mut DeltaStats) {
+        self.inst.push(COPY_SELF);
+        put_int(&mut self.inst, len as u64);
+        put_int(&mut self.addr, from as u64);
+        stats.copied += len as u64;
+    }
+}
+
+fn match_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+/// A delta that turns `source` into `target`. `app_header` is stored in
+/// the file header for the application (xdelta3 puts file names there).
+pub fn encode(source: &[u8], target: &[u8], app_header: &[u8]) -> Result<(Vec<u8>, DeltaStats)> {
+    if source.len() > u32::MAX as usize - 1 {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "source over 4 GiB; not supported by the delta encoder",
+        ));
+    }
+    let index = SourceIndex::build(source);
+    let mut stats = DeltaStats::default();
+
+    let mut out = Vec::with_capacity(64 + target.len() / 8);
+    out.extend_from_slice(&MAGIC);
+    out.push(VCD_APPHEADER);
+    put_int(&mut out, app_header.len() as u64);
+    out.extend_from_slice(app_header);
+
+    // B^(BLOCK-1), to take the outgoing byte off the rolling hash.
+    let out_mul = (1..BLOCK).fold(1u32, |m, _| m.wrapping_mul(HASH_MUL));
+    // Where the last COPY ended in the source; edits mostly continue there.
+    let mut next_src = 0usize;
+
+    for (w, win) in target.chunks(WINDOW).enumerate() {
+        let base = w * WINDOW;
+        let mut sec = Sections::default();
+        // First byte of the window not yet covered by an instruction.
+        let mut lit_from = 0usize;
+        let mut i = 0usize;
+        let mut hash = None;
+        while i + BLOCK <= win.len() {
+            let block = &win[i..i + BLOCK];
+            let h = *hash.get_or_insert_with(|| block_hash(block));
+            let found = [next_src, base + i]
+                .into_iter()
+                .find(|&c| source.get(c..c + BLOCK) == Some(block))
+                .or_else(|| index.find(source, h, block));
+
+            let Some(mut s) = found else {
+                hash = win.get(i + BLOCK).map(|&came| {
+                    h.wrapping_sub((win[i] as u32).wrapping_mul(out_mul))
+                        .wrapping_mul(HASH_MUL)
+                        .wrapping_add(came as u32)
+                });
+                i += 1;
+                continue;
+            };
+
+            let mut start = i;
+            let mut len = BLOCK + match_len(&source[s + BLOCK..], &win[i + BLOCK..]);
+            // Take back what matches from the bytes waiting to be added.
+            while start > lit_from && s > 0 && source[s - 1] == win[start - 1] {
+                start -= 1;
+                s -= 1;
+                len += 1;
+            }
+            sec.add(&win[lit_from..start], &mut stats);
+            sec.copy(s, len, &mut stats);
+            next_src = s + len;
+            i = start + len;
+            lit_from = i;
+            hash = None;
+        }
+        sec.add(&win[lit_from..], &mut stats);
+
+        let mut body = Vec::with_capacity(sec.data.len() + sec.inst.len() + sec.addr.len() + 24);
+        put_int(&mut body, win.len() as u64);
+        // Delta_Indicator: no section is compressed.
+        body.push(0);
+        put_int(&mut body, sec.data.len() as u64);
+        put_int(&mut body, sec.inst.len() as u64);
+        put_int(&mut body, sec.addr.len() as u64);
+        body.extend_from_slice(&sec.data);
+        body.extend_from_slice(&sec.inst);
+        body.extend_from_slice(&sec.addr);
+
+        if source.is_empty() {
+            out.push(0);
+        } else {
+            out.push(VCD_SOURCE);
+            put_int(&mut out, source.len() as u64);
+            put_int(&mut out, 0);
+        }
+        put_int(&mut out, body.len() as u64);
+        out.extend_from_slice(&body);
+        stats.windows += 1;
+    }
+    Ok((out, stats))
+}
+
+const VCD_DECOMPRESS: u8 = 0x01;
+const VCD_CODETABLE: u8 = 0x02;
+const VCD_TARGET: u8 = 0x02;
+/// xdelta3's extension: an Adler-32 of the target window.
+const VCD_ADLER32: u8 = 0x04;
+
+const NEAR: usize = 4;
+const SAME: usize = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Inst {
+    Noop,
+    Add,
+    Run,
+    Copy(u8),
+}
+
+/// The default code table (RFC 3284 §5.6): pairs of (instruction, size).
+fn code_table() -> Vec<[(Inst, usize); 2]> {
+    let none = (Inst::Noop, 0);
+    let mut t = Vec::with_capacity(256);
+    t.push([(Inst::Run, 0), none]);
+    for size in 0..=17 {
+        t.push([(Inst::Add, size), none]);
+    }
+    for mode in 0..9u8 {
+        t.push([(Inst::Copy(mode), 0), none]);
+        for size in 4..=18 {
+            t.push([(Inst::Copy(mode), size), none]);
+        }
+    }
+    for mode in 0..9u8 {
+        let copy_sizes = if mode < 6 { 4..=6 } else { 4..=4 };
+        for add in 1..=4 {
+            for size in copy_sizes.clone() {
+                t.push([(Inst::Add, add), (Inst::Copy(mode), size)]);
+            }
+        }
+    }
+    for mode in 0..9u8 {
+        t.push([(Inst::Copy(mode), 4), (Inst::Add, 1)]);
+    }
+    t
+}
+
+fn bad(msg: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("delta: {}", msg.into()))
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let b = *self.buf.get(self.pos).ok_or_else(|| bad("truncated"))?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn int(&mut self) -> Result<u64> {
+        let mut v = 0u64;
+        for _ in 0..10 {
+            let b = self.byte()?;
+            v = (v << 7) | (b & 0x7F) as u64;
+            if b & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err(bad("integer too long"))
+    }
+
+    fn size(&mut self) -> Result<usize> {
+        usize::try_from(self.int()?).map_err(|_| bad("size out of range"))
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let s = self
+            .buf
+            .get(self.pos..self.pos.saturating_add(n))
+            .ok_or_else(|| bad("truncated"))?;
+        self.pos += n;
+        Ok(s)
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &x in chunk {
+            a += x as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// The file header up to the first window, and its application data.
+fn read_header<'a>(r: &mut Reader<'a>) -> Result<Option<&'a [u8]>> {
+    if r.take(4)? != MAGIC {
+        return Err(bad("not a VCDIFF file"));
+    }
+    let ind = r.byte()?;
+    if ind & VCD_DECOMPRESS != 0 {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "delta uses secondary compression; recreate it with `xdelta3 -S none`",
+        ));
+    }
+    if ind & VCD_CODETABLE != 0 {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "delta uses a custom code table",
+        ));
+    }
+    if ind & VCD_APPHEADER != 0 {
+        let n = r.size()?;
+        return Ok(Some(r.take(n)?));
+    }
+    Ok(None)
+}
+
+/// The application data from the file header (xdelta3 stores file names,
+/// `encode` what its caller passed).
+pub fn app_header(delta: &[u8]) -> Result<Option<&[u8]>> {
+    read_header(&mut Reader::new(delta))
+}
+
+/// Applies `delta` to `source`.
+pub fn decode(source: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let table = code_table();
+    let mut r = Reader::new(delta);
+    read_header(&mut r)?;
+    let mut out: Vec<u8> = Vec::new();
+
+    while !r.at_end() {
+        let win_ind = r.byte()?;
+        let segment: Cow<[u8]> = if win_ind & (VCD_SOURCE | VCD_TARGET) != 0 {
+            let len = r.size()?;
+            let pos = r.size()?;
+            let from: &[u8] = if win_ind & VCD_SOURCE != 0 {
+                source
+            } else {
+                &out
+            };
+            let seg = from
+                .get(pos..pos.saturating_add(len))
+                .ok_or_else(|| bad(format!("segment {pos}+{len} outside the source")))?;
+            if win_ind & VCD_SOURCE != 0 {
+                Cow::Borrowed(seg)
+            } else {
+                // Copied out so `out` can grow while it's in use.
+                Cow::Owned(seg.to_vec())
+            }
+        } else {
+            Cow::Borrowed(&[])
+        };
+        let body_len = r.size()?;
+        let mut body = Reader::new(r.take(body_len)?);
+        let target_len = body.size()?;
+        if body.byte()? != 0 {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "delta window uses secondary compression; recreate it with `xdelta3 -S none`",
+            ));
+        }
+        let data_len = body.size()?;
+        let inst_len = body.size()?;
+        let addr_len = body.size()?;
+        let checksum = if win_ind & VCD_ADLER32 != 0 {
+            let b = body.take(4)?;
+            Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        } else {
+            None
+        };
+        let mut data = Reader::new(body.take(data_len)?);
+        let mut inst = Reader::new(body.take(inst_len)?);
+        let mut addr = Reader::new(body.take(addr_len)?);
+
+        let mut win: Vec<u8> = Vec::with_capacity(target_len.min(WINDOW * 4));
+        let mut near = [0usize; NEAR];
+        let mut next_near = 0;
+        let mut same = [0usize; SAME * 256];
+        while !inst.at_end() {
+            let code = inst.byte()?;
+            for (kind, table_size) in table[code as usize] {
+                if kind == Inst::Noop {
+                    continue;
+                }
+                let size = match table_size {
+                    0 => inst.size()?,
+                    n => n,
+                };
+                let end = win
+                    .len()
+                    .checked_add(size)
+                    .ok_or_else(|| bad("instruction size overflows window"))?;
+                if end > target_len {
+                    return Err(bad("window overruns its target size"));
+                }
+                match kind {
+                    Inst::Add => win.extend_from_slice(data.take(size)?),
+                    Inst::Run => {
+                        let b = data.byte()?;
+                        win.resize(end, b);
+                    }
+                    Inst::Copy(mode) => {
+                        let here = segment.len() + win.len();
+                        let a = match mode {
+                            0 => addr.size()?,
+                            1 => here
+                                .checked_sub(addr.size()?)
+                                .ok_or_else(|| bad("address before the window"))?,
+                            2..=5 => near[mode as usize - 2].wrapping_add(addr.size()?),
+                            _ => same[(mode as usize - 6) * 256 + addr.byte()? as usize],
+                        };
+                        if a >= here {
+                            return Err(bad(format!("COPY from {a}, past {here}")));
+                        }
+                        near[next_near] = a;
+                        next_near = (next_near + 1) % NEAR;
+                        same[a % (SAME * 256)] = a;
+                        let from_end = a
+                            .checked_add(size)
+                            .ok_or_else(|| bad("instruction size overflows window"))?;
+                        if from_end <= segment.len() {
+                            win.extend_from_slice(&segment[a..from_end]);
+                        } else {
+                            // May overlap what it writes, byte by byte.
+                            for k in 0..size {
+                                let p = a + k;
+                                let b = match p.checked_sub(segment.len()) {
+                                    Some(t) => win[t],
+                                    None => segment[p],
+                                };
+                                win.push(b);
+                            }
+                        }
+                    }
+                    Inst::Noop => {}
+                }
+            }
+        }
+        if win.len() != target_len {
+            return Err(bad(format!(
+                "window decoded to {} bytes, expected {target_len}",
+                win.len()
+            )));
+        }
+        if checksum.is_some_and(|c| c != adler32(&win)) {
+            return Err(bad("window checksum mismatch"));
+        }
+        out.extend_from_slice(&win);
+    }
+    Ok(out)
+}
@@ -0,0 +1,89 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    path::{Component, Path, PathBuf},
+};
+
+pub fn is_package(p: &Path) -> bool {
+    matches!(
+        p.extension()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_ascii_lowercase())
+            .as_deref(),
+        Some("upk" | "u" | "umap")
+    )
+}
+
+/// Every package under `root`, sorted for stable output.
+pub fn package_files(root: &Path) -> Vec<PathBuf> {
+    let mut out = Vec::new();
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(e) => e,
+            Err(e) => {
+                eprintln!("walk: skip {}: {}", dir.display(), e);
+                continue;
+            }
+        };
+        for e in entries.flatten() {
+            let p = e.path();
+            if p.is_dir() {
+                stack.push(p);
+            } else if is_package(&p) {
+                out.push(p);
+            }
+        }
+    }
+    out.sort();
+    out
+}
+
+/// Every file under `dir`, sorted; unreadable directories are skipped.
+pub fn files_under(dir: &Path) -> Vec<PathBuf> {
+    let mut out = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(d) = stack.pop() {
+        let Ok(entries) = std::fs::read_dir(&d) else {
+            continue;
+        };
+        for e in entries.flatten() {
+            let p = e.path();
+            if p.is_dir() {
+                stack.push(p);
+            } else {
+                out.push(p);
+            }
+        }
+    }
+    out.sort();
+    out
+}
+
+/// `p` relative to `root` with `/` separators, as manifests and indexes
+/// key files.
+pub fn rel_key(root: &Path, p: &Path) -> String {
+    p.strip_prefix(root)
+        .unwrap_or(p)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// A path from a manifest, to be joined under `within`: refused when
+/// empty, absolute or climbing out with `..`.
+pub fn contained_path<'a>(p: &'a str, within: &str) -> Result<&'a Path> {
+    let path = Path::new(p);
+    if p.is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("manifest path '{p}' escapes {within}"),
+        ));
+    }
+    Ok(path)
+}
+
+/// File name without extension; empty when there is none.
+pub fn stem(p: &Path) -> String {
+    p.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
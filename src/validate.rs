@@ -0,0 +1,401 @@
+use std::{
+    collections::HashMap,
+    io::{Cursor, Result},
+    path::Path,
+    rc::Rc,
+};
+
+use crate::{
+    disasm::{disassemble, reserialize_script, script_span},
+    exit::validation_failed,
+    package::Package,
+    schemadb::{LazyPackage, SchemaDb, open_package_file},
+    upkreader::{FName, UPKPak},
+    utils::{
+        backup::backup_original,
+        term::{self, Color, paint},
+    },
+    versions::VER_NETINDEX_STORED_AS_INT,
+};
+
+fn check_ref(pak: &UPKPak, what: &str, field: &str, idx: i32, problems: &mut Vec<String>) {
+    let imports = pak.import_table.len() as i32;
+    let exports = pak.export_table.len() as i32;
+    if idx < -imports || idx > exports {
+        problems.push(format!(
+            "{what}: {field} {idx} outside -{imports}..={exports}"
+        ));
+    }
+}
+
+fn check_name(pak: &UPKPak, what: &str, field: &str, n: &FName, problems: &mut Vec<String>) {
+    if n.name_index < 0 || n.name_index as usize >= pak.name_table.len() {
+        problems.push(format!(
+            "{what}: {field} name index {} outside the name table ({} names)",
+            n.name_index,
+            pak.name_table.len()
+        ));
+    }
+}
+
+/// For each name, the index of the first entry with the same text; a
+/// duplicate maps to an earlier index.
+fn first_occurrences(names: &[String]) -> Vec<i32> {
+    let mut first: HashMap<&str, i32> = HashMap::with_capacity(names.len());
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, n)| *first.entry(n.as_str()).or_insert(i as i32))
+        .collect()
+}
+
+/// References to duplicate names, per name index, and the same data with
+/// each of them pointed at the first entry of that text.
+struct NameRemap<'a> {
+    first: &'a [i32],
+    uses: Vec<usize>,
+}
+
+impl NameRemap<'_> {
+    fn fname(&mut self, n: &FName) -> FName {
+        let i = n.name_index;
+        match self.first.get(i as usize) {
+            Some(&f) if f != i => {
+                self.uses[i as usize] += 1;
+                FName {
+                    name_index: f,
+                    name_instance: n.name_instance,
+                }
+            }
+            _ => n.clone(),
+        }
+    }
+
+    /// `fresh` is `old` re-serialized with every name written as its first
+    /// entry; accepted only when that is the sole difference.
+    fn merge_words(&mut self, old: &[u8], fresh: &[u8]) -> Option<()> {
+        if old.len() != fresh.len() {
+            return None;
+        }
+        let word = |b: &[u8], at: usize| {
+            b.get(at..at + 4)
+                .map(|w| i32::from_le_bytes(w.try_into().unwrap()))
+        };
+        let mut i = 0;
+        while i < old.len() {
+            if old[i] == fresh[i] {
+                i += 1;
+                continue;
+            }
+            // The differing byte is somewhere in a name index.
+            let at = (i.saturating_sub(3)..=i).find(|&at| {
+                matches!(
+                    (word(old, at), word(fresh, at)),
+                    (Some(o), Some(f)) if o != f && self.first.get(o as usize) == Some(&f)
+                )
+            })?;
+            self.uses[word(old, at)? as usize] += 1;
+            i = at + 4;
+        }
+        Some(())
+    }
+
+    /// The export's bytes with names in its tagged properties and bytecode
+    /// merged; `Err` says why part of it couldn't be checked.
+    fn export(
+        &mut self,
+        lp: &LazyPackage,
+        db: Option<&SchemaDb>,
+        idx: i32,
+    ) -> std::result::Result<Vec<u8>, String> {
+        let old = lp.export_blob(idx).map_err(|e| e.to_string())?;
+        let mut blob = old.to_vec();
+        let p_ver = lp.header.p_ver;
+        let start = if p_ver >= VER_NETINDEX_STORED_AS_INT {
+            4
+        } else {
+            0
+        };
+
+        if old.len() > start {
+            let (props, end) = lp
+                .export_props(idx, db)
+                .map_err(|e| format!("properties: {e}"))?;
+            let mut w = Cursor::new(Vec::new());
+            for p in &props {
+                p.write(&mut w, &lp.pak, p_ver)
+                    .map_err(|e| format!("properties: {e}"))?;
+            }
+            let fresh = w.into_inner();
+            let region = old.get(start..end).unwrap_or_default();
+            self.merge_words(region, &fresh)
+                .ok_or("properties don't re-serialize byte for byte")?;
+            blob[start..end].copy_from_slice(&fresh);
+        }
+
+        if let Some(span) = lp
+            .pak
+            .script_kind(idx)
+            .and_then(|kind| script_span(old, kind, &lp.pak, p_ver))
+        {
+            let at = span.offset_in_blob as usize;
+            let script = old
+                .get(at..at + span.disk_size as usize)
+                .ok_or("script runs past its blob")?;
+            let dis = disassemble(script, &lp.pak, p_ver);
+            let merged = reserialize_script(script, &dis, Ok, |n| Ok(self.fname(&n)))
+                .map_err(|e| e.to_string())?;
+            blob[at..at + merged.len()].copy_from_slice(&merged);
+        }
+        Ok(blob)
+    }
+}
+
+/// Looks for duplicate names still in use; with `dedupe`, points every
+/// reference at the first entry of its text and saves. The duplicate
+/// entries stay where they are, like everything else in the name table,
+/// so native data this can't parse keeps resolving to the same text.
+fn duplicate_names(
+    upk_path: &Path,
+    lp: &LazyPackage,
+    db: Option<&SchemaDb>,
+    dedupe: bool,
+    out: Option<&Path>,
+    problems: &mut Vec<String>,
+) -> Result<()> {
+    let pak = &lp.pak;
+    let first = first_occurrences(&pak.name_table);
+    let duplicates = first
+        .iter()
+        .enumerate()
+        .filter(|&(i, &f)| f != i as i32)
+        .count();
+    if duplicates == 0 {
+        return Ok(());
+    }
+
+    let mut remap = NameRemap {
+        first: &first,
+        uses: vec![0; first.len()],
+    };
+    let imports: Vec<_> = pak
+        .import_table
+        .iter()
+        .map(|imp| {
+            let mut imp = imp.clone();
+            imp.class_package = remap.fname(&imp.class_package);
+            imp.class_name = remap.fname(&imp.class_name);
+            imp.object_name = remap.fname(&imp.object_name);
+            imp
+        })
+        .collect();
+    let exports: Vec<_> = pak
+        .export_table
+        .iter()
+        .map(|e| {
+            let mut e = e.clone();
+            e.object_name = remap.fname(&e.object_name);
+            e.legacy_component_map = e
+                .legacy_component_map
+                .iter()
+                .map(|(k, &v)| (remap.fname(k), v))
+                .collect();
+            e
+        })
+        .collect();
+    let mut blobs = Vec::new();
+    for idx in 1..=pak.export_table.len() as i32 {
+        let before = remap.uses.iter().sum::<usize>();
+        match remap.export(lp, db, idx) {
+            Ok(blob) if remap.uses.iter().sum::<usize>() > before => blobs.push((idx, blob)),
+            Ok(_) => {}
+            Err(e) => term::warn(
+                "names",
+                format_args!(
+                    "{}: {e}; its name references weren't checked",
+                    pak.get_export_full_name(idx)
+                ),
+            ),
+        }
+    }
+
+    let in_use: Vec<usize> = (0..first.len()).filter(|&i| remap.uses[i] > 0).collect();
+    if !dedupe {
+        for &i in &in_use {
+            problems.push(format!(
+                "name #{i} '{}' duplicates #{}; {} reference(s) use it (--dedupe-names merges them)",
+                pak.name_table[i], first[i], remap.uses[i]
+            ));
+        }
+        if in_use.len() < duplicates {
+            println!(
+                "{}",
+                paint(
+                    Color::Gray,
+                    format!(
+                        "{} duplicate name(s) with no references left",
+                        duplicates - in_use.len()
+                    )
+                )
+            );
+        }
+        return Ok(());
+    }
+    if in_use.is_empty() {
+        println!("{duplicates} duplicate name(s), none referenced; nothing to merge");
+        return Ok(());
+    }
+
+    let src = upk_path;
+    let mut pkg = Package::open(src)?;
+    pkg.imports = imports;
+    pkg.exports = exports;
+    for (idx, blob) in blobs {
+        pkg.set_export_blob(idx, blob)?;
+    }
+    let dst = match out {
+        Some(o) => o.to_path_buf(),
+        None => {
+            if let Some(bak) = backup_original(src)? {
+                println!("Backup: {}", bak.display());
+            }
+            src.to_path_buf()
+        }
+    };
+    let stats = pkg.save(&dst)?;
+    println!(
+        "Merged {} reference(s) to {} duplicate name(s); {} export(s) rewritten → {}",
+        remap.uses.iter().sum::<usize>(),
+        in_use.len(),
+        stats.replaced_exports,
+        dst.display()
+    );
+    Ok(())
+}
+
+/// Structural checks a loader would trip over: tables inside the file,
+/// export data inside the file and not overlapping, every object and name
+/// reference in range, no duplicate name in use.
+pub fn validate_cmd(
+    upk_path: &Path,
+    dedupe_names: bool,
+    out: Option<&Path>,
+    game_root: Option<&Path>,
+    verbose: bool,
+) -> Result<()> {
+    let lp = Rc::new(open_package_file(upk_path)?);
+    let (h, pak) = (&lp.header, &lp.pak);
+    let len = lp.bytes.len() as u64;
+    let mut problems = Vec::new();
+
+    for (table, offset, count) in [
+        ("name table", h.name_offset, h.name_count),
+        ("export table", h.export_offset, h.export_count),
+        ("import table", h.import_offset, h.import_count),
+        ("depends map", h.depends_offset, 0),
+    ] {
+        if count < 0 {
+            problems.push(format!("{table}: negative count {count}"));
+        }
+        if offset < 0 || offset as u64 > len {
+            problems.push(format!(
+                "{table}: offset 0x{offset:X} outside the package ({len} bytes)"
+            ));
+        }
+    }
+
+    let mut spans = Vec::new();
+    for (i, e) in pak.export_table.iter().enumerate() {
+        let what = format!("export #{}", i + 1);
+        check_name(pak, &what, "object", &e.object_name, &mut problems);
+        check_ref(pak, &what, "class", e.class_index, &mut problems);
+        check_ref(pak, &what, "super", e.super_index, &mut problems);
+        check_ref(pak, &what, "outer", e.outer_index, &mut problems);
+        check_ref(pak, &what, "archetype", e.archetype, &mut problems);
+        if e.outer_index == i as i32 + 1 {
+            problems.push(format!("{what}: is its own outer"));
+        }
+        if e.serial_size < 0 || e.serial_offset < 0 {
+            problems.push(format!(
+                "{what}: negative serial range {}+{}",
+                e.serial_offset, e.serial_size
+            ));
+            continue;
+        }
+        if e.serial_size == 0 {
+            continue;
+        }
+        let (start, end) = (
+            e.serial_offset as u64,
+            e.serial_offset as u64 + e.serial_size as u64,
+        );
+        if end > len {
+            problems.push(format!(
+                "{what}: data 0x{start:X}..0x{end:X} runs past the end of the package ({len} bytes)"
+            ));
+        }
+        spans.push((start, end, i + 1));
+    }
+    spans.sort();
+    for w in spans.windows(2) {
+        let ((_, a_end, a), (b_start, _, b)) = (w[0], w[1]);
+        if b_start < a_end {
+            problems.push(format!(
+                "export #{a} and export #{b}: data overlaps at 0x{b_start:X}"
+            ));
+        }
+    }
+
+    for (i, imp) in pak.import_table.iter().enumerate() {
+        let what = format!("import #{}", -(i as i32) - 1);
+        check_name(
+            pak,
+            &what,
+            "class package",
+            &imp.class_package,
+            &mut problems,
+        );
+        check_name(pak, &what, "class", &imp.class_name, &mut problems);
+        check_name(pak, &what, "object", &imp.object_name, &mut problems);
+        check_ref(pak, &what, "outer", imp.outer_index, &mut problems);
+    }
+
+    // Remapping needs every index in range first.
+    if problems.is_empty() {
+        // With a schema, arrays and structs are parsed down to their names.
+        let db = match game_root.filter(|g| !g.as_os_str().is_empty()) {
+            Some(gr) => {
+                let db = SchemaDb::new(gr)?.with_verbose(verbose);
+                db.inject_package(lp.clone());
+                Some(db)
+            }
+            None => None,
+        };
+        duplicate_names(upk_path, &lp, db.as_ref(), dedupe_names, out, &mut problems)?;
+    } else if dedupe_names {
+        term::warn(
+            "names",
+            format_args!("not merging duplicate names in a package with structural problems"),
+        );
+    }
+
+    for p in &problems {
+        term::error("error", p);
+    }
+    if !problems.is_empty() {
+        return Err(validation_failed(format!(
+            "{}: {} problem(s)",
+            upk_path.display(),
+            problems.len()
+        )));
+    }
+    println!(
+        "{}: {} ({} names, {} exports, {} imports)",
+        upk_path.display(),
+        paint(Color::Green, "OK"),
+        pak.name_table.len(),
+        pak.export_table.len(),
+        pak.import_table.len()
+    );
+    Ok(())
+}
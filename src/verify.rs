@@ -0,0 +1,310 @@
+//! Checking a game install against manifests of known-clean package
+//! hashes: which packages were modified, which are missing, which the
+//! manifest doesn't know.
+//!
+//! A manifest is JSON, one file per game, with any number of versions:
+//!
+//! ```json
+//! { "game": "Some Game",
+//!   "versions": [ { "label": "1.0.3",
+//!     "packages": { "CookedPC/Engine.upk": { "size": 123, "sha256": "…" } } } ] }
+//! ```
+//!
+//! Without `--db`, every `*.json` under `<config>/profiles/manifests` is
+//! tried, which is where `profiles update` puts the ones it ships.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    exit::validation_failed,
+    utils::{
+        backup::backup_path,
+        config::config_dir,
+        deadline,
+        hash::file_sha256,
+        pool::{self, par_map},
+        readonly,
+        term::{self, Color, paint},
+        walk::{package_files, rel_key},
+    },
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanPackage {
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestVersion {
+    pub label: String,
+    /// Path relative to the game dir, `/`-separated.
+    pub packages: BTreeMap<String, CleanPackage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallManifest {
+    pub game: String,
+    pub versions: Vec<ManifestVersion>,
+}
+
+impl InstallManifest {
+    fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+        serde_json::from_str(&text)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {e}", path.display())))
+    }
+}
+
+fn shipped_manifests() -> Result<Vec<PathBuf>> {
+    let dir = config_dir()?.join("profiles").join("manifests");
+    let mut out: Vec<PathBuf> = match std::fs::read_dir(&dir) {
+        Ok(rd) => rd
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| {
+                p.extension()
+                    .is_some_and(|x| x.eq_ignore_ascii_case("json"))
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    if out.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "no install manifests in {}; pass --db or run `profiles update`",
+                dir.display()
+            ),
+        ));
+    }
+    out.sort();
+    Ok(out)
+}
+
+/// Size and SHA-256 of every package under `root`, hashed on `jobs`
+/// threads. Unreadable files are left out with a warning.
+fn scan(root: &Path, jobs: Option<usize>) -> BTreeMap<String, (PathBuf, CleanPackage)> {
+    let files: Vec<PathBuf> = package_files(root);
+    let results = par_map(&files, pool::jobs(jobs), |p| {
+        // The deadline is per thread and per package.
+        deadline::arm();
+        std::fs::metadata(p).and_then(|m| {
+            Ok(CleanPackage {
+                size: m.len(),
+                sha256: file_sha256(p)?,
+            })
+        })
+    });
+
+    let mut out = BTreeMap::new();
+    for (p, r) in files.into_iter().zip(results) {
+        match r {
+            Ok(c) => {
+                out.insert(rel_key(root, &p), (p, c));
+            }
+            Err(e) => term::warn("skip", format_args!("{}: {e}", p.display())),
+        }
+    }
+    out
+}
+
+/// Installs on Windows don't keep case straight between patches.
+fn key_lc(k: &str) -> String {
+    k.to_ascii_lowercase()
+}
+
+/// How many installed packages match `v` exactly.
+fn matching(v: &ManifestVersion, have: &BTreeMap<String, &(PathBuf, CleanPackage)>) -> usize {
+    v.packages
+        .iter()
+        .filter(|(k, c)| {
+            have.get(&key_lc(k))
+                .is_some_and(|(_, h)| h.sha256.eq_ignore_ascii_case(&c.sha256))
+        })
+        .count()
+}
+
+pub fn verify_install_cmd(
+    root: &Path,
+    db: Option<&Path>,
+    label: Option<&str>,
+    jobs: Option<usize>,
+    verbose: bool,
+) -> Result<()> {
+    if !root.is_dir() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{} is not a directory", root.display()),
+        ));
+    }
+    let manifests: Vec<(PathBuf, InstallManifest)> = match db {
+        Some(p) => vec![(p.to_path_buf(), InstallManifest::load(p)?)],
+        None => shipped_manifests()?
+            .into_iter()
+            .filter_map(|p| match InstallManifest::load(&p) {
+                Ok(m) => Some((p, m)),
+                Err(e) => {
+                    term::warn("skip", e);
+                    None
+                }
+            })
+            .collect(),
+    };
+
+    let started = Instant::now();
+    let installed = scan(root, jobs);
+    let have: BTreeMap<String, &(PathBuf, CleanPackage)> =
+        installed.iter().map(|(k, v)| (key_lc(k), v)).collect();
+
+    // The named version, or whichever version the install agrees with
+    // most; ties go to the later entry, usually the newer patch.
+    let candidates = manifests
+        .iter()
+        .flat_map(|(p, m)| m.versions.iter().map(move |v| (p, m, v)))
+        .filter(|(_, _, v)| label.is_none_or(|l| v.label.eq_ignore_ascii_case(l)));
+    let Some((db_path, manifest, version)) = candidates.max_by_key(|(_, _, v)| matching(v, &have))
+    else {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            match label {
+                Some(l) => format!("no manifest has a version labelled '{l}'"),
+                None => "the manifests list no versions".to_string(),
+            },
+        ));
+    };
+    println!(
+        "Checking against {} {} ({}), {} package(s) hashed in {:.1?}",
+        paint(Color::Highlight, &manifest.game),
+        paint(Color::Highlight, &version.label),
+        paint(Color::Gray, db_path.display()),
+        installed.len(),
+        started.elapsed()
+    );
+
+    let known: BTreeSet<String> = version.packages.keys().map(|k| key_lc(k)).collect();
+    let (mut ok, mut modified, mut missing) = (0usize, 0usize, 0usize);
+    for (key, want) in &version.packages {
+        let Some((path, got)) = have.get(&key_lc(key)) else {
+            missing += 1;
+            println!("  {:<9} {key}", paint(Color::Red, "missing"));
+            continue;
+        };
+        if got.sha256.eq_ignore_ascii_case(&want.sha256) {
+            ok += 1;
+            if verbose {
+                println!("  {:<9} {key}", paint(Color::Green, "ok"));
+            }
+            continue;
+        }
+        modified += 1;
+        // An in-place edit left the original beside it.
+        let bak = backup_path(path);
+        let note = if bak.exists()
+            && file_sha256(&bak).is_ok_and(|h| h.eq_ignore_ascii_case(&want.sha256))
+        {
+            paint(Color::Gray, " (its .bak is the clean original)").to_string()
+        } else {
+            String::new()
+        };
+        let size = if got.size == want.size {
+            String::new()
+        } else {
+            paint(
+                Color::Gray,
+                format!(" ({} bytes, expected {})", got.size, want.size),
+            )
+            .to_string()
+        };
+        println!(
+            "  {:<9} {key}{size}{note}",
+            paint(Color::Yellow, "modified")
+        );
+    }
+    let mut unknown = 0usize;
+    for key in installed.keys().filter(|k| !known.contains(&key_lc(k))) {
+        unknown += 1;
+        println!("  {:<9} {key}", paint(Color::Cyan, "unknown"));
+    }
+
+    println!(
+        "{ok} ok, {modified} modified, {missing} missing, {unknown} unknown (not in the manifest)"
+    );
+    if modified + missing > 0 {
+        return Err(validation_failed(format!(
+            "install differs from {} {}",
+            manifest.game, version.label
+        )));
+    }
+    Ok(())
+}
+
+/// Records the packages under `root` as version `label` of `game` in
+/// the manifest at `out`, replacing an entry of the same label.
+pub fn record_cmd(
+    root: &Path,
+    out: &Path,
+    label: &str,
+    game: Option<&str>,
+    jobs: Option<usize>,
+) -> Result<()> {
+    if !root.is_dir() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{} is not a directory", root.display()),
+        ));
+    }
+    let out_path = out;
+    let mut manifest = if out_path.exists() {
+        InstallManifest::load(out_path)?
+    } else {
+        InstallManifest {
+            game: String::new(),
+            versions: Vec::new(),
+        }
+    };
+    if let Some(g) = game {
+        manifest.game = g.to_string();
+    }
+    if manifest.game.is_empty() {
+        manifest.game = root
+            .canonicalize()
+            .ok()
+            .and_then(|p| p.file_name().map(|s| s.to_string_lossy().to_string()))
+            .unwrap_or_else(|| "unknown".to_string());
+    }
+
+    let packages: BTreeMap<String, CleanPackage> = scan(root, jobs)
+        .into_iter()
+        .map(|(k, (_, c))| (k, c))
+        .collect();
+    let count = packages.len();
+    let version = ManifestVersion {
+        label: label.to_string(),
+        packages,
+    };
+    match manifest.versions.iter_mut().find(|v| v.label == label) {
+        Some(v) => *v = version,
+        None => manifest.versions.push(version),
+    }
+
+    let text = serde_json::to_string_pretty(&manifest).map_err(Error::other)?;
+    let mut part = out_path.as_os_str().to_os_string();
+    part.push(".part");
+    readonly::write(&part, text)?;
+    readonly::rename(&part, out_path)?;
+    println!(
+        "Recorded {count} package(s) as {} {label} in {}",
+        manifest.game,
+        out.display()
+    );
+    Ok(())
+}
@@ -88,6 +88,10 @@ pub const CPF_OUT_PARM: u64 = 0x0000000000000100;
 pub const CPF_RETURN_PARM: u64 = 0x0000000000000400;
 pub const CPF_NATIVE: u64 = 0x0000000000001000;
 
+pub const EF_FORCED_EXPORT: u32 = 0x00000001;
+
+pub const RF_PUBLIC: u64 = 0x0000000000000004;
+pub const RF_STANDALONE: u64 = 0x0000000000080000;
 pub const RF_HAS_STACK: u64 = 0x0000000000020000;
 pub const RF_CLASS_DEFAULT_OBJECT: u64 = 0x0000000000000200;
 
@@ -150,6 +154,81 @@ pub fn format_function_flags(flags: u32) -> String {
         .join(" | ")
 }
 
+/// UE3 object flags as stored with each name-table entry (the `n_fl` /
+/// `n_fh` dword pair): the `RF_` mask the name was saved with, usually
+/// `LoadForClient | LoadForServer | LoadForEdit | TagExp`. Laid out like
+/// the 64-bit `EObjectFlags`, which differs from the export flag bits
+/// above.
+const NAME_FLAG_NAMES: &[(u64, &str)] = &[
+    (0x0000_0000_0000_0002, "InSingularFunc"),
+    (0x0000_0000_0000_0004, "StateChanged"),
+    (0x0000_0000_0000_0008, "DebugPostLoad"),
+    (0x0000_0000_0000_0010, "DebugSerialize"),
+    (0x0000_0000_0000_0020, "DebugFinishDestroyed"),
+    (0x0000_0000_0000_0040, "EdSelected"),
+    (0x0000_0000_0000_0080, "ZombieComponent"),
+    (0x0000_0000_0000_0100, "Protected"),
+    (0x0000_0000_0000_0200, "ClassDefaultObject"),
+    (0x0000_0000_0000_0400, "ArchetypeObject"),
+    (0x0000_0000_0000_0800, "ForceTagExp"),
+    (0x0000_0000_0000_1000, "TokenStreamAssembled"),
+    (0x0000_0000_0000_2000, "MisalignedObject"),
+    (0x0000_0000_0000_4000, "RootSet"),
+    (0x0000_0000_0000_8000, "BeginDestroyed"),
+    (0x0000_0000_0001_0000, "FinishDestroyed"),
+    (0x0000_0000_0002_0000, "DebugBeginDestroyed"),
+    (0x0000_0000_0004_0000, "MarkedByCooker"),
+    (0x0000_0000_0008_0000, "LocalizedResource"),
+    (0x0000_0000_0010_0000, "InitializedProps"),
+    (0x0000_0000_0020_0000, "PendingFieldPatches"),
+    (0x0000_0000_0040_0000, "IsCrossLevelReferenced"),
+    (0x0000_0000_8000_0000, "Saved"),
+    (0x0000_0001_0000_0000, "Transactional"),
+    (0x0000_0002_0000_0000, "Unreachable"),
+    (0x0000_0004_0000_0000, "Public"),
+    (0x0000_0008_0000_0000, "TagImp"),
+    (0x0000_0010_0000_0000, "TagExp"),
+    (0x0000_0020_0000_0000, "Obsolete"),
+    (0x0000_0040_0000_0000, "TagGarbage"),
+    (0x0000_0080_0000_0000, "DisregardForGC"),
+    (0x0000_0100_0000_0000, "PerObjectLocalized"),
+    (0x0000_0200_0000_0000, "NeedLoad"),
+    (0x0000_0400_0000_0000, "AsyncLoading"),
+    (0x0000_0800_0000_0000, "NeedPostLoadSubobjects"),
+    (0x0000_1000_0000_0000, "Suppress"),
+    (0x0000_2000_0000_0000, "InEndState"),
+    (0x0000_4000_0000_0000, "Transient"),
+    (0x0000_8000_0000_0000, "Cooked"),
+    (0x0001_0000_0000_0000, "LoadForClient"),
+    (0x0002_0000_0000_0000, "LoadForServer"),
+    (0x0004_0000_0000_0000, "LoadForEdit"),
+    (0x0008_0000_0000_0000, "Standalone"),
+    (0x0010_0000_0000_0000, "NotForClient"),
+    (0x0020_0000_0000_0000, "NotForServer"),
+    (0x0040_0000_0000_0000, "NotForEdit"),
+    (0x0100_0000_0000_0000, "NeedPostLoad"),
+    (0x0200_0000_0000_0000, "HasStack"),
+    (0x0400_0000_0000_0000, "Native"),
+    (0x0800_0000_0000_0000, "Marked"),
+    (0x1000_0000_0000_0000, "ErrorShutdown"),
+    (0x2000_0000_0000_0000, "PendingKill"),
+];
+
+/// Names of the set bits of a name entry's flags; bits without a name
+/// are listed as one hex value at the end.
+pub fn format_name_flags(flags: u64) -> String {
+    let mut parts: Vec<String> = NAME_FLAG_NAMES
+        .iter()
+        .filter(|(bit, _)| flags & bit != 0)
+        .map(|(_, n)| n.to_string())
+        .collect();
+    let known = NAME_FLAG_NAMES.iter().fold(0, |m, (bit, _)| m | bit);
+    if flags & !known != 0 {
+        parts.push(format!("0x{:X}", flags & !known));
+    }
+    parts.join(" | ")
+}
+
 pub fn script_pointer_size(p_ver: i16) -> usize {
     // if p_ver < VER_ADDITIONAL_COOK_PACKAGE_SUMMARY {
     //     4
@@ -0,0 +1,619 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::File,
+    io::{BufReader, Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    native::{BulkCompression, Convert},
+    package::{Package, SaveStats},
+    pseudo_parse,
+    upkpacker::{self, BulkArg, PackOptions, export_path_dotted},
+    upkreader::UpkHeader,
+    utils::{
+        hash::{ContentHash, file_hash},
+        readonly,
+        term::{Color, epaint, paint},
+        walk::{files_under, package_files, rel_key},
+    },
+};
+
+const STATE_FILE: &str = "workspace.toml";
+
+#[derive(Subcommand)]
+pub enum WorkspaceCmd {
+    #[command(about = "Record the game's packages (versions + hashes) into a new workspace")]
+    Init {
+        game_dir: PathBuf,
+        work_dir: PathBuf,
+    },
+
+    #[command(about = "Extract a package (or one object) into the workspace")]
+    Extract {
+        work_dir: PathBuf,
+        package: String,
+        object: Option<String>,
+        #[arg(long)]
+        force: bool,
+    },
+
+    #[command(about = "List modified extracted files and changed source packages")]
+    Status { work_dir: PathBuf },
+
+    #[command(about = "Re-import modified files into copies of their source packages")]
+    Build {
+        work_dir: PathBuf,
+        #[arg(long, value_name = "METHOD", num_args = 0..=1, default_missing_value = "original")]
+        compress_bulk: Option<BulkArg>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageRecord {
+    pub size: u64,
+    pub hash: String,
+    pub p_ver: i16,
+    pub l_ver: i16,
+    pub engine_ver: i32,
+}
+
+/// Last successful pack of one `.uo`: the hash of everything it was built
+/// from and where its override landed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuiltObject {
+    pub input: String,
+    pub package: String,
+    pub key: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workspace {
+    pub game_dir: PathBuf,
+    #[serde(default)]
+    pub packages: BTreeMap<String, PackageRecord>,
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+    #[serde(default)]
+    pub built: BTreeMap<String, BuiltObject>,
+    /// Package rel path -> digest of the overrides its build output came from.
+    #[serde(default)]
+    pub outputs: BTreeMap<String, String>,
+    #[serde(skip)]
+    pub root: PathBuf,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FileState {
+    Modified,
+    Missing,
+    New,
+}
+
+impl Workspace {
+    pub fn load(work_dir: &Path) -> Result<Self> {
+        let path = work_dir.join(STATE_FILE);
+        let text = std::fs::read_to_string(&path).map_err(|e| {
+            Error::new(
+                e.kind(),
+                format!("{}: {e} (run `workspace init` first)", path.display()),
+            )
+        })?;
+        let mut ws: Workspace = toml::from_str(&text)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {e}", path.display())))?;
+        ws.root = work_dir.to_path_buf();
+        Ok(ws)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let text = toml::to_string_pretty(self).map_err(Error::other)?;
+        readonly::write(self.root.join(STATE_FILE), text)
+    }
+
+    pub fn extracted_dir(&self) -> PathBuf {
+        self.root.join("extracted")
+    }
+
+    pub fn overrides_dir(&self) -> PathBuf {
+        self.root.join("overrides")
+    }
+
+    pub fn build_dir(&self) -> PathBuf {
+        self.root.join("build")
+    }
+
+    pub fn source_path(&self, rel: &str) -> PathBuf {
+        self.game_dir.join(rel)
+    }
+
+    /// Record key (relative path) of the package with this file stem.
+    pub fn package_by_stem(&self, stem: &str) -> Option<&str> {
+        self.packages
+            .keys()
+            .find(|rel| {
+                Path::new(rel)
+                    .file_stem()
+                    .is_some_and(|s| s.to_string_lossy().eq_ignore_ascii_case(stem))
+            })
+            .map(|s| s.as_str())
+    }
+
+    pub fn package_paths(&self) -> HashMap<String, PathBuf> {
+        self.packages
+            .keys()
+            .filter_map(|rel| {
+                let stem = Path::new(rel).file_stem()?.to_string_lossy().to_lowercase();
+                Some((stem, self.source_path(rel)))
+            })
+            .collect()
+    }
+
+    fn rel_extracted(&self, p: &Path) -> String {
+        rel_key(&self.extracted_dir(), p)
+    }
+
+    /// Re-hash everything under `extracted/<sub>` as the new baseline.
+    fn record_tree(&mut self, sub: &str) -> Result<usize> {
+        let prefix = format!("{sub}/");
+        self.files.retain(|k, _| !k.starts_with(&prefix));
+        let mut n = 0;
+        for p in files_under(&self.extracted_dir().join(sub)) {
+            let key = self.rel_extracted(&p);
+            self.files.insert(key, file_hash(&p)?);
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    pub fn file_states(&self, sub: Option<&str>) -> Result<BTreeMap<String, FileState>> {
+        let mut out = BTreeMap::new();
+        let in_scope = |k: &str| sub.is_none_or(|s| k.starts_with(&format!("{s}/")));
+        for (key, hash) in &self.files {
+            if !in_scope(key) {
+                continue;
+            }
+            let p = self.extracted_dir().join(key);
+            if !p.exists() {
+                out.insert(key.clone(), FileState::Missing);
+            } else if file_hash(&p)? != *hash {
+                out.insert(key.clone(), FileState::Modified);
+            }
+        }
+        for p in files_under(&self.extracted_dir()) {
+            let key = self.rel_extracted(&p);
+            if in_scope(&key) && !self.files.contains_key(&key) {
+                out.insert(key, FileState::New);
+            }
+        }
+        Ok(out)
+    }
+
+    /// `.uo` files that changed themselves or whose sidecars changed.
+    pub fn modified_uo_files(&self) -> Result<Vec<PathBuf>> {
+        let states = self.file_states(None)?;
+        let changed = |k: &str| matches!(states.get(k), Some(FileState::Modified | FileState::New));
+        let mut out = Vec::new();
+        for p in files_under(&self.extracted_dir()) {
+            if p.extension().and_then(|s| s.to_str()) != Some("uo") {
+                continue;
+            }
+            let key = self.rel_extracted(&p);
+            let mut hit = changed(&key);
+            if !hit {
+                let text = std::fs::read_to_string(&p)?;
+                if let Ok(uo) = pseudo_parse::parse(&text) {
+                    let dir = p.parent().unwrap_or(Path::new("."));
+                    hit = uo
+                        .sidecars
+                        .iter()
+                        .any(|s| changed(&self.rel_extracted(&dir.join(s))));
+                }
+            }
+            if hit {
+                out.push(p);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Hash of a `.uo`, its sidecars and the source package it packs against.
+    fn input_hash(&self, uo: &Path) -> Result<String> {
+        let key = self.rel_extracted(uo);
+        let mut h = ContentHash::new();
+        if let Some(rel) = key.split('/').next().and_then(|s| self.package_by_stem(s)) {
+            h.update(self.packages[rel].hash.as_bytes());
+        }
+        let text = std::fs::read(uo)?;
+        h.update(&text);
+        if let Ok(parsed) = pseudo_parse::parse(&String::from_utf8_lossy(&text)) {
+            let dir = uo.parent().unwrap_or(Path::new("."));
+            for s in &parsed.sidecars {
+                h.update(s.as_bytes());
+                if let Ok(bytes) = std::fs::read(dir.join(s)) {
+                    h.update(&bytes);
+                }
+            }
+        }
+        Ok(h.hex())
+    }
+}
+
+fn record_package(path: &Path) -> Result<PackageRecord> {
+    let size = std::fs::metadata(path)?.len();
+    let h = UpkHeader::read(&mut BufReader::new(File::open(path)?))?;
+    Ok(PackageRecord {
+        size,
+        hash: file_hash(path)?,
+        p_ver: h.p_ver,
+        l_ver: h.l_ver,
+        engine_ver: h.engine_ver,
+    })
+}
+
+fn init(game_dir: &Path, work: &Path) -> Result<()> {
+    if work.join(STATE_FILE).exists() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} already has a {STATE_FILE}", work.display()),
+        ));
+    }
+    let game = std::fs::canonicalize(game_dir)?;
+    let mut ws = Workspace {
+        game_dir: game.clone(),
+        packages: BTreeMap::new(),
+        files: BTreeMap::new(),
+        built: BTreeMap::new(),
+        outputs: BTreeMap::new(),
+        root: work.to_path_buf(),
+    };
+    for p in package_files(&game) {
+        let rel = rel_key(&game, &p);
+        match record_package(&p) {
+            Ok(r) => {
+                ws.packages.insert(rel, r);
+            }
+            Err(e) => eprintln!("  {} {rel}: {e}", epaint(Color::Yellow, "skip")),
+        }
+    }
+    readonly::create_dir_all(ws.extracted_dir())?;
+    ws.save()?;
+    println!(
+        "Workspace {} created: {} package(s) recorded from {}",
+        work.display(),
+        ws.packages.len(),
+        game.display()
+    );
+    Ok(())
+}
+
+fn extract(
+    work_dir: &Path,
+    package: &str,
+    object: Option<&str>,
+    force: bool,
+    verbose: bool,
+) -> Result<()> {
+    let mut ws = Workspace::load(work_dir)?;
+    let rel = ws
+        .package_by_stem(package)
+        .or_else(|| ws.packages.contains_key(package).then_some(package))
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("package '{package}' is not part of this workspace"),
+            )
+        })?
+        .to_string();
+    let src = ws.source_path(&rel);
+    let stem = src
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let dirty = ws.file_states(Some(&stem))?;
+    if !force && dirty.values().any(|s| *s != FileState::Missing) {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!(
+                "{} extracted file(s) of {stem} were modified; re-extracting would overwrite them (use --force)",
+                dirty.len()
+            ),
+        ));
+    }
+
+    crate::extract_file(
+        &src,
+        object.as_slice(),
+        &ws.extracted_dir(),
+        Some(&ws.game_dir),
+        verbose,
+        &[Convert::Native],
+    )?;
+    let n = ws.record_tree(&stem)?;
+    ws.save()?;
+    println!("Workspace: {n} file(s) of {stem} tracked");
+    Ok(())
+}
+
+fn status(work_dir: &Path) -> Result<()> {
+    let ws = Workspace::load(work_dir)?;
+    let states = ws.file_states(None)?;
+    if states.is_empty() {
+        println!("No modified files");
+    }
+    for (key, st) in &states {
+        let tag = match st {
+            FileState::Modified => paint(Color::Yellow, "modified"),
+            FileState::Missing => paint(Color::Red, "missing"),
+            FileState::New => paint(Color::Green, "new"),
+        };
+        println!("  {tag:<8}  {key}");
+    }
+
+    let mut stems: Vec<&str> = states.keys().filter_map(|k| k.split('/').next()).collect();
+    stems.dedup();
+    for stem in stems {
+        let Some(rel) = ws.package_by_stem(stem) else {
+            continue;
+        };
+        let rec = &ws.packages[rel];
+        let src = ws.source_path(rel);
+        match record_package(&src) {
+            Ok(now) if now.hash != rec.hash => {
+                println!(
+                    "  {}  {rel} (game update?)",
+                    paint(Color::Red, "source changed")
+                )
+            }
+            Err(e) => println!("  {}  {rel}: {e}", paint(Color::Red, "source unreadable")),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn build(work_dir: &Path, bulk: BulkCompression, verbose: bool) -> Result<()> {
+    let mut ws = Workspace::load(work_dir)?;
+    let modified = ws.modified_uo_files()?;
+    let overrides = ws.overrides_dir();
+
+    // Objects that are back to their extracted state no longer override
+    // anything; their packages are re-packed from scratch so the namemap
+    // doesn't keep names only they needed.
+    let current: BTreeSet<String> = modified.iter().map(|p| ws.rel_extracted(p)).collect();
+    let reverted: BTreeSet<String> = ws
+        .built
+        .iter()
+        .filter(|(k, _)| !current.contains(*k))
+        .map(|(_, b)| b.package.clone())
+        .collect();
+    for pkg in &reverted {
+        let dir = overrides.join(pkg);
+        if dir.exists() {
+            readonly::remove_dir_all(&dir)?;
+        }
+        ws.built.retain(|_, b| b.package != *pkg);
+    }
+
+    let mut inputs = HashMap::new();
+    let mut to_pack = Vec::new();
+    for p in &modified {
+        let key = ws.rel_extracted(p);
+        let input = ws.input_hash(p)?;
+        let fresh = ws.built.get(&key).is_some_and(|b| {
+            b.input == input
+                && overrides
+                    .join(&b.package)
+                    .join(format!("{}.bin", b.key))
+                    .exists()
+        });
+        if !fresh {
+            to_pack.push(p.clone());
+        }
+        inputs.insert(key, input);
+    }
+
+    if !to_pack.is_empty() {
+        let package_paths = ws.package_paths();
+        let extracted = ws.extracted_dir();
+        let packed = upkpacker::pack_mod(&PackOptions {
+            extracted_dir: &extracted,
+            game_root: Some(&ws.game_dir),
+            out_dir: Some(&overrides),
+            verbose,
+            only_files: Some(&to_pack),
+            package_paths: Some(&package_paths),
+            keep_names: true,
+            bulk,
+        })?;
+        for p in packed {
+            let key = ws.rel_extracted(&p.uo);
+            let Some(input) = inputs.remove(&key) else {
+                continue;
+            };
+            ws.built.insert(
+                key,
+                BuiltObject {
+                    input,
+                    package: p.pkg_name,
+                    key: p.key,
+                },
+            );
+        }
+    }
+    println!(
+        "Workspace: {} modified object(s), {} re-packed, {} reused",
+        modified.len(),
+        to_pack.len(),
+        modified.len() - to_pack.len()
+    );
+
+    let mut built = 0usize;
+    let mut up_to_date = 0usize;
+    let mut live = BTreeSet::new();
+    let pkg_dirs = std::fs::read_dir(&overrides)
+        .map(|rd| rd.flatten().map(|e| e.path()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    for pkg_dir in pkg_dirs {
+        if !pkg_dir.is_dir() {
+            continue;
+        }
+        let stem = pkg_dir
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let Some(rel) = ws.package_by_stem(&stem).map(str::to_string) else {
+            eprintln!(
+                "  {} {stem}: not a workspace package",
+                epaint(Color::Yellow, "skip")
+            );
+            continue;
+        };
+        live.insert(rel.clone());
+        let out = ws.build_dir().join(&rel);
+        let digest = overrides_digest(&pkg_dir, &ws.packages[&rel].hash)?;
+        if out.exists() && ws.outputs.get(&rel) == Some(&digest) {
+            up_to_date += 1;
+            continue;
+        }
+        let stats = apply_overrides(&ws.source_path(&rel), &pkg_dir, &stem, &out)?;
+        ws.outputs.insert(rel.clone(), digest);
+        built += 1;
+        println!(
+            "  {rel}: {} export(s) replaced, {} unchanged, {} name(s) added → {}",
+            stats.save.replaced_exports,
+            stats.unchanged_exports,
+            stats.save.added_names,
+            out.display()
+        );
+    }
+
+    let gone: Vec<String> = ws
+        .outputs
+        .keys()
+        .filter(|k| !live.contains(*k))
+        .cloned()
+        .collect();
+    for rel in gone {
+        let out = ws.build_dir().join(&rel);
+        if out.exists() {
+            readonly::remove_file(&out)?;
+        }
+        ws.outputs.remove(&rel);
+        println!("  {rel}: no overrides left, removed {}", out.display());
+    }
+
+    ws.save()?;
+    println!(
+        "Workspace build: {built} package(s) written, {up_to_date} up to date in {}",
+        ws.build_dir().display()
+    );
+    Ok(())
+}
+
+/// Digest of an override directory (names + contents) plus the source
+/// package hash; equal digests mean the build output can be reused as is.
+fn overrides_digest(pkg_dir: &Path, source_hash: &str) -> Result<String> {
+    let mut h = ContentHash::new();
+    h.update(source_hash.as_bytes());
+    for p in files_under(pkg_dir) {
+        h.update(
+            p.strip_prefix(pkg_dir)
+                .unwrap_or(&p)
+                .to_string_lossy()
+                .as_bytes(),
+        );
+        h.update(file_hash(&p)?.as_bytes());
+    }
+    Ok(h.hex())
+}
+
+pub struct OverrideStats {
+    pub save: SaveStats,
+    pub unchanged_exports: usize,
+}
+
+/// Splices pack-mod output (`<key>.bin` + `<pkg>.namemap`) into a copy of
+/// the source package.
+pub fn apply_overrides(
+    src: &Path,
+    pkg_dir: &Path,
+    pkg_name: &str,
+    out: &Path,
+) -> Result<OverrideStats> {
+    let mut pkg = Package::open(src)?;
+
+    let map_path = pkg_dir.join(format!("{pkg_name}.namemap"));
+    if map_path.exists() {
+        let text = std::fs::read_to_string(&map_path)?;
+        for (i, name) in text.lines().enumerate() {
+            if i < pkg.names.len() {
+                if pkg.names[i].name != name {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "{}: name #{i} is '{name}' but the package has '{}'",
+                            map_path.display(),
+                            pkg.names[i].name
+                        ),
+                    ));
+                }
+                continue;
+            }
+            pkg.add_name(name);
+        }
+    }
+
+    let pak = pkg.pak();
+    let keys: HashMap<String, i32> = (1..=pak.export_table.len() as i32)
+        .map(|i| (export_path_dotted(&pak, i), i))
+        .collect();
+    let mut unchanged = 0usize;
+    for p in files_under(pkg_dir) {
+        if p.extension().and_then(|s| s.to_str()) != Some("bin") {
+            continue;
+        }
+        let key = p
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let idx = *keys.get(&key).ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("{}: no export '{key}' in {pkg_name}", p.display()),
+            )
+        })?;
+        if !pkg.set_export_blob(idx, std::fs::read(&p)?)? {
+            unchanged += 1;
+        }
+    }
+
+    Ok(OverrideStats {
+        save: pkg.save(out)?,
+        unchanged_exports: unchanged,
+    })
+}
+
+pub fn run(cmd: WorkspaceCmd, verbose: bool) -> Result<()> {
+    match cmd {
+        WorkspaceCmd::Init { game_dir, work_dir } => init(&game_dir, &work_dir),
+        WorkspaceCmd::Extract {
+            work_dir,
+            package,
+            object,
+            force,
+        } => extract(&work_dir, &package, object.as_deref(), force, verbose),
+        WorkspaceCmd::Status { work_dir } => status(&work_dir),
+        WorkspaceCmd::Build {
+            work_dir,
+            compress_bulk,
+        } => build(
+            &work_dir,
+            upkpacker::bulk_compression(compress_bulk),
+            verbose,
+        ),
+    }
+}
@@ -0,0 +1,124 @@
+//! Paths past Windows' 260-character MAX_PATH, and (on Linux) directory
+//! names that aren't UTF-8: both have to survive every command untouched.
+
+use std::{ffi::OsString, fs, path::PathBuf, process::Command};
+
+use ue3_tools::utils::{readonly, walk::package_files};
+
+/// A fresh directory for one test, removed when dropped.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new(tag: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("ue3-tools-{tag}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Scratch(dir)
+    }
+
+    /// A directory a dozen levels down whose path is well past 260
+    /// characters, ending in `leaf`.
+    fn deep(&self, leaf: impl Into<OsString>) -> PathBuf {
+        let mut p = self.0.clone();
+        for i in 0..12 {
+            p.push(format!("Level{i:02}_ExportedObjectTree_Segment"));
+        }
+        p.push(leaf.into());
+        assert!(p.as_os_str().len() > 260, "{} is too short", p.display());
+        fs::create_dir_all(&p).unwrap();
+        p
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A directory name that is valid on Linux but not UTF-8 (Latin-1 `é`).
+#[cfg(target_os = "linux")]
+fn non_utf8() -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(b"Caf\xe9".to_vec())
+}
+
+fn ue3_tools() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_ue3-tools"))
+}
+
+#[test]
+fn walk_finds_packages_in_deep_hierarchies() {
+    let s = Scratch::new("walk");
+    let dir = s.deep("CookedPC");
+    let pkg = dir.join("Deep.upk");
+    fs::write(&pkg, b"").unwrap();
+    fs::write(dir.join("Deep.txt"), b"").unwrap();
+    assert_eq!(package_files(&s.0), vec![pkg]);
+}
+
+#[test]
+fn readonly_writes_and_renames_in_deep_hierarchies() {
+    let s = Scratch::new("write");
+    let dir = s.deep("Extracted");
+    let part = dir.join("Object.Class.uo.part");
+    let done = dir.join("Object.Class.uo");
+    readonly::write(&part, b"data").unwrap();
+    readonly::rename(&part, &done).unwrap();
+    assert_eq!(fs::read(&done).unwrap(), b"data");
+    assert!(!part.exists());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn walk_keeps_non_utf8_directory_names() {
+    let s = Scratch::new("walk-non-utf8");
+    let dir = s.deep(non_utf8());
+    let pkg = dir.join("Startup.upk");
+    fs::write(&pkg, b"").unwrap();
+    let found = package_files(&s.0);
+    assert_eq!(found, vec![pkg]);
+    assert!(found[0].to_str().is_none());
+}
+
+/// clap refuses a `String` argument that isn't UTF-8 before the command
+/// runs; a path argument reaches the command, which reports the file as
+/// missing (exit code 2) rather than a usage error (1).
+#[cfg(target_os = "linux")]
+#[test]
+fn cli_takes_deep_non_utf8_paths() {
+    let s = Scratch::new("cli-non-utf8");
+    let dir = s.deep(non_utf8());
+
+    let out = ue3_tools().arg("opcode-stats").arg(&dir).output().unwrap();
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("no packages under"), "{stderr}");
+
+    let missing = dir.join("Missing.upk");
+    let out = ue3_tools().arg("list").arg(&missing).output().unwrap();
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+}
+
+/// The same for arguments of nested subcommands.
+#[cfg(target_os = "linux")]
+#[test]
+fn subcommands_take_deep_non_utf8_paths() {
+    let s = Scratch::new("sub-non-utf8");
+    let dir = s.deep(non_utf8());
+    let missing = dir.join("Missing.upk");
+
+    for args in [
+        &["chunks", "list"][..],
+        &["table", "show", "--export", "1"][..],
+    ] {
+        let out = ue3_tools().args(args).arg(&missing).output().unwrap();
+        assert_eq!(out.status.code(), Some(2), "{args:?}: {out:?}");
+    }
+    let out = ue3_tools()
+        .args(["workspace", "status"])
+        .arg(&dir)
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+}
@@ -0,0 +1,25 @@
+use ue3_tools::utils::fsname::component;
+
+#[test]
+fn safe_names_are_kept() {
+    assert_eq!(component("TestFont_PageA"), "TestFont_PageA");
+    assert_eq!(component("Texture2D"), "Texture2D");
+}
+
+#[test]
+fn substituted_names_stay_distinct() {
+    let names = ["A:B", "A?B", "A_B", "A*B", "A.", "A_", "CON", "_CON"];
+    let mut out: Vec<String> = names.iter().map(|n| component(n)).collect();
+    assert!(out[0].starts_with("A_B~"), "{}", out[0]);
+    out.sort();
+    out.dedup();
+    assert_eq!(out.len(), names.len(), "{out:?}");
+}
+
+#[test]
+fn overlong_names_fit_and_stay_distinct() {
+    let a = component(&"x".repeat(300));
+    let b = component(&"x".repeat(301));
+    assert!(a.len() <= 200 && b.len() <= 200);
+    assert_ne!(a, b);
+}